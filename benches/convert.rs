@@ -0,0 +1,72 @@
+//! Throughput of the RGB to YUV420 kernels across common resolutions and pixel formats.
+//!
+//! Run `cargo bench` for the checked kernel and `cargo bench --features unsafe-fast`
+//! for the unchecked one; criterion compares the two runs against the saved baseline.
+
+#[macro_use]
+extern crate criterion;
+extern crate rgb2yuv420;
+
+use criterion::{BenchmarkId, Criterion, Throughput};
+use rgb2yuv420::{ChannelOrder, ChromaFilter, Converter, Matrix, Quality, Range, YuvFormat};
+use std::hint::black_box;
+
+const RESOLUTIONS: &[(&str, u32, u32)] = &[
+    ("480p", 640, 480),
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("2160p", 3840, 2160),
+];
+
+const PIXEL_FORMATS: &[(&str, usize)] = &[("rgb", 3), ("rgba", 4)];
+
+fn test_image(width: u32, height: u32, bytes_per_pixel: usize) -> Vec<u8> {
+    (0..(width * height) as usize * bytes_per_pixel).map(|i| (i * 7 % 256) as u8).collect()
+}
+
+fn bench_kernel(c: &mut Criterion, name: &str, convert: fn(&[u8], u32, u32, usize) -> Vec<u8>) {
+    let mut group = c.benchmark_group(name);
+    for &(res_name, width, height) in RESOLUTIONS {
+        for &(fmt_name, bytes_per_pixel) in PIXEL_FORMATS {
+            let img = test_image(width, height, bytes_per_pixel);
+            group.throughput(Throughput::Elements(u64::from(width * height)));
+            group.bench_with_input(BenchmarkId::new(fmt_name, res_name), &img, |b, img| {
+                b.iter(|| convert(black_box(img), width, height, bytes_per_pixel))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn yuv420p(c: &mut Criterion) {
+    bench_kernel(c, "yuv420p", rgb2yuv420::convert_rgb_to_yuv420p);
+}
+
+fn yuv420sp_nv12(c: &mut Criterion) {
+    bench_kernel(c, "yuv420sp_nv12", rgb2yuv420::convert_rgb_to_yuv420sp_nv12);
+}
+
+fn converter_options(c: &mut Criterion) {
+    let (width, height) = (1920, 1080);
+    let img = test_image(width, height, 4);
+    let mut group = c.benchmark_group("converter_1080p_rgba_nv12");
+    group.throughput(Throughput::Elements(u64::from(width * height)));
+    let base = Converter::builder().input(ChannelOrder::Rgba).output(YuvFormat::Nv12);
+    let variants = vec![
+        ("point", base.clone().build()),
+        ("average", base.clone().chroma_filter(ChromaFilter::Average).build()),
+        ("linear_average", base.clone().chroma_filter(ChromaFilter::LinearAverage).build()),
+        ("fast", base.clone().quality(Quality::Fast).build()),
+        ("bt709_limited", base.clone().matrix(Matrix::Bt709).range(Range::Limited).build()),
+        ("threads_4", base.threads(4).build()),
+    ];
+    for (name, converter) in variants {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &img, |b, img| {
+            b.iter(|| converter.convert(black_box(img), width, height).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, yuv420p, yuv420sp_nv12, converter_options);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+/* C bindings of rgb2yuv420, built with `cargo rustc --release --features capi --crate-type cdylib`.
+ * Generated with cbindgen (see cbindgen.toml). */
+
+#ifndef RGB2YUV420_H
+#define RGB2YUV420_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define RGB2YUV420_OK 0
+#define RGB2YUV420_BUFFER_TOO_SMALL 1
+#define RGB2YUV420_STRIDE_MISMATCH 2
+#define RGB2YUV420_UNSUPPORTED_DIMENSIONS 3
+/* An unknown format, channel order, matrix or range constant, too */
+#define RGB2YUV420_UNSUPPORTED_FORMAT 4
+#define RGB2YUV420_INVALID_REGION 5
+#define RGB2YUV420_DEVICE 6
+#define RGB2YUV420_NULL_POINTER 7
+
+#define RGB2YUV420_I420 0
+#define RGB2YUV420_NV12 1
+
+#define RGB2YUV420_RGB 0
+#define RGB2YUV420_BGR 1
+#define RGB2YUV420_RGBA 2
+#define RGB2YUV420_BGRA 3
+#define RGB2YUV420_ARGB 4
+#define RGB2YUV420_ABGR 5
+
+#define RGB2YUV420_BT601 0
+#define RGB2YUV420_BT709 1
+#define RGB2YUV420_BT2020 2
+
+#define RGB2YUV420_FULL 0
+#define RGB2YUV420_LIMITED 1
+
+/* How `rgb2yuv420_convert` converts */
+typedef struct Rgb2Yuv420Options {
+  /* `RGB2YUV420_I420` or `RGB2YUV420_NV12` */
+  int format;
+  /* `RGB2YUV420_BT601`, `RGB2YUV420_BT709` or `RGB2YUV420_BT2020` */
+  int matrix;
+  /* `RGB2YUV420_FULL` or `RGB2YUV420_LIMITED` */
+  int range;
+} Rgb2Yuv420Options;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Writes the bytes of a tightly packed `format` frame to `size` */
+int rgb2yuv420_frame_size(int format, uint32_t width, uint32_t height, size_t *size);
+
+/* Writes the offsets and strides of the Y, U (or UV) and V planes of a tightly packed
+ * `format` frame to `offsets` and `strides`; the third ones are 0 for NV12 */
+int rgb2yuv420_plane_layout(int format, uint32_t width, uint32_t height, size_t *offsets, size_t *strides);
+
+/* Converts `width` x `height` 8 bit pixels in `channel_order`, whose rows start `stride`
+ * bytes apart in the `src_len` bytes at `src`, into a tightly packed frame at the start of
+ * the `dst_len` bytes at `dst` */
+int rgb2yuv420_convert(const uint8_t *src,
+                       size_t src_len,
+                       uint32_t width,
+                       uint32_t height,
+                       size_t stride,
+                       int channel_order,
+                       const Rgb2Yuv420Options *options,
+                       uint8_t *dst,
+                       size_t dst_len);
+
+#ifdef __cplusplus
+}  /* extern "C" */
+#endif
+
+#endif  /* RGB2YUV420_H */
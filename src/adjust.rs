@@ -0,0 +1,216 @@
+//! Color adjustments of YUV420 frames that have already been converted
+
+use config::{Adjustment, ConversionConfig, Range};
+#[cfg(feature = "alloc")]
+use frame::YuvFrame;
+use kernel::{self, ChromaPlanes};
+use planes::FrameMut;
+
+/// Applies `adjustment` to a frame in `range`, the same way converting with it would (give
+/// or take a code for the rounding)
+///
+/// Brightness and contrast map the luma codes, and saturation and hue scale and turn every
+/// `[u, v]` pair around gray. The padding of the rows is left alone.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Adjustment, FrameMut, Range};
+///
+/// let (mut y, mut u, mut v) = ([16, 116, 216, 235], [100], [160]);
+/// let adjustment = Adjustment { brightness: 10, saturation: 50, ..Adjustment::default() };
+/// rgb2yuv420::adjust_yuv(&mut FrameMut::i420(2, 2, &mut y, 2, &mut u, &mut v, 1).unwrap(), &adjustment,
+///                        Range::Limited);
+/// assert_eq!(y, [26, 126, 226, 245]);
+/// assert_eq!((u, v), ([114], [144]));
+/// ```
+pub fn adjust_yuv(frame: &mut FrameMut, adjustment: &Adjustment, range: Range) {
+    if adjustment.is_neutral() {
+        return;
+    }
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let coef = ConversionConfig { range, adjustment: *adjustment, ..ConversionConfig::default() }.coefficients();
+    let planes = frame.planes();
+    for row in planes.y.chunks_mut(planes.strides.y).take(height) {
+        coef.adjust_luma(&mut row[..width]);
+    }
+    let [[uu, uv], [vu, vv]] = adjustment.chroma_matrix();
+    let turn = |u: &mut u8, v: &mut u8| {
+        let (du, dv) = (i32::from(*u) - 128, i32::from(*v) - 128);
+        *u = (((uu * du + uv * dv + 128) >> 8) + 128).clamp(0, 255) as u8;
+        *v = (((vu * du + vv * dv + 128) >> 8) + 128).clamp(0, 255) as u8;
+    };
+    let stride = planes.strides.chroma;
+    match planes.chroma {
+        ChromaPlanes::Planar(u, v) => {
+            for (u, v) in u.chunks_mut(stride).zip(v.chunks_mut(stride)).take(chroma_height) {
+                for (u, v) in u[..chroma_width].iter_mut().zip(&mut v[..chroma_width]) {
+                    turn(u, v);
+                }
+            }
+        }
+        ChromaPlanes::SemiPlanar(uv) => {
+            for row in uv.chunks_mut(stride).take(chroma_height) {
+                for pair in row[..2 * chroma_width].chunks_exact_mut(2) {
+                    let (u, v) = pair.split_at_mut(1);
+                    turn(&mut u[0], &mut v[0]);
+                }
+            }
+        }
+    }
+}
+
+// The half swing of the chroma codes of each range in 1/256ths, as the converting
+// coefficients scale them
+const FULL_CHROMA_SWING: i32 = 127 * 255;
+const LIMITED_CHROMA_SWING: i32 = 112 * 256;
+
+/// `n / d` rounded half away from zero
+fn div_round(n: i32, d: i32) -> i32 {
+    if n < 0 { -((-n + d / 2) / d) } else { (n + d / 2) / d }
+}
+
+/// The luma and the chroma codes of `from` range in `to` range
+fn range_tables(from: Range, to: Range) -> ([u8; 256], [u8; 256]) {
+    let (mut luma, mut chroma) = ([0; 256], [0; 256]);
+    for code in 0..256 {
+        let (y, c) = match (from, to) {
+            (Range::Full, Range::Limited) => (16 + div_round(code * 219, 255),
+                                              128 + div_round((code - 128) * LIMITED_CHROMA_SWING, FULL_CHROMA_SWING)),
+            (Range::Limited, Range::Full) => (div_round((code - 16) * 255, 219),
+                                              128 + div_round((code - 128) * FULL_CHROMA_SWING, LIMITED_CHROMA_SWING)),
+            _ => (code, code),
+        };
+        luma[code as usize] = y.clamp(0, 255) as u8;
+        chroma[code as usize] = c.clamp(0, 255) as u8;
+    }
+    (luma, chroma)
+}
+
+/// Remaps the samples of a frame in `from` range into `to` range: limited range codes are
+/// expanded to full range, clipping the footroom and the headroom, or full range codes are
+/// compressed into the limited range
+///
+/// The colors stay the same, give or take a code; only their codes change. The padding of the
+/// rows is left alone.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{FrameMut, Range};
+///
+/// let (mut y, mut u, mut v) = ([16, 126, 235, 250], [16], [240]);
+/// rgb2yuv420::convert_range(&mut FrameMut::i420(2, 2, &mut y, 2, &mut u, &mut v, 1).unwrap(), Range::Limited,
+///                           Range::Full);
+/// assert_eq!(y, [0, 128, 255, 255]);
+/// assert_eq!((u, v), ([1], [255]));
+/// ```
+pub fn convert_range(frame: &mut FrameMut, from: Range, to: Range) {
+    if from == to {
+        return;
+    }
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let (luma, chroma) = range_tables(from, to);
+    let planes = frame.planes();
+    for row in planes.y.chunks_mut(planes.strides.y).take(height) {
+        for y in &mut row[..width] {
+            *y = luma[usize::from(*y)];
+        }
+    }
+    let stride = planes.strides.chroma;
+    let row_bytes = match planes.chroma {
+        ChromaPlanes::Planar(..) => chroma_width,
+        ChromaPlanes::SemiPlanar(_) => 2 * chroma_width,
+    };
+    let remap = |plane: &mut [u8]| {
+        for row in plane.chunks_mut(stride).take(chroma_height) {
+            for c in &mut row[..row_bytes] {
+                *c = chroma[usize::from(*c)];
+            }
+        }
+    };
+    match planes.chroma {
+        ChromaPlanes::Planar(u, v) => {
+            remap(u);
+            remap(v);
+        }
+        ChromaPlanes::SemiPlanar(uv) => remap(uv),
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl YuvFrame {
+    /// Applies `adjustment` to the frame in its range, see `adjust_yuv`
+    pub fn adjust(&mut self, adjustment: &Adjustment) {
+        let range = self.range();
+        adjust_yuv(&mut self.as_frame_mut(), adjustment, range);
+    }
+
+    /// Remaps the samples of the frame from its range into `range` and records it, see
+    /// `convert_range`
+    pub fn convert_range(&mut self, range: Range) {
+        let from = self.range();
+        convert_range(&mut self.as_frame_mut(), from, range);
+        self.set_range(range);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Adjustment, ConversionConfig, Range};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn adjusting_frames_matches_adjusting_while_converting() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 71 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap();
+        let adjustments = [Adjustment { brightness: -12, contrast: 130, ..Adjustment::default() },
+                           Adjustment { saturation: 60, hue: 30, ..Adjustment::default() },
+                           Adjustment { hue: -135, ..Adjustment::default() }];
+        for &range in &[Range::Full, Range::Limited] {
+            for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+                for adjustment in &adjustments {
+                    let cfg = ConversionConfig { range, ..ConversionConfig::default() };
+                    let mut frame = ::convert(&src, format, &cfg);
+                    frame.adjust(adjustment);
+                    let adjusted = ::convert(&src, format, &ConversionConfig { adjustment: *adjustment, ..cfg });
+                    for (&a, &b) in frame.data().iter().zip(adjusted.data()) {
+                        assert!((i32::from(a) - i32::from(b)).abs() <= 2, "{:?} {:?}: {} {}", range, adjustment, a, b);
+                    }
+                }
+            }
+        }
+        // half a turn mirrors the chroma through gray
+        let cfg = ConversionConfig::default();
+        let mut frame = ::convert(&src, YuvFormat::I420, &cfg);
+        let original = frame.clone();
+        frame.adjust(&Adjustment { hue: 180, ..Adjustment::default() });
+        assert_eq!(frame.y(), original.y());
+        for (&turned, &u) in frame.u().unwrap().iter().zip(original.u().unwrap()) {
+            assert_eq!(i32::from(turned), (256 - i32::from(u)).min(255));
+        }
+    }
+
+    #[test]
+    fn converted_ranges_match_converting_into_them() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 71 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let [full, limited] = [Range::Full, Range::Limited]
+                .map(|range| ::convert(&src, format, &ConversionConfig { range, ..ConversionConfig::default() }));
+            let mut compressed = full.clone();
+            compressed.convert_range(Range::Limited);
+            assert_eq!(compressed.range(), Range::Limited);
+            let mut expanded = limited.clone();
+            expanded.convert_range(Range::Full);
+            for (converted, expected) in [(&compressed, &limited), (&expanded, &full)] {
+                for (&a, &b) in converted.data().iter().zip(expected.data()) {
+                    assert!((i32::from(a) - i32::from(b)).abs() <= 2, "{:?}: {} {}", format, a, b);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Frames in memory a caller allocates, eg.: hugepages, pinned CUDA host memory or an arena
+//! owned by a media engine
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{FrameDescriptor, YuvFormat};
+use layout;
+use split::YuvPlanes;
+use view::RgbView;
+
+/// Allocates the memory of `AllocatedFrame`s
+///
+/// The memory is given back by dropping the buffer, so an allocator returning blocks to a
+/// pool or an arena does that in the `Drop` of its buffer type.
+pub trait FrameAllocator {
+    type Buffer: AsRef<[u8]> + AsMut<[u8]>;
+
+    /// Allocates at least `len` bytes, which don't need to be zeroed
+    ///
+    /// # Errors
+    ///
+    /// Whatever the allocator reports, eg.: `BufferTooSmall` if no block of `len` bytes is
+    /// left
+    fn allocate(&self, len: usize) -> Result<Self::Buffer, ConversionError>;
+}
+
+/// Allocates frames on the heap, like `YuvFrame`s are
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Global;
+
+#[cfg(feature = "alloc")]
+impl FrameAllocator for Global {
+    type Buffer = Vec<u8>;
+
+    fn allocate(&self, len: usize) -> Result<Vec<u8>, ConversionError> {
+        Ok(vec![0; len])
+    }
+}
+
+/// A tightly packed frame at the start of a buffer of a `FrameAllocator`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{AllocatedFrame, ChannelOrder, ConversionConfig, ConversionError, FrameAllocator, RgbView,
+///                  YuvFormat};
+///
+/// /// Rounds the frames up to 2 MiB pages, as a hugepage allocator would
+/// struct Hugepages;
+///
+/// impl FrameAllocator for Hugepages {
+///     type Buffer = Vec<u8>;
+///
+///     fn allocate(&self, len: usize) -> Result<Vec<u8>, ConversionError> {
+///         Ok(vec![0; len.div_ceil(2 << 20) * (2 << 20)])
+///     }
+/// }
+///
+/// let rgb = vec![0u8; 640 * 480 * 3];
+/// let src = RgbView::new(&rgb, 640, 480, ChannelOrder::Rgb).unwrap();
+/// let frame = AllocatedFrame::convert(&Hugepages, &src, YuvFormat::Nv12, &ConversionConfig::default()).unwrap();
+/// assert_eq!((frame.data().len(), frame.buffer().len()), (640 * 480 * 3 / 2, 2 << 20));
+/// assert_eq!(frame.as_planes().y().data()[0], 0);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AllocatedFrame<B> {
+    buffer: B,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    matrix: Matrix,
+    range: Range,
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> AllocatedFrame<B> {
+    /// Converts `src` with `cfg` into a buffer of `allocator`
+    ///
+    /// # Errors
+    ///
+    /// The error of allocating, or `BufferTooSmall` if the buffer is shorter than the frame
+    pub fn convert<A>(allocator: &A, src: &RgbView, format: YuvFormat, cfg: &ConversionConfig)
+                      -> Result<AllocatedFrame<B>, ConversionError>
+        where A: FrameAllocator<Buffer = B> + ?Sized {
+        let len = layout::required_buffer_size(format, src.width(), src.height());
+        let mut buffer = allocator.allocate(len)?;
+        ::convert_into(src, format, cfg, buffer.as_mut())?;
+        Ok(AllocatedFrame { buffer, format, width: src.width(), height: src.height(), matrix: cfg.matrix,
+                            range: cfg.range })
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// The layout of the frame at the start of the buffer
+    pub fn descriptor(&self) -> FrameDescriptor {
+        let (y_stride, chroma_stride) = layout::plane_strides(self.format, self.width);
+        FrameDescriptor { format: self.format, width: self.width, height: self.height, y_stride, chroma_stride,
+                          matrix: self.matrix, range: self.range }
+    }
+
+    /// The tightly packed frame, without the rest of the buffer
+    pub fn data(&self) -> &[u8] {
+        &self.buffer.as_ref()[..layout::required_buffer_size(self.format, self.width, self.height)]
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        let len = layout::required_buffer_size(self.format, self.width, self.height);
+        &mut self.buffer.as_mut()[..len]
+    }
+
+    /// The whole buffer the frame was allocated in
+    pub fn buffer(&self) -> &B {
+        &self.buffer
+    }
+
+    pub fn into_buffer(self) -> B {
+        self.buffer
+    }
+
+    pub fn as_planes(&self) -> YuvPlanes<'_> {
+        YuvPlanes::split(self.data(), self.format, self.width, self.height).expect("the frame fits its buffer")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use super::{AllocatedFrame, FrameAllocator};
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    /// Hands out the blocks of a fixed arena, front to back
+    struct Arena<'a>(RefCell<Option<&'a mut [u8]>>);
+
+    impl<'a> FrameAllocator for Arena<'a> {
+        type Buffer = &'a mut [u8];
+
+        fn allocate(&self, len: usize) -> Result<&'a mut [u8], ConversionError> {
+            let free = self.0.borrow_mut().take().unwrap_or_default();
+            if free.len() < len {
+                let got = free.len();
+                *self.0.borrow_mut() = Some(free);
+                return Err(ConversionError::BufferTooSmall { expected: len, got });
+            }
+            let (block, rest) = free.split_at_mut(len);
+            *self.0.borrow_mut() = Some(rest);
+            Ok(block)
+        }
+    }
+
+    #[test]
+    fn frames_live_in_the_memory_of_the_allocator() {
+        let rgb: Vec<u8> = (0..4 * 2 * 3).map(|i| (i * 41 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let mut memory = [0u8; 20];
+        let memory_start = memory.as_ptr();
+        let arena = Arena(RefCell::new(Some(&mut memory)));
+        let frame = AllocatedFrame::convert(&arena, &src, YuvFormat::I420, &cfg).unwrap();
+        assert_eq!(frame.data(), ::convert(&src, YuvFormat::I420, &cfg).data());
+        assert_eq!(frame.as_planes().y().data().as_ptr(), memory_start);
+        assert_eq!(AllocatedFrame::convert(&arena, &src, YuvFormat::Nv12, &cfg).err(),
+                   Some(ConversionError::BufferTooSmall { expected: 12, got: 8 }));
+    }
+}
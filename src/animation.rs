@@ -0,0 +1,176 @@
+//! Animated image conversion (`image` feature)
+
+use std::time::Duration;
+
+use image::{AnimationDecoder, Frame, Frames, ImageDecoder, ImageResult, RgbaImage};
+
+use converter::Converter;
+use format::ChannelOrder;
+use frame::{FrameTiming, YuvFrame};
+use view::RgbView;
+
+/// A converted frame of an animation and when it is shown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationFrame {
+    /// The frame, with its `FrameTiming` set to the timestamp, duration and number below
+    pub frame: YuvFrame,
+    /// Time from the start of the animation to the frame
+    pub timestamp: Duration,
+    /// How long the frame is shown
+    pub duration: Duration,
+}
+
+/// Converts the frames of an animated image into a sequence of timestamped YUV420 frames,
+/// eg.: for encoding a GIF or an APNG into a video
+///
+/// The decoders of `image` (`GifDecoder`, `ApngDecoder`, `WebPDecoder` through
+/// `AnimationDecoder::into_frames`) dispose and blend the frames themselves; frames that
+/// cover part of the canvas replace that part of the previous one. The canvas starts out
+/// transparent, so use `ConverterBuilder::composite` to pick what shows through.
+///
+/// # Examples
+///
+/// ```
+/// extern crate image;
+/// # extern crate rgb2yuv420;
+///
+/// use std::time::Duration;
+///
+/// use image::{Delay, Frame, Frames, RgbaImage};
+/// use rgb2yuv420::{AnimationFrames, Converter};
+///
+/// let delay = Delay::from_numer_denom_ms(40, 1);
+/// let frames = (0..3u8).map(move |i| {
+///     Ok(Frame::from_parts(RgbaImage::from_pixel(4, 2, [i * 100, 0, 0, 255].into()), 0, 0, delay))
+/// });
+/// let converted = AnimationFrames::new(Frames::new(Box::new(frames)), 4, 2, Converter::builder().build());
+/// let timestamps: Vec<Duration> = converted.map(|frame| frame.unwrap().timestamp).collect();
+/// assert_eq!(timestamps, [Duration::ZERO, Duration::from_millis(40), Duration::from_millis(80)]);
+/// ```
+pub struct AnimationFrames<'a> {
+    frames: Frames<'a>,
+    converter: Converter,
+    canvas: RgbaImage,
+    timestamp: Duration,
+    index: u64,
+}
+
+impl<'a> AnimationFrames<'a> {
+    /// Converts `frames` on a `width` x `height` canvas (the size of the animated image)
+    pub fn new(frames: Frames<'a>, width: u32, height: u32, converter: Converter) -> AnimationFrames<'a> {
+        AnimationFrames { frames, converter, canvas: RgbaImage::new(width, height), timestamp: Duration::ZERO,
+                          index: 0 }
+    }
+
+    /// Converts the frames of `decoder` on a canvas of the size of the image, eg.: the
+    /// `GifDecoder`, `ApngDecoder` or `WebPDecoder` of a file
+    pub fn from_decoder<D: ImageDecoder + AnimationDecoder<'a>>(decoder: D, converter: Converter)
+                                                                -> AnimationFrames<'a> {
+        let (width, height) = decoder.dimensions();
+        AnimationFrames::new(decoder.into_frames(), width, height, converter)
+    }
+
+    // copies the part of `frame` that falls on the canvas into it
+    fn draw(&mut self, frame: &Frame) {
+        let (x, y, width) = (frame.left() as usize, frame.top() as usize, self.canvas.width() as usize);
+        let buffer = frame.buffer();
+        if x >= width || buffer.width() == 0 {
+            return;
+        }
+        let row_bytes = 4 * (buffer.width() as usize).min(width - x);
+        let rows = self.canvas.chunks_mut(4 * width).skip(y).zip(buffer.chunks(4 * buffer.width() as usize));
+        for (row, pixels) in rows {
+            row[4 * x..][..row_bytes].copy_from_slice(&pixels[..row_bytes]);
+        }
+    }
+}
+
+impl<'a> Iterator for AnimationFrames<'a> {
+    type Item = ImageResult<AnimationFrame>;
+
+    fn next(&mut self) -> Option<ImageResult<AnimationFrame>> {
+        let frame = match self.frames.next()? {
+            Ok(frame) => frame,
+            Err(err) => return Some(Err(err)),
+        };
+        self.draw(&frame);
+        let view = RgbView::new(&self.canvas, self.canvas.width(), self.canvas.height(), ChannelOrder::Rgba)
+            .expect("the canvas holds all of its pixels");
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let duration = Duration::from_nanos(u64::from(numerator) * 1_000_000 / u64::from(denominator.max(1)));
+        let (timestamp, index) = (self.timestamp, self.index);
+        self.timestamp += duration;
+        self.index += 1;
+        let timing = FrameTiming { pts: Some(timestamp), duration: Some(duration), index: Some(index) };
+        Some(Ok(AnimationFrame { frame: self.converter.convert_view(&view).with_timing(timing), timestamp, duration }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{AnimationDecoder, ColorType, Delay, Frame, Frames, ImageDecoder, ImageResult, Rgba, RgbaImage};
+
+    use super::AnimationFrames;
+    use config::Colorimetry;
+    use converter::Converter;
+    use format::ChannelOrder;
+    use geometry::Backdrop;
+    use view::RgbView;
+
+    #[test]
+    fn partial_frames_are_drawn_over_the_canvas() {
+        let delay = Delay::from_numer_denom_ms(100, 3);
+        let full = RgbaImage::from_fn(6, 4, |x, y| Rgba([(x * 40) as u8, (y * 60) as u8, 90, 255]));
+        let patch = RgbaImage::from_pixel(3, 3, Rgba([255, 255, 255, 255]));
+        let frames = vec![Ok(Frame::from_parts(full.clone(), 0, 0, delay)), Ok(Frame::from_parts(patch, 4, 2, delay))];
+        let converter = Converter::builder().composite(Backdrop::Color([0; 3])).build();
+        let frames = Frames::new(Box::new(frames.into_iter()));
+        let converted: Vec<_> = AnimationFrames::new(frames, 6, 4, converter.clone()).map(Result::unwrap).collect();
+        let mut expected = full;
+        for (x, y) in [(4, 2), (5, 2), (4, 3), (5, 3)] {
+            expected.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+        }
+        let view = RgbView::new(&expected, 6, 4, ChannelOrder::Rgba).unwrap();
+        assert_eq!(converted[1].frame, converter.convert_view(&view));
+        assert_eq!(converted[1].timestamp.as_nanos(), 33_333_333);
+        assert_eq!(converted[1].duration, converted[0].duration);
+    }
+
+    // a decoder of one frame covering the top left pixel of a 3x2 animation
+    struct OnePixel;
+
+    impl ImageDecoder for OnePixel {
+        fn dimensions(&self) -> (u32, u32) {
+            (3, 2)
+        }
+
+        fn color_type(&self) -> ColorType {
+            ColorType::Rgba8
+        }
+
+        fn read_image(self, _buf: &mut [u8]) -> ImageResult<()> {
+            unimplemented!("only the frames are read")
+        }
+
+        fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+            (*self).read_image(buf)
+        }
+    }
+
+    impl<'a> AnimationDecoder<'a> for OnePixel {
+        fn into_frames(self) -> Frames<'a> {
+            let pixel = RgbaImage::from_pixel(1, 1, Rgba([255; 4]));
+            Frames::new(Box::new(Some(Ok(Frame::new(pixel))).into_iter()))
+        }
+    }
+
+    #[test]
+    fn decoders_give_the_canvas_size() {
+        let frames: Vec<_> = AnimationFrames::from_decoder(OnePixel, Converter::builder()
+            .colorimetry(Colorimetry::Bt601FullJfif).build()).collect();
+        assert_eq!(frames.len(), 1);
+        let frame = &frames[0].as_ref().unwrap().frame;
+        assert_eq!((frame.width(), frame.height()), (3, 2));
+        assert_eq!(frame.y()[..3], [255, 0, 0]);
+    }
+}
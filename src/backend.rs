@@ -0,0 +1,80 @@
+//! An object safe interface over the conversion backends, so that applications can pick one
+//! at runtime, eg.: from their configuration or after benchmarking them on the machine
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::YuvFormat;
+use frame::YuvFrame;
+use view::RgbView;
+
+/// Converts RGB images into new YUV frames on some backend
+///
+/// Implemented by `CpuBackend` and, with their features, by `gpu::GpuBackend`,
+/// `opencl::ClBackend` and `cuda::CudaConverter`. Backends reject the inputs and formats they
+/// can't convert with an error instead of falling back to another one.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, Convert, CpuBackend, RgbView, YuvFormat};
+///
+/// let backends: Vec<Box<dyn Convert>> = vec![Box::new(CpuBackend::default())];
+/// let rgba = [0u8; 4 * 4 * 4];
+/// let src = RgbView::new(&rgba, 4, 4, ChannelOrder::Rgba).unwrap();
+/// for backend in &backends {
+///     let frame = backend.convert_frame(&src, YuvFormat::Nv12).unwrap();
+///     println!("{}: {} bytes", backend.name(), frame.data().len());
+/// }
+/// ```
+pub trait Convert {
+    /// Short name of the backend, eg.: to label benchmark results
+    fn name(&self) -> &'static str;
+
+    /// Converts `src` into a new `format` frame
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` or `UnsupportedDimensions` if the backend can't convert `src` into
+    /// `format`, or `Device` if the device fails
+    fn convert_frame(&self, src: &RgbView, format: YuvFormat) -> Result<YuvFrame, ConversionError>;
+}
+
+/// The CPU kernels, converting any input and format as `convert` does with `config`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct CpuBackend {
+    pub config: ConversionConfig,
+}
+
+impl CpuBackend {
+    pub fn new(config: ConversionConfig) -> CpuBackend {
+        CpuBackend { config }
+    }
+}
+
+impl Convert for CpuBackend {
+    fn name(&self) -> &'static str {
+        "cpu"
+    }
+
+    fn convert_frame(&self, src: &RgbView, format: YuvFormat) -> Result<YuvFrame, ConversionError> {
+        Ok(::convert(src, format, &self.config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Convert, CpuBackend};
+    use config::{ConversionConfig, Matrix};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn boxed_backends_convert_like_convert() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, ..ConversionConfig::default() };
+        let backend: Box<dyn Convert> = Box::new(CpuBackend::new(cfg.clone()));
+        assert_eq!(backend.name(), "cpu");
+        assert_eq!(backend.convert_frame(&src, YuvFormat::I420), Ok(::convert(&src, YuvFormat::I420, &cfg)));
+    }
+}
@@ -0,0 +1,189 @@
+//! Converts a PNG, BMP or raw RGB image into a raw .yuv frame or a one frame .y4m stream
+//! (`cli` feature)
+//!
+//! ```text
+//! img2yuv pic/ferris.png ferris.yuv --format nv12 --matrix bt709 --range limited
+//! img2yuv frame.rgb frame.y4m --size 1920x1080 --bpp 32 --scale 1280x720
+//! ```
+//!
+//! Raw RGB inputs need `--size` and `--bpp` (24 for RGB, 32 for RGBA); other inputs are
+//! picked by their extension. `--align` pads the rows of raw outputs to a multiple of its
+//! bytes, as encoders reading rows of a fixed pitch expect them.
+
+extern crate clap;
+extern crate rgb2yuv420;
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+
+use clap::{Arg, ArgMatches, Command};
+use rgb2yuv420::y4m::Y4mWriter;
+use rgb2yuv420::{ChannelOrder, ConversionConfig, Converter, FrameMut, Matrix, Range, ScaleFilter, YuvFormat,
+                 YuvFrame};
+
+fn command() -> Command {
+    let arg = |name: &'static str, help: &'static str| Arg::new(name).long(name).help(help);
+    Command::new("img2yuv")
+        .about("Converts a PNG, BMP or raw RGB image into a raw .yuv frame or a .y4m stream")
+        .arg(Arg::new("input").required(true).help("The .png, .bmp or raw RGB image"))
+        .arg(Arg::new("output").required(true).help("The .y4m stream, or the raw frame for other extensions"))
+        .arg(arg("format", "The layout of raw frames (.y4m streams are always I420)").short('f')
+            .value_parser(["i420", "nv12"]).default_value("i420"))
+        .arg(arg("matrix", "The YUV matrix").short('m').value_parser(["bt601", "bt709", "bt2020"])
+            .default_value("bt601"))
+        .arg(arg("range", "The YUV range").short('r').value_parser(["full", "limited"]).default_value("full"))
+        .arg(arg("size", "The WIDTHxHEIGHT of a raw RGB input").value_name("WIDTHxHEIGHT"))
+        .arg(arg("bpp", "The bits per pixel of a raw RGB input: 24 for RGB, 32 for RGBA")
+            .value_parser(["24", "32"]).default_value("24"))
+        .arg(arg("scale", "Scales the frame to WIDTHxHEIGHT").value_name("WIDTHxHEIGHT"))
+        .arg(arg("filter", "The filter of --scale").value_parser(["nearest", "bilinear", "box"])
+            .default_value("box"))
+        .arg(arg("align", "Pads the rows of raw frames to a multiple of this many bytes").value_name("BYTES")
+            .value_parser(clap::value_parser!(usize)).default_value("1"))
+}
+
+// a WIDTHxHEIGHT argument
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid size {:?}, expected WIDTHxHEIGHT", size);
+    let mut parts = size.splitn(2, 'x');
+    let width = parts.next().and_then(|width| width.parse().ok()).ok_or_else(invalid)?;
+    let height = parts.next().and_then(|height| height.parse().ok()).ok_or_else(invalid)?;
+    Ok((width, height))
+}
+
+fn value<'a>(matches: &'a ArgMatches, name: &str) -> &'a str {
+    matches.get_one::<String>(name).map(String::as_str).unwrap_or_default()
+}
+
+fn config(matches: &ArgMatches) -> ConversionConfig {
+    let matrix = match value(matches, "matrix") {
+        "bt709" => Matrix::Bt709,
+        "bt2020" => Matrix::Bt2020,
+        _ => Matrix::Bt601,
+    };
+    let range = if value(matches, "range") == "limited" { Range::Limited } else { Range::Full };
+    ConversionConfig { matrix, range, ..ConversionConfig::default() }
+}
+
+fn extension(path: &str) -> String {
+    Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_ascii_lowercase()
+}
+
+fn read_frame(matches: &ArgMatches, format: YuvFormat, cfg: &ConversionConfig) -> Result<YuvFrame, String> {
+    let input = value(matches, "input");
+    let error = |err: &dyn std::fmt::Display| format!("{}: {}", input, err);
+    if let Some(size) = matches.get_one::<String>("size") {
+        let (width, height) = parse_size(size)?;
+        let order = if value(matches, "bpp") == "32" { ChannelOrder::Rgba } else { ChannelOrder::Rgb };
+        let rgb = fs::read(input).map_err(|err| error(&err))?;
+        return Converter::builder().input(order).output(format).matrix(cfg.matrix).range(cfg.range).build()
+            .convert(&rgb, width, height).map_err(|err| error(&err));
+    }
+    match extension(input).as_str() {
+        "png" => {
+            let file = File::open(input).map_err(|err| error(&err))?;
+            rgb2yuv420::from_png_reader(BufReader::new(file), format, cfg).map_err(|err| error(&err))
+        }
+        "bmp" => {
+            let file = File::open(input).map_err(|err| error(&err))?;
+            rgb2yuv420::from_bmp_reader(BufReader::new(file), format, cfg).map_err(|err| error(&err))
+        }
+        "jpg" | "jpeg" => Err(error(&"JPEG images aren't supported, convert them to PNG first")),
+        _ => Err(error(&"unknown image type, pass --size and --bpp for raw RGB")),
+    }
+}
+
+// the planes of `frame` with their rows padded to a multiple of `align` bytes
+fn padded(frame: &YuvFrame, align: usize) -> Result<Vec<u8>, String> {
+    let (width, height) = (frame.width(), frame.height());
+    let pad = |bytes: usize| bytes.div_ceil(align) * align;
+    let (chroma_width, chroma_height) = ((width as usize).div_ceil(2), (height as usize).div_ceil(2));
+    let y_stride = pad(width as usize);
+    let luma = y_stride * height as usize;
+    let error = |err: rgb2yuv420::ConversionError| err.to_string();
+    match frame.format() {
+        YuvFormat::I420 => {
+            let stride = pad(chroma_width);
+            let mut data = vec![0; luma + 2 * stride * chroma_height];
+            {
+                let (y, chroma) = data.split_at_mut(luma);
+                let (u, v) = chroma.split_at_mut(stride * chroma_height);
+                let mut dst = FrameMut::i420(width, height, y, y_stride, u, v, stride).map_err(error)?;
+                frame.as_planes().copy_into(&mut dst).map_err(error)?;
+            }
+            Ok(data)
+        }
+        YuvFormat::Nv12 => {
+            let stride = pad(2 * chroma_width);
+            let mut data = vec![0; luma + stride * chroma_height];
+            {
+                let (y, uv) = data.split_at_mut(luma);
+                let mut dst = FrameMut::nv12(width, height, y, y_stride, uv, stride).map_err(error)?;
+                frame.as_planes().copy_into(&mut dst).map_err(error)?;
+            }
+            Ok(data)
+        }
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<(), String> {
+    let output = value(matches, "output");
+    let y4m = extension(output) == "y4m";
+    let format = if y4m || value(matches, "format") == "i420" { YuvFormat::I420 } else { YuvFormat::Nv12 };
+    let align = *matches.get_one::<usize>("align").unwrap_or(&1);
+    if !align.is_power_of_two() {
+        return Err(format!("--align must be a power of two, not {}", align));
+    }
+    if y4m && align > 1 {
+        return Err("--align can't pad the rows of .y4m streams".into());
+    }
+    let cfg = config(matches);
+    let mut frame = read_frame(matches, format, &cfg)?;
+    if let Some(size) = matches.get_one::<String>("scale") {
+        let (width, height) = parse_size(size)?;
+        let filter = match value(matches, "filter") {
+            "nearest" => ScaleFilter::Nearest,
+            "bilinear" => ScaleFilter::Bilinear,
+            _ => ScaleFilter::Box,
+        };
+        frame = frame.scaled(width, height, filter);
+    }
+    let error = |err: std::io::Error| format!("{}: {}", output, err);
+    let mut out = BufWriter::new(File::create(output).map_err(error)?);
+    if y4m {
+        let mut writer = Y4mWriter::new(out, frame.width(), frame.height(), (25, 1), frame.range()).map_err(error)?;
+        writer.write_frame(&frame.as_planes()).map_err(error)?;
+        writer.flush().map_err(error)
+    } else {
+        out.write_all(&padded(&frame, align)?).map_err(error)?;
+        out.flush().map_err(error)
+    }
+}
+
+fn main() {
+    if let Err(err) = run(&command().get_matches()) {
+        eprintln!("img2yuv: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command, padded, parse_size};
+    use rgb2yuv420::{Converter, YuvFormat};
+
+    #[test]
+    fn arguments_and_padding() {
+        assert_eq!(parse_size("1280x720"), Ok((1280, 720)));
+        assert!(parse_size("1280").is_err());
+        let matches = command().try_get_matches_from(["img2yuv", "in.png", "out.yuv", "-f", "nv12"]).unwrap();
+        assert_eq!(super::value(&matches, "format"), "nv12");
+        assert!(command().try_get_matches_from(["img2yuv", "in.png", "out.yuv", "-f", "i444"]).is_err());
+        let frame = Converter::builder().output(YuvFormat::Nv12).build().convert(&[255u8; 3 * 2 * 3], 3, 2).unwrap();
+        assert_eq!(padded(&frame, 1).unwrap(), frame.data());
+        assert_eq!(padded(&frame, 8).unwrap(), [255, 255, 255, 0, 0, 0, 0, 0, 255, 255, 255, 0, 0, 0, 0, 0,
+                                                128, 128, 128, 128, 0, 0, 0, 0]);
+    }
+}
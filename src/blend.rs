@@ -0,0 +1,143 @@
+//! Alpha blending of YUV420 frames over each other, eg.: for picture-in-picture without
+//! converting both sources back to RGB
+
+use error::ConversionError;
+use kernel::{self, ChromaPlanes};
+use planes::{check_plane, FrameMut};
+use split::YuvPlanes;
+
+/// How opaque the frame blended over another one is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opacity<'a> {
+    /// The same alpha for every pixel, 255 being opaque
+    Constant(u8),
+    /// An alpha plane of one sample per pixel and its stride, eg.: the alpha plane of a frame
+    Plane(&'a [u8], usize),
+}
+
+// `src` over `dst` with `alpha` out of 255
+fn mix(src: u8, dst: u8, alpha: u32) -> u8 {
+    ((u32::from(src) * alpha + u32::from(dst) * (255 - alpha) + 127) / 255) as u8
+}
+
+/// Blends `src` over the pixels of `dst` from (`x`, `y`) on
+///
+/// Both frames have to be in the same matrix and range, as the samples are mixed as they
+/// are. The chroma samples are blended with the mean alpha of their 2x2 pixels. `src` and
+/// `dst` can be in different formats.
+///
+/// # Errors
+///
+/// `InvalidRegion` if `src` doesn't fit into `dst` at (`x`, `y`) or if either is odd, and
+/// `StrideMismatch` or `BufferTooSmall` if the alpha plane can't hold the rows of `src`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{FrameMut, Opacity, YuvFormat, YuvPlanes};
+///
+/// // a 2x2 frame half over the right of a 4x2 one
+/// let overlay = [255, 255, 255, 255, 0, 255];
+/// let src = YuvPlanes::split(&overlay, YuvFormat::I420, 2, 2).unwrap();
+/// let (mut y, mut uv) = ([0; 8], [128; 4]);
+/// let mut dst = FrameMut::nv12(4, 2, &mut y, 4, &mut uv, 4).unwrap();
+/// rgb2yuv420::blend_yuv(&src, Opacity::Constant(128), &mut dst, 2, 0).unwrap();
+/// assert_eq!(y, [0, 0, 128, 128, 0, 0, 128, 128]);
+/// assert_eq!(uv, [128, 128, 64, 192]);
+/// ```
+pub fn blend_yuv(src: &YuvPlanes, opacity: Opacity, dst: &mut FrameMut, x: u32, y: u32)
+                 -> Result<(), ConversionError> {
+    let (width, height) = (src.width(), src.height());
+    if u64::from(x) + u64::from(width) > u64::from(dst.width())
+        || u64::from(y) + u64::from(height) > u64::from(dst.height())
+        || !x.is_multiple_of(2) || !y.is_multiple_of(2) {
+        return Err(ConversionError::InvalidRegion { x, y, width, height });
+    }
+    let (x, y, width, height) = (x as usize, y as usize, width as usize, height as usize);
+    if let Opacity::Plane(alpha, stride) = opacity {
+        check_plane(alpha.len(), stride, width, height)?;
+    }
+    let alpha = |col: usize, row: usize| match opacity {
+        Opacity::Constant(alpha) => u32::from(alpha),
+        Opacity::Plane(alpha, stride) => u32::from(alpha[row * stride + col]),
+    };
+    let mut planes = dst.planes();
+    let luma = src.y();
+    for row in 0..height {
+        let out = &mut planes.y[(y + row) * planes.strides.y + x..][..width];
+        for (col, (out, &sample)) in out.iter_mut().zip(luma.row(row)).enumerate() {
+            *out = mix(sample, *out, alpha(col, row));
+        }
+    }
+    // the U and V of `src` at chroma sample (col, row), and the mean alpha of its pixels
+    let chroma = |col: usize, row: usize| {
+        let (u, v) = src.chroma_sample(col, row);
+        let (cols, rows) = (2 * col..(2 * col + 2).min(width), 2 * row..(2 * row + 2).min(height));
+        let pixels = cols.len() * rows.len();
+        let sum: u32 = rows.flat_map(|row| cols.clone().map(move |col| alpha(col, row))).sum();
+        (u, v, (2 * sum + pixels as u32) / (2 * pixels as u32))
+    };
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let (stride, (x, y)) = (planes.strides.chroma, (x / 2, y / 2));
+    for row in 0..chroma_height {
+        for col in 0..chroma_width {
+            let (u, v, alpha) = chroma(col, row);
+            match planes.chroma {
+                ChromaPlanes::Planar(ref mut dst_u, ref mut dst_v) => {
+                    let at = (y + row) * stride + x + col;
+                    dst_u[at] = mix(u, dst_u[at], alpha);
+                    dst_v[at] = mix(v, dst_v[at], alpha);
+                }
+                ChromaPlanes::SemiPlanar(ref mut uv) => {
+                    let at = (y + row) * stride + 2 * (x + col);
+                    uv[at] = mix(u, uv[at], alpha);
+                    uv[at + 1] = mix(v, uv[at + 1], alpha);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blend_yuv, Opacity};
+    use config::{Colorimetry, ConversionConfig};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn alpha_planes_blend_picture_in_picture() {
+        let cfg = ConversionConfig::from(Colorimetry::Bt601FullJfif);
+        let rgb: Vec<u8> = (0..8 * 6 * 3).map(|i| (i * 41 % 256) as u8).collect();
+        let background = ::convert(&RgbView::new(&rgb, 8, 6, ChannelOrder::Rgb).unwrap(), YuvFormat::Nv12, &cfg);
+        let white = [255; 4 * 3 * 3];
+        let inset = ::convert(&RgbView::new(&white, 4, 3, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        // opaque on the left half of the inset, transparent on the right
+        let alpha: Vec<u8> = (0..3 * 6).map(|i| if i % 6 < 2 { 255 } else { 0 }).collect();
+        let mut frame = background.clone();
+        blend_yuv(&inset.as_planes(), Opacity::Plane(&alpha, 6), &mut frame.as_frame_mut(), 4, 2).unwrap();
+        for (row, (blended, original)) in frame.y().chunks(8).zip(background.y().chunks(8)).enumerate() {
+            let inside = (2..5).contains(&row);
+            for (col, (&blended, &original)) in blended.iter().zip(original).enumerate() {
+                let expected = if inside && (4..6).contains(&col) { 255 } else { original };
+                assert_eq!(blended, expected, "({}, {})", col, row);
+            }
+        }
+        // the chroma of the opaque 2x2 pixels is the inset's, the next samples are untouched
+        let uv = |frame: &::YuvFrame, col: usize, row: usize| frame.uv().unwrap()[row * 8 + 2 * col..][..2].to_vec();
+        assert_eq!(uv(&frame, 2, 1), [inset.u().unwrap()[0], inset.v().unwrap()[0]]);
+        assert_eq!(uv(&frame, 3, 1), uv(&background, 3, 1));
+        // the last chroma row of the inset covers one row of pixels
+        assert_eq!(uv(&frame, 2, 2), [inset.u().unwrap()[2], inset.v().unwrap()[2]]);
+        let mut frame = background.clone();
+        blend_yuv(&inset.as_planes(), Opacity::Constant(0), &mut frame.as_frame_mut(), 0, 0).unwrap();
+        assert_eq!(frame, background);
+        let planes = inset.as_planes();
+        assert_eq!(blend_yuv(&planes, Opacity::Constant(255), &mut frame.as_frame_mut(), 6, 0),
+                   Err(ConversionError::InvalidRegion { x: 6, y: 0, width: 4, height: 3 }));
+        assert_eq!(blend_yuv(&planes, Opacity::Plane(&alpha, 3), &mut frame.as_frame_mut(), 0, 0),
+                   Err(ConversionError::StrideMismatch { stride: 3, row_bytes: 4 }));
+    }
+}
@@ -0,0 +1,154 @@
+//! BMP decoding into YUV420 frames (`bmp` feature)
+
+use std::io::{self, Read};
+
+use config::ConversionConfig;
+use format::{ChannelOrder, YuvFormat};
+use frame::YuvFrame;
+use view::RgbView;
+
+// size of the file header and of the smallest info header in front of the masks
+const HEADER_SIZE: usize = 54;
+// compression of uncompressed pixels, and of pixels described by channel masks
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// reads `len` bytes, sized by the header: the buffer grows as they arrive, so a header
+// claiming gigabytes doesn't allocate them before the file runs out
+fn read_bytes<R: Read>(input: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    input.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the BMP is truncated"));
+    }
+    Ok(bytes)
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+/// Reads the BMP in `input` and converts it with `cfg` into a `format` frame
+///
+/// Uncompressed 24 and 32 bit images are read, whose pixels are stored as BGR(X) with rows
+/// padded to 4 bytes and usually bottom-up; the rows are flipped and the padding skipped
+/// here, which is what gets the colours of BMP screenshots wrong when they are converted as
+/// plain RGB. The fourth byte of 32 bit pixels is ignored.
+///
+/// # Errors
+///
+/// The error of reading `input`, or an `InvalidData` error if it isn't a BMP, or is
+/// compressed, palettized, of another bit depth or larger than a buffer can hold
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// // a 1x2 image of a black pixel under a white one, bottom row first
+/// let mut bmp = b"BM\0\0\0\0\0\0\0\0\x36\0\0\0\x28\0\0\0\x01\0\0\0\x02\0\0\0\x01\0\x18\0".to_vec();
+/// bmp.extend([0; 24].iter().chain(&[0, 0, 0, 0, 255, 255, 255, 0]));
+/// let frame = rgb2yuv420::from_bmp_reader(&bmp[..], YuvFormat::I420, &ConversionConfig::default()).unwrap();
+/// assert_eq!(frame.y(), [255, 0]);
+/// ```
+pub fn from_bmp_reader<R: Read>(mut input: R, format: YuvFormat, cfg: &ConversionConfig) -> io::Result<YuvFrame> {
+    let mut header = [0; HEADER_SIZE];
+    input.read_exact(&mut header)?;
+    if &header[..2] != b"BM" {
+        return Err(invalid("not a BMP file"));
+    }
+    if (read_u32(&header, 14) as usize) < HEADER_SIZE - 14 {
+        return Err(invalid("the info header is too short"));
+    }
+    // the rest of the info header, the masks and the palette, up to the pixels
+    let rest = (read_u32(&header, 10) as usize).checked_sub(HEADER_SIZE);
+    let masks = read_bytes(&mut input, rest.ok_or_else(|| invalid("the pixels start within the header"))?)?;
+    let (width, height) = (read_u32(&header, 18) as i32, read_u32(&header, 22) as i32);
+    let bits = u16::from_le_bytes([header[28], header[29]]);
+    let order = match (bits, read_u32(&header, 30)) {
+        (24, BI_RGB) => ChannelOrder::Bgr,
+        (32, BI_RGB) => ChannelOrder::Bgra,
+        (32, BI_BITFIELDS) if masks.len() >= 12
+            && [read_u32(&masks, 0), read_u32(&masks, 4), read_u32(&masks, 8)] == [0xff_0000, 0xff00, 0xff] => {
+            ChannelOrder::Bgra
+        }
+        _ => return Err(invalid("only uncompressed 24 and 32 bit BMPs are supported")),
+    };
+    if width < 0 {
+        return Err(invalid("the width is negative"));
+    }
+    // a negative height means the rows are stored top-down
+    let (width, rows) = (width as u32, height.unsigned_abs());
+    let too_large = || invalid("the image is too large");
+    let stride = (width as usize).checked_mul(order.bytes_per_pixel()).and_then(|row| row.div_ceil(4).checked_mul(4))
+        .ok_or_else(too_large)?;
+    // and no buffer holds more than `isize::MAX` bytes
+    let size = stride.checked_mul(rows as usize).filter(|&size| size <= isize::MAX as usize).ok_or_else(too_large)?;
+    let mut data = read_bytes(&mut input, size)?;
+    if height > 0 {
+        for top in 0..rows as usize / 2 {
+            let (upper, lower) = data.split_at_mut((rows as usize - 1 - top) * stride);
+            upper[top * stride..][..stride].swap_with_slice(&mut lower[..stride]);
+        }
+    }
+    let src = RgbView::with_layout(&data, width, rows, stride, order, 8).expect("the rows are read in full");
+    Ok(::convert(&src, format, cfg))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::from_bmp_reader;
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    // a BMP of `rows` (stored in that order) with a 40 byte info header and `masks` after it
+    fn bmp(width: i32, height: i32, bits: u16, compression: u32, masks: &[u32], rows: &[Vec<u8>]) -> Vec<u8> {
+        let offset = 54 + 4 * masks.len() as u32;
+        let mut file = b"BM".to_vec();
+        for field in &[0, 0, offset, 40, width as u32, height as u32] {
+            file.extend(field.to_le_bytes());
+        }
+        file.extend(1u16.to_le_bytes().iter().chain(&bits.to_le_bytes()));
+        file.extend(compression.to_le_bytes());
+        file.extend([0; 20]);
+        file.extend(masks.iter().flat_map(|mask| mask.to_le_bytes()));
+        file.extend(rows.iter().flatten());
+        file
+    }
+
+    #[test]
+    fn bottom_up_padded_bgr_rows_convert_like_rgb() {
+        let cfg = ConversionConfig::default();
+        let rgb: Vec<u8> = (0..3 * 3 * 3).map(|i| (i * 29 % 256) as u8).collect();
+        let expected = ::convert(&RgbView::new(&rgb, 3, 3, ChannelOrder::Rgb).unwrap(), YuvFormat::Nv12, &cfg);
+        let bgr = |row: &[u8], bytes: usize| -> Vec<u8> {
+            let mut out: Vec<u8> = row.chunks(3).flat_map(|px| [px[2], px[1], px[0], 0][..bytes].to_vec()).collect();
+            out.resize(out.len().div_ceil(4) * 4, 0);
+            out
+        };
+        let (top_down, mut bottom_up): (Vec<_>, Vec<_>) = (rgb.chunks(9).map(|row| bgr(row, 4)).collect(),
+                                                           rgb.chunks(9).map(|row| bgr(row, 3)).collect());
+        bottom_up.reverse();
+        // 3 24 bit pixels are padded to 12 bytes
+        assert_eq!(bottom_up[0].len(), 12);
+        for file in &[bmp(3, 3, 24, 0, &[], &bottom_up), bmp(3, -3, 32, 3, &[0xff_0000, 0xff00, 0xff], &top_down)] {
+            assert_eq!(from_bmp_reader(&file[..], YuvFormat::Nv12, &cfg).unwrap(), expected);
+        }
+        let paletted = bmp(3, 3, 8, 0, &[0; 256], &vec![vec![0; 12]; 3]);
+        assert_eq!(from_bmp_reader(&paletted[..], YuvFormat::Nv12, &cfg).unwrap_err().kind(), ErrorKind::InvalidData);
+        let truncated = &bmp(3, 3, 24, 0, &[], &bottom_up)[..80];
+        assert_eq!(from_bmp_reader(truncated, YuvFormat::Nv12, &cfg).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        // sizes no file holds are refused, and huge ones read only as far as the file goes
+        let huge = bmp(i32::MAX, i32::MIN, 32, 0, &[], &[]);
+        assert_eq!(from_bmp_reader(&huge[..], YuvFormat::Nv12, &cfg).unwrap_err().kind(), ErrorKind::InvalidData);
+        let large = bmp(1 << 16, 1 << 15, 32, 0, &[], &[vec![0; 64]]);
+        assert_eq!(from_bmp_reader(&large[..], YuvFormat::Nv12, &cfg).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+    }
+}
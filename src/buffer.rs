@@ -0,0 +1,98 @@
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "arrayvec")]
+use core::iter;
+
+#[cfg(feature = "arrayvec")]
+use arrayvec::ArrayVec;
+#[cfg(feature = "smallvec")]
+use smallvec::{Array, SmallVec};
+
+use error::ConversionError;
+
+/// A container a frame can be converted into
+///
+/// Growable containers are resized to hold exactly one frame, fixed ones must already be
+/// large enough and receive the frame at their start.
+pub trait OutputBuffer {
+    /// Returns at least `len` bytes of storage
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if the container can't hold `len` bytes
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError>;
+}
+
+fn fixed(buf: &mut [u8], len: usize) -> Result<&mut [u8], ConversionError> {
+    if buf.len() < len {
+        return Err(ConversionError::BufferTooSmall { expected: len, got: buf.len() });
+    }
+    Ok(buf)
+}
+
+impl OutputBuffer for [u8] {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        fixed(self, len)
+    }
+}
+
+impl<const N: usize> OutputBuffer for [u8; N] {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        fixed(self, len)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl OutputBuffer for Box<[u8]> {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        fixed(self, len)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl OutputBuffer for Vec<u8> {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        self.resize(len, 0);
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A: Array<Item = u8>> OutputBuffer for SmallVec<A> {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        self.resize(len, 0);
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "arrayvec")]
+impl<const CAP: usize> OutputBuffer for ArrayVec<u8, CAP> {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        if len > CAP {
+            return Err(ConversionError::BufferTooSmall { expected: len, got: CAP });
+        }
+        self.truncate(len);
+        let missing = len - self.len();
+        self.extend(iter::repeat_n(0, missing));
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputBuffer;
+    use error::ConversionError;
+
+    #[test]
+    fn growable_and_fixed_buffers() {
+        let mut vec = vec![1u8; 10];
+        assert_eq!(vec.prepare(6).unwrap().len(), 6);
+        assert_eq!(vec.len(), 6);
+        let mut array = [0u8; 4];
+        assert_eq!(array.prepare(6).unwrap_err(), ConversionError::BufferTooSmall { expected: 6, got: 4 });
+        assert_eq!(array.prepare(3).unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,114 @@
+//! Frame numbers and timestamps burnt into frames for debugging (`burn-in` feature)
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::time::Duration;
+
+use config::Range;
+use frame::YuvFrame;
+
+/// 3x5 glyphs, a row of 3 bits per byte with the leftmost pixel in bit 2
+fn glyph(c: u8) -> [u8; 5] {
+    match c {
+        b'0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        b'1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        b'2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        b'3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        b'4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        b'5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        b'6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        b'7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        b'8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        b'9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        b':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        b'.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0; 5],
+    }
+}
+
+impl YuvFrame {
+    /// Stamps `frame_number` and the `timestamp` (as `h:mm:ss.mmm`) into the top left corner,
+    /// white on a black box with neutral chroma, so that dropped or repeated frames and A/V
+    /// offsets can be read off raw YUV dumps
+    ///
+    /// The glyphs are 5 pixels tall for every 144 rows of the frame (35 for 1080p).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rgb2yuv420::{ConversionConfig, YuvFormat, YuvFrame};
+    ///
+    /// let mut frame = YuvFrame::filled([255, 0, 0], YuvFormat::I420, 64, 16, &ConversionConfig::default());
+    /// frame.burn_in(7, Some(Duration::from_millis(280)));
+    /// // the top row of the "7", after the border
+    /// assert_eq!(&frame.y()[64..64 + 5], &[0, 255, 255, 255, 0]);
+    /// ```
+    pub fn burn_in(&mut self, frame_number: u64, timestamp: Option<Duration>) {
+        let mut text = String::new();
+        let _ = write!(text, "{}", frame_number);
+        if let Some(timestamp) = timestamp {
+            let ms = timestamp.as_millis();
+            let _ = write!(text, " {}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000);
+        }
+        let text = text.as_bytes();
+        let (black, white) = match self.range() {
+            Range::Full => (0, 255),
+            Range::Limited => (16, 235),
+        };
+        // a border of one glyph pixel around the text, and one between the glyphs
+        let scale = (self.height() as usize / 144).max(1);
+        let width = ((4 * text.len() + 1) * scale).min(self.width() as usize);
+        let height = (7 * scale).min(self.height() as usize);
+        let lit = |x: usize, y: usize| {
+            let (x, y) = (x / scale, y / scale);
+            if x == 0 || !(1..=5).contains(&y) || (x - 1) % 4 == 3 || (x - 1) / 4 >= text.len() {
+                return false;
+            }
+            glyph(text[(x - 1) / 4])[y - 1] >> (2 - (x - 1) % 4) & 1 == 1
+        };
+        let (y_stride, chroma_stride) = (self.y_stride(), self.chroma_stride());
+        let (y_plane, chroma, v) = self.planes_mut();
+        for (row, line) in y_plane.chunks_mut(y_stride).take(height).enumerate() {
+            for (x, y) in line[..width].iter_mut().enumerate() {
+                *y = if lit(x, row) { white } else { black };
+            }
+        }
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let samples = if v.is_some() { chroma_width } else { 2 * chroma_width };
+        for line in chroma.chunks_mut(chroma_stride).take(chroma_height) {
+            line[..samples].fill(128);
+        }
+        if let Some(v) = v {
+            for line in v.chunks_mut(chroma_stride).take(chroma_height) {
+                line[..samples].fill(128);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use config::{ConversionConfig, Range};
+    use format::YuvFormat;
+    use frame::YuvFrame;
+
+    #[test]
+    fn burn_in_is_scaled_and_clipped() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        let mut frame = YuvFrame::filled([0, 0, 255], YuvFormat::Nv12, 100, 300, &cfg);
+        let blue = frame.clone();
+        frame.burn_in(1, Some(Duration::from_secs(3723)));
+        // "1 1:02:03.000" at twice the size, clipped at the right edge
+        let (y, stride) = (frame.y(), frame.y_stride());
+        assert_eq!(&y[2 * stride..][..12], &[16, 16, 16, 16, 235, 235, 16, 16, 16, 16, 16, 16]);
+        assert!(y[13 * stride..][..100].iter().all(|&y| y == 16));
+        assert_eq!(&y[14 * stride..], &blue.y()[14 * stride..]);
+        let uv = frame.uv().unwrap();
+        assert!(uv[..6 * 100].iter().all(|&c| c == 128));
+        assert_eq!(&uv[7 * 100..], &blue.uv().unwrap()[7 * 100..]);
+    }
+}
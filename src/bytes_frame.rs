@@ -0,0 +1,118 @@
+//! `bytes` integration (`bytes` feature)
+
+use bytes::{Bytes, BytesMut};
+
+use buffer::OutputBuffer;
+use config::ConversionConfig;
+use error::ConversionError;
+use format::YuvFormat;
+use layout;
+use view::RgbView;
+
+impl OutputBuffer for BytesMut {
+    fn prepare(&mut self, len: usize) -> Result<&mut [u8], ConversionError> {
+        self.resize(len, 0);
+        Ok(self)
+    }
+}
+
+/// A converted frame in reference counted `Bytes`
+///
+/// The planes are cheap slices of one shared buffer, so each can be handed to a different
+/// stream or task without copying.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{BytesFrame, ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// let rgb = vec![0u8; 4 * 2 * 3];
+/// let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+/// let frame = BytesFrame::convert(&src, YuvFormat::Nv12, &ConversionConfig::default());
+/// assert_eq!(frame.y().len(), 8);
+/// assert_eq!(frame.uv().unwrap(), &[128, 128, 128, 128][..]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BytesFrame {
+    data: Bytes,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+}
+
+impl BytesFrame {
+    /// Converts `src` into a new `BytesMut` and freezes it
+    pub fn convert(src: &RgbView, format: YuvFormat, cfg: &ConversionConfig) -> BytesFrame {
+        let mut data = BytesMut::new();
+        ::convert_into(src, format, cfg, &mut data).expect("BytesMut grows to fit the frame");
+        BytesFrame { data: data.freeze(), format, width: src.width(), height: src.height() }
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The whole tightly packed frame
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.data
+    }
+
+    /// The luma plane
+    pub fn y(&self) -> Bytes {
+        self.data.slice(..self.offsets().1)
+    }
+
+    /// The U plane of an I420 frame
+    pub fn u(&self) -> Option<Bytes> {
+        let (_, u, v) = self.offsets();
+        v.map(|v| self.data.slice(u..v))
+    }
+
+    /// The V plane of an I420 frame
+    pub fn v(&self) -> Option<Bytes> {
+        self.offsets().2.map(|v| self.data.slice(v..))
+    }
+
+    /// The interleaved chroma plane of an NV12 frame
+    pub fn uv(&self) -> Option<Bytes> {
+        match self.format {
+            YuvFormat::I420 => None,
+            YuvFormat::Nv12 => Some(self.data.slice(self.offsets().1..)),
+        }
+    }
+
+    fn offsets(&self) -> (usize, usize, Option<usize>) {
+        layout::plane_offsets(self.format, self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BytesFrame;
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn planes_share_the_buffer() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let frame = BytesFrame::convert(&src, YuvFormat::I420, &ConversionConfig::default());
+        let (y, u, v) = (frame.y(), frame.u().unwrap(), frame.v().unwrap());
+        assert_eq!((y.len(), u.len(), v.len()), (15, 6, 6));
+        assert_eq!(u.as_ptr(), frame.data()[15..].as_ptr());
+        assert_eq!([&y[..], &u[..], &v[..]].concat(), ::convert_rgb_to_yuv420p(&rgb, 5, 3, 3));
+    }
+}
@@ -0,0 +1,199 @@
+//! C bindings (`capi` feature), declared in `include/rgb2yuv420.h`
+//!
+//! Build them into a shared library with
+//! `cargo rustc --release --features capi --crate-type cdylib`, or a static one with
+//! `--crate-type staticlib`. The header can be regenerated with `cbindgen`. Every function
+//! returns an `RGB2YUV420_*` status; formats, channel orders, matrices and ranges are passed
+//! as the `RGB2YUV420_*` constants below.
+
+use core::ffi::c_int;
+use core::slice;
+
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use layout;
+use view::RgbView;
+
+pub const RGB2YUV420_OK: c_int = 0;
+pub const RGB2YUV420_BUFFER_TOO_SMALL: c_int = 1;
+pub const RGB2YUV420_STRIDE_MISMATCH: c_int = 2;
+pub const RGB2YUV420_UNSUPPORTED_DIMENSIONS: c_int = 3;
+/// An unknown format, channel order, matrix or range constant, too
+pub const RGB2YUV420_UNSUPPORTED_FORMAT: c_int = 4;
+pub const RGB2YUV420_INVALID_REGION: c_int = 5;
+pub const RGB2YUV420_DEVICE: c_int = 6;
+pub const RGB2YUV420_NULL_POINTER: c_int = 7;
+
+pub const RGB2YUV420_I420: c_int = 0;
+pub const RGB2YUV420_NV12: c_int = 1;
+
+pub const RGB2YUV420_RGB: c_int = 0;
+pub const RGB2YUV420_BGR: c_int = 1;
+pub const RGB2YUV420_RGBA: c_int = 2;
+pub const RGB2YUV420_BGRA: c_int = 3;
+pub const RGB2YUV420_ARGB: c_int = 4;
+pub const RGB2YUV420_ABGR: c_int = 5;
+
+pub const RGB2YUV420_BT601: c_int = 0;
+pub const RGB2YUV420_BT709: c_int = 1;
+pub const RGB2YUV420_BT2020: c_int = 2;
+
+pub const RGB2YUV420_FULL: c_int = 0;
+pub const RGB2YUV420_LIMITED: c_int = 1;
+
+/// How `rgb2yuv420_convert` converts
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb2Yuv420Options {
+    /// `RGB2YUV420_I420` or `RGB2YUV420_NV12`
+    pub format: c_int,
+    /// `RGB2YUV420_BT601`, `RGB2YUV420_BT709` or `RGB2YUV420_BT2020`
+    pub matrix: c_int,
+    /// `RGB2YUV420_FULL` or `RGB2YUV420_LIMITED`
+    pub range: c_int,
+}
+
+const UNKNOWN: ConversionError = ConversionError::UnsupportedFormat("unknown constant");
+
+fn status(result: Result<(), ConversionError>) -> c_int {
+    match result {
+        Ok(()) => RGB2YUV420_OK,
+        Err(ConversionError::BufferTooSmall { .. }) => RGB2YUV420_BUFFER_TOO_SMALL,
+        Err(ConversionError::StrideMismatch { .. }) => RGB2YUV420_STRIDE_MISMATCH,
+        Err(ConversionError::UnsupportedDimensions { .. }) => RGB2YUV420_UNSUPPORTED_DIMENSIONS,
+        Err(ConversionError::UnsupportedFormat(_)) => RGB2YUV420_UNSUPPORTED_FORMAT,
+        Err(ConversionError::InvalidRegion { .. }) => RGB2YUV420_INVALID_REGION,
+        Err(ConversionError::Device(_)) => RGB2YUV420_DEVICE,
+    }
+}
+
+fn yuv_format(format: c_int) -> Result<YuvFormat, ConversionError> {
+    match format {
+        RGB2YUV420_I420 => Ok(YuvFormat::I420),
+        RGB2YUV420_NV12 => Ok(YuvFormat::Nv12),
+        _ => Err(UNKNOWN),
+    }
+}
+
+fn channel_order(order: c_int) -> Result<ChannelOrder, ConversionError> {
+    match order {
+        RGB2YUV420_RGB => Ok(ChannelOrder::Rgb),
+        RGB2YUV420_BGR => Ok(ChannelOrder::Bgr),
+        RGB2YUV420_RGBA => Ok(ChannelOrder::Rgba),
+        RGB2YUV420_BGRA => Ok(ChannelOrder::Bgra),
+        RGB2YUV420_ARGB => Ok(ChannelOrder::Argb),
+        RGB2YUV420_ABGR => Ok(ChannelOrder::Abgr),
+        _ => Err(UNKNOWN),
+    }
+}
+
+fn config(options: &Rgb2Yuv420Options) -> Result<ConversionConfig, ConversionError> {
+    let matrix = match options.matrix {
+        RGB2YUV420_BT601 => Matrix::Bt601,
+        RGB2YUV420_BT709 => Matrix::Bt709,
+        RGB2YUV420_BT2020 => Matrix::Bt2020,
+        _ => return Err(UNKNOWN),
+    };
+    let range = match options.range {
+        RGB2YUV420_FULL => Range::Full,
+        RGB2YUV420_LIMITED => Range::Limited,
+        _ => return Err(UNKNOWN),
+    };
+    Ok(ConversionConfig { matrix, range, ..ConversionConfig::default() })
+}
+
+/// Writes the bytes of a tightly packed `format` frame to `size`
+///
+/// # Safety
+///
+/// `size` must be null or point to a writable `size_t`
+#[no_mangle]
+pub unsafe extern "C" fn rgb2yuv420_frame_size(format: c_int, width: u32, height: u32, size: *mut usize) -> c_int {
+    if size.is_null() {
+        return RGB2YUV420_NULL_POINTER;
+    }
+    status(yuv_format(format).map(|format| *size = layout::required_buffer_size(format, width, height)))
+}
+
+/// Writes the offsets and strides of the Y, U (or UV) and V planes of a tightly packed
+/// `format` frame to `offsets` and `strides`; the third ones are 0 for NV12
+///
+/// # Safety
+///
+/// `offsets` and `strides` must be null or point to 3 writable `size_t`s each
+#[no_mangle]
+pub unsafe extern "C" fn rgb2yuv420_plane_layout(format: c_int, width: u32, height: u32, offsets: *mut usize,
+                                                 strides: *mut usize) -> c_int {
+    if offsets.is_null() || strides.is_null() {
+        return RGB2YUV420_NULL_POINTER;
+    }
+    status(yuv_format(format).map(|format| {
+        let (y, chroma, v) = layout::plane_offsets(format, width, height);
+        let (y_stride, chroma_stride) = layout::plane_strides(format, width);
+        let v_stride = if v.is_some() { chroma_stride } else { 0 };
+        slice::from_raw_parts_mut(offsets, 3).copy_from_slice(&[y, chroma, v.unwrap_or(0)]);
+        slice::from_raw_parts_mut(strides, 3).copy_from_slice(&[y_stride, chroma_stride, v_stride]);
+    }))
+}
+
+/// Converts `width` x `height` 8 bit pixels in `channel_order`, whose rows start `stride`
+/// bytes apart in the `src_len` bytes at `src`, into a tightly packed frame at the start of
+/// the `dst_len` bytes at `dst`
+///
+/// # Safety
+///
+/// `src` and `dst` must be null or point to `src_len` readable and `dst_len` writable bytes
+/// that don't overlap, and `options` must be null or point to options
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rgb2yuv420_convert(src: *const u8, src_len: usize, width: u32, height: u32, stride: usize,
+                                            channel_order: c_int, options: *const Rgb2Yuv420Options, dst: *mut u8,
+                                            dst_len: usize) -> c_int {
+    if src.is_null() || options.is_null() || dst.is_null() {
+        return RGB2YUV420_NULL_POINTER;
+    }
+    let (src, dst) = (slice::from_raw_parts(src, src_len), slice::from_raw_parts_mut(dst, dst_len));
+    status(self::channel_order(channel_order).and_then(|order| {
+        let src = RgbView::with_layout(src, width, height, stride, order, 8)?;
+        ::convert_into(&src, yuv_format((*options).format)?, &config(&*options)?, dst).map(|_| ())
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr;
+
+    use super::*;
+    use view::RgbView;
+
+    #[test]
+    fn c_callers_convert_into_their_buffers() {
+        let (mut size, mut offsets, mut strides) = (0, [9; 3], [9; 3]);
+        unsafe {
+            assert_eq!(rgb2yuv420_frame_size(RGB2YUV420_NV12, 3, 3, &mut size), RGB2YUV420_OK);
+            assert_eq!(rgb2yuv420_plane_layout(RGB2YUV420_NV12, 3, 3, offsets.as_mut_ptr(), strides.as_mut_ptr()),
+                       RGB2YUV420_OK);
+            assert_eq!(rgb2yuv420_frame_size(7, 3, 3, &mut size), RGB2YUV420_UNSUPPORTED_FORMAT);
+            assert_eq!(rgb2yuv420_frame_size(RGB2YUV420_I420, 3, 3, ptr::null_mut()), RGB2YUV420_NULL_POINTER);
+        }
+        assert_eq!((size, offsets, strides), (9 + 8, [0, 9, 0], [3, 4, 0]));
+        // BGRA rows of 16 bytes
+        let bgra: Vec<u8> = (0..3 * 16).map(|i| (i * 61 % 256) as u8).collect();
+        let options = Rgb2Yuv420Options { format: RGB2YUV420_NV12, matrix: RGB2YUV420_BT709,
+                                          range: RGB2YUV420_LIMITED };
+        let cfg = config(&options).unwrap();
+        let src = RgbView::with_layout(&bgra, 3, 3, 16, ChannelOrder::Bgra, 8).unwrap();
+        let expected = ::convert(&src, YuvFormat::Nv12, &cfg);
+        let mut dst = [0; 17];
+        let convert = |dst: &mut [u8], stride, options: &Rgb2Yuv420Options| unsafe {
+            rgb2yuv420_convert(bgra.as_ptr(), bgra.len(), 3, 3, stride, RGB2YUV420_BGRA, options, dst.as_mut_ptr(),
+                               dst.len())
+        };
+        assert_eq!(convert(&mut dst, 16, &options), RGB2YUV420_OK);
+        assert_eq!(dst[..], expected.data()[..]);
+        assert_eq!(convert(&mut dst[..16], 16, &options), RGB2YUV420_BUFFER_TOO_SMALL);
+        assert_eq!(convert(&mut dst, 8, &options), RGB2YUV420_STRIDE_MISMATCH);
+        assert_eq!(convert(&mut dst, 16, &Rgb2Yuv420Options { range: 2, ..options }), RGB2YUV420_UNSUPPORTED_FORMAT);
+    }
+}
@@ -0,0 +1,158 @@
+//! Comparing YUV420 frames, eg.: the output of a pipeline against golden frames
+
+use error::ConversionError;
+#[cfg(feature = "alloc")]
+use frame::YuvFrame;
+use kernel;
+use split::YuvPlanes;
+
+/// How the samples of two planes differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PlaneDiff {
+    pub samples: u64,
+    /// The samples that differ at all
+    pub differing: u64,
+    /// The largest absolute difference of two samples
+    pub max: u8,
+    pub sum_of_squares: u64,
+}
+
+impl PlaneDiff {
+    fn add(&mut self, a: u8, b: u8) {
+        let diff = a.abs_diff(b);
+        self.samples += 1;
+        self.differing += u64::from(diff != 0);
+        self.max = self.max.max(diff);
+        self.sum_of_squares += u64::from(diff) * u64::from(diff);
+    }
+
+    fn merge(self, other: PlaneDiff) -> PlaneDiff {
+        PlaneDiff {
+            samples: self.samples + other.samples,
+            differing: self.differing + other.differing,
+            max: self.max.max(other.max),
+            sum_of_squares: self.sum_of_squares + other.sum_of_squares,
+        }
+    }
+
+    /// The mean squared difference, 0 for empty planes
+    pub fn mse(&self) -> f64 {
+        if self.samples == 0 { 0.0 } else { self.sum_of_squares as f64 / self.samples as f64 }
+    }
+
+    /// The peak signal to noise ratio in dB, infinite for identical planes
+    #[cfg(feature = "std")]
+    pub fn psnr(&self) -> f64 {
+        10.0 * (255.0 * 255.0 / self.mse()).log10()
+    }
+}
+
+/// How two frames differ, plane by plane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FrameDiff {
+    pub y: PlaneDiff,
+    pub u: PlaneDiff,
+    pub v: PlaneDiff,
+}
+
+impl FrameDiff {
+    /// The three planes taken together, so that the PSNR of the frame weighs every sample the
+    /// same
+    pub fn total(&self) -> PlaneDiff {
+        self.y.merge(self.u).merge(self.v)
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.total().differing == 0
+    }
+}
+
+/// Compares two frames of the same size sample by sample; they can be in different formats
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` with the size of `b` if it differs from the size of `a`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{YuvFormat, YuvPlanes};
+///
+/// let golden = [16, 16, 16, 16, 128, 128];
+/// let output = [16, 18, 16, 16, 128, 127];
+/// let (golden, output) = (YuvPlanes::split(&golden, YuvFormat::I420, 2, 2).unwrap(),
+///                         YuvPlanes::split(&output, YuvFormat::I420, 2, 2).unwrap());
+/// let diff = rgb2yuv420::compare_yuv(&golden, &output).unwrap();
+/// assert_eq!((diff.y.max, diff.y.differing, diff.y.mse()), (2, 1, 1.0));
+/// assert_eq!((diff.u.max, diff.v.max), (0, 1));
+/// assert_eq!(diff.total().sum_of_squares, 5);
+/// ```
+pub fn compare_yuv(a: &YuvPlanes, b: &YuvPlanes) -> Result<FrameDiff, ConversionError> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Err(ConversionError::UnsupportedDimensions { width: b.width(), height: b.height() });
+    }
+    let mut diff = FrameDiff::default();
+    for (a, b) in a.y().rows().zip(b.y().rows()) {
+        for (&a, &b) in a.iter().zip(b) {
+            diff.y.add(a, b);
+        }
+    }
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(a.width() as usize, a.height() as usize);
+    for row in 0..chroma_height {
+        for col in 0..chroma_width {
+            let ((a_u, a_v), (b_u, b_v)) = (a.chroma_sample(col, row), b.chroma_sample(col, row));
+            diff.u.add(a_u, b_u);
+            diff.v.add(a_v, b_v);
+        }
+    }
+    Ok(diff)
+}
+
+#[cfg(feature = "alloc")]
+impl YuvFrame {
+    /// Compares the frame with `other`, see `compare_yuv`
+    ///
+    /// Only the samples are compared, not the matrix, range or strides of the frames.
+    pub fn compare(&self, other: &YuvFrame) -> Result<FrameDiff, ConversionError> {
+        compare_yuv(&self.as_planes(), &other.as_planes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use config::Range;
+    use config::{Colorimetry, ConversionConfig};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn diffs_measure_every_plane() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::from(Colorimetry::Bt601FullJfif);
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        let same = i420.compare(&nv12).unwrap();
+        assert!(same.is_identical());
+        assert_eq!((same.y.samples, same.u.samples, same.total().samples), (24, 6, 36));
+        #[cfg(feature = "std")]
+        assert_eq!(same.total().psnr(), f64::INFINITY);
+        // every luma sample off by 4 and one V sample by 8
+        let mut off = nv12.clone();
+        off.y_mut().iter_mut().for_each(|y| *y ^= 4);
+        off.data_mut()[24 + 3] ^= 8;
+        let diff = i420.compare(&off).unwrap();
+        assert_eq!((diff.y.differing, diff.u.differing, diff.v.differing), (24, 0, 1));
+        assert_eq!((diff.y.max, diff.v.max, diff.y.mse(), diff.total().sum_of_squares), (4, 8, 16.0, 24 * 16 + 64));
+        #[cfg(feature = "std")]
+        {
+            assert!((diff.y.psnr() - 36.09).abs() < 0.01);
+            let limited = ::convert(&src, YuvFormat::I420, &ConversionConfig { range: Range::Limited, ..cfg });
+            assert!(i420.compare(&limited).unwrap().total().psnr() < 40.0);
+        }
+        let smaller = RgbView::new(&rgb[..6 * 2 * 3], 6, 2, ChannelOrder::Rgb).unwrap();
+        let smaller = ::convert(&smaller, YuvFormat::I420, &cfg);
+        assert_eq!(i420.compare(&smaller), Err(ConversionError::UnsupportedDimensions { width: 6, height: 2 }));
+    }
+}
@@ -0,0 +1,592 @@
+use format::YuvFormat;
+
+/// Color matrix used to derive luma and chroma from RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Matrix {
+    /// ITU-R BT.601 (SD video, JPEG)
+    Bt601,
+    /// ITU-R BT.709 (HD video)
+    Bt709,
+    /// ITU-R BT.2020 (UHD video, non-constant luminance)
+    Bt2020,
+}
+
+impl Matrix {
+    // (Kr, Kb)
+    fn weights(self) -> (f64, f64) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+            Matrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// Value range of the output samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Range {
+    /// 0-255 for every plane (full swing)
+    Full,
+    /// 16-235 for luma and 16-240 for chroma (studio swing)
+    Limited,
+}
+
+/// A matrix and a range by the names players and encoders know them by, so that frames are
+/// converted and tagged alike
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Colorimetry, ConversionConfig, Matrix, Range};
+///
+/// let cfg = ConversionConfig::from(Colorimetry::Bt709Limited);
+/// assert_eq!((cfg.matrix, cfg.range), (Matrix::Bt709, Range::Limited));
+/// assert_eq!(Colorimetry::new(Matrix::Bt601, Range::Full), Colorimetry::Bt601FullJfif);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Colorimetry {
+    /// BT.601 in studio swing, as SD video (Rec. 601, SMPTE 170M)
+    Bt601Limited,
+    /// BT.601 in full swing, as JPEG/JFIF stores it; the default of the crate
+    Bt601FullJfif,
+    /// BT.709 in studio swing, as HD video
+    Bt709Limited,
+    /// BT.709 in full swing, as some screen capture and webcam streams
+    Bt709Full,
+    /// BT.2020 in studio swing, as UHD video
+    Bt2020Limited,
+    /// BT.2020 in full swing
+    Bt2020Full,
+}
+
+impl Colorimetry {
+    /// The preset of `matrix` in `range`
+    pub fn new(matrix: Matrix, range: Range) -> Colorimetry {
+        match (matrix, range) {
+            (Matrix::Bt601, Range::Limited) => Colorimetry::Bt601Limited,
+            (Matrix::Bt601, Range::Full) => Colorimetry::Bt601FullJfif,
+            (Matrix::Bt709, Range::Limited) => Colorimetry::Bt709Limited,
+            (Matrix::Bt709, Range::Full) => Colorimetry::Bt709Full,
+            (Matrix::Bt2020, Range::Limited) => Colorimetry::Bt2020Limited,
+            (Matrix::Bt2020, Range::Full) => Colorimetry::Bt2020Full,
+        }
+    }
+
+    pub fn matrix(self) -> Matrix {
+        match self {
+            Colorimetry::Bt601Limited | Colorimetry::Bt601FullJfif => Matrix::Bt601,
+            Colorimetry::Bt709Limited | Colorimetry::Bt709Full => Matrix::Bt709,
+            Colorimetry::Bt2020Limited | Colorimetry::Bt2020Full => Matrix::Bt2020,
+        }
+    }
+
+    pub fn range(self) -> Range {
+        match self {
+            Colorimetry::Bt601Limited | Colorimetry::Bt709Limited | Colorimetry::Bt2020Limited => Range::Limited,
+            Colorimetry::Bt601FullJfif | Colorimetry::Bt709Full | Colorimetry::Bt2020Full => Range::Full,
+        }
+    }
+}
+
+/// How the chroma of a 2x2 pixel block is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaFilter {
+    /// Takes the top right pixel of the block
+    Point,
+    /// Averages the pixels of the block
+    Average,
+    /// Averages the pixels of the block in linear light, taking the image as sRGB encoded,
+    /// which keeps thin colored lines and high contrast edges from bleeding dark fringes into
+    /// their neighbours, for a few table lookups per pixel
+    LinearAverage,
+    /// Writes neutral chroma (128) without reading the block, for grayscale frames (eg.: from
+    /// infrared cameras)
+    Neutral,
+}
+
+/// How the chroma samples are spread over the pixels when converting back to RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChromaUpsampling {
+    /// Repeats every sample over its block
+    #[default]
+    Nearest,
+    /// Interpolates between the two nearest samples in each direction, taking 4:2:0 samples as
+    /// centered in their 2x2 blocks and 4:2:2 samples as sited on the even pixels
+    Bilinear,
+    /// Interpolates between the four nearest samples in each direction with a Catmull-Rom
+    /// spline, sharper than `Bilinear` at the cost of some ringing next to hard edges
+    CatmullRom,
+}
+
+/// How much accuracy a conversion gives up for speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quality {
+    /// Rounds every sample and samples chroma with the `ChromaFilter` of the configuration
+    #[default]
+    Accurate,
+    /// Truncates the samples instead of rounding them (leaving them up to one code lower),
+    /// point samples the chroma whatever the `ChromaFilter` (unless it's `Neutral`) and
+    /// converts the luma four pixels at a time, for latency critical paths
+    Fast,
+}
+
+/// How the kernels walk the image to write the luma and the chroma planes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlanePasses {
+    /// Writes two luma rows and the chroma row under them while the pixels are cached, and
+    /// splits the frame into bands of rows across `threads`
+    #[default]
+    Fused,
+    /// Writes the luma plane in one pass and the chroma planes in another, on two threads
+    /// when `threads` is more than 1; each pass touches a single output plane, which caches
+    /// better on wide frames
+    Separate,
+}
+
+/// Simple color correction applied while converting, without a pass over the RGB image
+///
+/// `brightness` is added to the luma codes, `contrast` scales luma around mid gray and
+/// `saturation` scales chroma around 128; both scales are in percent. `hue` turns the
+/// chroma around gray by that many degrees, from blue towards red.
+///
+/// `adjust_yuv` applies the same adjustment to frames that have already been converted.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Adjustment, ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// let adjustment = Adjustment { brightness: 20, saturation: 0, ..Adjustment::default() };
+/// let cfg = ConversionConfig { adjustment, ..ConversionConfig::default() };
+/// let rgb = [255u8, 0, 0, 255, 0, 0];
+/// let yuv = rgb2yuv420::convert(&RgbView::new(&rgb, 2, 1, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+/// assert_eq!(yuv.data(), &[97, 97, 128, 128]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Adjustment {
+    pub brightness: i16,
+    pub contrast: u16,
+    pub saturation: u16,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hue: i16,
+}
+
+impl Default for Adjustment {
+    /// Leaves the colors alone
+    fn default() -> Adjustment {
+        Adjustment { brightness: 0, contrast: 100, saturation: 100, hue: 0 }
+    }
+}
+
+impl Adjustment {
+    /// Whether the adjustment leaves the colors alone
+    pub fn is_neutral(&self) -> bool {
+        *self == Adjustment::default()
+    }
+
+    /// The 8.8 fixed point matrix that scales and turns `[u - 128, v - 128]`
+    pub(crate) fn chroma_matrix(&self) -> [[i32; 2]; 2] {
+        let (sin, cos) = sin_cos(self.hue);
+        let gain = f64::from(self.saturation) * 2.56;
+        [[round(gain * cos), round(-gain * sin)], [round(gain * sin), round(gain * cos)]]
+    }
+}
+
+/// A key color (eg.: of a green screen) made transparent in the alpha plane of the frames
+///
+/// Pixels whose chroma (in full swing BT.601, whatever the output colorimetry) is within
+/// `tolerance` of the key's get an alpha of 0 and the others 255; comparing chroma instead of
+/// RGB makes the key depend less on how evenly the screen is lit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChromaKey {
+    pub color: [u8; 3],
+    pub tolerance: u8,
+}
+
+impl ChromaKey {
+    pub(crate) fn keyer(&self) -> Keyer {
+        let coef = Coefficients::new(Matrix::Bt601, Range::Full, Adjustment::default());
+        let [r, g, b] = self.color.map(i32::from);
+        let (u, v) = coef.chroma(r, g, b);
+        Keyer { coef, u: i32::from(u), v: i32::from(v), limit: i32::from(self.tolerance).pow(2) }
+    }
+}
+
+/// A `ChromaKey` set up for keying pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Keyer {
+    coef: Coefficients,
+    // the chroma of the key and the largest squared distance to it
+    u: i32,
+    v: i32,
+    limit: i32,
+}
+
+impl Keyer {
+    #[inline(always)]
+    pub fn alpha(&self, r: i32, g: i32, b: i32) -> u8 {
+        let (u, v) = self.coef.chroma(r, g, b);
+        let (du, dv) = (i32::from(u) - self.u, i32::from(v) - self.v);
+        if du * du + dv * dv <= self.limit { 0 } else { 255 }
+    }
+}
+
+/// Colorimetry and performance settings shared by the converters
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConversionConfig {
+    pub matrix: Matrix,
+    pub range: Range,
+    pub chroma_filter: ChromaFilter,
+    /// Number of threads a frame is split across (1 converts on the calling thread), capped by
+    /// `set_max_threads`
+    pub threads: usize,
+    /// Left out of older serialized configurations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub adjustment: Adjustment,
+    /// Keys the alpha plane of the frames out of the colors instead of copying the alpha
+    /// channel; left out of older serialized configurations too
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chroma_key: Option<ChromaKey>,
+    /// Only used when converting back to RGB; left out of older serialized configurations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chroma_upsampling: ChromaUpsampling,
+    /// Left out of older serialized configurations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub quality: Quality,
+    /// Writes the chroma of semi-planar outputs as VU instead of UV, turning NV12 into NV21
+    /// for the devices that take it; planar outputs keep their U and V planes, and frames still
+    /// report `YuvFormat::Nv12`. Left out of older serialized configurations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub swap_uv: bool,
+    /// Left out of older serialized configurations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub plane_passes: PlanePasses,
+}
+
+// The colorimetry of the default configuration, picked by the `default-*` features; BT.2020
+// wins over BT.709 when both are enabled somewhere in the dependency graph
+const DEFAULT_MATRIX: Matrix = if cfg!(feature = "default-bt2020") {
+    Matrix::Bt2020
+} else if cfg!(feature = "default-bt709") {
+    Matrix::Bt709
+} else {
+    Matrix::Bt601
+};
+const DEFAULT_RANGE: Range = if cfg!(feature = "default-limited") { Range::Limited } else { Range::Full };
+
+/// The default configuration in the matrix and range of `colorimetry`
+impl From<Colorimetry> for ConversionConfig {
+    fn from(colorimetry: Colorimetry) -> ConversionConfig {
+        ConversionConfig { matrix: colorimetry.matrix(), range: colorimetry.range(), ..ConversionConfig::default() }
+    }
+}
+
+impl Default for ConversionConfig {
+    /// Full swing BT.601 (`Colorimetry::Bt601FullJfif`) with point sampled chroma on the
+    /// calling thread
+    ///
+    /// The `default-bt709` and `default-bt2020` features change the matrix and
+    /// `default-limited` the range, for everything converting with the default configuration
+    /// (`Converter::builder`, the legacy functions, ...).
+    fn default() -> ConversionConfig {
+        ConversionConfig {
+            matrix: DEFAULT_MATRIX,
+            range: DEFAULT_RANGE,
+            chroma_filter: ChromaFilter::Point,
+            threads: 1,
+            adjustment: Adjustment::default(),
+            chroma_key: None,
+            chroma_upsampling: ChromaUpsampling::Nearest,
+            quality: Quality::Accurate,
+            swap_uv: false,
+            plane_passes: PlanePasses::Fused,
+        }
+    }
+}
+
+impl ConversionConfig {
+    /// The matrix and range of the configuration
+    pub fn colorimetry(&self) -> Colorimetry {
+        Colorimetry::new(self.matrix, self.range)
+    }
+
+    pub(crate) fn coefficients(&self) -> Coefficients {
+        let coef = Coefficients::new(self.matrix, self.range, self.adjustment);
+        Coefficients { fast: self.quality == Quality::Fast, ..coef }
+    }
+
+    /// `coefficients` for `format` output, with the chroma rows swapped for `swap_uv`
+    pub(crate) fn coefficients_for(&self, format: YuvFormat) -> Coefficients {
+        let coef = self.coefficients();
+        match format {
+            YuvFormat::Nv12 if self.swap_uv => Coefficients { u: coef.v, v: coef.u, ..coef },
+            _ => coef,
+        }
+    }
+
+    /// The chroma filter the kernels sample with, which `Quality::Fast` turns into `Point`
+    pub(crate) fn sampled_chroma_filter(&self) -> ChromaFilter {
+        match (self.quality, self.chroma_filter) {
+            (Quality::Fast, ChromaFilter::Average | ChromaFilter::LinearAverage) => ChromaFilter::Point,
+            (_, filter) => filter,
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub(crate) fn inverse_coefficients(&self) -> InverseCoefficients {
+        InverseCoefficients::new(self.matrix, self.range)
+    }
+}
+
+/// 8.8 fixed point conversion factors, one row of `[r, g, b]` weights per plane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Coefficients {
+    pub y: [i32; 3],
+    pub u: [i32; 3],
+    pub v: [i32; 3],
+    pub y_offset: i32,
+    /// 8.8 fixed point gain and offset applied to the luma codes, if they are adjusted
+    pub luma_adjustment: Option<(i32, i32)>,
+    /// Truncates instead of rounding, for `Quality::Fast`
+    pub fast: bool,
+}
+
+impl Coefficients {
+    // The chroma rows are rounded so that they sum to zero, which keeps grays exactly at 128;
+    // full swing chroma is scaled to +-127 so that it never clips unless saturation is raised.
+    fn new(matrix: Matrix, range: Range, adjustment: Adjustment) -> Coefficients {
+        let (kr, kb) = matrix.weights();
+        let (y_scale, c_scale, y_offset) = match range {
+            Range::Full => (256.0, 127.0, 0),
+            Range::Limited => (256.0 * 219.0 / 255.0, 256.0 * 112.0 / 255.0, 16),
+        };
+        let c_scale = c_scale * f64::from(adjustment.saturation) / 100.0;
+        let y_total = round(y_scale);
+        let y_r = round(kr * y_scale);
+        let y_b = round(kb * y_scale);
+        let c_half = round(c_scale);
+        let u_r = round(-c_scale * kr / (1.0 - kb));
+        let v_b = round(-c_scale * kb / (1.0 - kr));
+        let (mut u, mut v) = ([u_r, -u_r - c_half, c_half], [c_half, -c_half - v_b, v_b]);
+        if adjustment.hue != 0 {
+            let (sin, cos) = sin_cos(adjustment.hue);
+            let turned = |a: i32, b: i32, sin: f64| round(f64::from(a) * cos + f64::from(b) * sin);
+            let (turned_u, turned_v) = (u, v);
+            for i in [0, 2] {
+                u[i] = turned(turned_u[i], turned_v[i], -sin);
+                v[i] = turned(turned_v[i], turned_u[i], sin);
+            }
+            // keeps the rows summing to zero
+            u[1] = -u[0] - u[2];
+            v[1] = -v[0] - v[2];
+        }
+        Coefficients {
+            y: [y_r, y_total - y_r - y_b, y_b],
+            u,
+            v,
+            y_offset,
+            luma_adjustment: if adjustment.brightness == 0 && adjustment.contrast == 100 {
+                None
+            } else {
+                // contrast pivots around the luma of mid gray
+                let gain = round(f64::from(adjustment.contrast) * 2.56);
+                let pivot = y_offset + round(y_scale * 128.0 / 256.0);
+                Some((gain, (pivot + i32::from(adjustment.brightness)) * 256 - pivot * gain))
+            },
+            fast: false,
+        }
+    }
+
+    #[inline(always)]
+    pub fn luma(&self, r: i32, g: i32, b: i32) -> u8 {
+        // The luma weights are positive and sum to at most 256, so the sum fits 16 bits and
+        // the result never needs clamping; 16 bit math is what SSE2 can vectorize.
+        let (r, g, b) = (r as u16, g as u16, b as u16);
+        let round = if self.fast { 0 } else { 128 };
+        let y = (self.y[0] as u16 * r + self.y[1] as u16 * g + self.y[2] as u16 * b + round) >> 8;
+        y as u8 + self.y_offset as u8
+    }
+
+    /// Applies the brightness and contrast to a row of luma codes
+    #[inline(always)]
+    pub fn adjust_luma(&self, row: &mut [u8]) {
+        if let Some((gain, offset)) = self.luma_adjustment {
+            for y in row {
+                *y = clamp((i32::from(*y) * gain + offset + 128) >> 8);
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn chroma(&self, r: i32, g: i32, b: i32) -> (u8, u8) {
+        let round = if self.fast { 0 } else { 128 };
+        (clamp(((self.u[0] * r + self.u[1] * g + self.u[2] * b + round) >> 8) + 128),
+         clamp(((self.v[0] * r + self.v[1] * g + self.v[2] * b + round) >> 8) + 128))
+    }
+}
+
+/// 16.16 fixed point factors from YUV back to RGB, inverting the scales of `Coefficients`
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct InverseCoefficients {
+    pub y_gain: i32,
+    pub y_offset: i32,
+    pub r_v: i32,
+    pub g_u: i32,
+    pub g_v: i32,
+    pub b_u: i32,
+}
+
+#[cfg(feature = "alloc")]
+impl InverseCoefficients {
+    fn new(matrix: Matrix, range: Range) -> InverseCoefficients {
+        let (kr, kb) = matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_scale, c_scale, y_offset) = match range {
+            Range::Full => (256.0, 127.0, 0),
+            Range::Limited => (256.0 * 219.0 / 255.0, 256.0 * 112.0 / 255.0, 16),
+        };
+        // the differences of R and B to luma per chroma code
+        let (r_v, b_u) = ((1.0 - kr) * 256.0 / c_scale, (1.0 - kb) * 256.0 / c_scale);
+        let fixed = |val: f64| round(val * 65536.0);
+        InverseCoefficients {
+            y_gain: fixed(256.0 / y_scale),
+            y_offset,
+            r_v: fixed(r_v),
+            g_u: fixed(-kb * b_u / kg),
+            g_v: fixed(-kr * r_v / kg),
+            b_u: fixed(b_u),
+        }
+    }
+
+    #[inline(always)]
+    pub fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let y = (i32::from(y) - self.y_offset) * self.y_gain + 32768;
+        let (u, v) = (i32::from(u) - 128, i32::from(v) - 128);
+        [clamp((y + self.r_v * v) >> 16), clamp((y + self.g_u * u + self.g_v * v) >> 16),
+         clamp((y + self.b_u * u) >> 16)]
+    }
+}
+
+// `f64::round` (half away from zero) needs `std`
+fn round(val: f64) -> i32 {
+    if val < 0.0 { -((-val + 0.5) as i32) } else { (val + 0.5) as i32 }
+}
+
+// the sine and cosine of `degrees`, from Taylor series as `core` has no trigonometry
+fn sin_cos(degrees: i16) -> (f64, f64) {
+    // the quarter turns and the rest, within 45 degrees either way
+    let degrees = i32::from(degrees).rem_euclid(360);
+    let quarters = (degrees + 45) / 90;
+    let x = f64::from(degrees - 90 * quarters) * core::f64::consts::PI / 180.0;
+    let x2 = x * x;
+    let sin = x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))));
+    let cos = 1.0 - x2 / 2.0 * (1.0 - x2 / 12.0 * (1.0 - x2 / 30.0 * (1.0 - x2 / 56.0)));
+    match quarters % 4 {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+#[inline(always)]
+fn clamp(val: i32) -> u8 {
+    // branchless, so that the row loops can be vectorized
+    val.clamp(0, 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adjustment, ChromaKey, Coefficients, ConversionConfig, Matrix, Range};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn default_colorimetry_follows_the_features() {
+        let cfg = ConversionConfig::default();
+        let matrix = match (cfg!(feature = "default-bt2020"), cfg!(feature = "default-bt709")) {
+            (true, _) => Matrix::Bt2020,
+            (false, true) => Matrix::Bt709,
+            (false, false) => Matrix::Bt601,
+        };
+        assert_eq!(cfg.matrix, matrix);
+        assert_eq!(cfg.range == Range::Limited, cfg!(feature = "default-limited"));
+    }
+
+    #[test]
+    fn full_range_bt601_matches_legacy_factors() {
+        let coef = Coefficients::new(Matrix::Bt601, Range::Full, Adjustment::default());
+        assert_eq!(coef.y, [77, 150, 29]);
+        assert_eq!(coef.u, [-43, -84, 127]);
+        assert_eq!(coef.v, [127, -106, -21]);
+        assert_eq!(coef.y_offset, 0);
+    }
+
+    #[test]
+    fn limited_range_extremes() {
+        for &matrix in &[Matrix::Bt601, Matrix::Bt709, Matrix::Bt2020] {
+            let coef = Coefficients::new(matrix, Range::Limited, Adjustment::default());
+            assert_eq!(coef.luma(0, 0, 0), 16);
+            assert_eq!(coef.luma(255, 255, 255), 235);
+            assert_eq!(coef.chroma(128, 128, 128), (128, 128));
+            assert_eq!(coef.chroma(0, 0, 255).0, 240);
+            assert_eq!(coef.chroma(255, 0, 0).1, 240);
+        }
+    }
+
+    #[test]
+    fn adjustments_pivot_around_mid_gray() {
+        let adjusted = |adjustment| {
+            let coef = Coefficients::new(Matrix::Bt709, Range::Full, adjustment);
+            let mut row = [coef.luma(0, 0, 0), coef.luma(128, 128, 128), coef.luma(255, 255, 255)];
+            coef.adjust_luma(&mut row);
+            (row, coef.chroma(255, 0, 0), coef.chroma(128, 128, 128))
+        };
+        let neutral = adjusted(Adjustment::default());
+        assert_eq!(neutral.0, [0, 128, 255]);
+        let contrast = adjusted(Adjustment { contrast: 50, ..Adjustment::default() });
+        assert_eq!(contrast.0, [64, 128, 192]);
+        assert_eq!(adjusted(Adjustment { brightness: -10, ..Adjustment::default() }).0, [0, 118, 245]);
+        let gray = adjusted(Adjustment { saturation: 0, ..Adjustment::default() });
+        assert_eq!((gray.1, gray.2), ((128, 128), (128, 128)));
+        let vivid = adjusted(Adjustment { saturation: 200, ..Adjustment::default() });
+        assert_eq!(((vivid.1).1, vivid.2), (255, (128, 128)));
+    }
+
+    #[test]
+    fn keyer_matches_the_chroma_of_the_key() {
+        let keyer = ChromaKey { color: [0, 255, 0], tolerance: 24 }.keyer();
+        assert_eq!(keyer.alpha(0, 255, 0), 0);
+        assert_eq!(keyer.alpha(20, 240, 10), 0);
+        assert_eq!(keyer.alpha(0, 128, 0), 255);
+        assert_eq!(keyer.alpha(128, 128, 128), 255);
+        assert_eq!(keyer.alpha(255, 0, 0), 255);
+    }
+
+    #[test]
+    fn swap_uv_turns_nv12_into_nv21() {
+        let rgb: Vec<u8> = (0..4 * 2 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+        let swapped = ConversionConfig { swap_uv: true, ..ConversionConfig::default() };
+        let nv12 = ::convert(&src, YuvFormat::Nv12, &ConversionConfig::default());
+        let nv21 = ::convert(&src, YuvFormat::Nv12, &swapped);
+        assert_eq!(nv21.y(), nv12.y());
+        let uv = nv12.uv().unwrap();
+        assert_ne!(uv[0], uv[1]);
+        let vu: Vec<u8> = uv.chunks(2).flat_map(|pair| [pair[1], pair[0]]).collect();
+        assert_eq!(nv21.uv().unwrap(), &vu[..]);
+        let i420 = ::convert(&src, YuvFormat::I420, &ConversionConfig::default());
+        assert_eq!(::convert(&src, YuvFormat::I420, &swapped).data(), i420.data());
+    }
+}
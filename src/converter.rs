@@ -0,0 +1,750 @@
+use alloc::sync::Arc;
+#[cfg(feature = "burn-in")]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use config::{Adjustment, ChromaFilter, ChromaKey, Colorimetry, ConversionConfig, Matrix, PlanePasses, Quality, Range};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use frame::{FrameTiming, Pitch, YuvFrame};
+use geometry::{Backdrop, Geometry, Overlay, Rect, Rotation, ScaleFilter};
+#[cfg(feature = "std")]
+use kernel;
+use kernel::{Planes, Setup, Source};
+use plan::Plan;
+use stats::{FrameStats, Histogram};
+#[cfg(feature = "std")]
+use throughput::{Throughput, ThroughputHandle};
+use view::RgbView;
+
+/// A reusable RGB to YUV420 converter
+///
+/// Converters hold no scratch buffers and are `Send + Sync`, so one configured instance can
+/// be shared (eg.: in an `Arc`) by every thread of a capture pool.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, Converter, Matrix, Range, YuvFormat};
+///
+/// let converter = Converter::builder()
+///     .input(ChannelOrder::Rgba)
+///     .output(YuvFormat::Nv12)
+///     .matrix(Matrix::Bt709)
+///     .range(Range::Limited)
+///     .build();
+/// let rgba = vec![0u8; 16];
+/// let frame = converter.convert(&rgba, 2, 2).unwrap();
+/// assert_eq!(frame.y(), &[16, 16, 16, 16][..]);
+/// assert_eq!(frame.uv(), Some(&[128, 128][..]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Converter {
+    input: ChannelOrder,
+    output: YuvFormat,
+    config: ConversionConfig,
+    pitch: Pitch,
+    geometry: Geometry,
+    #[cfg(feature = "std")]
+    throughput: Option<ThroughputHandle>,
+}
+
+impl Converter {
+    /// Starts configuring a converter (RGB to I420 with the default `ConversionConfig`)
+    pub fn builder() -> ConverterBuilder {
+        ConverterBuilder {
+            input: ChannelOrder::Rgb,
+            output: YuvFormat::I420,
+            config: ConversionConfig::default(),
+            pitch: Pitch::default(),
+            geometry: Geometry::default(),
+            #[cfg(feature = "std")]
+            throughput: None,
+        }
+    }
+
+    /// Converts a tightly packed image
+    ///
+    /// # Arguments
+    ///
+    /// * `img` - should contain `width * height` pixels in the configured channel order
+    ///
+    /// # Return
+    ///
+    /// The frame in the configured output format, or `BufferTooSmall` if `img` is too short
+    pub fn convert(&self, img: &[u8], width: u32, height: u32) -> Result<YuvFrame, ConversionError> {
+        Ok(self.convert_source(&Source::packed(img, width, height, self.input.layout())?, &self.geometry))
+    }
+
+    /// Converts the image described by `src`, which may have padded rows or 16 bit channels
+    ///
+    /// The channel order of the view takes precedence over the configured input order.
+    pub fn convert_view(&self, src: &RgbView) -> YuvFrame {
+        self.convert_source(src.source(), &self.geometry)
+    }
+
+    /// Converts `src` and stamps `frame_number` and `timestamp` into the frame, see
+    /// `YuvFrame::burn_in`, and into its `FrameTiming`
+    #[cfg(feature = "burn-in")]
+    pub fn convert_burned_in(&self, src: &RgbView, frame_number: u64, timestamp: Option<Duration>) -> YuvFrame {
+        let mut frame = self.convert_view(src);
+        frame.burn_in(frame_number, timestamp);
+        frame.set_timing(FrameTiming { pts: timestamp, index: Some(frame_number), ..frame.timing() });
+        frame
+    }
+
+    /// Converts `src` and gathers the luma statistics of the frame on the way, while the rows
+    /// are still cached
+    pub fn convert_with_stats(&self, src: &RgbView) -> (YuvFrame, FrameStats) {
+        let histogram = Histogram::new();
+        let frame = self.convert_counting(src.source(), &self.geometry, Some(&histogram));
+        (frame, histogram.into_stats(self.config.range))
+    }
+
+    /// Converts `src` into a frame and a second one at half its width and height, eg.: for the
+    /// high and low layers of simulcast, without scaling the image twice
+    ///
+    /// The small frame averages the 2x2 luma samples and the 2x2 chroma samples (the chroma of
+    /// 4x4 pixels) of the large one; it has no alpha plane and isn't padded or aligned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, Converter, RgbView};
+    ///
+    /// let rgb: Vec<u8> = (0..4 * 4).flat_map(|i| vec![if i % 2 == 0 { 255 } else { 0 }; 3]).collect();
+    /// let src = RgbView::new(&rgb, 4, 4, ChannelOrder::Rgb).unwrap();
+    /// let (high, low) = Converter::builder().build().convert_layers(&src);
+    /// assert_eq!((high.width(), low.width()), (4, 2));
+    /// assert_eq!(low.y(), &[128; 4]);
+    /// ```
+    pub fn convert_layers(&self, src: &RgbView) -> (YuvFrame, YuvFrame) {
+        let frame = self.convert_view(src);
+        let halved = frame.halved();
+        (frame, halved)
+    }
+
+    /// Converts the `source` rectangle of `src` into the `destination` rectangle of the frame,
+    /// scaling it (after rotating it) to fit, eg.: for streaming a region of the screen or for
+    /// digital zoom
+    ///
+    /// The frame has the size set by `scale` or `letterbox`, or else ends with the
+    /// destination; the area around the destination has the `background` color.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRegion` if `source` reaches outside of `src` or `destination` outside of the
+    /// frame
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, Converter, Rect, RgbView};
+    ///
+    /// let screen = vec![255u8; 64 * 48 * 4];
+    /// let screen = RgbView::new(&screen, 64, 48, ChannelOrder::Bgra).unwrap();
+    /// let zoomed = Converter::builder().scale(32, 24).build()
+    ///     .convert_region(&screen, Rect { x: 16, y: 12, width: 16, height: 12 },
+    ///                     Rect { x: 0, y: 0, width: 32, height: 24 })
+    ///     .unwrap();
+    /// assert_eq!((zoomed.width(), zoomed.height()), (32, 24));
+    /// ```
+    pub fn convert_region(&self, src: &RgbView, source: Rect, destination: Rect)
+                          -> Result<YuvFrame, ConversionError> {
+        let src = src.crop(source.x, source.y, source.width, source.height)?;
+        let geometry = Geometry { destination: Some(destination), ..self.geometry.clone() };
+        let (width, height) = geometry.output_size(src.width() as usize, src.height() as usize);
+        if destination.x as usize + destination.width as usize > width
+            || destination.y as usize + destination.height as usize > height {
+            let Rect { x, y, width, height } = destination;
+            return Err(ConversionError::InvalidRegion { x, y, width, height });
+        }
+        Ok(self.convert_source(src.source(), &geometry))
+    }
+
+    /// Converts the two fields of an interlaced image into a frame each, the top field (the
+    /// even rows) first
+    ///
+    /// Every field is subsampled on its own, so its chroma never mixes in the rows of the
+    /// other field. The image is converted as it is; the transforms only apply to progressive
+    /// frames.
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if the height of `src` is odd
+    pub fn convert_fields(&self, src: &RgbView) -> Result<(YuvFrame, YuvFrame), ConversionError> {
+        if !src.height().is_multiple_of(2) {
+            return Err(ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() });
+        }
+        let geometry = Geometry::default();
+        let src = src.source();
+        Ok((self.convert_source(&src.field(false), &geometry), self.convert_source(&src.field(true), &geometry)))
+    }
+
+    /// Converts an interlaced image into one frame with the rows of its fields interleaved,
+    /// the way field coding encoders take it
+    ///
+    /// Every field is subsampled on its own and its chroma rows are interleaved too: the even
+    /// chroma rows belong to the top field and the odd ones to the bottom field. The image is
+    /// converted as it is; the transforms only apply to progressive frames.
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if the height of `src` is not a multiple of 4, so that the
+    /// fields have the same number of chroma rows
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, Converter, RgbView};
+    ///
+    /// // white top field and black bottom field
+    /// let rgb: Vec<u8> = (0..4).flat_map(|row| vec![if row % 2 == 0 { 255 } else { 0 }; 2 * 3]).collect();
+    /// let frame = Converter::builder().build()
+    ///     .convert_interlaced(&RgbView::new(&rgb, 2, 4, ChannelOrder::Rgb).unwrap()).unwrap();
+    /// assert_eq!(frame.y(), &[255, 255, 0, 0, 255, 255, 0, 0][..]);
+    /// ```
+    pub fn convert_interlaced(&self, src: &RgbView) -> Result<YuvFrame, ConversionError> {
+        if !src.height().is_multiple_of(4) {
+            return Err(ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() });
+        }
+        let (width, height) = (src.width(), src.height());
+        let (padded_width, padded_height) = self.pitch.padded(width, height);
+        let mut frame = YuvFrame::pitched(self.output, padded_width, padded_height, &self.config, self.pitch);
+        for &bottom in &[false, true] {
+            let field = src.source().field(bottom);
+            Setup::new(field.width, field.height, field.layout, field.wide, self.output, &self.config)
+                .run_planes(&field, frame.kernel_planes().field(bottom));
+        }
+        frame.finish(width, height, self.pitch.alpha_filter);
+        Ok(frame)
+    }
+
+    /// Converts `src` like `convert_view` into `frame`, reusing its buffer if it is laid out
+    /// like the converted frames (eg.: it was converted by this converter from an image of the
+    /// same size) and replacing it otherwise
+    ///
+    /// The timing of `frame` is reset, and the padding of its rows keeps its bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, Converter, RgbView};
+    ///
+    /// let converter = Converter::builder().alignment(64).build();
+    /// let rgb = [255u8; 4 * 2 * 3];
+    /// let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+    /// let mut frame = converter.convert_view(&RgbView::new(&[0; 4 * 2 * 3], 4, 2, ChannelOrder::Rgb).unwrap());
+    /// let buffer = frame.data().as_ptr();
+    /// converter.convert_view_into(&src, &mut frame);
+    /// assert_eq!((frame.y()[0], frame.data().as_ptr()), (255, buffer));
+    /// ```
+    pub fn convert_view_into(&self, src: &RgbView, frame: &mut YuvFrame) {
+        let (width, height) = self.geometry.output_size(src.width() as usize, src.height() as usize);
+        let (padded_width, padded_height) = self.pitch.padded(width as u32, height as u32);
+        if frame.fits(self.output, padded_width, padded_height, &self.config, self.pitch) {
+            frame.set_timing(FrameTiming::default());
+            self.convert_counting_into(src.source(), &self.geometry, None, frame);
+        } else {
+            *frame = self.convert_view(src);
+        }
+    }
+
+    fn convert_source(&self, src: &Source, geometry: &Geometry) -> YuvFrame {
+        self.convert_counting(src, geometry, None)
+    }
+
+    // `convert_source`, counting the luma codes into `stats`
+    fn convert_counting(&self, src: &Source, geometry: &Geometry, stats: Option<&Histogram>) -> YuvFrame {
+        let (width, height) = geometry.output_size(src.width, src.height);
+        let (padded_width, padded_height) = self.pitch.padded(width as u32, height as u32);
+        let mut frame = YuvFrame::pitched(self.output, padded_width, padded_height, &self.config, self.pitch);
+        self.convert_counting_into(src, geometry, stats, &mut frame);
+        frame
+    }
+
+    // `convert_counting` into a frame `pitched` for the output
+    fn convert_counting_into(&self, src: &Source, geometry: &Geometry, stats: Option<&Histogram>,
+                             frame: &mut YuvFrame) {
+        let (width, height) = geometry.output_size(src.width, src.height);
+        let (width, height) = (width as u32, height as u32);
+        #[cfg(feature = "std")]
+        let start = self.throughput.as_ref().map(|_| Instant::now());
+        geometry.run(src, self.output, &self.config, Planes { stats, ..frame.kernel_planes() });
+        frame.finish(width, height, self.pitch.alpha_filter);
+        #[cfg(feature = "std")]
+        if let (Some(throughput), Some(start)) = (&self.throughput, start) {
+            throughput.0.record(width, height, start.elapsed(), kernel::kernel_name(src));
+        }
+    }
+
+    pub fn input(&self) -> ChannelOrder {
+        self.input
+    }
+
+    pub fn output(&self) -> YuvFormat {
+        self.output
+    }
+
+    pub fn config(&self) -> &ConversionConfig {
+        &self.config
+    }
+
+    /// Alignment of the planes and rows of the converted frames in bytes
+    pub fn alignment(&self) -> usize {
+        self.pitch.alignment
+    }
+}
+
+/// Configures a `Converter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConverterBuilder {
+    input: ChannelOrder,
+    output: YuvFormat,
+    config: ConversionConfig,
+    pitch: Pitch,
+    geometry: Geometry,
+    #[cfg(feature = "std")]
+    throughput: Option<ThroughputHandle>,
+}
+
+impl ConverterBuilder {
+    pub fn input(mut self, input: ChannelOrder) -> ConverterBuilder {
+        self.input = input;
+        self
+    }
+
+    pub fn output(mut self, output: YuvFormat) -> ConverterBuilder {
+        self.output = output;
+        self
+    }
+
+    pub fn matrix(mut self, matrix: Matrix) -> ConverterBuilder {
+        self.config.matrix = matrix;
+        self
+    }
+
+    pub fn range(mut self, range: Range) -> ConverterBuilder {
+        self.config.range = range;
+        self
+    }
+
+    /// Sets both the matrix and the range
+    pub fn colorimetry(mut self, colorimetry: Colorimetry) -> ConverterBuilder {
+        self.config.matrix = colorimetry.matrix();
+        self.config.range = colorimetry.range();
+        self
+    }
+
+    pub fn chroma_filter(mut self, filter: ChromaFilter) -> ConverterBuilder {
+        self.config.chroma_filter = filter;
+        self
+    }
+
+    /// Trades accuracy for speed, see `Quality`
+    pub fn quality(mut self, quality: Quality) -> ConverterBuilder {
+        self.config.quality = quality;
+        self
+    }
+
+    /// Writes the luma and the chroma planes in one pass or in two, see `PlanePasses`
+    pub fn plane_passes(mut self, passes: PlanePasses) -> ConverterBuilder {
+        self.config.plane_passes = passes;
+        self
+    }
+
+    /// Writes NV12 chroma as VU, giving NV21, see `ConversionConfig::swap_uv`
+    pub fn swap_uv(mut self, swap: bool) -> ConverterBuilder {
+        self.config.swap_uv = swap;
+        self
+    }
+
+    /// Corrects brightness, contrast and saturation while converting, see `Adjustment`
+    pub fn adjust(mut self, adjustment: Adjustment) -> ConverterBuilder {
+        self.config.adjustment = adjustment;
+        self
+    }
+
+    /// Splits every frame across `threads` threads (1 converts on the calling thread)
+    pub fn threads(mut self, threads: usize) -> ConverterBuilder {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Replaces all of the colorimetry and performance settings at once
+    pub fn config(mut self, config: ConversionConfig) -> ConverterBuilder {
+        self.config = config;
+        self
+    }
+
+    /// Pads the rows of the converted frames to at least `y_stride` and `chroma_stride` bytes
+    /// (the stride of U and V for I420 and of UV for NV12), eg.: for encoders taking pitched
+    /// surfaces
+    ///
+    /// Wider rows keep their size, and with `alignment` the strides are rounded up to it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, YuvFormat};
+    ///
+    /// let converter = Converter::builder().output(YuvFormat::Nv12).strides(16, 16).build();
+    /// let frame = converter.convert(&[255u8; 6 * 2 * 3], 6, 2).unwrap();
+    /// assert_eq!((frame.y_stride(), frame.chroma_stride()), (16, 16));
+    /// assert!(frame.y().chunks(16).all(|row| row[..6] == [255; 6] && row[6..] == [0; 10]));
+    /// assert_eq!(frame.uv(), Some(&[&[128u8; 6][..], &[0; 10]].concat()[..]));
+    /// ```
+    pub fn strides(mut self, y_stride: usize, chroma_stride: usize) -> ConverterBuilder {
+        self.pitch.y_stride = y_stride;
+        self.pitch.chroma_stride = chroma_stride;
+        self
+    }
+
+    /// Rotates the image clockwise while converting it, so that eg.: portrait camera frames
+    /// need no separate rotation pass
+    ///
+    /// The converted frames are `height` x `width` for quarter turns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, Rotation};
+    ///
+    /// // a white pixel left of a black one
+    /// let rgb = [255, 255, 255, 0, 0, 0];
+    /// let frame = Converter::builder().rotate(Rotation::Cw90).build().convert(&rgb, 2, 1).unwrap();
+    /// assert_eq!((frame.width(), frame.height()), (1, 2));
+    /// assert_eq!(frame.y(), &[255, 0][..]);
+    /// ```
+    pub fn rotate(mut self, rotation: Rotation) -> ConverterBuilder {
+        self.geometry.rotation = rotation;
+        self
+    }
+
+    /// Mirrors the converted frames left to right (after rotating them), eg.: for a selfie
+    /// preview
+    pub fn flip_horizontal(mut self, flip: bool) -> ConverterBuilder {
+        self.geometry.flip_horizontal = flip;
+        self
+    }
+
+    /// Turns the converted frames upside down (after rotating them), eg.: for OpenGL's
+    /// bottom-up `glReadPixels`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::Converter;
+    ///
+    /// // a white row above a black one
+    /// let rgb = [[255u8; 6], [0; 6]].concat();
+    /// let frame = Converter::builder().flip_vertical(true).build().convert(&rgb, 2, 2).unwrap();
+    /// assert_eq!(frame.y(), &[0, 0, 255, 255][..]);
+    /// ```
+    pub fn flip_vertical(mut self, flip: bool) -> ConverterBuilder {
+        self.geometry.flip_vertical = flip;
+        self
+    }
+
+    /// Scales the (rotated) image to `width` x `height` while converting it, eg.: a 4K capture
+    /// to 1080p, without a second pass over the frame
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, ScaleFilter};
+    ///
+    /// let converter = Converter::builder().scale(1920, 1080).scale_filter(ScaleFilter::Nearest).build();
+    /// let frame = converter.convert(&vec![255u8; 3840 * 2160 * 3], 3840, 2160).unwrap();
+    /// assert_eq!((frame.width(), frame.height()), (1920, 1080));
+    /// ```
+    pub fn scale(mut self, width: u32, height: u32) -> ConverterBuilder {
+        self.geometry.size = Some((width as usize, height as usize));
+        self.geometry.letterbox = false;
+        self
+    }
+
+    /// Scales the (rotated) image to fit into `width` x `height` frames, keeping its aspect
+    /// ratio, and centers it between bars of the `background` color, eg.: for an encoder
+    /// taking a single resolution
+    ///
+    /// This replaces `scale` and the other way around.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::Converter;
+    ///
+    /// // a 4:3 image in a 16:9 frame
+    /// let frame = Converter::builder().letterbox(16, 9).build().convert(&[255u8; 4 * 3 * 3], 4, 3).unwrap();
+    /// assert_eq!(&frame.y()[..16], &[0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0]);
+    /// ```
+    pub fn letterbox(mut self, width: u32, height: u32) -> ConverterBuilder {
+        self.geometry.size = Some((width as usize, height as usize));
+        self.geometry.letterbox = true;
+        self
+    }
+
+    /// Color of the bars of `letterbox` (black by default)
+    pub fn background(mut self, rgb: [u8; 3]) -> ConverterBuilder {
+        self.geometry.background = rgb;
+        self
+    }
+
+    /// Composites images with an alpha channel over `backdrop` while converting them,
+    /// instead of ignoring their alpha
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Backdrop, ChannelOrder, Converter};
+    ///
+    /// let converter = Converter::builder().input(ChannelOrder::Rgba).composite(Backdrop::Color([255; 3])).build();
+    /// // transparent black over white
+    /// let frame = converter.convert(&[0, 0, 0, 0], 1, 1).unwrap();
+    /// assert_eq!(frame.y(), &[255][..]);
+    /// ```
+    pub fn composite(mut self, backdrop: Backdrop) -> ConverterBuilder {
+        self.geometry.composite = Some(backdrop);
+        self
+    }
+
+    /// Blends `overlay` over the converted frames with its top left corner at `x`, `y` of the
+    /// frame (after the image is transformed), leaving out the parts outside of it
+    ///
+    /// The overlay is shared by the clones of the converter. See `Overlay`'s example.
+    pub fn overlay(mut self, overlay: Overlay, x: u32, y: u32) -> ConverterBuilder {
+        self.geometry.overlay = Some((Arc::new(overlay), x as usize, y as usize));
+        self
+    }
+
+    /// How `scale` samples the image (bilinear by default)
+    pub fn scale_filter(mut self, filter: ScaleFilter) -> ConverterBuilder {
+        self.geometry.filter = filter;
+        self
+    }
+
+    /// Pads the converted frames to multiples of `multiple` pixels in both directions (eg.: 16
+    /// for H.264 macroblocks or 64 for HEVC coding tree units), repeating the edge pixels of
+    /// every plane into the padding
+    ///
+    /// The frames have the padded size; the picture is their top left `width` x `height`
+    /// pixels, which encoders signal as the cropping window.
+    ///
+    /// # Panics
+    ///
+    /// If `multiple` is 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::Converter;
+    ///
+    /// let frame = Converter::builder().pad_to(16).build().convert(&[255u8; 30 * 20 * 3], 30, 20).unwrap();
+    /// assert_eq!((frame.width(), frame.height()), (32, 32));
+    /// assert!(frame.y().iter().all(|&y| y == 255));
+    /// ```
+    pub fn pad_to(mut self, multiple: usize) -> ConverterBuilder {
+        assert!(multiple > 0, "frames can't be padded to multiples of 0");
+        self.pitch.multiple = multiple;
+        self
+    }
+
+    /// Starts every plane and every row of the converted frames at a multiple of `alignment`
+    /// bytes (eg.: 32 or 64 for SIMD encoders and DMA engines), padding the rows as needed
+    ///
+    /// # Panics
+    ///
+    /// If `alignment` is not a power of two
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::Converter;
+    ///
+    /// let frame = Converter::builder().alignment(64).build().convert(&[0u8; 6 * 2 * 3], 6, 2).unwrap();
+    /// assert_eq!((frame.y_stride(), frame.chroma_stride()), (64, 64));
+    /// assert_eq!(frame.y().as_ptr() as usize % 64, 0);
+    /// assert_eq!(frame.u().unwrap().as_ptr() as usize % 64, 0);
+    /// ```
+    pub fn alignment(mut self, alignment: usize) -> ConverterBuilder {
+        assert!(alignment.is_power_of_two(), "alignment must be a power of two");
+        self.pitch.alignment = alignment;
+        self
+    }
+
+    /// Adds an alpha plane (see `alpha_plane`) in which the pixels of `key`'s color are
+    /// transparent, eg.: for sending a green screen straight to an encoder taking alpha
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChromaKey, Converter};
+    ///
+    /// let key = ChromaKey { color: [0, 255, 0], tolerance: 24 };
+    /// let converter = Converter::builder().chroma_key(key).build();
+    /// // an unevenly lit green and a red pixel
+    /// let frame = converter.convert(&[20, 240, 10, 255, 0, 0], 2, 1).unwrap();
+    /// assert_eq!(frame.alpha(), Some(&[0, 255][..]));
+    /// ```
+    pub fn chroma_key(mut self, key: ChromaKey) -> ConverterBuilder {
+        self.config.chroma_key = Some(key);
+        self.pitch.alpha = true;
+        self
+    }
+
+    /// Adds a full resolution alpha plane after the chroma planes of the converted frames
+    /// (A420, as VP9 and AV1 encoders take transparency), which is opaque if the input has no
+    /// alpha channel
+    ///
+    /// `Plan::convert_into` still writes the YUV420 planes only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, Converter};
+    ///
+    /// let converter = Converter::builder().input(ChannelOrder::Rgba).alpha_plane(true).build();
+    /// let frame = converter.convert(&[255, 255, 255, 0, 0, 0, 0, 128], 2, 1).unwrap();
+    /// assert_eq!(frame.alpha(), Some(&[0, 128][..]));
+    /// assert_eq!(frame.data().len(), 2 + 2 + 2);
+    /// ```
+    pub fn alpha_plane(mut self, alpha: bool) -> ConverterBuilder {
+        self.pitch.alpha = alpha;
+        self.pitch.alpha_filter = None;
+        self
+    }
+
+    /// Adds an alpha plane like `alpha_plane`, subsampled 2x2 with `filter` to the size of the
+    /// chroma planes, for compositors and codecs that take alpha at the chroma resolution
+    ///
+    /// `ChromaFilter::Neutral` makes the plane opaque. Frames converted into afterwards, eg.: by
+    /// `convert_regions`, keep their subsampled alpha plane as it is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, ChromaFilter, Converter};
+    ///
+    /// let converter = Converter::builder().input(ChannelOrder::Rgba).subsampled_alpha(ChromaFilter::Average).build();
+    /// let rgba: Vec<u8> = [0u8, 255, 255, 0].iter().flat_map(|&a| [0, 0, 0, a]).collect();
+    /// let frame = converter.convert(&rgba, 2, 2).unwrap();
+    /// assert_eq!((frame.alpha(), frame.alpha_stride()), (Some(&[128][..]), 1));
+    /// assert_eq!(frame.data().len(), 4 + 2 + 1);
+    /// ```
+    pub fn subsampled_alpha(mut self, filter: ChromaFilter) -> ConverterBuilder {
+        self.pitch.alpha = true;
+        self.pitch.alpha_filter = Some(filter);
+        self
+    }
+
+    /// Counts the frames the converter converts into `throughput`, which any number of
+    /// converters can share (`std` feature)
+    #[cfg(feature = "std")]
+    pub fn throughput(mut self, throughput: Arc<Throughput>) -> ConverterBuilder {
+        self.throughput = Some(ThroughputHandle(throughput));
+        self
+    }
+
+    /// Works out everything needed to convert `width` x `height` frames, see `Plan`
+    pub fn plan(self, width: u32, height: u32) -> Plan {
+        Plan::new(self.input, self.output, self.config, self.pitch, self.geometry, width, height)
+    }
+
+    pub fn build(self) -> Converter {
+        Converter {
+            input: self.input,
+            output: self.output,
+            config: self.config,
+            pitch: self.pitch,
+            geometry: self.geometry,
+            #[cfg(feature = "std")]
+            throughput: self.throughput,
+        }
+    }
+}
+
+// sharing one converter between threads is part of the API
+const _: () = {
+    const fn send_sync<T: Send + Sync>() {}
+    send_sync::<Converter>();
+    send_sync::<ConverterBuilder>();
+    send_sync::<Plan>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::Converter;
+    use config::{ChromaFilter, Quality};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use frame::YuvFrame;
+    use view::RgbView;
+
+    #[test]
+    fn channel_order_is_respected() {
+        let rgb = [10u8, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120];
+        let bgra = [30u8, 20, 10, 0, 60, 50, 40, 0, 90, 80, 70, 0, 120, 110, 100, 0];
+        let from_rgb = Converter::builder().output(YuvFormat::Nv12).build().convert(&rgb, 2, 2).unwrap();
+        let from_bgra = Converter::builder().input(ChannelOrder::Bgra).output(YuvFormat::Nv12).build()
+            .convert(&bgra, 2, 2).unwrap();
+        assert_eq!(from_rgb, from_bgra);
+        assert_eq!(from_rgb.data(), &::convert_rgb_to_yuv420sp_nv12(&rgb, 2, 2, 3)[..]);
+    }
+
+    #[test]
+    fn fast_quality_stays_within_a_code() {
+        // wider than a block of four pixels, and a few left over
+        let rgb: Vec<u8> = (0..7 * 4 * 3).map(|i| (i * 67 % 256) as u8).collect();
+        let average = Converter::builder().chroma_filter(ChromaFilter::Average);
+        let fast = average.clone().quality(Quality::Fast).build().convert(&rgb, 7, 4).unwrap();
+        let point = Converter::builder().build().convert(&rgb, 7, 4).unwrap();
+        for (&fast, &accurate) in fast.data().iter().zip(point.data()) {
+            assert!(fast <= accurate && accurate - fast <= 1, "{} is not within a code of {}", fast, accurate);
+        }
+        assert_ne!(average.build().convert(&rgb, 7, 4).unwrap().data(), point.data());
+    }
+
+    #[test]
+    fn low_layers_average_the_high_ones() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 59 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let (high, low) = Converter::builder().output(YuvFormat::Nv12).build().convert_layers(&src);
+        let y = |x: usize, row: usize| u16::from(high.y()[row * 5 + x]);
+        assert_eq!((low.width(), low.height(), low.y().len(), low.uv().unwrap().len()), (3, 2, 6, 4));
+        assert_eq!(u16::from(low.y()[1]), (y(2, 0) + y(3, 0) + y(2, 1) + y(3, 1) + 2) / 4);
+        assert_eq!(u16::from(low.y()[5]), y(4, 2));
+        let aligned = Converter::builder().output(YuvFormat::Nv12).alignment(16).build().convert_layers(&src).1;
+        assert_eq!(aligned, low);
+    }
+
+    #[test]
+    fn fields_are_subsampled_on_their_own() {
+        let rgb: Vec<u8> = (0..5 * 8 * 3).map(|i| (i * 59 % 256) as u8).collect();
+        let field = |bottom: usize| -> Vec<u8> {
+            rgb.chunks(5 * 3).skip(bottom).step_by(2).flatten().cloned().collect()
+        };
+        let src = RgbView::new(&rgb, 5, 8, ChannelOrder::Rgb).unwrap();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let converter = Converter::builder().output(format).chroma_filter(ChromaFilter::Average).build();
+            let (top, bottom) = converter.convert_fields(&src).unwrap();
+            assert_eq!(top, converter.convert(&field(0), 5, 4).unwrap());
+            assert_eq!(bottom, converter.convert(&field(1), 5, 4).unwrap());
+            let frame = converter.convert_interlaced(&src).unwrap();
+            let rows = |plane: &[u8], stride: usize, field: usize| -> Vec<u8> {
+                plane.chunks(stride).skip(field).step_by(2).flatten().cloned().collect()
+            };
+            assert_eq!(rows(frame.y(), 5, 0), top.y());
+            assert_eq!(rows(frame.y(), 5, 1), bottom.y());
+            let chroma = |frame: &YuvFrame| frame.u().or(frame.uv()).unwrap().to_vec();
+            assert_eq!(rows(&chroma(&frame), frame.chroma_stride(), 0), chroma(&top));
+            assert_eq!(rows(&chroma(&frame), frame.chroma_stride(), 1), chroma(&bottom));
+        }
+        let odd = RgbView::new(&rgb[..5 * 7 * 3], 5, 7, ChannelOrder::Rgb).unwrap();
+        let converter = Converter::builder().build();
+        assert_eq!(converter.convert_fields(&odd), Err(ConversionError::UnsupportedDimensions { width: 5, height: 7 }));
+        assert_eq!(converter.convert_interlaced(&src.crop(0, 0, 5, 6).unwrap()),
+                   Err(ConversionError::UnsupportedDimensions { width: 5, height: 6 }));
+    }
+}
@@ -0,0 +1,235 @@
+//! CUDA backend (`cuda` feature)
+//!
+//! Converts RGB(A) device memory (eg.: a mapped screen capture) to NV12 in device memory
+//! pitched the way NVENC expects for a registered `CUdeviceptr` input resource: the luma
+//! plane is followed directly by the interleaved chroma plane, both using the same pitch.
+//! The frame never passes through system memory unless `Nv12DeviceFrame::to_host` is called.
+//! The CUDA driver and NVRTC libraries are loaded at runtime.
+
+use std::sync::Arc;
+
+use cudarc::driver::{CudaFunction, CudaSlice, CudaStream, DriverError, LaunchConfig, PushKernelArg};
+use cudarc::nvrtc::compile_ptx;
+
+use backend::Convert;
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::YuvFormat;
+use frame::YuvFrame;
+use view::RgbView;
+
+/// Row pitch alignment of the output frames, in bytes
+pub const PITCH_ALIGNMENT: usize = 256;
+
+const BLOCK_SIZE: u32 = 16;
+
+// One thread per 2x2 block, using the same integer math and chroma siting as the CPU kernel.
+const KERNEL_SOURCE: &str = r#"
+__device__ unsigned char clamp_u8(int v) {
+    return (unsigned char)(v < 0 ? 0 : (v > 255 ? 255 : v));
+}
+
+extern "C" __global__ void rgb_to_nv12(const unsigned char* img, unsigned int src_pitch,
+                                       unsigned int bytes_per_pixel, unsigned char* nv12,
+                                       unsigned int pitch, unsigned int width, unsigned int height) {
+    const unsigned int cx = blockIdx.x * blockDim.x + threadIdx.x;
+    const unsigned int cy = blockIdx.y * blockDim.y + threadIdx.y;
+    if (cx * 2 >= width || cy * 2 >= height) {
+        return;
+    }
+    for (unsigned int dy = 0; dy < 2; dy++) {
+        for (unsigned int dx = 0; dx < 2; dx++) {
+            const unsigned int x = cx * 2 + dx;
+            const unsigned int y = cy * 2 + dy;
+            const unsigned char* p = img + y * src_pitch + x * bytes_per_pixel;
+            nv12[y * pitch + x] = clamp_u8((77 * p[0] + 150 * p[1] + 29 * p[2] + 128) >> 8);
+        }
+    }
+    const unsigned char* p = img + (cy * 2) * src_pitch + (cx * 2 + 1) * bytes_per_pixel;
+    const int r = p[0];
+    const int g = p[1];
+    const int b = p[2];
+    unsigned char* uv = nv12 + height * pitch + cy * pitch + cx * 2;
+    uv[0] = clamp_u8(((-43 * r - 84 * g + 127 * b + 128) >> 8) + 128);
+    uv[1] = clamp_u8(((127 * r - 106 * g - 21 * b + 128) >> 8) + 128);
+}
+"#;
+
+/// A compiled RGB to NV12 CUDA kernel bound to a stream
+pub struct CudaConverter {
+    stream: Arc<CudaStream>,
+    function: CudaFunction,
+}
+
+impl CudaConverter {
+    /// Compiles the conversion kernel and loads it into the stream's context
+    ///
+    /// # Errors
+    ///
+    /// `Device` with the compiler or driver error message on failure
+    pub fn new(stream: Arc<CudaStream>) -> Result<CudaConverter, ConversionError> {
+        let ptx = compile_ptx(KERNEL_SOURCE).map_err(|e| ConversionError::Device(e.to_string()))?;
+        let module = stream.context().load_module(ptx)?;
+        let function = module.load_function("rgb_to_nv12")?;
+        Ok(CudaConverter { stream, function })
+    }
+
+    /// Converts an RGB(A) image that already lives in device memory to NV12
+    ///
+    /// # Arguments
+    ///
+    /// * `img` - should contain rows of pixel data in the following format:
+    ///   `[r, g, b, ... , r, g, b, ...]`, `src_pitch` bytes apart
+    ///
+    /// * `bytes_per_pixel` - should contain the number of bytes used by one pixel
+    ///   (eg.: RGB is 3 bytes and RGBA is 4 bytes)
+    ///
+    /// * `width` and `height` - must be even
+    ///
+    /// # Return
+    ///
+    /// The frame in device memory. The conversion is queued on the converter's stream.
+    pub fn convert(&self, img: &CudaSlice<u8>, src_pitch: usize, bytes_per_pixel: usize,
+                   width: u32, height: u32) -> Result<Nv12DeviceFrame, ConversionError> {
+        if !width.is_multiple_of(2) || !height.is_multiple_of(2) || width == 0 || height == 0 {
+            return Err(ConversionError::UnsupportedDimensions { width, height });
+        }
+        if bytes_per_pixel < 3 {
+            return Err(ConversionError::UnsupportedFormat("bytes_per_pixel must be at least 3"));
+        }
+        let row_bytes = width as usize * bytes_per_pixel;
+        if src_pitch < row_bytes {
+            return Err(ConversionError::StrideMismatch { stride: src_pitch, row_bytes });
+        }
+        let expected = src_pitch * (height as usize - 1) + row_bytes;
+        if img.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: img.len() });
+        }
+        let pitch = (width as usize).div_ceil(PITCH_ALIGNMENT) * PITCH_ALIGNMENT;
+        let mut data = self.stream.alloc_zeros::<u8>(pitch * height as usize * 3 / 2)?;
+        let (src_pitch, bytes_per_pixel, pitch_arg) = (src_pitch as u32, bytes_per_pixel as u32, pitch as u32);
+        let cfg = LaunchConfig {
+            grid_dim: ((width / 2).div_ceil(BLOCK_SIZE), (height / 2).div_ceil(BLOCK_SIZE), 1),
+            block_dim: (BLOCK_SIZE, BLOCK_SIZE, 1),
+            shared_mem_bytes: 0,
+        };
+        let mut builder = self.stream.launch_builder(&self.function);
+        builder.arg(img).arg(&src_pitch).arg(&bytes_per_pixel).arg(&mut data)
+            .arg(&pitch_arg).arg(&width).arg(&height);
+        unsafe { builder.launch(cfg) }?;
+        Ok(Nv12DeviceFrame { data, pitch, width, height })
+    }
+
+    /// Uploads a tightly packed RGB(A) image and converts it to NV12
+    ///
+    /// See `convert` for the arguments.
+    pub fn upload_and_convert(&self, img: &[u8], bytes_per_pixel: usize, width: u32, height: u32)
+                              -> Result<Nv12DeviceFrame, ConversionError> {
+        let row = width as usize * bytes_per_pixel;
+        let expected = row * height as usize;
+        if img.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: img.len() });
+        }
+        let src = self.stream.clone_htod(&img[..expected])?;
+        self.convert(&src, row, bytes_per_pixel, width, height)
+    }
+}
+
+/// Uploads RGB, RGBA and RGBX images of 8 bit channels with even widths and heights and
+/// converts them into full swing BT.601 NV12 frames, copied back into system memory
+impl Convert for CudaConverter {
+    fn name(&self) -> &'static str {
+        "cuda"
+    }
+
+    fn convert_frame(&self, src: &RgbView, format: YuvFormat) -> Result<YuvFrame, ConversionError> {
+        let layout = src.channel_order().layout();
+        if format != YuvFormat::Nv12 || src.bit_depth() != 8 || (layout.r, layout.g, layout.b) != (0, 1, 2) {
+            return Err(ConversionError::UnsupportedFormat("the CUDA backend converts RGB, RGBA and RGBX into NV12"));
+        }
+        let (width, height) = (src.width(), src.height());
+        let row_bytes = width as usize * layout.bytes_per_pixel;
+        let img = if height == 0 { &[][..] } else { &src.data()[..src.stride() * (height as usize - 1) + row_bytes] };
+        let frame = self.convert(&self.stream.clone_htod(img)?, src.stride(), layout.bytes_per_pixel, width, height)?;
+        let cfg = ConversionConfig { matrix: Matrix::Bt601, range: Range::Full, ..ConversionConfig::default() };
+        Ok(YuvFrame::from_packed(frame.to_host(&self.stream)?, format, width, height, &cfg))
+    }
+}
+
+/// A pitched NV12 frame in device memory
+pub struct Nv12DeviceFrame {
+    data: CudaSlice<u8>,
+    pitch: usize,
+    width: u32,
+    height: u32,
+}
+
+impl Nv12DeviceFrame {
+    /// The device buffer, `pitch * height * 3 / 2` bytes long
+    pub fn data(&self) -> &CudaSlice<u8> {
+        &self.data
+    }
+
+    /// Row pitch of both planes in bytes, a multiple of `PITCH_ALIGNMENT`
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Byte offset of the interleaved chroma plane from the start of the buffer
+    pub fn chroma_offset(&self) -> usize {
+        self.pitch * self.height as usize
+    }
+
+    /// Frame width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Frame height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Copies the frame into system memory with the padding removed
+    ///
+    /// # Return
+    ///
+    /// `[y, y, y, ... , u, v, u, v, ...]`
+    pub fn to_host(&self, stream: &Arc<CudaStream>) -> Result<Vec<u8>, ConversionError> {
+        let pitched = stream.clone_dtoh(&self.data)?;
+        let width = self.width as usize;
+        let rows = self.height as usize * 3 / 2;
+        let mut yuv = Vec::with_capacity(width * rows);
+        for row in pitched.chunks(self.pitch).take(rows) {
+            yuv.extend_from_slice(&row[..width]);
+        }
+        Ok(yuv)
+    }
+}
+
+impl From<DriverError> for ConversionError {
+    fn from(err: DriverError) -> ConversionError {
+        ConversionError::Device(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cudarc::driver::CudaContext;
+
+    use super::CudaConverter;
+
+    #[test]
+    fn matches_cpu_kernel() {
+        let context = match ::std::panic::catch_unwind(|| CudaContext::new(0)) {
+            Ok(Ok(context)) => context,
+            _ => return,
+        };
+        let stream = context.default_stream();
+        let converter = CudaConverter::new(stream.clone()).unwrap();
+        let rgba: Vec<u8> = (0..8 * 4 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let frame = converter.upload_and_convert(&rgba, 4, 8, 4).unwrap();
+        assert_eq!(frame.pitch(), super::PITCH_ALIGNMENT);
+        assert_eq!(frame.to_host(&stream).unwrap(), ::convert_rgb_to_yuv420sp_nv12(&rgba, 8, 4, 4));
+    }
+}
@@ -0,0 +1,309 @@
+//! A compatibility layer with the `ImageFormat` and `convert_image` API of
+//! dcv-color-primitives, so that code written against it can switch crates by changing its
+//! imports
+//!
+//! Images are converted between the RGB formats and I420 or NV12, in either direction, with
+//! the kernels of the crate. The 4:4:4 and 4:2:2 formats aren't converted, and errors are
+//! `ConversionError`s.
+//!
+//! # Examples
+//!
+//! ```
+//! use rgb2yuv420::dcp::{convert_image, get_buffers_size, ColorSpace, ImageFormat, PixelFormat};
+//!
+//! let (width, height) = (4, 2);
+//! let src_format = ImageFormat { pixel_format: PixelFormat::Bgra, color_space: ColorSpace::Rgb,
+//!                                 num_planes: 1 };
+//! let dst_format = ImageFormat { pixel_format: PixelFormat::Nv12, color_space: ColorSpace::Bt709,
+//!                                 num_planes: 2 };
+//! let mut sizes = [0; 2];
+//! get_buffers_size(width, height, &dst_format, None, &mut sizes).unwrap();
+//! assert_eq!(sizes, [8, 4]);
+//! let bgra = [255u8; 4 * 2 * 4];
+//! let (mut y, mut uv) = (vec![0; sizes[0]], vec![0; sizes[1]]);
+//! convert_image(width, height, &src_format, None, &[&bgra], &dst_format, None, &mut [&mut y, &mut uv])
+//!     .unwrap();
+//! assert_eq!((y[0], uv[0]), (235, 128));
+//! ```
+
+use alloc::vec::Vec;
+
+use config::{Colorimetry, ConversionConfig};
+use error::ConversionError;
+use format::ChannelOrder;
+use inverse::{self, YuvSource};
+use kernel;
+use planes::{self, check_plane, FrameMut};
+use view::RgbView;
+
+/// A stride that asks for the rows to be tightly packed
+pub const STRIDE_AUTO: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Argb,
+    Bgra,
+    Bgr,
+    Rgba,
+    Rgb,
+    I444,
+    I422,
+    I420,
+    Nv12,
+}
+
+impl PixelFormat {
+    fn channel_order(self) -> Option<ChannelOrder> {
+        match self {
+            PixelFormat::Argb => Some(ChannelOrder::Argb),
+            PixelFormat::Bgra => Some(ChannelOrder::Bgra),
+            PixelFormat::Bgr => Some(ChannelOrder::Bgr),
+            PixelFormat::Rgba => Some(ChannelOrder::Rgba),
+            PixelFormat::Rgb => Some(ChannelOrder::Rgb),
+            _ => None,
+        }
+    }
+}
+
+/// The colorimetry of an image: `Rgb` for the RGB formats, and a matrix in limited range, or
+/// in full range for the `FR` ones, for the YUV formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Rgb,
+    Bt601,
+    Bt709,
+    Bt601FR,
+    Bt709FR,
+}
+
+impl ColorSpace {
+    fn config(self) -> Result<ConversionConfig, ConversionError> {
+        let colorimetry = match self {
+            ColorSpace::Rgb => return Err(ConversionError::UnsupportedFormat("YUV images need a YUV color space")),
+            ColorSpace::Bt601 => Colorimetry::Bt601Limited,
+            ColorSpace::Bt709 => Colorimetry::Bt709Limited,
+            ColorSpace::Bt601FR => Colorimetry::Bt601FullJfif,
+            ColorSpace::Bt709FR => Colorimetry::Bt709Full,
+        };
+        Ok(ConversionConfig::from(colorimetry))
+    }
+}
+
+/// The format of the buffers of an image
+///
+/// `num_planes` is 1 for the RGB formats. I420 comes in 3 planes and NV12 in 2, or both in 1
+/// plane holding them one after the other; the chroma rows are then pitched at half the luma
+/// stride (rounded up) for I420 and at the luma stride for NV12.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageFormat {
+    pub pixel_format: PixelFormat,
+    pub color_space: ColorSpace,
+    pub num_planes: u32,
+}
+
+/// The bytes of a row, the number of rows and the stride of a plane
+#[derive(Debug, Clone, Copy)]
+struct PlaneLayout {
+    row_bytes: usize,
+    rows: usize,
+    stride: usize,
+}
+
+impl PlaneLayout {
+    fn size(&self) -> usize {
+        self.stride * self.rows
+    }
+}
+
+// the planes of an image, whether or not they share a buffer
+fn plane_layouts(width: u32, height: u32, format: &ImageFormat, strides: Option<&[usize]>)
+                 -> Result<Vec<PlaneLayout>, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let planes = match format.pixel_format {
+        PixelFormat::Rgb | PixelFormat::Bgr => vec![(3 * width, height)],
+        PixelFormat::Argb | PixelFormat::Bgra | PixelFormat::Rgba => vec![(4 * width, height)],
+        PixelFormat::I420 => vec![(width, height), (chroma_width, chroma_height), (chroma_width, chroma_height)],
+        PixelFormat::Nv12 => vec![(width, height), (2 * chroma_width, chroma_height)],
+        PixelFormat::I444 | PixelFormat::I422 => {
+            return Err(ConversionError::UnsupportedFormat("only 4:2:0 YUV images are converted"));
+        }
+    };
+    let shared = match format.num_planes as usize {
+        1 => true,
+        count if count == planes.len() => false,
+        _ => return Err(ConversionError::UnsupportedFormat("wrong number of planes for the format")),
+    };
+    let stride = |plane: usize| strides.and_then(|strides| strides.get(plane)).copied()
+        .filter(|&stride| stride != STRIDE_AUTO);
+    let luma_stride = stride(0);
+    Ok(planes.iter().enumerate().map(|(plane, &(row_bytes, rows))| {
+        let stride = match (shared, luma_stride) {
+            (false, _) => stride(plane),
+            (true, Some(luma)) if plane > 0 && format.pixel_format == PixelFormat::I420 => Some(luma.div_ceil(2)),
+            (true, luma) => luma,
+        };
+        PlaneLayout { row_bytes, rows, stride: stride.unwrap_or(row_bytes) }
+    }).collect())
+}
+
+/// Computes the size of the buffers of an image into `buffers_size`, one per plane
+///
+/// # Errors
+///
+/// `UnsupportedFormat` if the format isn't converted or `buffers_size` has fewer entries than
+/// `format.num_planes`
+pub fn get_buffers_size(width: u32, height: u32, format: &ImageFormat, strides: Option<&[usize]>,
+                        buffers_size: &mut [usize]) -> Result<(), ConversionError> {
+    let planes = plane_layouts(width, height, format, strides)?;
+    if buffers_size.len() < format.num_planes as usize {
+        return Err(ConversionError::UnsupportedFormat("buffers_size needs an entry per plane"));
+    }
+    if format.num_planes == 1 {
+        buffers_size[0] = planes.iter().map(PlaneLayout::size).sum();
+    } else {
+        for (size, plane) in buffers_size.iter_mut().zip(&planes) {
+            *size = plane.size();
+        }
+    }
+    Ok(())
+}
+
+// the buffer of each plane, split out of the first buffer if they share it; the last plane
+// gets the rest of it
+fn split<'a>(buffers: &[&'a [u8]], planes: &[PlaneLayout], shared: bool) -> Result<Vec<&'a [u8]>, ConversionError> {
+    if !shared {
+        return match buffers.get(..planes.len()) {
+            Some(buffers) => Ok(buffers.to_vec()),
+            None => Err(ConversionError::UnsupportedFormat("a buffer per plane is needed")),
+        };
+    }
+    let mut rest = *buffers.first().ok_or(ConversionError::UnsupportedFormat("a buffer per plane is needed"))?;
+    let mut split = Vec::with_capacity(planes.len());
+    for plane in &planes[..planes.len() - 1] {
+        let (data, tail) = rest.split_at(plane.size().min(rest.len()));
+        split.push(data);
+        rest = tail;
+    }
+    split.push(rest);
+    Ok(split)
+}
+
+fn split_mut<'a>(buffers: &'a mut [&mut [u8]], planes: &[PlaneLayout], shared: bool)
+                 -> Result<Vec<&'a mut [u8]>, ConversionError> {
+    if !shared {
+        return match buffers.get_mut(..planes.len()) {
+            Some(buffers) => Ok(buffers.iter_mut().map(|buffer| &mut **buffer).collect()),
+            None => Err(ConversionError::UnsupportedFormat("a buffer per plane is needed")),
+        };
+    }
+    let first = buffers.first_mut().ok_or(ConversionError::UnsupportedFormat("a buffer per plane is needed"))?;
+    let mut rest = &mut **first;
+    let mut split = Vec::with_capacity(planes.len());
+    for plane in &planes[..planes.len() - 1] {
+        let at = plane.size().min(rest.len());
+        let (data, tail) = rest.split_at_mut(at);
+        split.push(data);
+        rest = tail;
+    }
+    split.push(rest);
+    Ok(split)
+}
+
+/// Converts an image between an RGB format and I420 or NV12
+///
+/// # Arguments
+///
+/// * `src_strides` and `dst_strides` - the stride of each plane, `None` or `STRIDE_AUTO` for
+///   tightly packed rows
+///
+/// * `src_buffers` and `dst_buffers` - a buffer per plane, or one holding every plane when
+///   the format has a single plane
+///
+/// The YUV image is in the matrix and range of its color space, and the RGB image in the
+/// `Rgb` one.
+///
+/// # Errors
+///
+/// `UnsupportedFormat` if the formats, color spaces or numbers of planes don't go together,
+/// and `StrideMismatch` or `BufferTooSmall` if a plane can't hold its rows
+#[allow(clippy::too_many_arguments)]
+pub fn convert_image(width: u32, height: u32, src_format: &ImageFormat, src_strides: Option<&[usize]>,
+                     src_buffers: &[&[u8]], dst_format: &ImageFormat, dst_strides: Option<&[usize]>,
+                     dst_buffers: &mut [&mut [u8]]) -> Result<(), ConversionError> {
+    let src_planes = plane_layouts(width, height, src_format, src_strides)?;
+    let dst_planes = plane_layouts(width, height, dst_format, dst_strides)?;
+    let src = split(src_buffers, &src_planes, src_format.num_planes == 1)?;
+    let mut dst = split_mut(dst_buffers, &dst_planes, dst_format.num_planes == 1)?;
+    match (src_format.pixel_format.channel_order(), dst_format.pixel_format.channel_order()) {
+        (Some(order), None) if src_format.color_space == ColorSpace::Rgb => {
+            let cfg = dst_format.color_space.config()?;
+            let src = RgbView::with_layout(src[0], width, height, src_planes[0].stride, order, 8)?;
+            let mut frame = match dst[..] {
+                [ref mut y, ref mut u, ref mut v] => {
+                    FrameMut::i420(width, height, y, dst_planes[0].stride, u, v, dst_planes[1].stride)?
+                }
+                [ref mut y, ref mut uv] => {
+                    FrameMut::nv12(width, height, y, dst_planes[0].stride, uv, dst_planes[1].stride)?
+                }
+                _ => unreachable!("YUV images have 2 or 3 planes"),
+            };
+            planes::convert_into_frame(&src, &cfg, &mut frame)
+        }
+        (None, Some(order)) if dst_format.color_space == ColorSpace::Rgb => {
+            let cfg = src_format.color_space.config()?;
+            let (y_stride, chroma_stride) = (src_planes[0].stride, src_planes[1].stride);
+            let yuv = match src[..] {
+                [y, u, v] => YuvSource::i420(y, y_stride, u, v, chroma_stride, width, height)?,
+                [y, uv] => YuvSource::nv12(y, y_stride, uv, chroma_stride, width, height)?,
+                _ => unreachable!("YUV images have 2 or 3 planes"),
+            };
+            let PlaneLayout { row_bytes, rows, stride } = dst_planes[0];
+            check_plane(dst[0].len(), stride, row_bytes, rows)?;
+            inverse::to_rgb(&yuv, &cfg.inverse_coefficients(), cfg.chroma_upsampling, order.layout(), dst[0], stride);
+            Ok(())
+        }
+        _ => Err(ConversionError::UnsupportedFormat("only RGB images and YUV images are converted into each other")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_image, get_buffers_size, ColorSpace, ImageFormat, PixelFormat, STRIDE_AUTO};
+    use config::{ConversionConfig, Matrix, Range};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn images_convert_like_the_crate_does() {
+        let (width, height) = (5, 3);
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let rgb_format = ImageFormat { pixel_format: PixelFormat::Rgb, color_space: ColorSpace::Rgb, num_planes: 1 };
+        let i420 = ImageFormat { pixel_format: PixelFormat::I420, color_space: ColorSpace::Bt709, num_planes: 3 };
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let expected = ::convert(&RgbView::new(&rgb, width, height, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        // padded planes
+        let strides = [8, 4, 4];
+        let mut sizes = [0; 3];
+        get_buffers_size(width, height, &i420, Some(&strides), &mut sizes).unwrap();
+        assert_eq!(sizes, [24, 8, 8]);
+        let (mut y, mut u, mut v) = (vec![0; 24], vec![0; 8], vec![0; 8]);
+        convert_image(width, height, &rgb_format, None, &[&rgb], &i420, Some(&strides), &mut [&mut y, &mut u, &mut v])
+            .unwrap();
+        assert_eq!(y.chunks(8).flat_map(|row| &row[..5]).copied().collect::<Vec<u8>>(), expected.y());
+        assert_eq!(u.chunks(4).flat_map(|row| &row[..3]).copied().collect::<Vec<u8>>(), expected.u().unwrap());
+        // all planes in one buffer, and back
+        let single = ImageFormat { num_planes: 1, ..i420 };
+        let mut packed = vec![0; expected.data().len()];
+        convert_image(width, height, &rgb_format, None, &[&rgb], &single, Some(&[STRIDE_AUTO]), &mut [&mut packed])
+            .unwrap();
+        assert_eq!(packed, expected.data());
+        let bgra = ImageFormat { pixel_format: PixelFormat::Bgra, ..rgb_format };
+        let mut back = vec![0; 5 * 3 * 4];
+        convert_image(width, height, &single, None, &[&packed], &bgra, None, &mut [&mut back]).unwrap();
+        assert_eq!(back, expected.to_rgb(ChannelOrder::Bgra, cfg.chroma_upsampling));
+        let error = convert_image(width, height, &rgb_format, None, &[&rgb], &bgra, None, &mut [&mut back]);
+        assert!(matches!(error, Err(ConversionError::UnsupportedFormat(_))));
+    }
+}
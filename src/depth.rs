@@ -0,0 +1,127 @@
+//! Conversions from 10 bit YUV420 down to 8 bits, eg.: for previews of HDR streams or for
+//! encoders that only take 8 bit input
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{Yuv10Format, YuvFormat};
+use frame::YuvFrame;
+use kernel;
+use layout;
+
+/// Thresholds of a 2x2 ordered dither for the 2 bits that are dropped, which keeps gradients
+/// from banding without the noise of a random dither
+const BAYER: [[u16; 2]; 2] = [[0, 2], [3, 1]];
+
+/// Converts a tightly packed 10 bit frame into a tightly packed 8 bit frame in `output`,
+/// dropping the 2 lowest bits of every sample
+///
+/// Without `dither` the samples are rounded to the nearest 8 bit code; with it they follow a
+/// 2x2 ordered dither pattern that averages to the 10 bit value. The matrix and range of
+/// `cfg` are only recorded in the frame; limited range stays limited range.
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `yuv` is shorter than twice `required_buffer_size`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, Yuv10Format, YuvFormat};
+///
+/// // a 2x2 P010 frame with luma of 514 (128.5 in 8 bits) and neutral chroma
+/// let p010: Vec<u8> = [514u16, 514, 514, 514, 512, 512].iter().flat_map(|&s| (s << 6).to_le_bytes()).collect();
+/// let cfg = ConversionConfig::default();
+/// let rounded = rgb2yuv420::convert_10bit_to_8bit(&p010, Yuv10Format::P010, 2, 2, YuvFormat::Nv12, false, &cfg)
+///     .unwrap();
+/// assert_eq!(rounded.y(), &[129, 129, 129, 129]);
+/// let dithered = rgb2yuv420::convert_10bit_to_8bit(&p010, Yuv10Format::P010, 2, 2, YuvFormat::Nv12, true, &cfg)
+///     .unwrap();
+/// assert_eq!(dithered.y(), &[128, 129, 129, 128]);
+/// assert_eq!(dithered.uv().unwrap(), &[128, 128]);
+/// ```
+pub fn convert_10bit_to_8bit(yuv: &[u8], format: Yuv10Format, width: u32, height: u32, output: YuvFormat,
+                             dither: bool, cfg: &ConversionConfig) -> Result<YuvFrame, ConversionError> {
+    let expected = 2 * layout::required_buffer_size(output, width, height);
+    if yuv.len() < expected {
+        return Err(ConversionError::BufferTooSmall { expected, got: yuv.len() });
+    }
+    let (width, height) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let sample = |i: usize| {
+        let word = u16::from_le_bytes([yuv[2 * i], yuv[2 * i + 1]]);
+        match format {
+            Yuv10Format::P010 => word >> 6,
+            Yuv10Format::I010 => word & 0x3ff,
+        }
+    };
+    let reduce = |value: u16, x: usize, row: usize| {
+        let offset = if dither { BAYER[row % 2][x % 2] } else { 2 };
+        ((value + offset) >> 2).min(255) as u8
+    };
+    // the indices of the U and V samples of chroma row `row`
+    let luma_size = width * height;
+    let chroma = |row: usize, x: usize| match format {
+        Yuv10Format::P010 => (luma_size + 2 * (row * chroma_width + x), luma_size + 2 * (row * chroma_width + x) + 1),
+        Yuv10Format::I010 => (luma_size + row * chroma_width + x,
+                              luma_size + (chroma_height + row) * chroma_width + x),
+    };
+    let mut data = vec![0; expected / 2];
+    for (i, y) in data[..luma_size].iter_mut().enumerate() {
+        *y = reduce(sample(i), i % width, i / width);
+    }
+    for row in 0..chroma_height {
+        for x in 0..chroma_width {
+            let (u, v) = chroma(row, x);
+            let (u, v) = (reduce(sample(u), x, row), reduce(sample(v), x, row));
+            match output {
+                YuvFormat::I420 => {
+                    data[luma_size + row * chroma_width + x] = u;
+                    data[luma_size + (chroma_height + row) * chroma_width + x] = v;
+                }
+                YuvFormat::Nv12 => {
+                    data[luma_size + 2 * (row * chroma_width + x)] = u;
+                    data[luma_size + 2 * (row * chroma_width + x) + 1] = v;
+                }
+            }
+        }
+    }
+    Ok(YuvFrame::from_packed(data, output, width as u32, height as u32, cfg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::convert_10bit_to_8bit;
+    use config::{ConversionConfig, Range};
+    use error::ConversionError;
+    use format::{Yuv10Format, YuvFormat};
+
+    #[test]
+    fn dithering_keeps_the_average_of_the_dropped_bits() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        // 4x4 I010 with 2x2 luma blocks of 64 to 67 (16 to 16.75 in 8 bits), U of 940 and V of 1023
+        let mut samples: Vec<u16> = (0..16).map(|i| 64 + i % 4 / 2 + i / 8 * 2).collect();
+        samples.extend_from_slice(&[940; 4]);
+        samples.extend_from_slice(&[1023; 4]);
+        let i010: Vec<u8> = samples.iter().flat_map(|&s| s.to_le_bytes()).collect();
+        let p010: Vec<u8> = samples[..16].iter().cloned()
+            .chain(samples[16..20].iter().zip(&samples[20..]).flat_map(|(&u, &v)| [u, v]))
+            .flat_map(|s| (s << 6 | 0x3f).to_le_bytes()).collect();
+        for &dither in &[false, true] {
+            let i420 = convert_10bit_to_8bit(&i010, Yuv10Format::I010, 4, 4, YuvFormat::I420, dither, &cfg).unwrap();
+            let nv12 = convert_10bit_to_8bit(&p010, Yuv10Format::P010, 4, 4, YuvFormat::Nv12, dither, &cfg).unwrap();
+            assert_eq!(nv12.y(), i420.y());
+            assert_eq!(nv12.uv().unwrap(), &[235, 255, 235, 255, 235, 255, 235, 255]);
+            assert_eq!((i420.u().unwrap(), i420.v().unwrap()), (&[235; 4][..], &[255; 4][..]));
+            assert_eq!(i420.range(), Range::Limited);
+        }
+        let dithered = convert_10bit_to_8bit(&i010, Yuv10Format::I010, 4, 4, YuvFormat::I420, true, &cfg).unwrap();
+        // every block averages to its 10 bit value
+        let y = dithered.y();
+        let block = |i: usize| [i, i + 1, i + 4, i + 5].iter().map(|&i| u32::from(y[i])).sum();
+        let sums: Vec<u32> = [0, 2, 8, 10].iter().map(|&i| block(i)).collect();
+        assert_eq!(sums, vec![64, 65, 66, 67]);
+        assert_eq!(convert_10bit_to_8bit(&i010[..47], Yuv10Format::I010, 4, 4, YuvFormat::I420, false, &cfg)
+                       .map(|_| ()),
+                   Err(ConversionError::BufferTooSmall { expected: 48, got: 47 }));
+    }
+}
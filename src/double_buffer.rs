@@ -0,0 +1,159 @@
+//! Two preallocated frames handed back and forth between a converting and an encoding thread
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use frame::YuvFrame;
+
+#[derive(Debug)]
+struct State {
+    // frames nobody holds, to be converted into
+    free: Vec<YuvFrame>,
+    // the converted frame the consumer hasn't taken yet
+    ready: Option<YuvFrame>,
+    closed: bool,
+}
+
+/// Two frames alternating between a producer that converts into one of them and a consumer
+/// that encodes the other, so both run at once without allocating
+///
+/// Frames are handed over by value: the producer takes a free frame with `writable`, converts
+/// into it and hands it over with `publish`; the consumer takes it with `readable` and gives it
+/// back with `release` once it is encoded. Whoever holds a frame is the only one that can touch
+/// it, so the threads never share samples.
+///
+/// Publishing while the previous frame is still unread replaces it, as it went stale, and gives
+/// it back to the producer.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use std::thread;
+///
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, DoubleBuffer, RgbView, YuvFormat, YuvFrame};
+///
+/// let cfg = ConversionConfig::default();
+/// let frame = YuvFrame::filled([0, 0, 0], YuvFormat::Nv12, 4, 2, &cfg);
+/// let buffers = Arc::new(DoubleBuffer::new(frame.clone(), frame));
+/// let producer = Arc::clone(&buffers);
+/// thread::spawn(move || {
+///     for i in 0..8u8 {
+///         let rgb = [i; 4 * 2 * 3];
+///         let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+///         let mut frame = producer.writable().unwrap();
+///         rgb2yuv420::convert_into_frame(&src, &cfg, &mut frame.as_frame_mut()).unwrap();
+///         producer.publish(frame);
+///     }
+///     producer.close();
+/// });
+/// let mut last = 0;
+/// while let Some(frame) = buffers.readable() {
+///     // frames come in order, though stale ones may have been skipped
+///     assert!(frame.y()[0] >= last);
+///     last = frame.y()[0];
+///     buffers.release(frame);
+/// }
+/// assert_eq!(last, 7);
+/// ```
+#[derive(Debug)]
+pub struct DoubleBuffer {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+impl DoubleBuffer {
+    /// Double buffering between `front` and `back`, which are usually the same size and format
+    pub fn new(front: YuvFrame, back: YuvFrame) -> DoubleBuffer {
+        DoubleBuffer {
+            state: Mutex::new(State { free: vec![front, back], ready: None, closed: false }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // the state stays consistent whichever thread panicked
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Takes a frame to convert into, blocking while neither is free; `None` once the buffer is
+    /// closed
+    ///
+    /// The frame still holds the samples and `FrameTiming` of an earlier conversion.
+    pub fn writable(&self) -> Option<YuvFrame> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(frame) = state.free.pop() {
+                return Some(frame);
+            }
+            state = self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Hands a converted frame over to the consumer
+    pub fn publish(&self, frame: YuvFrame) {
+        let mut state = self.lock();
+        if let Some(stale) = state.ready.replace(frame) {
+            state.free.push(stale);
+        }
+        self.changed.notify_all();
+    }
+
+    /// Takes the latest converted frame, blocking until there is one; `None` once the buffer is
+    /// closed
+    pub fn readable(&self) -> Option<YuvFrame> {
+        let mut state = self.lock();
+        loop {
+            // frames published before closing are still read
+            if let Some(frame) = state.ready.take() {
+                return Some(frame);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Gives a frame taken with `readable` back to the producer
+    pub fn release(&self, frame: YuvFrame) {
+        self.lock().free.push(frame);
+        self.changed.notify_all();
+    }
+
+    /// Wakes the threads blocked on the buffer and makes `writable` return `None` from now on,
+    /// and `readable` once the last published frame is read
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DoubleBuffer;
+    use config::ConversionConfig;
+    use format::YuvFormat;
+    use frame::YuvFrame;
+
+    #[test]
+    fn frames_change_hands_without_copies() {
+        let cfg = ConversionConfig::default();
+        let frame = YuvFrame::filled([0, 0, 0], YuvFormat::I420, 2, 2, &cfg);
+        let buffers = DoubleBuffer::new(frame.clone(), frame);
+        let first = buffers.writable().unwrap();
+        let data = first.data().as_ptr();
+        buffers.publish(first);
+        let second = buffers.writable().unwrap();
+        // the unread first frame is stale once the second comes in, and free again
+        buffers.publish(second);
+        assert_eq!(buffers.writable().map(|frame| frame.data().as_ptr()), Some(data));
+        let read = buffers.readable().unwrap();
+        assert_ne!(read.data().as_ptr(), data);
+        buffers.release(read);
+        buffers.close();
+        assert!(buffers.writable().is_none() && buffers.readable().is_none());
+    }
+}
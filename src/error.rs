@@ -0,0 +1,43 @@
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+use core::error::Error;
+use core::fmt;
+
+/// Why a conversion couldn't be performed
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConversionError {
+    /// A buffer is shorter than its dimensions require (sizes in bytes)
+    BufferTooSmall { expected: usize, got: usize },
+    /// The frame size is not supported (eg.: odd dimensions on a GPU backend)
+    UnsupportedDimensions { width: u32, height: u32 },
+    /// The pixel layout or bit depth is not supported
+    UnsupportedFormat(&'static str),
+    /// The row stride is smaller than a row of pixels (sizes in bytes)
+    StrideMismatch { stride: usize, row_bytes: usize },
+    /// A region of interest reaches outside of the image
+    InvalidRegion { x: u32, y: u32, width: u32, height: u32 },
+    /// A GPU backend failed to build or run its kernel
+    #[cfg(feature = "alloc")]
+    Device(String),
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConversionError::BufferTooSmall { expected, got } =>
+                write!(f, "buffer is too small for the given dimensions ({} bytes needed, got {})", expected, got),
+            ConversionError::UnsupportedDimensions { width, height } =>
+                write!(f, "unsupported frame size {}x{}", width, height),
+            ConversionError::UnsupportedFormat(reason) => write!(f, "unsupported format: {}", reason),
+            ConversionError::StrideMismatch { stride, row_bytes } =>
+                write!(f, "stride of {} bytes is smaller than a row of {} bytes", stride, row_bytes),
+            ConversionError::InvalidRegion { x, y, width, height } =>
+                write!(f, "region {}x{} at ({}, {}) reaches outside of the image", width, height, x, y),
+            #[cfg(feature = "alloc")]
+            ConversionError::Device(ref msg) => write!(f, "device error: {}", msg),
+        }
+    }
+}
+
+impl Error for ConversionError {}
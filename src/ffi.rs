@@ -0,0 +1,222 @@
+//! Plane pointers and strides in the layouts of C encoders (libvpx, x264, x265) and of
+//! mapped GPU textures, to hand frames over to them without staging copies
+
+use core::convert::TryFrom;
+use core::ptr;
+
+use error::ConversionError;
+use kernel::ChromaPlanes;
+use planes::FrameMut;
+
+/// `VPX_IMG_FMT_I420` of `vpx_img_fmt_t`
+pub const VPX_IMG_FMT_I420: u32 = 0x102;
+/// `VPX_IMG_FMT_NV12` of `vpx_img_fmt_t`
+pub const VPX_IMG_FMT_NV12: u32 = 0x109;
+
+/// The fields of a `vpx_image_t` describing the planes of a frame, for libvpx bindings
+/// such as vpx-sys
+///
+/// libvpx indexes `planes` and `stride` by `VPX_PLANE_Y`, `VPX_PLANE_U`, `VPX_PLANE_V` and
+/// `VPX_PLANE_ALPHA`. For NV12, U points at the interleaved plane and V at its second byte,
+/// both with its stride; there is never an alpha plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VpxPlanes {
+    /// `VPX_IMG_FMT_I420` or `VPX_IMG_FMT_NV12`
+    pub fmt: u32,
+    /// The displayed width and height
+    pub d_w: u32,
+    pub d_h: u32,
+    pub planes: [*mut u8; 4],
+    pub stride: [i32; 4],
+}
+
+/// `X264_CSP_I420` of x264's colorspaces
+pub const X264_CSP_I420: i32 = 0x0002;
+/// `X264_CSP_NV12` of x264's colorspaces
+pub const X264_CSP_NV12: i32 = 0x0004;
+
+/// The fields of the `img` of an `x264_picture_t` describing the planes of a frame, for
+/// x264 bindings
+///
+/// NV12 frames have 2 planes, Y and the interleaved UV, and I420 ones 3. The first three
+/// `plane` and `i_stride` of an I420 frame are also the `planes` and `stride` of an
+/// `x265_picture` with `X265_CSP_I420`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct X264Planes {
+    /// `X264_CSP_I420` or `X264_CSP_NV12`
+    pub i_csp: i32,
+    /// Number of planes
+    pub i_plane: i32,
+    pub i_stride: [i32; 4],
+    pub plane: [*mut u8; 4],
+}
+
+// a stride as C encoders take it
+fn c_stride(stride: usize) -> i32 {
+    i32::try_from(stride).expect("strides fit in an int")
+}
+
+impl<'a> FrameMut<'a> {
+    /// An NV12 frame in the memory of a mapped `DXGI_FORMAT_NV12` D3D11 texture, eg.: a
+    /// staging texture mapped with `D3D11_MAP_WRITE` before it is copied to the encoder's
+    ///
+    /// Both planes of the texture are in the one subresource: `data` starts at its `pData`
+    /// and the rows of the Y and UV planes are `row_pitch` (its `RowPitch`) bytes apart, with
+    /// UV following the `texture_height` rows of Y (which may be more than the frame's).
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if the frame is taller than the texture, `StrideMismatch` if
+    /// `row_pitch` is smaller than a row and `BufferTooSmall` if `data` can't hold the planes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::FrameMut;
+    ///
+    /// // a 4x4 texture with rows of 64 bytes, holding a 4x3 frame
+    /// let mut mapped = [0; 64 * 6];
+    /// let frame = FrameMut::d3d11_nv12(4, 3, &mut mapped, 64, 4).unwrap();
+    /// assert_eq!((frame.width(), frame.height()), (4, 3));
+    /// ```
+    pub fn d3d11_nv12(width: u32, height: u32, data: &'a mut [u8], row_pitch: usize, texture_height: u32)
+                      -> Result<FrameMut<'a>, ConversionError> {
+        if height > texture_height {
+            return Err(ConversionError::UnsupportedDimensions { width, height });
+        }
+        let uv_offset = row_pitch * texture_height as usize;
+        if data.len() < uv_offset {
+            return Err(ConversionError::BufferTooSmall { expected: uv_offset, got: data.len() });
+        }
+        let (y, uv) = data.split_at_mut(uv_offset);
+        FrameMut::nv12(width, height, y, row_pitch, uv, row_pitch)
+    }
+
+    /// The planes of the frame as a `vpx_image_t` describes them, eg.: to fill one with
+    /// `vpx_img_wrap` or to set its fields by hand before `vpx_codec_encode`
+    ///
+    /// The pointers are only valid while the frame borrows its planes, and libvpx must not
+    /// use them after that.
+    ///
+    /// # Panics
+    ///
+    /// If a stride doesn't fit in an `int`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{FrameMut, VPX_IMG_FMT_NV12};
+    ///
+    /// let (mut y, mut uv) = ([0; 32 * 2], [0; 32]);
+    /// let planes = FrameMut::nv12(30, 2, &mut y, 32, &mut uv, 32).unwrap().vpx_planes();
+    /// assert_eq!((planes.fmt, planes.d_w, planes.stride), (VPX_IMG_FMT_NV12, 30, [32, 32, 32, 0]));
+    /// assert_eq!(planes.planes[2], planes.planes[1].wrapping_add(1));
+    /// ```
+    pub fn vpx_planes(&mut self) -> VpxPlanes {
+        let (d_w, d_h) = (self.width(), self.height());
+        let planes = self.planes();
+        let (y_stride, chroma_stride) = (c_stride(planes.strides.y), c_stride(planes.strides.chroma));
+        let (fmt, u, v) = match planes.chroma {
+            ChromaPlanes::Planar(u, v) => (VPX_IMG_FMT_I420, u.as_mut_ptr(), v.as_mut_ptr()),
+            ChromaPlanes::SemiPlanar(uv) => (VPX_IMG_FMT_NV12, uv.as_mut_ptr(), uv.as_mut_ptr().wrapping_add(1)),
+        };
+        VpxPlanes {
+            fmt,
+            d_w,
+            d_h,
+            planes: [planes.y.as_mut_ptr(), u, v, ptr::null_mut()],
+            stride: [y_stride, chroma_stride, chroma_stride, 0],
+        }
+    }
+
+    /// The planes of the frame as the `img` of an `x264_picture_t` describes them, to pass
+    /// to `x264_encoder_encode` as they are
+    ///
+    /// x264 reads the rows of the planes only, so padded rows, eg.: the ones of frames of
+    /// `ConverterBuilder::alignment`, are fine. The pointers are only valid while the frame
+    /// borrows its planes.
+    ///
+    /// # Panics
+    ///
+    /// If a stride doesn't fit in an `int`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, YuvFormat, X264_CSP_NV12};
+    ///
+    /// let mut frame = Converter::builder().output(YuvFormat::Nv12).alignment(32).build()
+    ///     .convert(&[0u8; 20 * 2 * 3], 20, 2).unwrap();
+    /// let img = frame.as_frame_mut().x264_planes();
+    /// assert_eq!((img.i_csp, img.i_plane, img.i_stride), (X264_CSP_NV12, 2, [32, 32, 0, 0]));
+    /// assert_eq!(img.plane[1] as *const u8, frame.uv().unwrap().as_ptr());
+    /// ```
+    pub fn x264_planes(&mut self) -> X264Planes {
+        let planes = self.planes();
+        let (y_stride, chroma_stride) = (c_stride(planes.strides.y), c_stride(planes.strides.chroma));
+        let y = planes.y.as_mut_ptr();
+        match planes.chroma {
+            ChromaPlanes::Planar(u, v) => X264Planes {
+                i_csp: X264_CSP_I420,
+                i_plane: 3,
+                i_stride: [y_stride, chroma_stride, chroma_stride, 0],
+                plane: [y, u.as_mut_ptr(), v.as_mut_ptr(), ptr::null_mut()],
+            },
+            ChromaPlanes::SemiPlanar(uv) => X264Planes {
+                i_csp: X264_CSP_NV12,
+                i_plane: 2,
+                i_stride: [y_stride, chroma_stride, 0, 0],
+                plane: [y, uv.as_mut_ptr(), ptr::null_mut(), ptr::null_mut()],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ptr;
+
+    use super::{VPX_IMG_FMT_I420, X264_CSP_I420};
+    use config::{Colorimetry, ConversionConfig};
+    use error::ConversionError;
+    use format::ChannelOrder;
+    use planes::{self, FrameMut};
+    use view::RgbView;
+
+    #[test]
+    fn encoder_planes_point_into_the_frame() {
+        let (mut y, mut u, mut v) = ([0; 16 * 3], [0; 8 * 2], [0; 8 * 2]);
+        let (y_ptr, u_ptr, v_ptr) = (y.as_mut_ptr(), u.as_mut_ptr(), v.as_mut_ptr());
+        let mut frame = FrameMut::i420(5, 3, &mut y, 16, &mut u, &mut v, 8).unwrap();
+        let planes = frame.vpx_planes();
+        assert_eq!((planes.fmt, planes.d_w, planes.d_h), (VPX_IMG_FMT_I420, 5, 3));
+        assert_eq!(planes.planes, [y_ptr, u_ptr, v_ptr, ptr::null_mut()]);
+        assert_eq!(planes.stride, [16, 8, 8, 0]);
+        let img = frame.x264_planes();
+        assert_eq!((img.i_csp, img.i_plane, img.i_stride), (X264_CSP_I420, 3, [16, 8, 8, 0]));
+        assert_eq!(img.plane, planes.planes);
+        // converting through the frame afterwards fills what the pointers describe
+        let rgb = [255; 5 * 3 * 3];
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        planes::convert_into_frame(&src, &ConversionConfig::from(Colorimetry::Bt601FullJfif), &mut frame).unwrap();
+        assert_eq!((y[2 * 16 + 4], y[2 * 16 + 5], u[8 + 2]), (255, 0, 128));
+    }
+
+    #[test]
+    fn mapped_textures_hold_uv_after_all_of_their_rows() {
+        // 6x3 in a 6x4 texture with a pitch of 16
+        let rgb = [255; 6 * 3 * 3];
+        let src = RgbView::new(&rgb, 6, 3, ChannelOrder::Rgb).unwrap();
+        let mut mapped = [7; 16 * 6];
+        {
+            let mut frame = FrameMut::d3d11_nv12(6, 3, &mut mapped, 16, 4).unwrap();
+            planes::convert_into_frame(&src, &ConversionConfig::from(Colorimetry::Bt601FullJfif), &mut frame).unwrap();
+        }
+        assert_eq!((mapped[2 * 16 + 5], mapped[2 * 16 + 6], mapped[3 * 16]), (255, 7, 7));
+        assert_eq!((mapped[4 * 16], mapped[5 * 16 + 5], mapped[5 * 16 + 6]), (128, 128, 7));
+        assert_eq!(FrameMut::d3d11_nv12(6, 5, &mut mapped, 16, 4).err(),
+                   Some(ConversionError::UnsupportedDimensions { width: 6, height: 5 }));
+        assert_eq!(FrameMut::d3d11_nv12(6, 3, &mut mapped[..16 * 6 - 11], 16, 4).err(),
+                   Some(ConversionError::BufferTooSmall { expected: 16 + 6, got: 16 + 5 }));
+    }
+}
@@ -0,0 +1,127 @@
+//! Piping frames into ffmpeg as `rawvideo`
+
+use std::io;
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+
+use config::{Matrix, Range};
+use format::{FrameDescriptor, YuvFormat};
+use raw::YuvWriter;
+
+/// The input options telling ffmpeg to read the frames a `YuvWriter` writes from its
+/// standard input, up to and including `-i -`
+///
+/// The format, size and colorimetry are taken from `frame`; its strides don't matter, as
+/// the writer packs the rows tightly. `framerate` is frames per second as a fraction.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ffmpeg_input_args, Converter};
+///
+/// let frame = Converter::builder().build().convert(&[0u8; 4 * 2 * 3], 4, 2).unwrap();
+/// assert_eq!(ffmpeg_input_args(&frame.descriptor(), (30000, 1001)).join(" "),
+///            "-f rawvideo -pix_fmt yuv420p -s 4x2 -r 30000/1001 -colorspace smpte170m -color_range pc -i -");
+/// ```
+pub fn ffmpeg_input_args(frame: &FrameDescriptor, framerate: (u32, u32)) -> Vec<String> {
+    let pix_fmt = match frame.format {
+        YuvFormat::I420 => "yuv420p",
+        YuvFormat::Nv12 => "nv12",
+    };
+    let colorspace = match frame.matrix {
+        Matrix::Bt601 => "smpte170m",
+        Matrix::Bt709 => "bt709",
+        Matrix::Bt2020 => "bt2020nc",
+    };
+    let range = match frame.range {
+        Range::Full => "pc",
+        Range::Limited => "tv",
+    };
+    let args = ["-f", "rawvideo", "-pix_fmt", pix_fmt, "-s", &format!("{}x{}", frame.width, frame.height), "-r",
+                &format!("{}/{}", framerate.0, framerate.1), "-colorspace", colorspace, "-color_range", range,
+                "-i", "-"];
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// An ffmpeg process reading frames from its standard input, eg.: to encode them
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::process::Command;
+///
+/// use rgb2yuv420::{Converter, FfmpegPipe};
+///
+/// let frame = Converter::builder().build().convert(&vec![0u8; 64 * 48 * 3], 64, 48).unwrap();
+/// let mut ffmpeg = FfmpegPipe::spawn(Command::new("ffmpeg"), &frame.descriptor(), (25, 1),
+///                                    &["-c:v", "libx264", "out.mp4"]).unwrap();
+/// for _ in 0..25 {
+///     ffmpeg.writer().write_frame(&frame.as_planes()).unwrap();
+/// }
+/// assert!(ffmpeg.finish().unwrap().success());
+/// ```
+#[derive(Debug)]
+pub struct FfmpegPipe {
+    child: Child,
+    writer: YuvWriter<ChildStdin>,
+}
+
+impl FfmpegPipe {
+    /// Runs `command` (eg.: `Command::new("ffmpeg")` or a path to it) with the arguments of
+    /// `ffmpeg_input_args` followed by `output`, writing frames like `frame` into it
+    ///
+    /// # Errors
+    ///
+    /// The error of starting the process
+    pub fn spawn(mut command: Command, frame: &FrameDescriptor, framerate: (u32, u32), output: &[&str])
+                 -> io::Result<FfmpegPipe> {
+        let mut child = command.args(ffmpeg_input_args(frame, framerate)).args(output).stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("the standard input is piped");
+        Ok(FfmpegPipe { child, writer: YuvWriter::new(stdin, frame.format, frame.width, frame.height) })
+    }
+
+    /// The writer of the frames into the process
+    pub fn writer(&mut self) -> &mut YuvWriter<ChildStdin> {
+        &mut self.writer
+    }
+
+    /// Closes the standard input of the process, which ends the input of ffmpeg, and waits
+    /// for it to exit
+    pub fn finish(self) -> io::Result<ExitStatus> {
+        let FfmpegPipe { mut child, writer } = self;
+        drop(writer.into_inner());
+        child.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process::Command;
+
+    use super::{ffmpeg_input_args, FfmpegPipe};
+    use config::{Matrix, Range};
+    use format::{FrameDescriptor, YuvFormat};
+
+    #[test]
+    fn pipes_get_the_frames_and_their_layout() {
+        let frame = FrameDescriptor { format: YuvFormat::Nv12, width: 6, height: 2, y_stride: 8, chroma_stride: 8,
+                                      matrix: Matrix::Bt709, range: Range::Limited };
+        let args = ffmpeg_input_args(&frame, (25, 1));
+        assert_eq!(args[..6], ["-f", "rawvideo", "-pix_fmt", "nv12", "-s", "6x2"]);
+        assert_eq!(args[8..], ["-colorspace", "bt709", "-color_range", "tv", "-i", "-"]);
+        if cfg!(unix) {
+            // a shell copying its input into a file, passed the arguments ffmpeg would get
+            let path = std::env::temp_dir().join(format!("rgb2yuv420-ffmpeg-{}", std::process::id()));
+            let mut command = Command::new("sh");
+            command.args(["-c", "[ \"$2\" = rawvideo ] && cat > \"$0\"", path.to_str().unwrap()]);
+            let mut pipe = FfmpegPipe::spawn(command, &frame, (25, 1), &["out.mp4"]).unwrap();
+            let data: Vec<u8> = (0..18).collect();
+            for _ in 0..2 {
+                pipe.writer().write_frame(&::YuvPlanes::split(&data, YuvFormat::Nv12, 6, 2).unwrap()).unwrap();
+            }
+            assert!(pipe.finish().unwrap().success());
+            assert_eq!(fs::read(&path).unwrap(), data.repeat(2));
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Conversions with dimensions and output format fixed at compile time
+//!
+//! For fixed size pipelines (embedded cameras, kiosks) the output buffer is a plain array
+//! whose length is checked against the dimensions when the code is compiled, so neither the
+//! frame nor any size check needs the heap or a runtime error path for the output.
+//!
+//! # Examples
+//!
+//! ```
+//! use rgb2yuv420::fixed::{self, Nv12};
+//! use rgb2yuv420::{ChannelOrder, ConversionConfig};
+//!
+//! let rgb = vec![0u8; 640 * 480 * 3];
+//! let mut nv12 = [0u8; fixed::frame_size(640, 480)];
+//! fixed::convert_into::<640, 480, Nv12, _>(&rgb, ChannelOrder::Rgb, &ConversionConfig::default(), &mut nv12)
+//!     .unwrap();
+//! ```
+//!
+//! A buffer of the wrong size doesn't compile:
+//!
+//! ```compile_fail
+//! use rgb2yuv420::fixed::{self, Nv12};
+//! use rgb2yuv420::{ChannelOrder, ConversionConfig};
+//!
+//! let rgb = vec![0u8; 4 * 4 * 3];
+//! let mut nv12 = [0u8; 16];
+//! fixed::convert_into::<4, 4, Nv12, _>(&rgb, ChannelOrder::Rgb, &ConversionConfig::default(), &mut nv12);
+//! ```
+//!
+//! The format markers describe their plane layout with `YuvFormatSpec`, including the 10 bit
+//! formats, which can't be converted into:
+//!
+//! ```compile_fail
+//! use rgb2yuv420::fixed::{self, I010};
+//! use rgb2yuv420::{ChannelOrder, ConversionConfig};
+//!
+//! let rgb = vec![0u8; 4 * 4 * 3];
+//! let mut i010 = [0u8; 48];
+//! fixed::convert_into::<4, 4, I010, _>(&rgb, ChannelOrder::Rgb, &ConversionConfig::default(), &mut i010);
+//! ```
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use kernel::{self, Source};
+
+/// The plane layout of a YUV420 format, as constants of a marker type
+pub trait YuvFormatSpec {
+    /// Bits a sample holds
+    const BIT_DEPTH: u32;
+    /// Bytes a sample takes
+    const BYTES_PER_SAMPLE: usize;
+    /// 3 with U and V in planes of their own, 2 with them interleaved after the luma
+    const PLANES: usize;
+
+    /// Size of a tightly packed `width` x `height` frame in bytes
+    fn frame_size(width: u32, height: u32) -> usize {
+        kernel::frame_size(width as usize, height as usize) * Self::BYTES_PER_SAMPLE
+    }
+
+    /// Offsets of the Y, U (or UV) and V planes of a tightly packed frame in bytes, like
+    /// `plane_offsets`
+    fn plane_offsets(width: u32, height: u32) -> (usize, usize, Option<usize>) {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        let luma = width as usize * height as usize * Self::BYTES_PER_SAMPLE;
+        let chroma = chroma_width * chroma_height * Self::BYTES_PER_SAMPLE;
+        (0, luma, if Self::PLANES == 3 { Some(luma + chroma) } else { None })
+    }
+}
+
+/// An output format usable as a type parameter
+pub trait FixedFormat: YuvFormatSpec {
+    const FORMAT: YuvFormat;
+}
+
+/// YUV420p (planar/3 planes), see `YuvFormat::I420`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct I420;
+
+impl YuvFormatSpec for I420 {
+    const BIT_DEPTH: u32 = 8;
+    const BYTES_PER_SAMPLE: usize = 1;
+    const PLANES: usize = 3;
+}
+
+impl FixedFormat for I420 {
+    const FORMAT: YuvFormat = YuvFormat::I420;
+}
+
+/// YUV420sp NV12 (semi-planar/2 planes), see `YuvFormat::Nv12`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Nv12;
+
+impl YuvFormatSpec for Nv12 {
+    const BIT_DEPTH: u32 = 8;
+    const BYTES_PER_SAMPLE: usize = 1;
+    const PLANES: usize = 2;
+}
+
+impl FixedFormat for Nv12 {
+    const FORMAT: YuvFormat = YuvFormat::Nv12;
+}
+
+/// The 10 bit I420 in 16 bit words, see `Yuv10Format::I010`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct I010;
+
+impl YuvFormatSpec for I010 {
+    const BIT_DEPTH: u32 = 10;
+    const BYTES_PER_SAMPLE: usize = 2;
+    const PLANES: usize = 3;
+}
+
+/// The 10 bit NV12 in 16 bit words, see `Yuv10Format::P010`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct P010;
+
+impl YuvFormatSpec for P010 {
+    const BIT_DEPTH: u32 = 10;
+    const BYTES_PER_SAMPLE: usize = 2;
+    const PLANES: usize = 2;
+}
+
+/// Size of a `width` x `height` YUV420 frame in bytes, usable as an array length
+pub const fn frame_size(width: u32, height: u32) -> usize {
+    kernel::frame_size(width as usize, height as usize)
+}
+
+/// Converts a tightly packed `W` x `H` image into an array holding exactly one frame
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `img` is shorter than `W * H` pixels
+pub fn convert_into<const W: u32, const H: u32, F: FixedFormat, const N: usize>(
+    img: &[u8], order: ChannelOrder, cfg: &ConversionConfig, dst: &mut [u8; N]) -> Result<(), ConversionError> {
+    const { assert!(N == frame_size(W, H), "the output array must hold exactly one frame") };
+    let src = Source::new(img, W, H, W as usize * order.bytes_per_pixel(), order.layout(), false)?;
+    kernel::convert_into(&src, F::FORMAT, cfg, dst);
+    Ok(())
+}
+
+/// Converts a tightly packed `W` x `H` image into a new array
+///
+/// See `convert_into`; `N` is usually inferred from the binding.
+pub fn convert<const W: u32, const H: u32, F: FixedFormat, const N: usize>(
+    img: &[u8], order: ChannelOrder, cfg: &ConversionConfig) -> Result<[u8; N], ConversionError> {
+    let mut dst = [0; N];
+    convert_into::<W, H, F, N>(img, order, cfg, &mut dst)?;
+    Ok(dst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, YuvFormatSpec, I010, I420, Nv12, P010};
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use layout;
+
+    #[test]
+    fn matches_runtime_conversion() {
+        let rgb: Vec<u8> = (0..6 * 4 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let yuv: [u8; 6 * 4 * 3 / 2] = convert::<6, 4, I420, _>(&rgb, ChannelOrder::Rgba, &ConversionConfig::default())
+            .unwrap();
+        assert_eq!(yuv[..], ::convert_rgb_to_yuv420p(&rgb, 6, 4, 4)[..]);
+    }
+
+    #[test]
+    fn specs_describe_the_plane_layouts() {
+        assert_eq!((I420::frame_size(5, 3), Nv12::plane_offsets(5, 3)),
+                   (layout::required_buffer_size(YuvFormat::I420, 5, 3), layout::plane_offsets(YuvFormat::Nv12, 5, 3)));
+        assert_eq!(I420::plane_offsets(5, 3), layout::plane_offsets(YuvFormat::I420, 5, 3));
+        assert_eq!((I010::BIT_DEPTH, I010::frame_size(4, 4), I010::plane_offsets(4, 4)), (10, 48, (0, 32, Some(40))));
+        assert_eq!((P010::PLANES, P010::plane_offsets(4, 4)), (2, (0, 32, None)));
+    }
+}
@@ -0,0 +1,126 @@
+//! Copies into the flexible YUV420 layouts (`YUV_420_888`) of the planes of Android's
+//! `Image`s, as `MediaCodec` input images and `ImageWriter` hand them out
+
+use error::ConversionError;
+use kernel;
+use planes::check_plane;
+use split::YuvPlanes;
+
+/// The U and V planes of a `YUV_420_888` image, with the `rowStride` and `pixelStride` of
+/// their `Image.Plane`s
+#[derive(Debug)]
+pub enum FlexibleChroma<'a> {
+    /// Separate U and V buffers, whose samples are `pixel_stride` bytes apart (1 for I420-like
+    /// layouts)
+    Planar { u: &'a mut [u8], v: &'a mut [u8], row_stride: usize, pixel_stride: usize },
+    /// U and V planes of `pixelStride` 2 whose buffers start one byte apart, as one buffer from
+    /// the start of the first to the end of the second; `v_first` for NV21-like layouts, where
+    /// the V buffer starts first
+    Interleaved { uv: &'a mut [u8], row_stride: usize, v_first: bool },
+}
+
+impl<'a> YuvPlanes<'a> {
+    /// Copies the planes into the buffers of a `YUV_420_888` image: the Y plane (whose
+    /// `pixelStride` is always 1) into `y` in rows of `y_row_stride` bytes, and U and V into
+    /// `chroma`; bytes between the samples and rows are never written to
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` if a `pixel_stride` is 0, `StrideMismatch` if a row stride is
+    /// smaller than a row and `BufferTooSmall` if a buffer can't hold all of its rows; nothing
+    /// is written on errors
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{FlexibleChroma, YuvFormat, YuvPlanes};
+    ///
+    /// let frame = YuvPlanes::split(&[16, 17, 18, 19, 100, 200], YuvFormat::I420, 2, 2).unwrap();
+    /// let (mut y, mut vu) = ([0; 8], [0; 2]);
+    /// let chroma = FlexibleChroma::Interleaved { uv: &mut vu, row_stride: 4, v_first: true };
+    /// frame.copy_into_flexible(&mut y, 4, chroma).unwrap();
+    /// assert_eq!((y, vu), ([16, 17, 0, 0, 18, 19, 0, 0], [200, 100]));
+    /// ```
+    pub fn copy_into_flexible(&self, y: &mut [u8], y_row_stride: usize, chroma: FlexibleChroma)
+                              -> Result<(), ConversionError> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+        check_plane(y.len(), y_row_stride, width, height)?;
+        // the bytes from the first sample of a chroma row to its last one
+        let row_bytes = |pixel_stride: usize| (chroma_width * pixel_stride).saturating_sub(pixel_stride - 1);
+        match chroma {
+            FlexibleChroma::Planar { ref u, ref v, row_stride, pixel_stride } => {
+                if pixel_stride == 0 {
+                    return Err(ConversionError::UnsupportedFormat("pixel_stride must not be 0"));
+                }
+                check_plane(u.len(), row_stride, row_bytes(pixel_stride), chroma_height)?;
+                check_plane(v.len(), row_stride, row_bytes(pixel_stride), chroma_height)?;
+            }
+            FlexibleChroma::Interleaved { ref uv, row_stride, .. } => {
+                check_plane(uv.len(), row_stride, 2 * chroma_width, chroma_height)?;
+            }
+        }
+        for (row, src) in self.y().rows().enumerate() {
+            y[row * y_row_stride..][..width].copy_from_slice(src);
+        }
+        // the U and V planes of the frame, the bytes between their samples and their stride
+        let (src_u, src_v, step, src_stride) = match (self.u(), self.v(), self.uv()) {
+            (Some(u), Some(v), _) => (u.data(), v.data(), 1, u.stride()),
+            (_, _, Some(uv)) => (uv.data(), &uv.data()[1..], 2, uv.stride()),
+            _ => unreachable!("frames have either U and V or UV planes"),
+        };
+        let sample = |plane: &[u8], row: usize, x: usize| plane[row * src_stride + x * step];
+        match chroma {
+            FlexibleChroma::Planar { u, v, row_stride, pixel_stride } => {
+                for row in 0..chroma_height {
+                    for x in 0..chroma_width {
+                        u[row * row_stride + x * pixel_stride] = sample(src_u, row, x);
+                        v[row * row_stride + x * pixel_stride] = sample(src_v, row, x);
+                    }
+                }
+            }
+            FlexibleChroma::Interleaved { uv, row_stride, v_first } => {
+                let (u_at, v_at) = if v_first { (1, 0) } else { (0, 1) };
+                for row in 0..chroma_height {
+                    for x in 0..chroma_width {
+                        uv[row * row_stride + 2 * x + u_at] = sample(src_u, row, x);
+                        uv[row * row_stride + 2 * x + v_at] = sample(src_v, row, x);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlexibleChroma;
+    use error::ConversionError;
+    use format::YuvFormat;
+    use split::YuvPlanes;
+
+    #[test]
+    fn planes_are_written_with_row_and_pixel_strides() {
+        let data: Vec<u8> = (1..=3 * 3 + 2 * 2 * 2).collect();
+        let i420 = YuvPlanes::split(&data, YuvFormat::I420, 3, 3).unwrap();
+        let nv12 = data[..9].iter().chain(&[10, 14, 11, 15, 12, 16, 13, 17]).cloned().collect::<Vec<u8>>();
+        let nv12 = YuvPlanes::split(&nv12, YuvFormat::Nv12, 3, 3).unwrap();
+        for frame in &[i420, nv12] {
+            let mut y = [0; 4 * 3];
+            // separate buffers with a pixel stride of 2, the last sample of the last row ends them
+            let (mut u, mut v) = ([0; 8], [0; 8]);
+            let chroma = FlexibleChroma::Planar { u: &mut u, v: &mut v, row_stride: 5, pixel_stride: 2 };
+            frame.copy_into_flexible(&mut y, 4, chroma).unwrap();
+            assert_eq!(y, [1, 2, 3, 0, 4, 5, 6, 0, 7, 8, 9, 0]);
+            assert_eq!((u, v), ([10, 0, 11, 0, 0, 12, 0, 13], [14, 0, 15, 0, 0, 16, 0, 17]));
+            let mut uv = [0; 8];
+            let chroma = FlexibleChroma::Interleaved { uv: &mut uv, row_stride: 4, v_first: false };
+            frame.copy_into_flexible(&mut y, 4, chroma).unwrap();
+            assert_eq!(uv, [10, 14, 11, 15, 12, 16, 13, 17]);
+            let chroma = FlexibleChroma::Interleaved { uv: &mut uv[..7], row_stride: 4, v_first: true };
+            assert_eq!(frame.copy_into_flexible(&mut y, 4, chroma),
+                       Err(ConversionError::BufferTooSmall { expected: 8, got: 7 }));
+        }
+    }
+}
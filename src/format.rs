@@ -0,0 +1,124 @@
+use config::{Colorimetry, Matrix, Range};
+
+/// Order of the channels of an interleaved 8 bit input pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelOrder {
+    Rgb,
+    Bgr,
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+impl ChannelOrder {
+    /// Number of bytes used by one pixel
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            ChannelOrder::Rgb | ChannelOrder::Bgr => 3,
+            _ => 4,
+        }
+    }
+
+    pub(crate) fn layout(self) -> PixelLayout {
+        let (r, g, b) = match self {
+            ChannelOrder::Rgb | ChannelOrder::Rgba => (0, 1, 2),
+            ChannelOrder::Bgr | ChannelOrder::Bgra => (2, 1, 0),
+            ChannelOrder::Argb => (1, 2, 3),
+            ChannelOrder::Abgr => (3, 2, 1),
+        };
+        let alpha = match self {
+            ChannelOrder::Rgb | ChannelOrder::Bgr => None,
+            ChannelOrder::Rgba | ChannelOrder::Bgra => Some(3),
+            ChannelOrder::Argb | ChannelOrder::Abgr => Some(0),
+        };
+        PixelLayout { bytes_per_pixel: self.bytes_per_pixel(), r, g, b, alpha }
+    }
+}
+
+/// Byte offsets of the color channels within a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PixelLayout {
+    pub bytes_per_pixel: usize,
+    pub r: usize,
+    pub g: usize,
+    pub b: usize,
+    /// Only read by the transforms that use transparency
+    pub alpha: Option<usize>,
+}
+
+impl PixelLayout {
+    /// `[r, g, b, ...]` followed by `bytes_per_pixel - 3` ignored bytes
+    #[cfg(feature = "alloc")]
+    pub fn packed(bytes_per_pixel: usize) -> PixelLayout {
+        PixelLayout { bytes_per_pixel, r: 0, g: 1, b: 2, alpha: None }
+    }
+}
+
+/// Output layout of a YUV420 frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum YuvFormat {
+    /// YUV420p (planar/3 planes): `[y, y, y, ... , u, u, u, ... , v, v, v, ...]`
+    I420,
+    /// YUV420sp NV12 (semi-planar/2 planes): `[y, y, y, ... , u, v, u, v, ...]`
+    Nv12,
+}
+
+/// Layout of a packed 4:2:2 frame, two pixels sharing a chroma sample in every 4 bytes (the
+/// formats of UVC webcams)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PackedYuvFormat {
+    /// YUYV (YUY2): `[y, u, y, v, y, u, y, v, ...]`
+    Yuyv,
+    /// UYVY: `[u, y, v, y, u, y, v, y, ...]`
+    Uyvy,
+}
+
+/// Layout of a YUV420 frame with 10 bit samples in 16 bit little endian words, as decoders
+/// return HDR or 10 bit streams
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Yuv10Format {
+    /// The 10 bit NV12, with the samples in the high bits of their words
+    P010,
+    /// The 10 bit I420, with the samples in the low bits of their words
+    I010,
+}
+
+impl PackedYuvFormat {
+    /// Byte offsets of the first luma sample, U, the second luma sample and V in 4 bytes
+    #[cfg(feature = "alloc")]
+    pub(crate) fn offsets(self) -> [usize; 4] {
+        match self {
+            PackedYuvFormat::Yuyv => [0, 1, 2, 3],
+            PackedYuvFormat::Uyvy => [1, 0, 3, 2],
+        }
+    }
+}
+
+/// The layout and colorimetry of a YUV420 frame without its samples
+///
+/// With the `serde` feature it can be exchanged as a frame header between processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameDescriptor {
+    pub format: YuvFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Distance between the starts of two luma rows in bytes
+    pub y_stride: usize,
+    /// Distance between the starts of two chroma rows in bytes
+    pub chroma_stride: usize,
+    pub matrix: Matrix,
+    pub range: Range,
+}
+
+impl FrameDescriptor {
+    /// The matrix and range of the frame, eg.: for the metadata of the file it is written to
+    pub fn colorimetry(&self) -> Colorimetry {
+        Colorimetry::new(self.matrix, self.range)
+    }
+}
@@ -0,0 +1,933 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+use core::time::Duration;
+
+use config::{ChromaFilter, Colorimetry, ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{FrameDescriptor, YuvFormat};
+use geometry::Rotation;
+use kernel::{self, ChromaPlanes, Planes, Strides};
+use layout;
+use planes::FrameMut;
+use split::YuvPlanes;
+
+/// How the converters lay out the frames they allocate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Pitch {
+    // a power of two that the planes and rows start at multiples of
+    pub alignment: usize,
+    // the smallest row strides asked for
+    pub y_stride: usize,
+    pub chroma_stride: usize,
+    // the frames are padded to multiples of this many pixels
+    pub multiple: usize,
+    // the alpha channel gets a plane after the chroma planes
+    pub alpha: bool,
+    // and is subsampled 2x2 with this filter once converted
+    pub alpha_filter: Option<ChromaFilter>,
+}
+
+impl Pitch {
+    /// Size of the frame holding a `width` x `height` picture
+    pub fn padded(&self, width: u32, height: u32) -> (u32, u32) {
+        let round_up = |size: u32| (size as usize).div_ceil(self.multiple) as u32 * self.multiple as u32;
+        (round_up(width), round_up(height))
+    }
+}
+
+impl Default for Pitch {
+    fn default() -> Pitch {
+        Pitch { alignment: 1, y_stride: 0, chroma_stride: 0, multiple: 1, alpha: false, alpha_filter: None }
+    }
+}
+
+/// When a frame is shown, carried along with its samples from the capture to the encoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FrameTiming {
+    /// Presentation timestamp, from the start of the stream
+    pub pts: Option<Duration>,
+    /// How long the frame is shown
+    pub duration: Option<Duration>,
+    /// Number of the frame in its stream, from 0
+    pub index: Option<u64>,
+}
+
+/// A converted YUV420 frame owning its buffer
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Converter, YuvFormat};
+///
+/// let rgb = vec![0u8; 4 * 2 * 3];
+/// let frame = Converter::builder().output(YuvFormat::I420).build().convert(&rgb, 4, 2).unwrap();
+/// assert_eq!(frame.y(), &[0; 8][..]);
+/// assert_eq!(frame.u(), Some(&[128, 128][..]));
+/// assert_eq!(frame.y_stride(), 4);
+/// assert_eq!(frame.chroma_stride(), 2);
+/// ```
+///
+/// Frames converted with `ConverterBuilder::strides` or `ConverterBuilder::alignment` have
+/// padded rows, and the latter start every plane and every row at a multiple of the
+/// alignment; the padding is zeroed, and clones are aligned too.
+///
+/// Frames converted with `ConverterBuilder::alpha_plane` end with a full resolution alpha
+/// plane laid out like the luma plane (A420), and those converted with
+/// `ConverterBuilder::subsampled_alpha` with one subsampled like the chroma planes.
+///
+/// The `FrameTiming` of a frame stays with it through clones, `FrameQueue`s and the
+/// transforms below, but not through conversions into it; frames are equal if their samples
+/// are, whatever their timing.
+#[derive(Debug)]
+pub struct YuvFrame {
+    data: Vec<u8>,
+    // where the frame starts in `data`, for aligning it to `alignment` bytes
+    start: usize,
+    alignment: usize,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    y_stride: usize,
+    chroma_stride: usize,
+    alpha: bool,
+    // the alpha plane has the chroma resolution
+    alpha_subsampled: bool,
+    matrix: Matrix,
+    range: Range,
+    timing: FrameTiming,
+}
+
+impl YuvFrame {
+    /// Wraps a tightly packed buffer produced by the kernel with `cfg`
+    pub(crate) fn from_packed(data: Vec<u8>, format: YuvFormat, width: u32, height: u32,
+                              cfg: &ConversionConfig) -> YuvFrame {
+        let (y_stride, chroma_stride) = layout::plane_strides(format, width);
+        YuvFrame {
+            data,
+            start: 0,
+            alignment: 1,
+            format,
+            width,
+            height,
+            y_stride,
+            chroma_stride,
+            alpha: false,
+            alpha_subsampled: false,
+            matrix: cfg.matrix,
+            range: cfg.range,
+            timing: FrameTiming::default(),
+        }
+    }
+
+    /// A zeroed frame to convert into, laid out as `pitch` asks
+    pub(crate) fn pitched(format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig, pitch: Pitch)
+                          -> YuvFrame {
+        let Pitch { alignment, y_stride, chroma_stride, alpha, .. } = pitch;
+        debug_assert!(alignment.is_power_of_two());
+        let (y_row, chroma_row) = layout::plane_strides(format, width);
+        let round_up = |row: usize| row.div_ceil(alignment) * alignment;
+        let mut frame = YuvFrame {
+            data: Vec::new(),
+            start: 0,
+            alignment,
+            format,
+            width,
+            height,
+            y_stride: round_up(y_row.max(y_stride)),
+            chroma_stride: round_up(chroma_row.max(chroma_stride)),
+            alpha,
+            alpha_subsampled: false,
+            matrix: cfg.matrix,
+            range: cfg.range,
+            timing: FrameTiming::default(),
+        };
+        frame.allocate();
+        frame
+    }
+
+    /// Whether `pitched` would lay a frame out like this one, so it can be converted into
+    /// again; frames with an alpha plane never are, as it may have been subsampled
+    pub(crate) fn fits(&self, format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig, pitch: Pitch)
+                       -> bool {
+        let (y_row, chroma_row) = layout::plane_strides(format, width);
+        let round_up = |row: usize| row.div_ceil(pitch.alignment) * pitch.alignment;
+        (self.format, self.width, self.height) == (format, width, height)
+            && (self.matrix, self.range) == (cfg.matrix, cfg.range)
+            && self.alignment == pitch.alignment && !self.alpha && !pitch.alpha
+            && self.y_stride == round_up(y_row.max(pitch.y_stride))
+            && self.chroma_stride == round_up(chroma_row.max(pitch.chroma_stride))
+    }
+
+    /// A tightly packed frame of a single RGB `color`, converted with the matrix, range and
+    /// adjustment of `cfg`, eg.: for pre-roll, filling gaps in a stream or muting the video
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ConversionConfig, Range, YuvFormat, YuvFrame};
+    ///
+    /// let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+    /// let black = YuvFrame::filled([0, 0, 0], YuvFormat::Nv12, 4, 2, &cfg);
+    /// assert_eq!(black.y(), &[16; 8]);
+    /// assert_eq!(black.uv().unwrap(), &[128; 4]);
+    /// ```
+    pub fn filled(color: [u8; 3], format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig) -> YuvFrame {
+        let coef = cfg.coefficients_for(format);
+        let [r, g, b] = color.map(i32::from);
+        let mut luma = [coef.luma(r, g, b)];
+        coef.adjust_luma(&mut luma);
+        let (u, v) = coef.chroma(r, g, b);
+        let luma_size = width as usize * height as usize;
+        let mut data = vec![luma[0]; layout::required_buffer_size(format, width, height)];
+        match format {
+            YuvFormat::I420 => {
+                let chroma_size = (data.len() - luma_size) / 2;
+                let (u_plane, v_plane) = data[luma_size..].split_at_mut(chroma_size);
+                u_plane.fill(u);
+                v_plane.fill(v);
+            }
+            YuvFormat::Nv12 => {
+                for uv in data[luma_size..].chunks_mut(2) {
+                    uv.copy_from_slice(&[u, v]);
+                }
+            }
+        }
+        YuvFrame::from_packed(data, format, width, height, cfg)
+    }
+
+    /// A tightly packed copy at half the width and height (rounded up), averaging 2x2 luma
+    /// samples and 2x2 chroma samples (the chroma of 4x4 pixels); the alpha plane is dropped
+    pub(crate) fn halved(&self) -> YuvFrame {
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(w, h);
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut data = Vec::with_capacity(layout::required_buffer_size(self.format, width, height));
+        halve_plane(self.y(), self.y_stride, 1, (w, h), &mut data);
+        match (self.u(), self.v(), self.uv()) {
+            (Some(u), Some(v), _) => {
+                halve_plane(u, self.chroma_stride, 1, (chroma_width, chroma_height), &mut data);
+                halve_plane(v, self.chroma_stride, 1, (chroma_width, chroma_height), &mut data);
+            }
+            (_, _, Some(uv)) => halve_plane(uv, self.chroma_stride, 2, (chroma_width, chroma_height), &mut data),
+            _ => unreachable!("frames have either U and V or UV planes"),
+        }
+        let (y_stride, chroma_stride) = layout::plane_strides(self.format, width);
+        YuvFrame { data, start: 0, alignment: 1, width, height, y_stride, chroma_stride, alpha: false,
+                   alpha_subsampled: false, ..*self }
+    }
+
+    /// Rotates the frame clockwise, eg.: after the display orientation changed; a quarter turn
+    /// swaps the width and the height
+    ///
+    /// The chroma planes are rotated as they are, so with an odd width or height the chroma
+    /// of a quarter or half turn is one pixel off. The rotated frame keeps the alignment and
+    /// alpha plane but not the strides asked for; its rows are padded to the alignment only.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, Rotation};
+    ///
+    /// // a 2x1 image, red on the left
+    /// let rgb = [255, 0, 0, 0, 0, 0];
+    /// let mut frame = Converter::builder().build().convert(&rgb, 2, 1).unwrap();
+    /// frame.rotate(Rotation::Cw90);
+    /// assert_eq!((frame.width(), frame.height()), (1, 2));
+    /// assert_eq!(frame.y(), &[77, 0]);
+    /// ```
+    pub fn rotate(&mut self, rotation: Rotation) {
+        if rotation == Rotation::None {
+            return;
+        }
+        let (width, height) = match rotation {
+            Rotation::None | Rotation::Cw180 => (self.width, self.height),
+            Rotation::Cw90 | Rotation::Cw270 => (self.height, self.width),
+        };
+        let cfg = ConversionConfig { matrix: self.matrix, range: self.range, ..ConversionConfig::default() };
+        let pitch = Pitch { alignment: self.alignment, alpha: self.alpha, ..Pitch::default() };
+        let mut rotated = YuvFrame::pitched(self.format, width, height, &cfg, pitch);
+        rotated.timing = self.timing;
+        if self.alpha_subsampled {
+            rotated.set_alpha_subsampled();
+        }
+        let size = (self.width as usize, self.height as usize);
+        let chroma_size = kernel::chroma_dimensions(size.0, size.1);
+        let (y_stride, chroma_stride, alpha_stride) = (rotated.y_stride, rotated.chroma_stride, rotated.alpha_stride());
+        let (y, chroma, v, alpha) = rotated.split_planes();
+        rotate_plane(self.y(), self.y_stride, 1, size, rotation, y, y_stride);
+        if let (Some(src), Some(alpha)) = (self.alpha(), alpha) {
+            rotate_plane(src, self.alpha_stride(), 1, self.alpha_dimensions(), rotation, alpha, alpha_stride);
+        }
+        match (self.u(), self.v(), self.uv(), v) {
+            (Some(u), Some(src_v), _, Some(v)) => {
+                rotate_plane(u, self.chroma_stride, 1, chroma_size, rotation, chroma, chroma_stride);
+                rotate_plane(src_v, self.chroma_stride, 1, chroma_size, rotation, v, chroma_stride);
+            }
+            (_, _, Some(uv), None) => {
+                rotate_plane(uv, self.chroma_stride, 2, chroma_size, rotation, chroma, chroma_stride)
+            }
+            _ => unreachable!("frames have either U and V or UV planes"),
+        }
+        *self = rotated;
+    }
+
+    /// A tightly packed copy of the `width` x `height` rectangle whose top left pixel is at
+    /// (`x`, `y`), with the matrix and range of the frame; the alpha plane is dropped
+    ///
+    /// `as_planes` and `YuvPlanes::crop` crop without copying.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRegion` if the rectangle doesn't fit into the frame or starts at an odd pixel
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::Converter;
+    ///
+    /// // a 1920x1088 capture down to 1920x1080, keeping the middle rows
+    /// let frame = Converter::builder().build().convert(&vec![0u8; 1920 * 1088 * 3], 1920, 1088).unwrap();
+    /// let cropped = frame.cropped(0, 4, 1920, 1080).unwrap();
+    /// assert_eq!((cropped.width(), cropped.height()), (1920, 1080));
+    /// assert!(frame.cropped(0, 3, 1920, 1080).is_err());
+    /// ```
+    pub fn cropped(&self, x: u32, y: u32, width: u32, height: u32) -> Result<YuvFrame, ConversionError> {
+        let data = self.as_planes().crop(x, y, width, height)?.to_vec(self.format);
+        let cfg = ConversionConfig { matrix: self.matrix, range: self.range, ..ConversionConfig::default() };
+        Ok(YuvFrame::from_packed(data, self.format, width, height, &cfg).with_timing(self.timing))
+    }
+
+    // zeroes `data` for the planes, starting at an `alignment` byte boundary
+    fn allocate(&mut self) {
+        let len = self.alpha_offset() + self.alpha_size();
+        self.data = vec![0; len + self.alignment - 1];
+        self.start = (self.data.as_ptr() as usize).wrapping_neg() & (self.alignment - 1);
+        self.data.truncate(self.start + len);
+    }
+
+    /// Finishes a frame converted into its top left `width` x `height` pixels: fills the rest
+    /// and subsamples the alpha plane with `alpha_filter`, if any
+    pub(crate) fn finish(&mut self, width: u32, height: u32, alpha_filter: Option<ChromaFilter>) {
+        self.extend_edges(width, height);
+        if let (true, Some(filter)) = (self.alpha, alpha_filter) {
+            self.subsample_alpha(filter);
+        }
+    }
+
+    // fills the frame outside of its top left `width` x `height` pixels by repeating the last
+    // column and then the last row of every plane
+    fn extend_edges(&mut self, width: u32, height: u32) {
+        let (visible, padded) = ((width as usize, height as usize), (self.width as usize, self.height as usize));
+        if visible == padded {
+            return;
+        }
+        let (chroma, padded_chroma) = (kernel::chroma_dimensions(visible.0, visible.1),
+                                       kernel::chroma_dimensions(padded.0, padded.1));
+        let (y_stride, chroma_stride) = (self.y_stride, self.chroma_stride);
+        let (y, chroma_plane, v, alpha) = self.split_planes();
+        extend_plane(y, y_stride, 1, visible, padded);
+        if let Some(alpha) = alpha {
+            extend_plane(alpha, y_stride, 1, visible, padded);
+        }
+        match v {
+            Some(v) => {
+                extend_plane(chroma_plane, chroma_stride, 1, chroma, padded_chroma);
+                extend_plane(v, chroma_stride, 1, chroma, padded_chroma);
+            }
+            None => extend_plane(chroma_plane, chroma_stride, 2, chroma, padded_chroma),
+        }
+    }
+
+    // turns the full resolution alpha plane into one with the chroma resolution, taking the top
+    // right (or only) sample of every 2x2 block as the kernel takes its chroma, averaging the
+    // block, or making it opaque for `ChromaFilter::Neutral`
+    fn subsample_alpha(&mut self, filter: ChromaFilter) {
+        let (width, height, stride) = (self.width as usize, self.height as usize, self.y_stride);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+        let at = self.start + self.alpha_offset();
+        let full = &self.data[at..];
+        let sample = |x: usize, y: usize| u32::from(full[y.min(height - 1) * stride + x.min(width - 1)]);
+        let out_stride = chroma_width.div_ceil(self.alignment) * self.alignment;
+        let mut subsampled = vec![0; out_stride * chroma_height];
+        for (row, out) in subsampled.chunks_mut(out_stride).enumerate() {
+            for (x, a) in out[..chroma_width].iter_mut().enumerate() {
+                *a = match filter {
+                    ChromaFilter::Point => sample(2 * x + 1, 2 * row),
+                    // alpha is linear already
+                    ChromaFilter::Average | ChromaFilter::LinearAverage => {
+                        let (columns, rows) = (2 * x..(2 * x + 2).min(width), 2 * row..(2 * row + 2).min(height));
+                        let count = (columns.len() * rows.len()) as u32;
+                        let sum: u32 = rows.flat_map(|y| columns.clone().map(move |x| sample(x, y))).sum();
+                        (sum + count / 2) / count
+                    }
+                    ChromaFilter::Neutral => 255,
+                } as u8;
+            }
+        }
+        self.set_alpha_subsampled();
+        self.data[at..].copy_from_slice(&subsampled);
+    }
+
+    // shrinks the alpha plane to the chroma resolution
+    fn set_alpha_subsampled(&mut self) {
+        self.alpha_subsampled = true;
+        let len = self.start + self.alpha_offset() + self.alpha_size();
+        self.data.truncate(len);
+    }
+
+    /// The planes for the kernel to convert into; a subsampled alpha plane is left out
+    pub(crate) fn kernel_planes(&mut self) -> Planes<'_> {
+        let strides = Strides { y: self.y_stride, chroma: self.chroma_stride };
+        let subsampled = self.alpha_subsampled;
+        let (y, chroma, v, alpha) = self.split_planes();
+        let alpha = if subsampled { None } else { alpha };
+        let chroma = match v {
+            Some(v) => ChromaPlanes::Planar(chroma, v),
+            None => ChromaPlanes::SemiPlanar(chroma),
+        };
+        Planes { y, chroma, strides, alpha, stats: None }
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Color matrix the frame was converted with
+    pub fn matrix(&self) -> Matrix {
+        self.matrix
+    }
+
+    /// Value range the frame was converted to
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// The matrix and range together
+    pub fn colorimetry(&self) -> Colorimetry {
+        Colorimetry::new(self.matrix, self.range)
+    }
+
+    pub(crate) fn set_range(&mut self, range: Range) {
+        self.range = range;
+    }
+
+    /// When the frame is shown, unknown unless it was set
+    pub fn timing(&self) -> FrameTiming {
+        self.timing
+    }
+
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    /// The frame with `timing`, eg.: straight out of a conversion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use rgb2yuv420::{Converter, FrameTiming};
+    ///
+    /// let frame = Converter::builder().build().convert(&[0u8; 4 * 2 * 3], 4, 2).unwrap();
+    /// let duration = Duration::from_millis(40);
+    /// let timing = FrameTiming { pts: Some(duration * 3), duration: Some(duration), index: Some(3) };
+    /// let frame = frame.with_timing(timing);
+    /// // kept by the copies of the frame
+    /// assert_eq!(frame.cropped(0, 0, 2, 2).unwrap().timing(), timing);
+    /// ```
+    pub fn with_timing(mut self, timing: FrameTiming) -> YuvFrame {
+        self.timing = timing;
+        self
+    }
+
+    /// Everything but the samples, eg.: for a header sent ahead of the planes
+    pub fn descriptor(&self) -> FrameDescriptor {
+        FrameDescriptor {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            y_stride: self.y_stride,
+            chroma_stride: self.chroma_stride,
+            matrix: self.matrix,
+            range: self.range,
+        }
+    }
+
+    /// Distance between the starts of two luma rows in bytes
+    pub fn y_stride(&self) -> usize {
+        self.y_stride
+    }
+
+    /// Distance between the starts of two chroma rows in bytes
+    /// (of the U and V planes for I420 and of the interleaved plane for NV12)
+    pub fn chroma_stride(&self) -> usize {
+        self.chroma_stride
+    }
+
+    /// Number of rows of the chroma planes
+    pub fn chroma_height(&self) -> usize {
+        kernel::chroma_dimensions(self.width as usize, self.height as usize).1
+    }
+
+    /// The whole buffer with all planes
+    pub fn data(&self) -> &[u8] {
+        &self.data[self.start..]
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..]
+    }
+
+    /// The buffer with all planes, moved to the start of the `Vec` if the frame is aligned
+    pub fn into_vec(mut self) -> Vec<u8> {
+        self.data.drain(..self.start);
+        self.data
+    }
+
+    /// The luma plane
+    pub fn y(&self) -> &[u8] {
+        &self.data()[..self.y_size()]
+    }
+
+    pub fn y_mut(&mut self) -> &mut [u8] {
+        let size = self.y_size();
+        &mut self.data_mut()[..size]
+    }
+
+    /// The U plane of an I420 frame
+    pub fn u(&self) -> Option<&[u8]> {
+        match self.format {
+            YuvFormat::I420 => Some(&self.data()[self.y_size()..][..self.chroma_size()]),
+            YuvFormat::Nv12 => None,
+        }
+    }
+
+    /// The V plane of an I420 frame
+    pub fn v(&self) -> Option<&[u8]> {
+        match self.format {
+            YuvFormat::I420 => Some(&self.data()[self.y_size() + self.chroma_size()..][..self.chroma_size()]),
+            YuvFormat::Nv12 => None,
+        }
+    }
+
+    /// The interleaved chroma plane of an NV12 frame
+    pub fn uv(&self) -> Option<&[u8]> {
+        match self.format {
+            YuvFormat::I420 => None,
+            YuvFormat::Nv12 => Some(&self.data()[self.y_size()..][..self.chroma_size()]),
+        }
+    }
+
+    /// Distance between the starts of two alpha rows in bytes: `y_stride`, or the chroma
+    /// width rounded up to the alignment for a subsampled alpha plane
+    pub fn alpha_stride(&self) -> usize {
+        if self.alpha_subsampled {
+            let (chroma_width, _) = kernel::chroma_dimensions(self.width as usize, 0);
+            chroma_width.div_ceil(self.alignment) * self.alignment
+        } else {
+            self.y_stride
+        }
+    }
+
+    // the width and height of the alpha plane
+    pub(crate) fn alpha_dimensions(&self) -> (usize, usize) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if self.alpha_subsampled { kernel::chroma_dimensions(width, height) } else { (width, height) }
+    }
+
+    /// The alpha plane, with the rows `alpha_stride` apart, if the frame has one
+    pub fn alpha(&self) -> Option<&[u8]> {
+        if self.alpha { Some(&self.data()[self.alpha_offset()..]) } else { None }
+    }
+
+    pub fn alpha_mut(&mut self) -> Option<&mut [u8]> {
+        self.split_planes().3
+    }
+
+    /// The luma plane and the chroma planes (U and V for I420, UV for NV12), mutably
+    pub fn planes_mut(&mut self) -> (&mut [u8], &mut [u8], Option<&mut [u8]>) {
+        let (y, chroma, v, _) = self.split_planes();
+        (y, chroma, v)
+    }
+
+    /// The luma and chroma planes with their strides
+    pub fn as_planes(&self) -> YuvPlanes<'_> {
+        let (width, height) = (self.width, self.height);
+        match (self.u(), self.v(), self.uv()) {
+            (Some(u), Some(v), _) => YuvPlanes::i420(width, height, self.y(), self.y_stride, u, v, self.chroma_stride),
+            (_, _, Some(uv)) => YuvPlanes::nv12(width, height, self.y(), self.y_stride, uv, self.chroma_stride),
+            _ => unreachable!("frames have either U and V or UV planes"),
+        }.expect("the planes of a frame hold all of its rows")
+    }
+
+    /// The luma and chroma planes as a frame to write into, eg.: with `blend_yuv`
+    pub fn as_frame_mut(&mut self) -> FrameMut<'_> {
+        let (width, height, y_stride, chroma_stride) = (self.width, self.height, self.y_stride, self.chroma_stride);
+        match self.split_planes() {
+            (y, u, Some(v), _) => FrameMut::i420(width, height, y, y_stride, u, v, chroma_stride),
+            (y, uv, None, _) => FrameMut::nv12(width, height, y, y_stride, uv, chroma_stride),
+        }.expect("the planes of a frame hold all of its rows")
+    }
+
+    // the planes of `planes_mut` and the alpha plane
+    #[allow(clippy::type_complexity)]
+    fn split_planes(&mut self) -> (&mut [u8], &mut [u8], Option<&mut [u8]>, Option<&mut [u8]>) {
+        let (y_size, chroma_size, format, alpha) = (self.y_size(), self.chroma_size(), self.format, self.alpha);
+        let planes = self.format_planes();
+        let (y, chroma) = self.data[self.start..].split_at_mut(y_size);
+        let (chroma, rest) = chroma.split_at_mut(chroma_size * planes);
+        let alpha = if alpha { Some(rest) } else { None };
+        match format {
+            YuvFormat::I420 => {
+                let (u, v) = chroma.split_at_mut(chroma_size);
+                (y, u, Some(v), alpha)
+            }
+            YuvFormat::Nv12 => (y, chroma, None, alpha),
+        }
+    }
+
+    /// Moves the frame behind an `Arc` so it can be shared between consumers
+    pub fn into_shared(self) -> SharedYuvFrame {
+        SharedYuvFrame(Arc::new(self))
+    }
+
+    fn y_size(&self) -> usize {
+        self.y_stride * self.height as usize
+    }
+
+    // size of one chroma plane
+    fn chroma_size(&self) -> usize {
+        self.chroma_stride * self.chroma_height()
+    }
+
+    fn alpha_size(&self) -> usize {
+        if self.alpha { self.alpha_stride() * self.alpha_dimensions().1 } else { 0 }
+    }
+
+    // where the alpha plane starts, after the chroma planes
+    fn alpha_offset(&self) -> usize {
+        self.y_size() + self.format_planes() * self.chroma_size()
+    }
+
+    fn format_planes(&self) -> usize {
+        match self.format {
+            YuvFormat::I420 => 2,
+            YuvFormat::Nv12 => 1,
+        }
+    }
+}
+
+// appends the averages of the 2x2 samples of `size` samples of `sample` bytes, repeating the
+// last column and row of odd sizes
+fn halve_plane(plane: &[u8], stride: usize, sample: usize, size: (usize, usize), out: &mut Vec<u8>) {
+    let (width, height) = size;
+    for row in (0..height).step_by(2) {
+        let rows = (&plane[row * stride..], &plane[(row + 1).min(height - 1) * stride..]);
+        for x in (0..width).step_by(2) {
+            let (left, right) = (x * sample, (x + 1).min(width - 1) * sample);
+            for c in 0..sample {
+                let sum = u16::from(rows.0[left + c]) + u16::from(rows.0[right + c])
+                    + u16::from(rows.1[left + c]) + u16::from(rows.1[right + c]);
+                out.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+}
+
+// writes the `size` samples of `sample` bytes of `plane` into the rows of `out`, rotated
+// clockwise by `rotation`
+fn rotate_plane(plane: &[u8], stride: usize, sample: usize, size: (usize, usize), rotation: Rotation, out: &mut [u8],
+                out_stride: usize) {
+    let (width, height) = size;
+    let out_width = match rotation {
+        Rotation::None | Rotation::Cw180 => width,
+        Rotation::Cw90 | Rotation::Cw270 => height,
+    };
+    if out_width == 0 {
+        return;
+    }
+    for (y, row) in out.chunks_mut(out_stride).take(width * height / out_width).enumerate() {
+        for (x, px) in row[..out_width * sample].chunks_exact_mut(sample).enumerate() {
+            let (sx, sy) = match rotation {
+                Rotation::None => (x, y),
+                Rotation::Cw90 => (y, height - 1 - x),
+                Rotation::Cw180 => (width - 1 - x, height - 1 - y),
+                Rotation::Cw270 => (width - 1 - y, x),
+            };
+            px.copy_from_slice(&plane[sy * stride + sx * sample..][..sample]);
+        }
+    }
+}
+
+// `visible` and `padded` are in samples of `sample` bytes
+fn extend_plane(plane: &mut [u8], stride: usize, sample: usize, visible: (usize, usize), padded: (usize, usize)) {
+    let (row_bytes, padded_row_bytes) = (visible.0 * sample, padded.0 * sample);
+    if row_bytes == 0 || visible.1 == 0 {
+        return;
+    }
+    for row in plane.chunks_mut(stride).take(visible.1) {
+        let (picture, padding) = row[..padded_row_bytes].split_at_mut(row_bytes);
+        let last = &picture[row_bytes - sample..];
+        for sample in padding.chunks_mut(sample) {
+            sample.copy_from_slice(last);
+        }
+    }
+    let (picture, padding) = plane.split_at_mut(visible.1 * stride);
+    let last = &picture[(visible.1 - 1) * stride..][..padded_row_bytes];
+    for row in padding.chunks_mut(stride).take(padded.1 - visible.1) {
+        row[..padded_row_bytes].copy_from_slice(last);
+    }
+}
+
+impl Clone for YuvFrame {
+    fn clone(&self) -> YuvFrame {
+        let mut frame = YuvFrame { data: Vec::new(), ..*self };
+        if self.alignment == 1 {
+            frame.data = self.data.clone();
+        } else {
+            frame.allocate();
+            frame.data_mut().copy_from_slice(self.data());
+        }
+        frame
+    }
+}
+
+impl PartialEq for YuvFrame {
+    fn eq(&self, other: &YuvFrame) -> bool {
+        self.descriptor() == other.descriptor() && self.data() == other.data()
+    }
+}
+
+impl Eq for YuvFrame {}
+
+impl Hash for YuvFrame {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.descriptor().hash(state);
+        self.data().hash(state);
+    }
+}
+
+/// An immutable `YuvFrame` with cheap, reference counted clones
+///
+/// Dereferences to the frame for the plane accessors, so one converted frame can be fanned
+/// out to an encoder, a preview and a recorder at the same time.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use rgb2yuv420::Converter;
+///
+/// let rgb = vec![0u8; 4 * 2 * 3];
+/// let frame = Converter::builder().build().convert(&rgb, 4, 2).unwrap().into_shared();
+/// let preview = frame.clone();
+/// let luma = thread::spawn(move || preview.y().len()).join().unwrap();
+/// assert_eq!(luma, 8);
+/// assert_eq!(frame.u(), Some(&[128, 128][..]));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SharedYuvFrame(Arc<YuvFrame>);
+
+impl SharedYuvFrame {
+    /// Returns the frame, cloning it if it is still shared
+    pub fn into_owned(self) -> YuvFrame {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| (*shared).clone())
+    }
+}
+
+impl Deref for SharedYuvFrame {
+    type Target = YuvFrame;
+
+    fn deref(&self) -> &YuvFrame {
+        &self.0
+    }
+}
+
+impl From<YuvFrame> for SharedYuvFrame {
+    fn from(frame: YuvFrame) -> SharedYuvFrame {
+        frame.into_shared()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YuvFrame;
+    use config::{ConversionConfig, Matrix, Range};
+    use config::ChromaFilter;
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+    use geometry::Rotation;
+    use view::RgbView;
+
+    #[test]
+    fn filled_frames_match_converted_ones() {
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let rgb: Vec<u8> = [200u8, 30, 90].iter().cloned().cycle().take(5 * 3 * 3).collect();
+        for &format in [YuvFormat::I420, YuvFormat::Nv12].iter() {
+            let converted = ::convert(&RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap(), format, &cfg);
+            assert_eq!(YuvFrame::filled([200, 30, 90], format, 5, 3, &cfg), converted);
+        }
+    }
+
+    #[test]
+    fn planes_of_odd_sized_frames() {
+        let rgb = vec![255u8; 5 * 3 * 3];
+        let i420 = Converter::builder().build().convert(&rgb, 5, 3).unwrap();
+        assert_eq!(i420.y().len(), 15);
+        assert_eq!(i420.u().unwrap().len(), 6);
+        assert_eq!(i420.v().unwrap().len(), 6);
+        assert_eq!(i420.uv(), None);
+        let mut nv12 = Converter::builder().output(YuvFormat::Nv12).build().convert(&rgb, 5, 3).unwrap();
+        assert_eq!(nv12.chroma_stride(), 6);
+        assert_eq!(nv12.uv().unwrap().len(), 12);
+        assert_eq!(nv12.u(), None);
+        let (y, uv, v) = nv12.planes_mut();
+        assert_eq!((y.len(), uv.len(), v), (15, 12, None));
+    }
+
+    #[test]
+    fn aligned_planes_and_rows() {
+        let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let packed = Converter::builder().output(format).build().convert(&rgb, 7, 5).unwrap();
+            let frame = Converter::builder().output(format).alignment(64).build().convert(&rgb, 7, 5).unwrap();
+            assert_ne!(frame, packed);
+            for frame in &[frame.clone(), frame] {
+                assert_eq!((frame.y_stride(), frame.chroma_stride()), (64, 64));
+                let planes = [Some(frame.y()), frame.u(), frame.v(), frame.uv()];
+                for plane in planes.iter().flatten() {
+                    assert_eq!(plane.as_ptr() as usize % 64, 0);
+                }
+                let rows = |plane: &[u8], stride: usize, width: usize| -> Vec<u8> {
+                    plane.chunks(stride).flat_map(|row| row[..width].to_vec()).collect()
+                };
+                assert_eq!(rows(frame.y(), 64, 7), packed.y());
+                let chroma = frame.u().or(frame.uv()).unwrap();
+                assert_eq!(rows(chroma, 64, packed.chroma_stride()), packed.u().or(packed.uv()).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn padding_repeats_the_edges() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 53 % 256) as u8).collect();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let packed = Converter::builder().output(format).build().convert(&rgb, 5, 3).unwrap();
+            let padded = Converter::builder().output(format).pad_to(8).alignment(16).build()
+                .convert(&rgb, 5, 3).unwrap();
+            assert_eq!((padded.width(), padded.height(), padded.y_stride()), (8, 8, 16));
+            for (row, padded_row) in padded.y().chunks(16).enumerate() {
+                let row = &packed.y()[row.min(2) * 5..][..5];
+                assert_eq!(&padded_row[..5], row);
+                assert!(padded_row[5..8].iter().all(|&y| y == row[4]));
+            }
+            // 3 x 2 chroma blocks padded to 4 x 4
+            let (sample, planes) = match format {
+                YuvFormat::I420 => (1, vec![(packed.u().unwrap(), padded.u().unwrap()),
+                                            (packed.v().unwrap(), padded.v().unwrap())]),
+                YuvFormat::Nv12 => (2, vec![(packed.uv().unwrap(), padded.uv().unwrap())]),
+            };
+            for (plane, padded_plane) in planes {
+                for (row, padded_row) in padded_plane.chunks(16).enumerate() {
+                    let row = &plane[row.min(1) * 3 * sample..][..3 * sample];
+                    assert_eq!(&padded_row[..3 * sample], row);
+                    assert_eq!(&padded_row[3 * sample..4 * sample], &row[2 * sample..]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alpha_plane_follows_the_luma_layout() {
+        let rgba: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 29 % 256) as u8).collect();
+        let alpha: Vec<u8> = rgba.chunks(4).map(|px| px[3]).collect();
+        let builder = || Converter::builder().input(ChannelOrder::Rgba).alpha_plane(true);
+        let frame = builder().pad_to(8).alignment(16).build().convert(&rgba, 5, 3).unwrap();
+        let plane = frame.alpha().unwrap();
+        assert_eq!((plane.len(), plane.as_ptr() as usize % 16), (8 * 16, 0));
+        for (row, padded_row) in plane.chunks(16).enumerate() {
+            let row = &alpha[row.min(2) * 5..][..5];
+            assert_eq!(&padded_row[..5], row);
+            assert!(padded_row[5..8].iter().all(|&a| a == row[4]));
+        }
+        // the transforms carry the alpha channel along
+        let flipped = builder().flip_horizontal(true).build().convert(&rgba, 5, 3).unwrap();
+        let mirrored: Vec<u8> = alpha.chunks(5).flat_map(|row| row.iter().rev().cloned()).collect();
+        assert_eq!(flipped.alpha(), Some(&mirrored[..]));
+        let opaque = Converter::builder().alpha_plane(true).build().convert(&rgba[..5 * 3 * 3], 5, 3).unwrap();
+        assert!(opaque.alpha().unwrap().iter().all(|&a| a == 255));
+        assert_eq!(Converter::builder().build().convert(&rgba[..45], 5, 3).unwrap().alpha(), None);
+    }
+
+    #[test]
+    fn subsampled_alpha_matches_the_chroma_layout() {
+        // a 3x3 image with alpha rows of 0, 100 and 200
+        let rgba: Vec<u8> = (0..3 * 3).flat_map(|i| [0, 0, 0, (i / 3 * 100 + i % 3 * 10) as u8]).collect();
+        let builder = |filter| Converter::builder().input(ChannelOrder::Rgba).output(YuvFormat::Nv12)
+            .subsampled_alpha(filter);
+        let average = builder(ChromaFilter::Average).build().convert(&rgba, 3, 3).unwrap();
+        assert_eq!(average.alpha(), Some(&[55, 70, 205, 220][..]));
+        let point = builder(ChromaFilter::Point).build().convert(&rgba, 3, 3).unwrap();
+        assert_eq!(point.alpha(), Some(&[10, 20, 210, 220][..]));
+        let mut aligned = builder(ChromaFilter::Average).alignment(16).build().convert(&rgba, 3, 3).unwrap();
+        assert_eq!(aligned.alpha_stride(), 16);
+        assert_eq!(&aligned.alpha().unwrap()[16..18], &[205, 220]);
+        assert_eq!(aligned.clone(), aligned);
+        aligned.rotate(Rotation::Cw90);
+        assert_eq!(&aligned.alpha().unwrap()[..2], &[205, 55]);
+        let mut mirrored = average.clone();
+        mirrored.mirror();
+        // the odd width puts the mirrored samples between the blocks
+        assert_eq!(mirrored.alpha(), Some(&[63, 55, 213, 205][..]));
+        let opaque = builder(ChromaFilter::Neutral).build().convert(&rgba, 3, 3).unwrap();
+        assert_eq!(opaque.alpha(), Some(&[255; 4][..]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn descriptor_round_trips_through_serde() {
+        extern crate serde_json;
+
+        use config::{Matrix, Range};
+        use format::FrameDescriptor;
+
+        let frame = Converter::builder().output(YuvFormat::Nv12).matrix(Matrix::Bt709).range(Range::Limited)
+            .build().convert(&[0u8; 6 * 4 * 3], 6, 4).unwrap();
+        let descriptor = frame.descriptor();
+        assert_eq!((descriptor.y_stride, descriptor.chroma_stride, descriptor.matrix), (6, 6, Matrix::Bt709));
+        let json = serde_json::to_string(&descriptor).unwrap();
+        assert_eq!(serde_json::from_str::<FrameDescriptor>(&json).unwrap(), descriptor);
+    }
+
+    #[test]
+    fn rotated_frames_match_rotated_conversions() {
+        let rgb: Vec<u8> = (0..6 * 4 * 4).map(|i| (i * 59 % 256) as u8).collect();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            // averaged chroma doesn't depend on which pixel of a block ends up where
+            let builder = Converter::builder().input(ChannelOrder::Rgba).output(format)
+                .chroma_filter(ChromaFilter::Average).alpha_plane(true).alignment(16);
+            for &rotation in &[Rotation::Cw90, Rotation::Cw180, Rotation::Cw270] {
+                let mut frame = builder.clone().build().convert(&rgb, 6, 4).unwrap();
+                frame.rotate(rotation);
+                let converted = builder.clone().rotate(rotation).build().convert(&rgb, 6, 4).unwrap();
+                assert_eq!(frame, converted, "{:?}", rotation);
+                assert_eq!(frame.y().as_ptr() as usize % 16, 0);
+            }
+        }
+        let mut odd = Converter::builder().build().convert(&rgb[..5 * 3 * 3], 5, 3).unwrap();
+        odd.rotate(Rotation::Cw270);
+        assert_eq!((odd.width(), odd.height(), odd.u().unwrap().len()), (3, 5, 2 * 3));
+    }
+}
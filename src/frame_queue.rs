@@ -0,0 +1,187 @@
+//! A bounded queue of converted frames between the stages of a live pipeline
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use frame::YuvFrame;
+
+/// What pushing into a full `FrameQueue` does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backpressure {
+    /// Drops the oldest queued frame, so a slow consumer gets the latest frames and the
+    /// producer never stalls, as live capture wants it
+    DropOldest,
+    /// Blocks the producer until the consumer takes a frame, as a file encode wants it
+    Block,
+}
+
+#[derive(Debug)]
+struct State {
+    frames: VecDeque<YuvFrame>,
+    dropped: u64,
+    closed: bool,
+}
+
+/// A bounded first in, first out queue of `YuvFrame`s between a producer and a consumer
+/// thread, eg.: a converter and an encoder
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Backpressure, ConversionConfig, FrameQueue, YuvFormat, YuvFrame};
+///
+/// let queue = FrameQueue::new(2, Backpressure::DropOldest);
+/// let cfg = ConversionConfig::default();
+/// for gray in 0..3u8 {
+///     queue.push(YuvFrame::filled([gray; 3], YuvFormat::I420, 2, 2, &cfg)).unwrap();
+/// }
+/// queue.close();
+/// // the first frame made room for the third
+/// assert_eq!(queue.dropped(), 1);
+/// assert_eq!(queue.pop().map(|frame| frame.y()[0]), Some(1));
+/// assert_eq!(queue.pop().map(|frame| frame.y()[0]), Some(2));
+/// assert!(queue.pop().is_none());
+/// ```
+#[derive(Debug)]
+pub struct FrameQueue {
+    capacity: usize,
+    backpressure: Backpressure,
+    state: Mutex<State>,
+    // signalled when a frame is pushed, taken or the queue is closed
+    changed: Condvar,
+}
+
+impl FrameQueue {
+    /// A queue of at most `capacity` (at least 1) frames
+    pub fn new(capacity: usize, backpressure: Backpressure) -> FrameQueue {
+        let capacity = capacity.max(1);
+        FrameQueue {
+            capacity,
+            backpressure,
+            state: Mutex::new(State { frames: VecDeque::with_capacity(capacity), dropped: 0, closed: false }),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn backpressure(&self) -> Backpressure {
+        self.backpressure
+    }
+
+    /// Number of frames queued
+    pub fn len(&self) -> usize {
+        self.lock().frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of frames `Backpressure::DropOldest` dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.lock().dropped
+    }
+
+    /// Queues `frame` and returns the frame it dropped, if any, eg.: to convert the next
+    /// frame into instead of allocating one
+    ///
+    /// # Errors
+    ///
+    /// Gives `frame` back if the queue is closed, also while it was blocked
+    pub fn push(&self, frame: YuvFrame) -> Result<Option<YuvFrame>, YuvFrame> {
+        let mut state = self.lock();
+        let mut dropped = None;
+        loop {
+            if state.closed {
+                return Err(frame);
+            }
+            if state.frames.len() < self.capacity {
+                break;
+            }
+            match self.backpressure {
+                Backpressure::DropOldest => {
+                    dropped = state.frames.pop_front();
+                    state.dropped += 1;
+                }
+                Backpressure::Block => {
+                    state = self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+            }
+        }
+        state.frames.push_back(frame);
+        self.changed.notify_all();
+        Ok(dropped)
+    }
+
+    /// Takes the oldest frame, blocking until there is one; `None` once the queue is closed
+    /// and drained
+    pub fn pop(&self) -> Option<YuvFrame> {
+        let mut state = self.lock();
+        loop {
+            if let Some(frame) = state.frames.pop_front() {
+                self.changed.notify_all();
+                return Some(frame);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    /// Takes the oldest frame if there is one, without blocking
+    pub fn try_pop(&self) -> Option<YuvFrame> {
+        let frame = self.lock().frames.pop_front();
+        if frame.is_some() {
+            self.changed.notify_all();
+        }
+        frame
+    }
+
+    /// Refuses further frames and wakes the blocked threads; the queued frames can still be
+    /// taken
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{Backpressure, FrameQueue};
+    use config::{Colorimetry, ConversionConfig};
+    use format::YuvFormat;
+    use frame::YuvFrame;
+
+    #[test]
+    fn blocking_queues_keep_every_frame() {
+        let queue = Arc::new(FrameQueue::new(1, Backpressure::Block));
+        let producer = Arc::clone(&queue);
+        let handle = thread::spawn(move || {
+            let cfg = ConversionConfig::from(Colorimetry::Bt601FullJfif);
+            for gray in 0..16u8 {
+                let frame = YuvFrame::filled([gray; 3], YuvFormat::Nv12, 2, 2, &cfg);
+                assert_eq!(producer.push(frame).map(|dropped| dropped.is_none()), Ok(true));
+                assert!(producer.len() <= 1);
+            }
+            producer.close();
+        });
+        let grays: Vec<u8> = (0..).map_while(|_| queue.pop()).map(|frame| frame.y()[0]).collect();
+        handle.join().unwrap();
+        assert_eq!(grays, (0..16).collect::<Vec<u8>>());
+        assert_eq!(queue.dropped(), 0);
+        let frame = YuvFrame::filled([0; 3], YuvFormat::Nv12, 2, 2, &ConversionConfig::default());
+        assert!(queue.push(frame).is_err());
+        assert!(queue.try_pop().is_none());
+    }
+}
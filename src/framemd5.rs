@@ -0,0 +1,244 @@
+//! Frame checksums in the format of ffmpeg's `framemd5` muxer
+
+use std::io::{self, Write};
+
+use format::YuvFormat;
+use layout;
+use raw;
+use split::YuvPlanes;
+
+// shifts of the rounds and the sines they add
+const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+const SINES: [u32; 64] = [
+    0xd76a_a478, 0xe8c7_b756, 0x2420_70db, 0xc1bd_ceee, 0xf57c_0faf, 0x4787_c62a, 0xa830_4613, 0xfd46_9501,
+    0x6980_98d8, 0x8b44_f7af, 0xffff_5bb1, 0x895c_d7be, 0x6b90_1122, 0xfd98_7193, 0xa679_438e, 0x49b4_0821,
+    0xf61e_2562, 0xc040_b340, 0x265e_5a51, 0xe9b6_c7aa, 0xd62f_105d, 0x0244_1453, 0xd8a1_e681, 0xe7d3_fbc8,
+    0x21e1_cde6, 0xc337_07d6, 0xf4d5_0d87, 0x455a_14ed, 0xa9e3_e905, 0xfcef_a3f8, 0x676f_02d9, 0x8d2a_4c8a,
+    0xfffa_3942, 0x8771_f681, 0x6d9d_6122, 0xfde5_380c, 0xa4be_ea44, 0x4bde_cfa9, 0xf6bb_4b60, 0xbebf_bc70,
+    0x289b_7ec6, 0xeaa1_27fa, 0xd4ef_3085, 0x0488_1d05, 0xd9d4_d039, 0xe6db_99e5, 0x1fa2_7cf8, 0xc4ac_5665,
+    0xf429_2244, 0x432a_ff97, 0xab94_23a7, 0xfc93_a039, 0x655b_59c3, 0x8f0c_cc92, 0xffef_f47d, 0x8584_5dd1,
+    0x6fa8_7e4f, 0xfe2c_e6e0, 0xa301_4314, 0x4e08_11a1, 0xf753_7e82, 0xbd3a_f235, 0x2ad7_d2bb, 0xeb86_d391,
+];
+
+// MD5, written to like a file
+struct Md5 {
+    state: [u32; 4],
+    block: [u8; 64],
+    len: u64,
+}
+
+impl Md5 {
+    fn new() -> Md5 {
+        Md5 { state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476], block: [0; 64], len: 0 }
+    }
+
+    fn compress(&mut self) {
+        let mut words = [0; 16];
+        for (word, bytes) in words.iter_mut().zip(self.block.chunks(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let [mut a, mut b, mut c, mut d] = self.state;
+        for i in 0..64 {
+            let (f, word) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), 7 * i % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(SINES[i]).wrapping_add(words[word]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(SHIFTS[i / 16 * 4 + i % 4]));
+        }
+        for (state, word) in self.state.iter_mut().zip([a, b, c, d]) {
+            *state = state.wrapping_add(word);
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let at = (self.len % 64) as usize;
+            let taken = data.len().min(64 - at);
+            self.block[at..at + taken].copy_from_slice(&data[..taken]);
+            self.len += taken as u64;
+            data = &data[taken..];
+            if self.len.is_multiple_of(64) {
+                self.compress();
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        let bits = self.len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.len % 64 != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_le_bytes());
+        let mut digest = [0; 16];
+        for (bytes, word) in digest.chunks_mut(4).zip(&self.state) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+}
+
+impl Write for Md5 {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.update(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn hex(digest: &[u8; 16]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// The MD5s of the Y, U and V planes of `frame`, tightly packed, eg.: to tell which plane
+/// differs from a reference
+///
+/// The U and V samples of NV12 frames are hashed apart, so a frame hashes the same in
+/// either format.
+pub fn md5_planes(frame: &YuvPlanes) -> [[u8; 16]; 3] {
+    let mut planes = [Md5::new(), Md5::new(), Md5::new()];
+    for row in frame.y().rows() {
+        planes[0].update(row);
+    }
+    match (frame.u(), frame.v(), frame.uv()) {
+        (Some(u), Some(v), _) => {
+            for (u, v) in u.rows().zip(v.rows()) {
+                planes[1].update(u);
+                planes[2].update(v);
+            }
+        }
+        (_, _, Some(uv)) => {
+            for row in uv.rows() {
+                for sample in row.chunks(2) {
+                    planes[1].update(&sample[..1]);
+                    planes[2].update(&sample[1..]);
+                }
+            }
+        }
+        _ => unreachable!("frames have either U and V or UV planes"),
+    }
+    planes.map(Md5::finish)
+}
+
+/// Writes a checksum of every frame in the format of `ffmpeg -f framemd5`, so converted
+/// frames can be diffed against the output of a reference pipeline
+///
+/// Frames are hashed tightly packed in the writer's format, which is what ffmpeg hashes
+/// for `-f rawvideo -pix_fmt yuv420p` (I420) or `nv12` input. The time base is one frame,
+/// as ffmpeg gives rawvideo, so the timestamps of frame `n` are `n`.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{FrameMd5Writer, YuvFormat, YuvPlanes};
+///
+/// let mut writer = FrameMd5Writer::new(Vec::new(), YuvFormat::I420, 2, 2, (25, 1)).unwrap();
+/// writer.write_frame(&YuvPlanes::split(&[0; 6], YuvFormat::I420, 2, 2).unwrap()).unwrap();
+/// let output = String::from_utf8(writer.into_inner()).unwrap();
+/// assert!(output.starts_with("#format: frame checksums\n#version: 2\n#hash: MD5\n#tb 0: 1/25\n"));
+/// assert!(output.ends_with("\n0,          0,          0,        1,        6, 7319468847d7b1aee40dbf5dd963c999\n"));
+/// ```
+#[derive(Debug)]
+pub struct FrameMd5Writer<W: Write> {
+    out: W,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    frames: u64,
+    // a chroma row of a frame in the other format, repacked
+    row: Vec<u8>,
+}
+
+impl<W: Write> FrameMd5Writer<W> {
+    /// Writes the header for `width` x `height` frames in `format` at `framerate` frames
+    /// per second into `out`
+    ///
+    /// # Errors
+    ///
+    /// The error of writing the header
+    pub fn new(mut out: W, format: YuvFormat, width: u32, height: u32, framerate: (u32, u32))
+               -> io::Result<FrameMd5Writer<W>> {
+        write!(out, "#format: frame checksums\n#version: 2\n#hash: MD5\n#tb 0: {}/{}\n", framerate.1, framerate.0)?;
+        write!(out, "#media_type 0: video\n#codec_id 0: rawvideo\n#dimensions 0: {}x{}\n#sar 0: 1/1\n", width, height)?;
+        out.write_all(b"#stream#, dts,        pts, duration,     size, hash\n")?;
+        Ok(FrameMd5Writer { out, format, width, height, frames: 0, row: Vec::new() })
+    }
+
+    /// Writes the checksum of `frame`
+    ///
+    /// # Errors
+    ///
+    /// The error of writing, or an `InvalidInput` error holding `UnsupportedDimensions` if
+    /// `frame` isn't the size of the others
+    pub fn write_frame(&mut self, frame: &YuvPlanes) -> io::Result<()> {
+        raw::check_size((frame.width(), frame.height()), self.width, self.height)?;
+        let mut md5 = Md5::new();
+        raw::write_planes(&mut md5, frame, self.format, &mut self.row)?;
+        let size = layout::required_buffer_size(self.format, self.width, self.height);
+        let (frame, hash) = (self.frames, hex(&md5.finish()));
+        writeln!(self.out, "0, {:>10}, {:>10}, {:>8}, {:>8}, {}", frame, frame, 1, size, hash)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex, md5_planes, FrameMd5Writer, Md5};
+    use format::YuvFormat;
+    use split::YuvPlanes;
+
+    #[test]
+    fn checksums_match_ffmpeg() {
+        let md5 = |data: &[u8]| {
+            let mut md5 = Md5::new();
+            md5.update(data);
+            hex(&md5.finish())
+        };
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"The quick brown fox jumps over the lazy dog"), "9e107d9d372bb6826bd81d3542a419d6");
+        assert_eq!(md5(&[b'a'; 1000]), "cabe45dcc9ae5b66ba86600cca6b8ba8");
+        let data: Vec<u8> = (0..12).collect();
+        let frame = YuvPlanes::split(&data, YuvFormat::I420, 4, 2).unwrap();
+        // hashed in the writer's format
+        for &(format, hash) in &[(YuvFormat::I420, "50a73d7013e9803e3b20888f8fcafb15"),
+                                 (YuvFormat::Nv12, "735eb679b638755cd20657beba9d25f9")] {
+            let mut writer = FrameMd5Writer::new(Vec::new(), format, 4, 2, (30000, 1001)).unwrap();
+            writer.write_frame(&frame).unwrap();
+            writer.write_frame(&frame).unwrap();
+            let output = String::from_utf8(writer.into_inner()).unwrap();
+            let lines: Vec<_> = output.lines().collect();
+            assert_eq!(lines[3], "#tb 0: 1001/30000");
+            assert_eq!(lines[6], "#dimensions 0: 4x2");
+            assert_eq!(lines[10], format!("0,          1,          1,        1,       12, {}", hash));
+        }
+        let planes = md5_planes(&frame).map(|digest| hex(&digest));
+        assert_eq!(planes, ["3677509751ccf61539174d2b9635a7bf", "9ed4a12cf365a4e7f4569fee07c1e276",
+                            "5b1ad04637eedf255ed4f452cd26b3ed"]);
+        let nv12 = [0, 1, 2, 3, 4, 5, 6, 7, 8, 10, 9, 11];
+        let frame = YuvPlanes::split(&nv12, YuvFormat::Nv12, 4, 2).unwrap();
+        assert_eq!(md5_planes(&frame).map(|digest| hex(&digest)), planes);
+    }
+}
@@ -0,0 +1,710 @@
+//! Transforming the image while converting it
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::thread;
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{PixelLayout, YuvFormat};
+use kernel::{Planes, Setup, Source};
+#[cfg(feature = "std")]
+use threads;
+
+/// Clockwise rotation applied while converting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rotation {
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+/// How the pixels of a scaled frame are sampled from the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScaleFilter {
+    /// Takes the closest pixel
+    Nearest,
+    /// Interpolates between the 4 closest pixels (which aliases when shrinking by more than
+    /// half)
+    Bilinear,
+    /// Averages the pixels each scaled pixel covers, which doesn't alias when shrinking (and
+    /// comes down to `Nearest` when enlarging)
+    Box,
+}
+
+/// A rectangle of pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What transparent pixels are composited over
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backdrop {
+    Color([u8; 3]),
+    /// Squares of `size` pixels in the two colors, starting with the first one in the top
+    /// left corner of the frame
+    Checkerboard { size: u32, colors: [[u8; 3]; 2] },
+}
+
+impl Backdrop {
+    fn color_at(&self, x: usize, y: usize) -> [u8; 3] {
+        match *self {
+            Backdrop::Color(color) => color,
+            Backdrop::Checkerboard { size, colors } => {
+                let size = (size as usize).max(1);
+                colors[(x / size + y / size) % 2]
+            }
+        }
+    }
+}
+
+/// A small RGBA image blended over the converted frames, eg.: a logo or an on-screen display
+///
+/// The color channels are not premultiplied by alpha.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Converter, Overlay};
+///
+/// // a half transparent white square in the top right corner
+/// let logo = Overlay::new([255, 255, 255, 128].repeat(2 * 2), 2, 2).unwrap();
+/// let converter = Converter::builder().overlay(logo, 2, 0).build();
+/// let frame = converter.convert(&[0u8; 4 * 2 * 3], 4, 2).unwrap();
+/// assert_eq!(frame.y(), &[0, 0, 128, 128, 0, 0, 128, 128][..]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Overlay {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl Overlay {
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `rgba` holds fewer than `width * height` pixels
+    pub fn new(rgba: Vec<u8>, width: u32, height: u32) -> Result<Overlay, ConversionError> {
+        let expected = width as usize * height as usize * 4;
+        if rgba.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: rgba.len() });
+        }
+        Ok(Overlay { rgba, width, height })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // blends the part of row `row` of the overlay, put at `x`, that falls on `out`, a row of
+    // pixels in `layout`
+    fn blend_row(&self, x: usize, row: usize, layout: PixelLayout, wide: bool, out: &mut [u8]) {
+        let bpp = if wide { 2 * layout.bytes_per_pixel } else { layout.bytes_per_pixel };
+        let width = self.width as usize;
+        let pixels = &self.rgba[row * width * 4..][..width * 4];
+        for (px, overlay) in out.chunks_mut(bpp).skip(x).zip(pixels.chunks(4)) {
+            let a = u32::from(overlay[3]);
+            for (&channel, &color) in [layout.r, layout.g, layout.b].iter().zip(overlay) {
+                let color = u32::from(color);
+                if wide {
+                    let below = u32::from(u16::from_ne_bytes([px[2 * channel], px[2 * channel + 1]]));
+                    let blended = (color * 257 * a + below * (255 - a) + 127) / 255;
+                    px[2 * channel..][..2].copy_from_slice(&(blended as u16).to_ne_bytes());
+                } else {
+                    px[channel] = ((color * a + u32::from(px[channel]) * (255 - a) + 127) / 255) as u8;
+                }
+            }
+            // the overlay covers the pixels below it by its own opacity
+            if let Some(alpha) = layout.alpha {
+                if wide {
+                    let below = u32::from(u16::from_ne_bytes([px[2 * alpha], px[2 * alpha + 1]]));
+                    let covered = a * 257 + (below * (255 - a) + 127) / 255;
+                    px[2 * alpha..][..2].copy_from_slice(&(covered as u16).to_ne_bytes());
+                } else {
+                    px[alpha] = (a + (u32::from(px[alpha]) * (255 - a) + 127) / 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// How the pixels of the converted frame are derived from the source image
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct Geometry {
+    pub rotation: Rotation,
+    // mirror the rotated image
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    // size the rotated image is scaled to, or fitted into with bars of `background` around it
+    pub size: Option<(usize, usize)>,
+    pub filter: ScaleFilter,
+    pub letterbox: bool,
+    pub background: [u8; 3],
+    // where the picture goes in the frame, instead of filling or fitting it
+    pub destination: Option<Rect>,
+    // what the alpha channel (if the source has one) is composited over
+    pub composite: Option<Backdrop>,
+    // blended over the frame with its top left corner at the position
+    pub overlay: Option<(Arc<Overlay>, usize, usize)>,
+}
+
+impl Default for Geometry {
+    fn default() -> Geometry {
+        Geometry {
+            rotation: Rotation::None,
+            flip_horizontal: false,
+            flip_vertical: false,
+            size: None,
+            filter: ScaleFilter::Bilinear,
+            letterbox: false,
+            background: [0; 3],
+            destination: None,
+            composite: None,
+            overlay: None,
+        }
+    }
+}
+
+// luma rows gathered and converted at a time, few enough to stay in the cache
+const CHUNK_ROWS: usize = 16;
+
+impl Geometry {
+    /// Whether `src` is converted as it is
+    pub fn is_identity(&self, src: &Source) -> bool {
+        let (width, height) = (src.width, src.height);
+        self.rotation == Rotation::None && !self.flip_horizontal && !self.flip_vertical
+            && (self.composite.is_none() || src.layout.alpha.is_none()) && self.overlay.is_none()
+            && self.output_size(width, height) == (width, height)
+            && self.picture(width, height) == (0, 0, width, height)
+    }
+
+    /// Size of the frame converted from a `width` x `height` image
+    pub fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match (self.size, self.destination) {
+            (Some(size), _) => size,
+            (None, Some(rect)) => (rect.x as usize + rect.width as usize, rect.y as usize + rect.height as usize),
+            (None, None) => self.rotated_size(width, height),
+        }
+    }
+
+    fn rotated_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.rotation {
+            Rotation::None | Rotation::Cw180 => (width, height),
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+        }
+    }
+
+    // where the picture of a `width` x `height` image is in the converted frame (x, y,
+    // width, height)
+    fn picture(&self, width: usize, height: usize) -> (usize, usize, usize, usize) {
+        if let Some(rect) = self.destination {
+            return (rect.x as usize, rect.y as usize, rect.width as usize, rect.height as usize);
+        }
+        let (frame_width, frame_height) = self.output_size(width, height);
+        if !self.letterbox {
+            return (0, 0, frame_width, frame_height);
+        }
+        let (rotated_width, rotated_height) = self.rotated_size(width, height);
+        let (picture_width, picture_height) = if rotated_width * frame_height <= frame_width * rotated_height {
+            // pillarbox
+            (rounded_div(rotated_width * frame_height, rotated_height), frame_height)
+        } else {
+            (frame_width, rounded_div(rotated_height * frame_width, rotated_width))
+        };
+        // on the chroma grid, so the bars have no mixed chroma on the top and left edges
+        let x = ((frame_width - picture_width) / 2) & !1;
+        let y = ((frame_height - picture_height) / 2) & !1;
+        (x, y, picture_width, picture_height)
+    }
+
+    /// Converts `src` into `dst`, whose planes must hold a frame of `output_size`
+    ///
+    /// Rows of transformed pixels are gathered `CHUNK_ROWS` at a time and converted by the
+    /// kernel, so no transformed copy of the whole image is made; the chunks are split across
+    /// `cfg.threads` threads. Nothing is written if the image is empty.
+    pub fn run(&self, src: &Source, format: YuvFormat, cfg: &ConversionConfig, dst: Planes) {
+        if self.is_identity(src) {
+            Setup::new(src.width, src.height, src.layout, src.wide, format, cfg).run_planes(src, dst);
+            return;
+        }
+        let (width, height) = self.output_size(src.width, src.height);
+        if width * height * src.width * src.height == 0 {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("rgb2yuv420::transform", width, height, format = ?format,
+                                         rotation = ?self.rotation, flip_horizontal = self.flip_horizontal,
+                                         flip_vertical = self.flip_vertical, filter = ?self.filter,
+                                         composite = ?self.composite, overlay = self.overlay.is_some())
+            .entered();
+        let sampler = Sampler::new(self, src);
+        let chunks = height.div_ceil(CHUNK_ROWS);
+        #[cfg(feature = "std")]
+        let threads = threads::limit(cfg.threads).clamp(1, chunks);
+        #[cfg(not(feature = "std"))]
+        let threads = 1;
+        let group_rows = chunks.div_ceil(threads) * CHUNK_ROWS;
+        let mut groups = Vec::with_capacity(threads);
+        let mut rest = dst;
+        for first_row in (0..height).step_by(group_rows) {
+            let (group, tail) = rest.split_rows(group_rows);
+            groups.push((first_row, group));
+            rest = tail;
+        }
+        let cfg = ConversionConfig { threads: 1, ..cfg.clone() };
+        let convert = |first_row: usize, planes: Planes| {
+            let mut planes = planes;
+            let bpp = src.pixel_bytes();
+            let mut rgb = vec![0; CHUNK_ROWS * width * bpp];
+            let last_row = height.min(first_row + group_rows);
+            for row in (first_row..last_row).step_by(CHUNK_ROWS) {
+                let rows = CHUNK_ROWS.min(last_row - row);
+                for (i, out) in rgb.chunks_mut(width * bpp).take(rows).enumerate() {
+                    sampler.gather(row + i, out);
+                    if let Some(ref backdrop) = self.composite {
+                        sampler.composite(backdrop, row + i, out);
+                    }
+                    if let Some((ref overlay, x, y)) = self.overlay {
+                        if (y..y + overlay.height as usize).contains(&(row + i)) {
+                            overlay.blend_row(x, row + i - y, src.layout, src.wide, out);
+                        }
+                    }
+                }
+                let chunk = Source { data: &rgb, width, height: rows, stride: width * bpp, ..*src };
+                let (part, tail) = planes.split_rows(CHUNK_ROWS);
+                Setup::new(width, rows, src.layout, src.wide, format, &cfg).run_part(&chunk, part);
+                planes = tail;
+            }
+        };
+        #[cfg(feature = "std")]
+        {
+            if groups.len() > 1 {
+                let convert = &convert;
+                thread::scope(|s| {
+                    for (worker, (first_row, planes)) in groups.into_iter().enumerate() {
+                        s.spawn(move || {
+                            threads::pin_worker(worker);
+                            convert(first_row, planes)
+                        });
+                    }
+                });
+                return;
+            }
+        }
+        for (first_row, planes) in groups {
+            convert(first_row, planes);
+        }
+    }
+}
+
+/// Reads the pixels of the rotated, flipped and scaled image from the source
+struct Sampler<'a> {
+    src: &'a Source<'a>,
+    bpp: usize,
+    // the source pixel at `u`, `v` of the rotated and flipped image starts at
+    // `origin + u * du + v * dv` bytes
+    origin: isize,
+    dv: isize,
+    // the height of the rotated image and of the picture, and where the picture is
+    rows: (usize, usize),
+    picture: (usize, usize, usize, usize),
+    // in the layout of the source
+    background: Vec<u8>,
+    // `u * du` of the pixels each converted pixel is read from
+    columns: Columns,
+}
+
+enum Columns {
+    Nearest(Vec<isize>),
+    // the left and the right pixel, and the weight of the right one
+    Bilinear(Vec<(isize, isize, u32)>),
+    // `u * du` of the first of the pixels each one covers and how many it covers, and `du`
+    Box(Vec<(isize, usize)>, isize),
+}
+
+impl<'a> Sampler<'a> {
+    fn new(geometry: &Geometry, src: &'a Source<'a>) -> Sampler<'a> {
+        let (bpp, stride) = (src.pixel_bytes() as isize, src.stride as isize);
+        // where the first row of the image is, and how far its rows are apart
+        let (first, stride) = if src.bottom_up { ((src.height - 1) as isize * stride, -stride) } else { (0, stride) };
+        let (last_x, last_y) = ((src.width - 1) as isize * bpp, (src.height - 1) as isize * stride);
+        let (mut origin, mut du, mut dv) = match geometry.rotation {
+            Rotation::None => (first, bpp, stride),
+            Rotation::Cw90 => (first + last_y, -stride, bpp),
+            Rotation::Cw180 => (first + last_x + last_y, -bpp, -stride),
+            Rotation::Cw270 => (first + last_x, stride, -bpp),
+        };
+        let (rotated_width, rotated_height) = geometry.rotated_size(src.width, src.height);
+        if geometry.flip_horizontal {
+            origin += (rotated_width - 1) as isize * du;
+            du = -du;
+        }
+        if geometry.flip_vertical {
+            origin += (rotated_height - 1) as isize * dv;
+            dv = -dv;
+        }
+        let picture = geometry.picture(src.width, src.height);
+        let (width, height) = (picture.2, picture.3);
+        let layout = src.layout;
+        let mut background = vec![0; bpp as usize];
+        // opaque, so that compositing leaves the bars alone
+        let opaque = layout.alpha.map(|alpha| (alpha, 255));
+        for (channel, value) in [layout.r, layout.g, layout.b].iter().cloned().zip(geometry.background).chain(opaque) {
+            if src.wide {
+                background[2 * channel..][..2].copy_from_slice(&(u16::from(value) * 257).to_ne_bytes());
+            } else {
+                background[channel] = value;
+            }
+        }
+        let columns = match geometry.filter {
+            // unscaled pictures are copied, which is what both filters come down to
+            _ if (width, height) == (rotated_width, rotated_height) => {
+                Columns::Nearest((0..width).map(|x| x as isize * du).collect())
+            }
+            ScaleFilter::Nearest => {
+                Columns::Nearest((0..width).map(|x| nearest(x, rotated_width, width) as isize * du).collect())
+            }
+            ScaleFilter::Bilinear => Columns::Bilinear((0..width).map(|x| {
+                let (left, right, weight) = taps(x, rotated_width, width);
+                (left as isize * du, right as isize * du, weight)
+            }).collect()),
+            ScaleFilter::Box => Columns::Box((0..width).map(|x| {
+                let (first, count) = span(x, rotated_width, width);
+                (first as isize * du, count)
+            }).collect(), du),
+        };
+        Sampler { src, bpp: bpp as usize, origin, dv, rows: (rotated_height, height), picture, background, columns }
+    }
+
+    // writes the pixels of the converted row `row` into `out`
+    fn gather(&self, row: usize, out: &mut [u8]) {
+        let (data, bpp, (from, to)) = (self.src.data, self.bpp, self.rows);
+        let (x, y, width, height) = self.picture;
+        if row < y || row >= y + height {
+            for px in out.chunks_mut(bpp) {
+                px.copy_from_slice(&self.background);
+            }
+            return;
+        }
+        let row = row - y;
+        let (left, rest) = out.split_at_mut(x * bpp);
+        let (out, right) = rest.split_at_mut(width * bpp);
+        for px in left.chunks_mut(bpp).chain(right.chunks_mut(bpp)) {
+            px.copy_from_slice(&self.background);
+        }
+        let pixel = |v: usize, column: isize| &data[(self.origin + v as isize * self.dv + column) as usize..][..bpp];
+        match self.columns {
+            Columns::Nearest(ref columns) => {
+                let v = nearest(row, from, to);
+                for (px, &column) in out.chunks_mut(bpp).zip(columns) {
+                    px.copy_from_slice(pixel(v, column));
+                }
+            }
+            Columns::Bilinear(ref columns) => {
+                let (v0, v1, wy) = taps(row, from, to);
+                for (px, &(left, right, wx)) in out.chunks_mut(bpp).zip(columns) {
+                    let taps = [pixel(v0, left), pixel(v0, right), pixel(v1, left), pixel(v1, right)];
+                    let weights = [(256 - wx) * (256 - wy), wx * (256 - wy), (256 - wx) * wy, wx * wy];
+                    blend(px, taps, weights, self.src.wide);
+                }
+            }
+            Columns::Box(ref columns, du) => {
+                let (top, rows) = span(row, from, to);
+                let pixel = &pixel;
+                for (px, &(left, count)) in out.chunks_mut(bpp).zip(columns) {
+                    let covered = (top..top + rows)
+                        .flat_map(|v| (0..count).map(move |i| pixel(v, left + i as isize * du)));
+                    average(px, covered, rows * count, self.src.wide);
+                }
+            }
+        }
+    }
+
+    // composites the gathered row `row` over `backdrop` and makes it opaque
+    fn composite(&self, backdrop: &Backdrop, row: usize, out: &mut [u8]) {
+        let layout = self.src.layout;
+        let alpha = match layout.alpha {
+            Some(alpha) => alpha,
+            None => return,
+        };
+        for (x, px) in out.chunks_mut(self.bpp).enumerate() {
+            let color = backdrop.color_at(x, row);
+            if self.src.wide {
+                let read = |px: &[u8], channel: usize| {
+                    u64::from(u16::from_ne_bytes([px[2 * channel], px[2 * channel + 1]]))
+                };
+                let a = read(px, alpha);
+                for (&channel, &backdrop) in [layout.r, layout.g, layout.b].iter().zip(&color) {
+                    let blended = (read(px, channel) * a + u64::from(backdrop) * 257 * (65535 - a) + 32767) / 65535;
+                    px[2 * channel..][..2].copy_from_slice(&(blended as u16).to_ne_bytes());
+                }
+                px[2 * alpha..][..2].copy_from_slice(&[255; 2]);
+            } else {
+                let a = u32::from(px[alpha]);
+                for (&channel, &backdrop) in [layout.r, layout.g, layout.b].iter().zip(&color) {
+                    px[channel] = ((u32::from(px[channel]) * a + u32::from(backdrop) * (255 - a) + 127) / 255) as u8;
+                }
+                px[alpha] = 255;
+            }
+        }
+    }
+}
+
+fn rounded_div(a: usize, b: usize) -> usize {
+    (2 * a + b) / (2 * b)
+}
+
+// the pixel of `from` the center of sample `i` of `to` falls on
+pub(crate) fn nearest(i: usize, from: usize, to: usize) -> usize {
+    ((2 * i as u64 + 1) * from as u64 / (2 * to as u64)) as usize
+}
+
+// the two pixels of `from` the center of sample `i` of `to` falls between, and the weight
+// of the second one out of 256
+pub(crate) fn taps(i: usize, from: usize, to: usize) -> (usize, usize, u32) {
+    // in 1/256 pixels, from the center of the first pixel
+    let center = ((2 * i as u64 + 1) * from as u64 * 256 / (2 * to as u64)).saturating_sub(128);
+    let (first, weight) = ((center / 256) as usize, (center % 256) as u32);
+    if first + 1 >= from {
+        (from - 1, from - 1, 0)
+    } else {
+        (first, first + 1, weight)
+    }
+}
+
+// the first of the pixels of `from` sample `i` of `to` covers, and how many it covers
+pub(crate) fn span(i: usize, from: usize, to: usize) -> (usize, usize) {
+    let first = (i as u64 * from as u64 / to as u64) as usize;
+    let end = ((i as u64 + 1) * from as u64).div_ceil(to as u64) as usize;
+    (first, end - first)
+}
+
+// the channels of the `count` pixels of `pixels` averaged
+fn average<'p, I>(px: &mut [u8], pixels: I, count: usize, wide: bool) where I: Iterator<Item = &'p [u8]> {
+    let mut sums = [0u64; 8];
+    for pixel in pixels {
+        if wide {
+            for (sum, channel) in sums.iter_mut().zip(pixel.chunks_exact(2)) {
+                *sum += u64::from(u16::from_ne_bytes([channel[0], channel[1]]));
+            }
+        } else {
+            for (sum, &channel) in sums.iter_mut().zip(pixel) {
+                *sum += u64::from(channel);
+            }
+        }
+    }
+    let mean = |sum: u64| (2 * sum + count as u64) / (2 * count as u64);
+    if wide {
+        for (out, &sum) in px.chunks_exact_mut(2).zip(&sums) {
+            out.copy_from_slice(&(mean(sum) as u16).to_ne_bytes());
+        }
+    } else {
+        for (out, &sum) in px.iter_mut().zip(&sums) {
+            *out = mean(sum) as u8;
+        }
+    }
+}
+
+// the channels of `taps` weighted by `weights`, which add up to 65536
+fn blend(px: &mut [u8], taps: [&[u8]; 4], weights: [u32; 4], wide: bool) {
+    let mix = |channel: [u32; 4]| {
+        (channel.iter().zip(&weights).map(|(c, w)| c * w).sum::<u32>() + 32768) >> 16
+    };
+    if wide {
+        for (i, out) in px.chunks_mut(2).enumerate() {
+            let channel = taps.map(|tap| u16::from_ne_bytes([tap[2 * i], tap[2 * i + 1]]) as u32);
+            out.copy_from_slice(&(mix(channel) as u16).to_ne_bytes());
+        }
+    } else {
+        for (i, out) in px.iter_mut().enumerate() {
+            *out = mix(taps.map(|tap| tap[i] as u32)) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backdrop, Overlay, Rect, Rotation, ScaleFilter};
+    use config::Colorimetry;
+    use converter::{Converter, ConverterBuilder};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    // `rgb`, a `width` x `height` image, turned clockwise and then flipped, and its size
+    fn transform(rgb: &[u8], width: usize, height: usize, rotation: Rotation, flip: (bool, bool))
+                 -> (Vec<u8>, u32, u32) {
+        let (out_width, out_height) = match rotation {
+            Rotation::None | Rotation::Cw180 => (width, height),
+            Rotation::Cw90 | Rotation::Cw270 => (height, width),
+        };
+        let mut out = Vec::new();
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let x = if flip.0 { out_width - 1 - x } else { x };
+                let y = if flip.1 { out_height - 1 - y } else { y };
+                let (sx, sy) = match rotation {
+                    Rotation::None => (x, y),
+                    Rotation::Cw90 => (y, height - 1 - x),
+                    Rotation::Cw180 => (width - 1 - x, height - 1 - y),
+                    Rotation::Cw270 => (width - 1 - y, x),
+                };
+                out.extend_from_slice(&rgb[(sy * width + sx) * 3..][..3]);
+            }
+        }
+        (out, out_width as u32, out_height as u32)
+    }
+
+    // the bars and ramps below are in full swing BT.601, whatever the `default-*` features
+    fn full_swing() -> ConverterBuilder {
+        Converter::builder().colorimetry(Colorimetry::Bt601FullJfif)
+    }
+
+    #[test]
+    fn transforms_match_transforming_first() {
+        // tall enough for several chunks on several threads
+        let rgb: Vec<u8> = (0..7 * 37 * 3).map(|i| (i * 67 % 256) as u8).collect();
+        let flips = [(false, false), (true, false), (false, true), (true, true)];
+        for &rotation in &[Rotation::None, Rotation::Cw90, Rotation::Cw180, Rotation::Cw270] {
+            for &flip in &flips {
+                let (transformed, width, height) = transform(&rgb, 7, 37, rotation, flip);
+                for &threads in &[1, 3] {
+                    let builder = Converter::builder().output(YuvFormat::Nv12).threads(threads);
+                    let frame = builder.clone().rotate(rotation).flip_horizontal(flip.0).flip_vertical(flip.1)
+                        .build().convert(&rgb, 7, 37).unwrap();
+                    assert_eq!(frame, builder.build().convert(&transformed, width, height).unwrap());
+                }
+            }
+        }
+        let bgra = [1, 2, 3, 0, 4, 5, 6, 0];
+        let frame = Converter::builder().input(ChannelOrder::Bgra).rotate(Rotation::Cw90).plan(2, 1)
+            .convert(&bgra).unwrap();
+        assert_eq!((frame.width(), frame.height()), (1, 2));
+    }
+
+    #[test]
+    fn scaling_samples_the_image() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 29 % 256) as u8).collect();
+        // nearest doubling repeats every pixel twice in both directions
+        let doubled: Vec<u8> = (0..6 * 10 * 3).map(|i| rgb[(i / 30 / 2 * 5 + i / 3 % 10 / 2) * 3 + i % 3]).collect();
+        let nearest = full_swing().scale(10, 6).scale_filter(ScaleFilter::Nearest).threads(2).build();
+        assert_eq!(nearest.convert(&rgb, 5, 3).unwrap(),
+                   full_swing().build().convert(&doubled, 10, 6).unwrap());
+        // a 16 bit gray ramp stays a ramp
+        let ramp: Vec<u8> = (0..4 * 2 * 3).flat_map(|i| ((i / 3 % 4) as u16 * 20000).to_ne_bytes()).collect();
+        let src = RgbView::with_layout(&ramp, 4, 2, 4 * 6, ChannelOrder::Rgb, 16).unwrap();
+        let bilinear = full_swing().scale(7, 2).build().convert_view(&src);
+        let row = &bilinear.y()[..7];
+        assert_eq!((row[0], row[3], row[6]), (0, 117, 233));
+        assert!(row.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(full_swing().scale(4, 2).build().convert_view(&src),
+                   full_swing().build().convert_view(&src));
+        // a box averages the pairs of pixels each one covers
+        let averaged: Vec<u8> = [10000u16, 50000].iter().flat_map(|&v| [v; 3]).flat_map(u16::to_ne_bytes).collect();
+        let averaged = RgbView::with_layout(&averaged, 2, 1, 12, ChannelOrder::Rgb, 16).unwrap();
+        assert_eq!(full_swing().scale(2, 1).scale_filter(ScaleFilter::Box).build().convert_view(&src),
+                   full_swing().build().convert_view(&averaged));
+    }
+
+    #[test]
+    fn letterboxing_adds_bars() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 31 % 256) as u8).collect();
+        let blue = full_swing().build().convert(&[0, 0, 255], 1, 1).unwrap();
+        let plain = full_swing().build().convert(&rgb, 6, 4).unwrap();
+        let boxed = full_swing().letterbox(12, 4).background([0, 0, 255]).threads(2).build()
+            .convert(&rgb, 6, 4).unwrap();
+        assert_eq!((boxed.width(), boxed.height()), (12, 4));
+        for (row, plain_row) in boxed.y().chunks(12).zip(plain.y().chunks(6)) {
+            assert_eq!(&row[2..8], plain_row);
+            assert!(row[..2].iter().chain(&row[8..]).all(|&y| y == blue.y()[0]));
+        }
+        for (row, plain_row) in boxed.u().unwrap().chunks(6).zip(plain.u().unwrap().chunks(3)) {
+            assert_eq!(&row[1..4], plain_row);
+            assert!(row[..1].iter().chain(&row[4..]).all(|&u| u == blue.u().unwrap()[0]));
+        }
+        let tall = full_swing().letterbox(6, 8).build().convert(&rgb, 6, 4).unwrap();
+        assert_eq!(&tall.y()[2 * 6..6 * 6], plain.y());
+        assert!(tall.y()[..2 * 6].iter().chain(&tall.y()[6 * 6..]).all(|&y| y == 0));
+    }
+
+    #[test]
+    fn regions_are_scaled_into_place() {
+        let rgb: Vec<u8> = (0..9 * 7 * 3).map(|i| (i * 43 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 9, 7, ChannelOrder::Rgb).unwrap();
+        let source = Rect { x: 3, y: 1, width: 5, height: 4 };
+        let destination = Rect { x: 2, y: 4, width: 8, height: 6 };
+        let converter = full_swing().scale(12, 10).background([255; 3]).build();
+        let frame = converter.convert_region(&src, source, destination).unwrap();
+        let scaled = full_swing().scale(8, 6).build().convert_view(&src.crop(3, 1, 5, 4).unwrap());
+        for (i, row) in frame.y().chunks(12).enumerate() {
+            if (4..10).contains(&i) {
+                assert_eq!(&row[2..10], &scaled.y()[(i - 4) * 8..][..8]);
+            } else {
+                assert!(row.iter().all(|&y| y == 255));
+            }
+        }
+        let rect = |x, y, width, height| Rect { x, y, width, height };
+        assert_eq!(converter.convert_region(&src, rect(5, 0, 5, 4), destination),
+                   Err(ConversionError::InvalidRegion { x: 5, y: 0, width: 5, height: 4 }));
+        assert_eq!(converter.convert_region(&src, source, rect(6, 0, 8, 6)),
+                   Err(ConversionError::InvalidRegion { x: 6, y: 0, width: 8, height: 6 }));
+        // without a frame size the frame ends with the destination
+        let frame = full_swing().build().convert_region(&src, source, destination).unwrap();
+        assert_eq!((frame.width(), frame.height()), (10, 10));
+    }
+
+    #[test]
+    fn alpha_is_composited_over_the_backdrop() {
+        // transparent, half transparent and opaque columns
+        let bgra: Vec<u8> = (0..4 * 3).flat_map(|i| [10, 200, 90, [0, 128, 255][i % 3]]).collect();
+        let backdrop = Backdrop::Checkerboard { size: 2, colors: [[255; 3], [0; 3]] };
+        let frame = Converter::builder().input(ChannelOrder::Bgra).composite(backdrop).build()
+            .convert(&bgra, 3, 4).unwrap();
+        let expected: Vec<u8> = (0..4 * 3).flat_map(|i| {
+            let checker = if (i % 3 / 2 + i / 3 / 2) % 2 == 0 { 255 } else { 0 };
+            let blend = |c: u32| match i % 3 {
+                0 => checker,
+                1 => ((c * 128 + checker as u32 * 127 + 127) / 255) as u8,
+                _ => c as u8,
+            };
+            [blend(90), blend(200), blend(10)]
+        }).collect();
+        assert_eq!(frame, Converter::builder().build().convert(&expected, 3, 4).unwrap());
+        // without alpha there is nothing to composite
+        let rgb = Converter::builder().composite(backdrop).build().convert(&expected, 3, 4).unwrap();
+        assert_eq!(rgb, frame);
+    }
+
+    #[test]
+    fn overlays_are_blended_in_place() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        // opaque, half transparent and transparent columns, the last one outside of the frame
+        let rgba: Vec<u8> = (0..3 * 2).flat_map(|i| [250, 20, 100, [255, 128, 0][i % 3]]).collect();
+        let overlay = Overlay::new(rgba.clone(), 3, 2).unwrap();
+        let mut expected = rgb.clone();
+        for (i, px) in rgba.chunks(4).enumerate().filter(|&(i, _)| i % 3 < 2) {
+            let out = &mut expected[((1 + i / 3) * 6 + 4 + i % 3) * 3..][..3];
+            for (c, &color) in out.iter_mut().zip(px) {
+                let a = u32::from(px[3]);
+                *c = ((u32::from(color) * a + u32::from(*c) * (255 - a) + 127) / 255) as u8;
+            }
+        }
+        let frame = Converter::builder().overlay(overlay, 4, 1).threads(2).build().convert(&rgb, 6, 4).unwrap();
+        assert_eq!(frame, Converter::builder().build().convert(&expected, 6, 4).unwrap());
+        assert_eq!(Overlay::new(rgba, 3, 3), Err(ConversionError::BufferTooSmall { expected: 36, got: 24 }));
+    }
+}
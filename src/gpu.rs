@@ -0,0 +1,389 @@
+//! GPU compute backend (`wgpu` feature)
+//!
+//! Runs the RGB to NV12 conversion as a compute shader on a device and queue owned by the
+//! caller. The result either stays on the GPU in a storage buffer (for handing over to a
+//! hardware encoder without a round trip through system memory) or is read back into a `Vec<u8>`.
+//! Converted frames can also be uploaded as plane textures, and RGBA textures read back with
+//! padded rows converted on the CPU.
+//!
+//! The shader doesn't write textures: an encoder taking NV12 textures gets the planes of the
+//! storage buffer copied into them with `copy_buffer_to_texture` (whose rows have to be
+//! `padded_bytes_per_row` apart) or through system memory with `upload_planes`.
+
+use std::sync::mpsc;
+
+use wgpu;
+
+use backend::Convert;
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use frame::YuvFrame;
+use split::YuvPlanes;
+use view::RgbView;
+
+const WORKGROUP_SIZE: u32 = 8;
+
+/// A compiled RGB to NV12 compute pipeline
+///
+/// Creating the pipeline compiles the shader, so create it once per device and reuse it.
+pub struct GpuConverter {
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl GpuConverter {
+    /// Compiles the conversion shader for `device`
+    pub fn new(device: &wgpu::Device) -> GpuConverter {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rgb2yuv420 rgb_to_nv12"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/rgb_to_nv12.wgsl").into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("rgb2yuv420 rgb_to_nv12"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        GpuConverter { pipeline }
+    }
+
+    /// Converts an RGB(A) texture to NV12 and leaves the result on the GPU
+    ///
+    /// # Arguments
+    ///
+    /// * `src` - a view of a float-sampleable color texture (eg.: `Rgba8Unorm` or `Bgra8Unorm`)
+    ///   of at least `width` x `height` texels
+    ///
+    /// * `width` - must be a multiple of 4, `height` must be even
+    ///
+    /// # Return
+    ///
+    /// A `STORAGE | COPY_SRC` buffer holding `[y, y, y, ... , u, v, u, v, ...]`,
+    /// `width * height * 3 / 2` bytes long. The conversion has been submitted to `queue`
+    /// but not necessarily finished when this returns.
+    pub fn convert_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, src: &wgpu::TextureView,
+                           width: u32, height: u32) -> Result<wgpu::Buffer, ConversionError> {
+        if !width.is_multiple_of(4) || !height.is_multiple_of(2) || width == 0 || height == 0 {
+            return Err(ConversionError::UnsupportedDimensions { width, height });
+        }
+        let dst = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rgb2yuv420 nv12"),
+            size: u64::from(width) * u64::from(height) * 3 / 2,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rgb2yuv420 params"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut size = Vec::with_capacity(16);
+        for val in &[width, height, 0, 0] {
+            size.extend_from_slice(&val.to_le_bytes());
+        }
+        queue.write_buffer(&params, 0, &size);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("rgb2yuv420 rgb_to_nv12"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(src) },
+                wgpu::BindGroupEntry { binding: 1, resource: dst.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: params.as_entire_binding() },
+            ],
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("rgb2yuv420 rgb_to_nv12"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("rgb2yuv420 rgb_to_nv12"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups((width / 4).div_ceil(WORKGROUP_SIZE), (height / 2).div_ceil(WORKGROUP_SIZE), 1);
+        }
+        queue.submit(Some(encoder.finish()));
+        Ok(dst)
+    }
+
+    /// Uploads `img` to a texture, converts it to NV12 and reads the result back
+    ///
+    /// # Arguments
+    ///
+    /// * `img` - should contain RGBA pixel data in the following format:
+    ///   `[r, g, b, a, ... , r, g, b, a, ...]`
+    ///
+    /// * `width` - must be a multiple of 4, `height` must be even
+    ///
+    /// # Return
+    ///
+    /// `[y, y, y, ... , u, v, u, v, ...]`
+    pub fn convert_rgba(&self, device: &wgpu::Device, queue: &wgpu::Queue, img: &[u8], width: u32, height: u32)
+                        -> Result<Vec<u8>, ConversionError> {
+        let expected = width as usize * height as usize * 4;
+        if img.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: img.len() });
+        }
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rgb2yuv420 rgba"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &img[..expected],
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(width * 4), rows_per_image: Some(height) },
+            size,
+        );
+        let view = texture.create_view(&Default::default());
+        let nv12 = self.convert_texture(device, queue, &view, width, height)?;
+        read_back(device, queue, &nv12)
+    }
+}
+
+/// Copies a GPU buffer (eg.: the one returned by `GpuConverter::convert_texture`) into system memory
+///
+/// Blocks until the GPU has finished all work submitted to `queue` so far.
+pub fn read_back(device: &wgpu::Device, queue: &wgpu::Queue, buffer: &wgpu::Buffer) -> Result<Vec<u8>, ConversionError> {
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("rgb2yuv420 readback"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("rgb2yuv420 readback"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+    queue.submit(Some(encoder.finish()));
+    let (tx, rx) = mpsc::channel();
+    staging.map_async(wgpu::MapMode::Read, .., move |res| {
+        let _ = tx.send(res);
+    });
+    device.poll(wgpu::PollType::wait_indefinitely()).map_err(device_error)?;
+    rx.recv().map_err(device_error)?.map_err(device_error)?;
+    let data = staging.get_mapped_range(..).map_err(device_error)?.to_vec();
+    staging.unmap();
+    Ok(data)
+}
+
+/// Bytes per row of `row_bytes` long rows in a copy between a texture and a buffer,
+/// rounded up to `wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`
+pub fn padded_bytes_per_row(row_bytes: u32) -> u32 {
+    row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+/// Uploads the planes of `frame` into a new `R8Unorm` luma texture and a new `Rg8Unorm`
+/// chroma texture of half its size, eg.: to sample them in a shader that shows the frame
+///
+/// The rows are written with the strides of the frame, so padded planes upload without being
+/// repacked; the U and V planes of I420 frames are interleaved first. The textures are
+/// `TEXTURE_BINDING | COPY_DST | COPY_SRC`.
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` if the frame is empty, as textures can't be
+pub fn upload_planes(device: &wgpu::Device, queue: &wgpu::Queue, frame: &YuvPlanes)
+                     -> Result<(wgpu::Texture, wgpu::Texture), ConversionError> {
+    let (width, height) = (frame.width(), frame.height());
+    if width == 0 || height == 0 {
+        return Err(ConversionError::UnsupportedDimensions { width, height });
+    }
+    let texture = |label, width, height, format, data: &[u8], stride: usize| {
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            data,
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(stride as u32), rows_per_image: Some(height) },
+            size,
+        );
+        texture
+    };
+    let y = frame.y();
+    let luma = texture("rgb2yuv420 y", width, height, wgpu::TextureFormat::R8Unorm, y.data(), y.stride());
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let chroma = match (frame.u(), frame.v(), frame.uv()) {
+        (Some(u), Some(v), _) => {
+            let uv: Vec<u8> = u.rows().zip(v.rows())
+                .flat_map(|(u, v)| u.iter().zip(v).flat_map(|(&u, &v)| [u, v]))
+                .collect();
+            texture("rgb2yuv420 uv", chroma_width, chroma_height, wgpu::TextureFormat::Rg8Unorm, &uv,
+                    2 * chroma_width as usize)
+        }
+        (_, _, Some(uv)) => {
+            texture("rgb2yuv420 uv", chroma_width, chroma_height, wgpu::TextureFormat::Rg8Unorm, uv.data(),
+                    uv.stride())
+        }
+        _ => unreachable!("frames have either U and V or UV planes"),
+    };
+    Ok((luma, chroma))
+}
+
+/// Converts the texels of an RGBA or BGRA texture copied into a buffer (with
+/// `copy_texture_to_buffer` and rows of `padded_bytes_per_row(width * 4)` bytes, then read
+/// with `read_back`) into an NV12 frame with `cfg`, skipping the padding
+///
+/// # Errors
+///
+/// `UnsupportedFormat` if `format` is not one of the `Rgba8` or `Bgra8` formats and
+/// `BufferTooSmall` if `data` can't hold all of the rows
+pub fn convert_readback(data: &[u8], width: u32, height: u32, format: wgpu::TextureFormat, cfg: &ConversionConfig)
+                        -> Result<YuvFrame, ConversionError> {
+    let order = match format {
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => ChannelOrder::Rgba,
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => ChannelOrder::Bgra,
+        _ => return Err(ConversionError::UnsupportedFormat("only RGBA and BGRA textures can be converted")),
+    };
+    let src = RgbView::with_layout(data, width, height, padded_bytes_per_row(width * 4) as usize, order, 8)?;
+    Ok(::convert(&src, YuvFormat::Nv12, cfg))
+}
+
+/// A `GpuConverter` with the device and queue it converts on, as a `Convert` backend
+///
+/// It converts RGBA and RGBX images of 8 bit channels with widths that are multiples of 4 and
+/// even heights into full swing BT.601 NV12 frames, waiting for each frame to be read back.
+pub struct GpuBackend {
+    converter: GpuConverter,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+}
+
+impl GpuBackend {
+    /// Compiles the conversion shader for `device`
+    pub fn new(device: wgpu::Device, queue: wgpu::Queue) -> GpuBackend {
+        GpuBackend { converter: GpuConverter::new(&device), device, queue }
+    }
+}
+
+impl Convert for GpuBackend {
+    fn name(&self) -> &'static str {
+        "wgpu"
+    }
+
+    fn convert_frame(&self, src: &RgbView, format: YuvFormat) -> Result<YuvFrame, ConversionError> {
+        let source = src.source();
+        let layout = source.layout;
+        let rgbx = !source.wide && (layout.bytes_per_pixel, layout.r, layout.g, layout.b) == (4, 0, 1, 2);
+        if format != YuvFormat::Nv12 || !rgbx {
+            return Err(ConversionError::UnsupportedFormat("the GPU backend converts RGBA and RGBX into NV12"));
+        }
+        let (width, height) = (src.width(), src.height());
+        let row_bytes = width as usize * 4;
+        let data = if src.stride() == row_bytes {
+            self.converter.convert_rgba(&self.device, &self.queue, src.data(), width, height)?
+        } else {
+            let rgba: Vec<u8> = src.data().chunks(src.stride()).take(height as usize)
+                .flat_map(|row| &row[..row_bytes]).copied().collect();
+            self.converter.convert_rgba(&self.device, &self.queue, &rgba, width, height)?
+        };
+        let cfg = ConversionConfig { matrix: Matrix::Bt601, range: Range::Full, ..ConversionConfig::default() };
+        Ok(YuvFrame::from_packed(data, format, width, height, &cfg))
+    }
+}
+
+fn device_error<E: ToString>(err: E) -> ConversionError {
+    ConversionError::Device(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate pollster;
+
+    use wgpu;
+
+    use super::{convert_readback, padded_bytes_per_row, read_back, upload_planes, GpuConverter};
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use split::YuvPlanes;
+    use view::RgbView;
+
+    fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        if wgpu::Instance::enabled_backend_features().is_empty() {
+            return None;
+        }
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default())).ok()?;
+        pollster::block_on(adapter.request_device(&Default::default())).ok()
+    }
+
+    #[test]
+    fn matches_cpu_kernel() {
+        let (device, queue) = match device() {
+            Some(dq) => dq,
+            None => return,
+        };
+        let rgba: Vec<u8> = (0..8 * 4 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let converter = GpuConverter::new(&device);
+        let yuv = converter.convert_rgba(&device, &queue, &rgba, 8, 4).unwrap();
+        assert_eq!(yuv, ::convert_rgb_to_yuv420sp_nv12(&rgba, 8, 4, 4));
+    }
+
+    #[test]
+    fn planes_upload_and_readbacks_convert_with_padded_rows() {
+        let cfg = ConversionConfig::default();
+        let rgba: Vec<u8> = (0..70 * 2 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        // 280 byte rows padded to 512
+        assert_eq!((padded_bytes_per_row(280), padded_bytes_per_row(256)), (512, 256));
+        let padded: Vec<u8> = rgba.chunks(280).flat_map(|row| row.iter().cloned().chain([0; 232])).collect();
+        let frame = convert_readback(&padded, 70, 2, wgpu::TextureFormat::Rgba8Unorm, &cfg).unwrap();
+        assert_eq!(frame, ::convert(&RgbView::new(&rgba, 70, 2, ChannelOrder::Rgba).unwrap(), YuvFormat::Nv12, &cfg));
+        assert_eq!(convert_readback(&padded, 70, 2, wgpu::TextureFormat::R8Unorm, &cfg),
+                   Err(ConversionError::UnsupportedFormat("only RGBA and BGRA textures can be converted")));
+        let (device, queue) = match device() {
+            Some(dq) => dq,
+            None => return,
+        };
+        let i420 = frame.as_planes().to_vec(YuvFormat::I420);
+        let (_, chroma) = upload_planes(&device, &queue, &YuvPlanes::split(&i420, YuvFormat::I420, 70, 2).unwrap())
+            .unwrap();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            chroma.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None },
+            },
+            chroma.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+        assert_eq!(read_back(&device, &queue, &buffer).unwrap()[..70], frame.uv().unwrap()[..]);
+    }
+}
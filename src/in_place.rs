@@ -0,0 +1,117 @@
+//! Converting an RGB image into the front of its own buffer, for devices that can't hold a
+//! 4K RGBA image and its YUV420 frame at once
+
+use alloc::vec::Vec;
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use kernel;
+use view::RgbView;
+
+/// Converts the tightly packed `width` x `height` image at the front of `buffer` into a
+/// tightly packed `dst_format` frame at the front of the same buffer, and returns the size of
+/// the frame
+///
+/// The rows are converted two at a time, and their luma and chroma written right behind the
+/// rows converted before them: as the frame takes 1.5 bytes per pixel and the image 3 or 4,
+/// that is always behind the rows left to read. Only the first couple of pairs would land
+/// on their own pixels while they are read, they are converted through a scratch buffer of
+/// one pair of rows. The pairs are then rearranged into planes in place, by rotating them,
+/// which takes a few passes over the frame. So a 4K RGBA image converts without the 12 MB of
+/// a separate frame, at the cost of the image.
+///
+/// # Errors
+///
+/// The errors of `RgbView::new` if `buffer` doesn't hold the image
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, YuvFormat};
+///
+/// let mut buffer: Vec<u8> = (0..64 * 48 * 4).map(|i| (i % 256) as u8).collect();
+/// let expected = rgb2yuv420::convert_rgb_to_yuv420p(&buffer, 64, 48, 4);
+/// let len = rgb2yuv420::convert_in_place(&mut buffer, 64, 48, ChannelOrder::Rgba, YuvFormat::I420,
+///                                        &ConversionConfig::default()).unwrap();
+/// // the rest of the buffer is left over from the image
+/// buffer.truncate(len);
+/// assert_eq!(buffer, expected);
+/// ```
+pub fn convert_in_place(buffer: &mut [u8], width: u32, height: u32, channel_order: ChannelOrder,
+                        dst_format: YuvFormat, cfg: &ConversionConfig) -> Result<usize, ConversionError> {
+    RgbView::new(buffer, width, height, channel_order)?;
+    let (width, height) = (width as usize, height as usize);
+    let row_bytes = width * channel_order.bytes_per_pixel();
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let pair = 2 * width + 2 * chroma_width;
+    let mut scratch = Vec::new();
+    for first_row in (0..height).step_by(2) {
+        let rows = (height - first_row).min(2);
+        let (block, read) = (first_row / 2 * pair, first_row * row_bytes);
+        let len = rows * width + 2 * chroma_width;
+        if block + len <= read {
+            let (front, back) = buffer.split_at_mut(read);
+            let src = RgbView::new(&back[..rows * row_bytes], width as u32, rows as u32, channel_order)?;
+            kernel::convert_into(src.source(), dst_format, cfg, &mut front[block..][..len]);
+        } else {
+            // the block overlaps the rows it is converted from, but not the next ones
+            scratch.resize(len, 0);
+            let src = RgbView::new(&buffer[read..][..rows * row_bytes], width as u32, rows as u32, channel_order)?;
+            kernel::convert_into(src.source(), dst_format, cfg, &mut scratch);
+            buffer[block..][..len].copy_from_slice(&scratch);
+        }
+    }
+    // [y y c] pairs of rows (and a last [y c] row) into the luma plane and the chroma rows
+    let (pairs, size) = (height / 2, kernel::frame_size(width, height));
+    unshuffle(&mut buffer[..pairs * pair], pairs, 2 * width, 2 * chroma_width);
+    if height % 2 == 1 {
+        buffer[pairs * 2 * width..][..pairs * 2 * chroma_width + width].rotate_left(pairs * 2 * chroma_width);
+    }
+    if dst_format == YuvFormat::I420 {
+        unshuffle(&mut buffer[width * height..size], chroma_height, chroma_width, chroma_width);
+    }
+    Ok(size)
+}
+
+// turns `blocks` blocks of `first` then `second` bytes into the first parts followed by the
+// second parts, both in their order, by rotating the middle of every two halves
+fn unshuffle(data: &mut [u8], blocks: usize, first: usize, second: usize) {
+    if blocks < 2 {
+        return;
+    }
+    let half = blocks / 2;
+    let (left, right) = data.split_at_mut(half * (first + second));
+    unshuffle(left, half, first, second);
+    unshuffle(right, blocks - half, first, second);
+    data[half * first..][..half * second + (blocks - half) * first].rotate_left(half * second);
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ChromaFilter, ConversionConfig};
+    use format::{ChannelOrder, YuvFormat};
+    use kernel;
+    use view::RgbView;
+
+    #[test]
+    fn frames_overwrite_their_images() {
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average, swap_uv: true,
+                                     ..ConversionConfig::default() };
+        for &(width, height) in &[(1, 1), (1, 5), (2, 2), (7, 3), (6, 9), (16, 7), (33, 20)] {
+            for &order in &[ChannelOrder::Rgb, ChannelOrder::Bgra] {
+                let image: Vec<u8> = (0..width * height * order.bytes_per_pixel()).map(|i| (i * 37 % 251) as u8)
+                    .collect();
+                let src = RgbView::new(&image, width as u32, height as u32, order).unwrap();
+                for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+                    let expected = kernel::convert(src.source(), format, &cfg);
+                    let mut buffer = image.clone();
+                    let len = super::convert_in_place(&mut buffer, width as u32, height as u32, order, format, &cfg)
+                        .unwrap();
+                    assert_eq!(&buffer[..len], &expected[..], "{}x{} {:?} {:?}", width, height, order, format);
+                }
+            }
+        }
+        assert!(super::convert_in_place(&mut [0; 11], 2, 2, ChannelOrder::Rgb, YuvFormat::I420, &cfg).is_err());
+    }
+}
@@ -0,0 +1,577 @@
+//! Conversions from YUV420 back to interleaved RGB, eg.: for previewing decoded frames or
+//! testing a pipeline end to end
+
+use alloc::vec::Vec;
+
+use config::{ChromaUpsampling, ConversionConfig, InverseCoefficients};
+use error::ConversionError;
+use format::{ChannelOrder, PackedYuvFormat, PixelLayout, YuvFormat};
+use frame::YuvFrame;
+use kernel;
+use layout;
+use planes::check_plane;
+use upsample::Upsampler;
+
+/// The planes of a YUV420 frame to convert from
+pub(crate) struct YuvSource<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub y: &'a [u8],
+    pub y_stride: usize,
+    pub chroma: ChromaSource<'a>,
+    /// Distance between two rows of the U and V planes or of the interleaved plane
+    pub chroma_stride: usize,
+}
+
+pub(crate) enum ChromaSource<'a> {
+    Planar(&'a [u8], &'a [u8]),
+    SemiPlanar(&'a [u8]),
+    /// Interleaved V before U (NV21)
+    SemiPlanarVu(&'a [u8]),
+}
+
+impl<'a> YuvSource<'a> {
+    /// The planes of a tightly packed frame
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `yuv` is shorter than `required_buffer_size`
+    pub fn packed(yuv: &'a [u8], format: YuvFormat, width: u32, height: u32)
+                  -> Result<YuvSource<'a>, ConversionError> {
+        let expected = layout::required_buffer_size(format, width, height);
+        if yuv.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: yuv.len() });
+        }
+        let (y_offset, chroma_offset, v_offset) = layout::plane_offsets(format, width, height);
+        let (y_stride, chroma_stride) = layout::plane_strides(format, width);
+        let chroma = match v_offset {
+            Some(v_offset) => ChromaSource::Planar(&yuv[chroma_offset..v_offset], &yuv[v_offset..expected]),
+            None => ChromaSource::SemiPlanar(&yuv[chroma_offset..expected]),
+        };
+        Ok(YuvSource {
+            width: width as usize,
+            height: height as usize,
+            y: &yuv[y_offset..chroma_offset],
+            y_stride,
+            chroma,
+            chroma_stride,
+        })
+    }
+
+    /// The planes of an NV12 frame, each with its own stride
+    ///
+    /// # Errors
+    ///
+    /// `StrideMismatch` if a stride is smaller than a row of its plane and `BufferTooSmall`
+    /// if a plane can't hold all of its rows
+    pub fn nv12(y: &'a [u8], y_stride: usize, uv: &'a [u8], uv_stride: usize, width: u32, height: u32)
+                -> Result<YuvSource<'a>, ConversionError> {
+        let (width, height) = (width as usize, height as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+        check_plane(y.len(), y_stride, width, height)?;
+        check_plane(uv.len(), uv_stride, 2 * chroma_width, chroma_height)?;
+        Ok(YuvSource { width, height, y, y_stride, chroma: ChromaSource::SemiPlanar(uv), chroma_stride: uv_stride })
+    }
+
+    /// The planes of an I420 frame, the U and V planes with the same stride
+    ///
+    /// # Errors
+    ///
+    /// See `YuvSource::nv12`
+    pub fn i420(y: &'a [u8], y_stride: usize, u: &'a [u8], v: &'a [u8], chroma_stride: usize, width: u32,
+                height: u32) -> Result<YuvSource<'a>, ConversionError> {
+        let (width, height) = (width as usize, height as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+        check_plane(y.len(), y_stride, width, height)?;
+        check_plane(u.len(), chroma_stride, chroma_width, chroma_height)?;
+        check_plane(v.len(), chroma_stride, chroma_width, chroma_height)?;
+        Ok(YuvSource { width, height, y, y_stride, chroma: ChromaSource::Planar(u, v), chroma_stride })
+    }
+
+    /// The planes of a tightly packed NV21 frame
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `nv21` is shorter than `required_buffer_size`
+    pub fn nv21(nv21: &'a [u8], width: u32, height: u32) -> Result<YuvSource<'a>, ConversionError> {
+        let src = YuvSource::packed(nv21, YuvFormat::Nv12, width, height)?;
+        match src.chroma {
+            ChromaSource::SemiPlanar(vu) => Ok(YuvSource { chroma: ChromaSource::SemiPlanarVu(vu), ..src }),
+            _ => unreachable!("NV12 frames have an interleaved chroma plane"),
+        }
+    }
+
+    /// The U and V samples of chroma row `row`, every `step` bytes
+    pub fn chroma_row(&self, row: usize) -> (&'a [u8], &'a [u8], usize) {
+        let chroma_width = kernel::chroma_dimensions(self.width, self.height).0;
+        let start = row * self.chroma_stride;
+        match self.chroma {
+            ChromaSource::Planar(u, v) => (&u[start..][..chroma_width], &v[start..][..chroma_width], 1),
+            ChromaSource::SemiPlanar(uv) => {
+                let uv = &uv[start..][..2 * chroma_width];
+                (uv, &uv[1..], 2)
+            }
+            ChromaSource::SemiPlanarVu(vu) => {
+                let vu = &vu[start..][..2 * chroma_width];
+                (&vu[1..], vu, 2)
+            }
+        }
+    }
+
+    // converts into a tightly packed image with the channels in `order`
+    fn to_rgb(&self, order: ChannelOrder, cfg: &ConversionConfig) -> Vec<u8> {
+        let row_bytes = order.bytes_per_pixel() * self.width;
+        let mut rgb = vec![0; row_bytes * self.height];
+        to_rgb(self, &cfg.inverse_coefficients(), cfg.chroma_upsampling, order.layout(), &mut rgb, row_bytes);
+        rgb
+    }
+}
+
+/// Converts `src` into the rows of `dst`, `stride` bytes apart, spreading the chroma samples
+/// with `upsampling`; an alpha channel in `layout` is made opaque
+pub(crate) fn to_rgb(src: &YuvSource, coef: &InverseCoefficients, upsampling: ChromaUpsampling, layout: PixelLayout,
+                     dst: &mut [u8], stride: usize) {
+    let bpp = layout.bytes_per_pixel;
+    let mut upsampler = Upsampler::new(upsampling, src);
+    let (mut u, mut v) = (vec![0; src.width], vec![0; src.width]);
+    for (row, out) in dst.chunks_mut(stride).take(src.height).enumerate() {
+        let y = &src.y[row * src.y_stride..][..src.width];
+        upsampler.row(src, row, &mut u, &mut v);
+        for (px, ((&luma, &u), &v)) in out[..src.width * bpp].chunks_exact_mut(bpp).zip(y.iter().zip(&u).zip(&v)) {
+            let [r, g, b] = coef.rgb(luma, u, v);
+            px[layout.r] = r;
+            px[layout.g] = g;
+            px[layout.b] = b;
+            if let Some(alpha) = layout.alpha {
+                px[alpha] = 255;
+            }
+        }
+    }
+}
+
+/// Converts a tightly packed frame in `format` into a tightly packed image with the channels
+/// in `order`, the inverse of `convert`
+///
+/// The matrix, range and chroma upsampling of `cfg` apply; its adjustment, chroma key and
+/// threads don't.
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `yuv` is shorter than `required_buffer_size`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ChromaUpsampling, ConversionConfig, YuvFormat};
+///
+/// let cfg = ConversionConfig { chroma_upsampling: ChromaUpsampling::Bilinear, ..ConversionConfig::default() };
+/// // a 4x2 gray frame, blue on the left and red on the right
+/// let nv12 = [128; 12];
+/// let nv12 = [&nv12[..8], &[255, 128, 128, 255]].concat();
+/// let rgb = rgb2yuv420::yuv_to_rgb(&nv12, YuvFormat::Nv12, 4, 2, ChannelOrder::Rgb, &cfg).unwrap();
+/// let px: Vec<&[u8]> = rgb.chunks(3).collect();
+/// // the middle pixels blend both sides
+/// assert!(px[1][2] > px[1][0] && px[1][0] > px[0][0]);
+/// assert!(px[2][0] > px[2][2] && px[2][2] > px[3][2]);
+/// ```
+pub fn yuv_to_rgb(yuv: &[u8], format: YuvFormat, width: u32, height: u32, order: ChannelOrder,
+                  cfg: &ConversionConfig) -> Result<Vec<u8>, ConversionError> {
+    Ok(YuvSource::packed(yuv, format, width, height)?.to_rgb(order, cfg))
+}
+
+/// Converts a tightly packed I420 frame into RGB24 (`[r, g, b, r, g, b, ...]`) with the
+/// matrix and range of `cfg`, the inverse of `convert` with `YuvFormat::I420`
+///
+/// The chroma is upsampled with the filter of `cfg`. The adjustment, chroma key and threads
+/// of `cfg` don't apply.
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `yuv` is shorter than `required_buffer_size`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, Range};
+///
+/// let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+/// // a white and a black row
+/// let yuv = [235, 235, 16, 16, 128, 128];
+/// let rgb = rgb2yuv420::convert_yuv420p_to_rgb(&yuv, 2, 2, &cfg).unwrap();
+/// assert_eq!(rgb, [255, 255, 255, 255, 255, 255, 0, 0, 0, 0, 0, 0]);
+/// ```
+pub fn convert_yuv420p_to_rgb(yuv: &[u8], width: u32, height: u32, cfg: &ConversionConfig)
+                              -> Result<Vec<u8>, ConversionError> {
+    Ok(YuvSource::packed(yuv, YuvFormat::I420, width, height)?.to_rgb(ChannelOrder::Rgb, cfg))
+}
+
+/// Converts an NV12 frame with a stride per plane, as hardware decoders return them, into a
+/// tightly packed image with the channels in `order` (eg.: `ChannelOrder::Rgba` for textures)
+///
+/// Alpha channels are opaque; otherwise see `convert_yuv420p_to_rgb`.
+///
+/// # Errors
+///
+/// `StrideMismatch` if a stride is smaller than a row of its plane and `BufferTooSmall` if a
+/// plane can't hold all of its rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig};
+///
+/// // a 2x2 frame with rows padded to 4 bytes
+/// let (y, uv) = ([255, 255, 0, 0, 255, 255, 0, 0], [128, 128, 0, 0]);
+/// let rgba = rgb2yuv420::convert_nv12_to_rgb(&y, 4, &uv, 4, 2, 2, ChannelOrder::Rgba,
+///                                            &ConversionConfig::default()).unwrap();
+/// assert_eq!(rgba, [255; 16]);
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn convert_nv12_to_rgb(y: &[u8], y_stride: usize, uv: &[u8], uv_stride: usize, width: u32, height: u32,
+                           order: ChannelOrder, cfg: &ConversionConfig) -> Result<Vec<u8>, ConversionError> {
+    Ok(YuvSource::nv12(y, y_stride, uv, uv_stride, width, height)?.to_rgb(order, cfg))
+}
+
+/// Converts a tightly packed NV21 frame (interleaved V before U), the preview format of
+/// Android cameras, into RGBA with opaque alpha
+///
+/// See `convert_yuv420p_to_rgb` for how `cfg` applies.
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `nv21` is shorter than `required_buffer_size`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::ConversionConfig;
+///
+/// // a red 2x2 frame
+/// let nv21 = [76, 76, 76, 76, 255, 85];
+/// let rgba = rgb2yuv420::convert_nv21_to_rgba(&nv21, 2, 2, &ConversionConfig::default()).unwrap();
+/// assert_eq!(&rgba[..4], &[255, 0, 0, 255]);
+/// ```
+pub fn convert_nv21_to_rgba(nv21: &[u8], width: u32, height: u32, cfg: &ConversionConfig)
+                            -> Result<Vec<u8>, ConversionError> {
+    Ok(YuvSource::nv21(nv21, width, height)?.to_rgb(ChannelOrder::Rgba, cfg))
+}
+
+/// Converts a packed 4:2:2 frame (YUYV or UYVY, eg.: from a UVC webcam) into a tightly
+/// packed image with the channels in `order`
+///
+/// `ChromaUpsampling::Bilinear` averages the odd pixels of a row between the samples on either
+/// side and `ChromaUpsampling::CatmullRom` interpolates them from the two samples on either
+/// side; otherwise see `convert_nv12_to_rgb`.
+///
+/// # Arguments
+///
+/// * `stride` - distance between the starts of two rows in bytes, at least `4 * ((width + 1) / 2)`
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than a row and `BufferTooSmall` if `yuv` can't
+/// hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, PackedYuvFormat};
+///
+/// // a white and a black pixel
+/// let yuyv = [255, 128, 0, 128];
+/// let rgb = rgb2yuv420::convert_packed_yuv_to_rgb(&yuyv, 4, 2, 1, PackedYuvFormat::Yuyv, ChannelOrder::Rgb,
+///                                                 &ConversionConfig::default()).unwrap();
+/// assert_eq!(rgb, [255, 255, 255, 0, 0, 0]);
+/// ```
+#[allow(clippy::too_many_arguments)]
+pub fn convert_packed_yuv_to_rgb(yuv: &[u8], stride: usize, width: u32, height: u32, format: PackedYuvFormat,
+                                 order: ChannelOrder, cfg: &ConversionConfig) -> Result<Vec<u8>, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    check_plane(yuv.len(), stride, 4 * width.div_ceil(2), height)?;
+    let (coef, layout, [y0, u, y1, v]) = (cfg.inverse_coefficients(), order.layout(), format.offsets());
+    let bpp = layout.bytes_per_pixel;
+    let mut rgb = vec![0; bpp * width * height];
+    // weights out of 16 of the samples of the pairs before, at and after the odd pixels
+    let weights = match cfg.chroma_upsampling {
+        ChromaUpsampling::Nearest => [0, 16, 0, 0],
+        ChromaUpsampling::Bilinear => [0, 8, 8, 0],
+        ChromaUpsampling::CatmullRom => [-1, 9, 9, -1],
+    };
+    let store = |px: &mut [u8], [r, g, b]: [u8; 3]| {
+        px[layout.r] = r;
+        px[layout.g] = g;
+        px[layout.b] = b;
+        if let Some(alpha) = layout.alpha {
+            px[alpha] = 255;
+        }
+    };
+    let pairs = width.div_ceil(2);
+    if width > 0 {
+        for (row, out) in yuv.chunks(stride).zip(rgb.chunks_mut(bpp * width)) {
+            // the edge pairs repeat
+            let sample = |k: isize, offset: usize| i32::from(row[4 * k.clamp(0, pairs as isize - 1) as usize + offset]);
+            for (k, pixels) in out.chunks_mut(2 * bpp).enumerate() {
+                let pair = &row[4 * k..];
+                let (first, second) = pixels.split_at_mut(bpp);
+                store(first, coef.rgb(pair[y0], pair[u], pair[v]));
+                if !second.is_empty() {
+                    let blend = |offset| {
+                        let sum: i32 = weights.iter().enumerate()
+                            .map(|(i, &weight)| weight * sample(k as isize + i as isize - 1, offset)).sum();
+                        ((sum + 8) >> 4).clamp(0, 255) as u8
+                    };
+                    store(second, coef.rgb(pair[y1], blend(u), blend(v)));
+                }
+            }
+        }
+    }
+    Ok(rgb)
+}
+
+/// The planes of a planar 4:2:0 picture as decoders such as dav1d output them, each with
+/// the stride the decoder picked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedPicture<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub y: &'a [u8],
+    pub u: &'a [u8],
+    pub v: &'a [u8],
+    /// Distance between the starts of two luma rows in bytes (`stride[0]` of dav1d)
+    pub y_stride: usize,
+    /// Distance between the starts of two rows of U or of V in bytes (`stride[1]` of dav1d)
+    pub chroma_stride: usize,
+    /// 8, or 10 for samples in the low bits of little endian 16 bit words
+    pub bit_depth: u8,
+}
+
+// the samples of a plane of 10 bit words rounded to 8 bits, in rows of `width`
+fn narrow(plane: &[u8], stride: usize, width: usize, height: usize) -> Vec<u8> {
+    (0..height).flat_map(|row| plane[row * stride..][..2 * width].chunks(2))
+        .map(|word| (((u16::from_le_bytes([word[0], word[1]]) & 0x3ff) + 2) >> 2).min(255) as u8).collect()
+}
+
+/// Converts a decoded picture into a tightly packed image with the channels in `order`,
+/// eg.: RGBA for display
+///
+/// 10 bit pictures are rounded to 8 bits first. The matrix, range and chroma upsampling of
+/// `cfg` apply, as for `yuv_to_rgb`.
+///
+/// # Errors
+///
+/// `UnsupportedFormat` if `bit_depth` is not 8 or 10, `StrideMismatch` if a stride is
+/// smaller than a row of its plane and `BufferTooSmall` if a plane can't hold all of its rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, DecodedPicture};
+///
+/// // a white 2x2 10 bit picture in rows of 8 bytes
+/// let (y, chroma) = ([0xff, 0x03, 0xff, 0x03, 0, 0, 0, 0].repeat(2), [0x00, 0x02]);
+/// let picture = DecodedPicture { width: 2, height: 2, y: &y, u: &chroma, v: &chroma, y_stride: 8,
+///                                chroma_stride: 8, bit_depth: 10 };
+/// let rgba = rgb2yuv420::picture_to_rgb(&picture, ChannelOrder::Rgba, &ConversionConfig::default()).unwrap();
+/// assert_eq!(rgba, [255; 2 * 2 * 4]);
+/// ```
+pub fn picture_to_rgb(picture: &DecodedPicture, order: ChannelOrder, cfg: &ConversionConfig)
+                      -> Result<Vec<u8>, ConversionError> {
+    let bytes = match picture.bit_depth {
+        8 => 1,
+        10 => 2,
+        _ => return Err(ConversionError::UnsupportedFormat("bit_depth must be 8 or 10")),
+    };
+    let (width, height) = (picture.width as usize, picture.height as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    check_plane(picture.y.len(), picture.y_stride, bytes * width, height)?;
+    check_plane(picture.u.len(), picture.chroma_stride, bytes * chroma_width, chroma_height)?;
+    check_plane(picture.v.len(), picture.chroma_stride, bytes * chroma_width, chroma_height)?;
+    if bytes == 1 {
+        let src = YuvSource {
+            width,
+            height,
+            y: picture.y,
+            y_stride: picture.y_stride,
+            chroma: ChromaSource::Planar(picture.u, picture.v),
+            chroma_stride: picture.chroma_stride,
+        };
+        return Ok(src.to_rgb(order, cfg));
+    }
+    let y = narrow(picture.y, picture.y_stride, width, height);
+    let (u, v) = (narrow(picture.u, picture.chroma_stride, chroma_width, chroma_height),
+                  narrow(picture.v, picture.chroma_stride, chroma_width, chroma_height));
+    let src = YuvSource { width, height, y: &y, y_stride: width, chroma: ChromaSource::Planar(&u, &v),
+                          chroma_stride: chroma_width };
+    Ok(src.to_rgb(order, cfg))
+}
+
+impl YuvFrame {
+    /// Converts the frame back into a tightly packed image with the channels in `order`, in
+    /// the matrix and range of the frame and spreading the chroma with `upsampling`; an alpha
+    /// channel is made opaque
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, ChromaUpsampling, Converter};
+    ///
+    /// let frame = Converter::builder().alignment(16).build().convert(&[255u8; 6 * 2 * 3], 6, 2).unwrap();
+    /// assert_eq!(frame.to_rgb(ChannelOrder::Bgra, ChromaUpsampling::Nearest), [255; 6 * 2 * 4]);
+    /// ```
+    pub fn to_rgb(&self, order: ChannelOrder, upsampling: ChromaUpsampling) -> Vec<u8> {
+        let planes = self.as_planes();
+        let (chroma, chroma_stride) = match (planes.u(), planes.v(), planes.uv()) {
+            (Some(u), Some(v), _) => (ChromaSource::Planar(u.data(), v.data()), u.stride()),
+            (_, _, Some(uv)) => (ChromaSource::SemiPlanar(uv.data()), uv.stride()),
+            _ => unreachable!("frames have either U and V or UV planes"),
+        };
+        let src = YuvSource {
+            width: self.width() as usize,
+            height: self.height() as usize,
+            y: planes.y().data(),
+            y_stride: planes.y().stride(),
+            chroma,
+            chroma_stride,
+        };
+        let cfg = ConversionConfig { matrix: self.matrix(), range: self.range(), chroma_upsampling: upsampling,
+                                     ..ConversionConfig::default() };
+        src.to_rgb(order, &cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_nv12_to_rgb, convert_nv21_to_rgba, convert_packed_yuv_to_rgb, convert_yuv420p_to_rgb,
+                picture_to_rgb, yuv_to_rgb, DecodedPicture};
+    use config::{ChromaUpsampling, Colorimetry, ConversionConfig, Matrix, Range};
+    use error::ConversionError;
+    use format::{ChannelOrder, PackedYuvFormat, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn round_trips_stay_close_to_the_source() {
+        // a smooth image, so that subsampling the chroma loses little
+        let rgb: Vec<u8> = (0..7 * 5).flat_map(|i| [(i % 7 * 10 + i / 7 * 4) as u8, 90 + (i / 7 * 8) as u8,
+                                                    200 - (i % 7 * 6) as u8]).collect();
+        for &(matrix, range) in &[(Matrix::Bt601, Range::Full), (Matrix::Bt709, Range::Limited),
+                                  (Matrix::Bt2020, Range::Limited)] {
+            let cfg = ConversionConfig { matrix, range, ..ConversionConfig::default() };
+            let yuv = ::convert(&RgbView::new(&rgb, 7, 5, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+            let back = convert_yuv420p_to_rgb(yuv.data(), 7, 5, &cfg).unwrap();
+            let worst = rgb.iter().zip(&back).map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs()).max();
+            assert!(worst.unwrap() <= 12, "{:?} {:?}: off by {:?}", matrix, range, worst);
+        }
+        let cfg = ConversionConfig::from(Colorimetry::Bt601FullJfif);
+        let grays: Vec<u8> = (0..=255u8).flat_map(|g| [g; 3]).collect();
+        let yuv = ::convert(&RgbView::new(&grays, 16, 16, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        assert_eq!(convert_yuv420p_to_rgb(yuv.data(), 16, 16, &cfg).unwrap(), grays);
+        assert_eq!(convert_yuv420p_to_rgb(&[0; 5], 2, 2, &cfg),
+                   Err(ConversionError::BufferTooSmall { expected: 6, got: 5 }));
+    }
+
+    #[test]
+    fn semi_planar_frames_match_i420() {
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 53 % 256) as u8).collect();
+        let i420 = ::convert(&RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        let (u, v) = (i420.u().unwrap(), i420.v().unwrap());
+        // pitched to 8 bytes
+        let y: Vec<u8> = i420.y().chunks(5).flat_map(|row| [row, &[0; 3]].concat()).collect();
+        let uv: Vec<u8> = u.chunks(3).zip(v.chunks(3)).flat_map(|(u, v)| {
+            let mut row: Vec<u8> = u.iter().zip(v).flat_map(|(&u, &v)| [u, v]).collect();
+            row.resize(8, 0);
+            row
+        }).collect();
+        let rgba = convert_nv12_to_rgb(&y, 8, &uv, 8, 5, 3, ChannelOrder::Bgra, &cfg).unwrap();
+        let expected: Vec<u8> = convert_yuv420p_to_rgb(i420.data(), 5, 3, &cfg).unwrap().chunks(3)
+            .flat_map(|px| vec![px[2], px[1], px[0], 255]).collect();
+        assert_eq!(rgba, expected);
+        assert_eq!(convert_nv12_to_rgb(&y, 8, &uv, 4, 5, 3, ChannelOrder::Rgba, &cfg),
+                   Err(ConversionError::StrideMismatch { stride: 4, row_bytes: 6 }));
+        let nv12 = ::convert(&RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap(), YuvFormat::Nv12, &cfg);
+        let mut nv21 = nv12.data().to_vec();
+        for vu in nv21[15..].chunks_mut(2) {
+            vu.swap(0, 1);
+        }
+        assert_eq!(convert_nv21_to_rgba(&nv21, 5, 3, &cfg).unwrap(),
+                   convert_nv12_to_rgb(nv12.y(), 5, nv12.uv().unwrap(), 6, 5, 3, ChannelOrder::Rgba, &cfg).unwrap());
+        assert_eq!(convert_nv21_to_rgba(&nv21[..26], 5, 3, &cfg),
+                   Err(ConversionError::BufferTooSmall { expected: 27, got: 26 }));
+    }
+
+    #[test]
+    fn packed_422_repeats_chroma_over_pairs() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        // 3x2 with 8 byte rows: the odd last pixel still has a whole macropixel
+        let yuyv = [16, 90, 235, 240, 60, 128, 99, 128, 81, 90, 145, 54, 235, 128, 0, 128];
+        let uyvy: Vec<u8> = yuyv.chunks(2).flat_map(|pair| [pair[1], pair[0]]).collect();
+        let convert = |yuv: &[u8], stride, format| {
+            convert_packed_yuv_to_rgb(yuv, stride, 3, 2, format, ChannelOrder::Rgb, &cfg)
+        };
+        let expected = convert(&yuyv, 8, PackedYuvFormat::Yuyv).unwrap();
+        assert_eq!(convert(&uyvy, 8, PackedYuvFormat::Uyvy).unwrap(), expected);
+        let coef = cfg.inverse_coefficients();
+        let pixels: Vec<[u8; 3]> = [(16, 90, 240), (235, 90, 240), (60, 128, 128), (81, 90, 54), (145, 90, 54),
+                                    (235, 128, 128)].iter().map(|&(y, u, v)| coef.rgb(y, u, v)).collect();
+        assert_eq!(expected, pixels.concat());
+        assert_eq!(convert(&yuyv, 6, PackedYuvFormat::Yuyv),
+                   Err(ConversionError::StrideMismatch { stride: 6, row_bytes: 8 }));
+    }
+
+    #[test]
+    fn bilinear_upsampling_blends_the_nearest_samples() {
+        let nearest = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        let bilinear = ConversionConfig { chroma_upsampling: ChromaUpsampling::Bilinear, ..nearest.clone() };
+        // flat chroma comes out the same either way
+        let rgb = [40, 90, 160].repeat(6 * 4);
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let yuv = ::convert(&RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap(), format, &nearest);
+            assert_eq!(yuv_to_rgb(yuv.data(), format, 6, 4, ChannelOrder::Rgb, &bilinear).unwrap(),
+                       yuv_to_rgb(yuv.data(), format, 6, 4, ChannelOrder::Rgb, &nearest).unwrap());
+        }
+        // 4x4 I420 with U and V of 16, 32, 48 and 64 and 240, 224, 208 and 192
+        let i420 = [&[128; 16][..], &[16, 32, 48, 64], &[240, 224, 208, 192]].concat();
+        let rgb = yuv_to_rgb(&i420, YuvFormat::I420, 4, 4, ChannelOrder::Rgb, &bilinear).unwrap();
+        let coef = bilinear.inverse_coefficients();
+        // the edges clamp, everything else takes 9/16, 3/16, 3/16 and 1/16 of the 4 samples
+        assert_eq!(&rgb[..3], &coef.rgb(128, 16, 240));
+        assert_eq!(&rgb[3..6], &coef.rgb(128, 20, 236));
+        assert_eq!(&rgb[3 * 5..][..3], &coef.rgb(128, 28, 228));
+        assert_eq!(&rgb[3 * 15..], &coef.rgb(128, 64, 192));
+        // 4:2:2 averages the odd pixels, the last one repeats its sample
+        let yuyv = [128, 16, 128, 240, 128, 64, 128, 192];
+        let rgb = convert_packed_yuv_to_rgb(&yuyv, 8, 4, 1, PackedYuvFormat::Yuyv, ChannelOrder::Rgb, &bilinear);
+        let expected = [(16, 240), (40, 216), (64, 192), (64, 192)].iter()
+            .flat_map(|&(u, v)| coef.rgb(128, u, v).to_vec()).collect::<Vec<u8>>();
+        assert_eq!(rgb.unwrap(), expected);
+    }
+
+    #[test]
+    fn decoded_pictures_convert_like_packed_frames() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 23 % 256) as u8).collect();
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let i420 = ::convert(&RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        let expected = yuv_to_rgb(i420.data(), YuvFormat::I420, 5, 3, ChannelOrder::Rgba, &cfg).unwrap();
+        // the planes in rows of 16 bytes, as 8 and as 10 bit samples
+        let pad = |plane: &[u8], width: usize, wide: bool| -> Vec<u8> {
+            plane.chunks(width).flat_map(|row| {
+                let mut row: Vec<u8> = if wide {
+                    row.iter().flat_map(|&sample| (u16::from(sample) << 2).to_le_bytes()).collect()
+                } else {
+                    row.to_vec()
+                };
+                row.resize(16, 0xff);
+                row
+            }).collect()
+        };
+        for &(bit_depth, wide) in &[(8, false), (10, true)] {
+            let (y, u, v) = (pad(i420.y(), 5, wide), pad(i420.u().unwrap(), 3, wide), pad(i420.v().unwrap(), 3, wide));
+            let picture = DecodedPicture { width: 5, height: 3, y: &y, u: &u, v: &v, y_stride: 16, chroma_stride: 16,
+                                           bit_depth };
+            assert_eq!(picture_to_rgb(&picture, ChannelOrder::Rgba, &cfg).unwrap(), expected);
+            let short = DecodedPicture { chroma_stride: 2, ..picture };
+            let error = picture_to_rgb(&short, ChannelOrder::Rgba, &cfg).unwrap_err();
+            assert!(matches!(error, ConversionError::StrideMismatch { .. }));
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Lazily converted output
+
+use core::iter::FusedIterator;
+
+use config::{ChromaFilter, Coefficients, ConversionConfig};
+use format::YuvFormat;
+use kernel::{self, Source};
+use layout;
+use view::RgbView;
+
+/// An iterator over the bytes of a converted frame, in the order `convert` lays them out
+///
+/// Every sample is converted when it is reached, so nothing is buffered; the bytes are the
+/// same as those of `convert` with the same configuration (`threads` is ignored).
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// let rgb = [255u8; 2 * 2 * 3];
+/// let src = RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap();
+/// let mut bytes = rgb2yuv420::convert_iter(&src, YuvFormat::Nv12, &ConversionConfig::default());
+/// assert_eq!(bytes.len(), 6);
+/// assert!(bytes.by_ref().take(4).all(|y| y == 255));
+/// assert_eq!(bytes.collect::<Vec<_>>(), [128, 128]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct YuvBytes<'a> {
+    src: Source<'a>,
+    format: YuvFormat,
+    coef: Coefficients,
+    filter: ChromaFilter,
+    chroma_width: usize,
+    // offsets of the chroma planes and the end of the frame
+    chroma: usize,
+    v: Option<usize>,
+    len: usize,
+    pos: usize,
+    // NV12 reads U and V from the same block, so keep the last one
+    block: Option<(usize, (u8, u8))>,
+}
+
+impl<'a> YuvBytes<'a> {
+    pub(crate) fn new(src: &RgbView<'a>, format: YuvFormat, cfg: &ConversionConfig) -> YuvBytes<'a> {
+        let (_, chroma, v) = layout::plane_offsets(format, src.width(), src.height());
+        YuvBytes {
+            src: *src.source(),
+            format,
+            coef: cfg.coefficients_for(format),
+            filter: cfg.sampled_chroma_filter(),
+            chroma_width: kernel::chroma_dimensions(src.width() as usize, 0).0,
+            chroma,
+            v,
+            len: layout::required_buffer_size(format, src.width(), src.height()),
+            pos: 0,
+            block: None,
+        }
+    }
+
+    fn chroma_at(&mut self, block: usize) -> (u8, u8) {
+        match self.block {
+            Some((cached, uv)) if cached == block => uv,
+            _ => {
+                let uv = kernel::chroma_at(&self.src, &self.coef, self.filter, block % self.chroma_width,
+                                           block / self.chroma_width);
+                self.block = Some((block, uv));
+                uv
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for YuvBytes<'a> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.pos == self.len {
+            return None;
+        }
+        let pos = self.pos;
+        self.pos += 1;
+        if pos < self.chroma {
+            return Some(kernel::luma_at(&self.src, &self.coef, pos % self.src.width, pos / self.src.width));
+        }
+        let sample = match (self.format, self.v) {
+            (YuvFormat::I420, Some(v)) if pos >= v => self.chroma_at(pos - v).1,
+            (YuvFormat::I420, _) => self.chroma_at(pos - self.chroma).0,
+            (YuvFormat::Nv12, _) => {
+                let (u, v) = self.chroma_at((pos - self.chroma) / 2);
+                if (pos - self.chroma).is_multiple_of(2) { u } else { v }
+            }
+        };
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len - self.pos, Some(self.len - self.pos))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<u8> {
+        self.pos = self.len.min(self.pos.saturating_add(n));
+        self.next()
+    }
+}
+
+impl<'a> ExactSizeIterator for YuvBytes<'a> {}
+
+impl<'a> FusedIterator for YuvBytes<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use config::{ChromaFilter, ConversionConfig};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn matches_buffered_conversion() {
+        let rgb: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 71 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Bgra).unwrap();
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average, ..ConversionConfig::default() };
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let lazy: Vec<u8> = ::convert_iter(&src, format, &cfg).collect();
+            assert_eq!(lazy, ::convert(&src, format, &cfg).into_vec());
+            assert_eq!(::convert_iter(&src, format, &cfg).nth(20), Some(lazy[20]));
+        }
+    }
+}
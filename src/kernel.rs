@@ -0,0 +1,1051 @@
+//! Scalar CPU conversion kernel
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::iter::{StepBy, Zip};
+use core::ops::Range;
+use core::slice::ChunksMut;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::thread;
+
+use config::{ChromaFilter, Coefficients, ConversionConfig, Keyer, PlanePasses};
+use error::ConversionError;
+use format::{PixelLayout, YuvFormat};
+#[cfg(feature = "alloc")]
+use sink::PlaneSink;
+use stats::{self, Histogram};
+#[cfg(feature = "std")]
+use threads;
+use transfer;
+
+/// Width and height of the chroma planes
+pub(crate) const fn chroma_dimensions(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+/// A validated source image
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Source<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    /// Distance between the starts of two rows in bytes
+    pub stride: usize,
+    pub layout: PixelLayout,
+    /// Native endian 16 bit channels instead of 8 bit ones
+    pub wide: bool,
+    /// The rows are stored last row first, as OpenGL reads them back
+    pub bottom_up: bool,
+}
+
+impl<'a> Source<'a> {
+    // The unchecked reads rely on this single validation: every row is sliced out of `data`
+    // with checked indexing and pixels are taken `pixel_bytes` at a time, so every channel
+    // offset is in bounds. Sizes past `usize::MAX` (eg.: a huge stride on 32 bit targets)
+    // fit in no buffer, so they are `BufferTooSmall` rather than wrapped.
+    pub fn new(data: &'a [u8], width: u32, height: u32, stride: usize, layout: PixelLayout,
+               wide: bool) -> Result<Source<'a>, ConversionError> {
+        if layout.r.max(layout.g).max(layout.b) >= layout.bytes_per_pixel {
+            return Err(ConversionError::UnsupportedFormat("bytes_per_pixel must be at least 3"));
+        }
+        let source = Source { data, width: width as usize, height: height as usize, stride, layout, wide,
+                              bottom_up: false };
+        let too_small = ConversionError::BufferTooSmall { expected: usize::MAX, got: data.len() };
+        let row_bytes = source.width.checked_mul(source.pixel_bytes()).ok_or(too_small.clone())?;
+        if stride < row_bytes {
+            return Err(ConversionError::StrideMismatch { stride, row_bytes });
+        }
+        let expected = match source.height {
+            0 => 0,
+            h => (h - 1).checked_mul(stride).and_then(|start| start.checked_add(row_bytes)).ok_or(too_small)?,
+        };
+        if data.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: data.len() });
+        }
+        Ok(source)
+    }
+
+    /// Tightly packed 8 bit pixels
+    #[cfg(feature = "alloc")]
+    pub fn packed(data: &'a [u8], width: u32, height: u32, layout: PixelLayout)
+                  -> Result<Source<'a>, ConversionError> {
+        Source::new(data, width, height, (width as usize).saturating_mul(layout.bytes_per_pixel), layout, false)
+    }
+
+    /// The rows of the top (even) or the bottom (odd) field of an interlaced image
+    #[cfg(feature = "alloc")]
+    pub fn field(&self, bottom: bool) -> Source<'a> {
+        debug_assert!(!self.bottom_up);
+        let start = if bottom { self.stride.min(self.data.len()) } else { 0 };
+        let height = (self.height + usize::from(!bottom)) / 2;
+        Source { data: &self.data[start..], height, stride: 2 * self.stride, ..*self }
+    }
+
+    /// Number of bytes used by one pixel
+    pub fn pixel_bytes(&self) -> usize {
+        if self.wide { 2 * self.layout.bytes_per_pixel } else { self.layout.bytes_per_pixel }
+    }
+
+    /// The pixels of `row` rounded to 8 bits per channel
+    #[cfg(feature = "alloc")]
+    pub fn rgb_row(&self, row: usize) -> impl Iterator<Item = [u8; 3]> + 'a {
+        let (layout, wide) = (self.layout, self.wide);
+        self.row(row).chunks_exact(self.pixel_bytes()).map(move |px| {
+            let (r, g, b) = if wide { Wide(layout).read(px) } else { layout.read(px) };
+            [r as u8, g as u8, b as u8]
+        })
+    }
+
+    /// The alpha channel of `row` rounded to 8 bits, if the layout has one
+    #[cfg(feature = "alloc")]
+    pub fn alpha_row(&self, row: usize) -> Option<impl Iterator<Item = u8> + 'a> {
+        let (channel, wide) = (self.layout.alpha?, self.wide);
+        Some(self.row(row).chunks_exact(self.pixel_bytes())
+            .map(move |px| if wide { read_wide(px, channel) as u8 } else { px[channel] }))
+    }
+
+    // checked not to overflow by `new`
+    fn row_bytes(&self) -> usize {
+        self.width * self.pixel_bytes()
+    }
+
+    fn row(&self, row: usize) -> &'a [u8] {
+        let row = if self.bottom_up { self.height - 1 - row } else { row };
+        &self.data[row * self.stride..][..self.row_bytes()]
+    }
+}
+
+/// Size of a tightly packed YUV420 frame in bytes
+pub(crate) const fn frame_size(width: usize, height: usize) -> usize {
+    let (chroma_width, chroma_height) = chroma_dimensions(width, height);
+    width * height + 2 * chroma_width * chroma_height
+}
+
+/// Converts an image into a newly allocated, tightly packed YUV420 buffer
+#[cfg(feature = "alloc")]
+pub(crate) fn convert(src: &Source, format: YuvFormat, cfg: &ConversionConfig) -> Vec<u8> {
+    let mut yuv = vec![0; frame_size(src.width, src.height)];
+    convert_into(src, format, cfg, &mut yuv);
+    yuv
+}
+
+/// Converts an image into the first `frame_size` bytes of `yuv` without allocating
+/// (unless the frame is split across threads)
+pub(crate) fn convert_into(src: &Source, format: YuvFormat, cfg: &ConversionConfig, yuv: &mut [u8]) {
+    Setup::new(src.width, src.height, src.layout, src.wide, format, cfg).run(src, yuv);
+}
+
+/// The bands of luma rows and their chroma rows, with the index of their first row
+type Jobs<'a> = Zip<Zip<StepBy<Range<usize>>, ChunksMut<'a, u8>>, ChromaBands<'a>>;
+
+type RunBands = fn(Jobs, usize, &Source, Strides, &Coefficients, ChromaFilter, Option<&Histogram>);
+
+/// Writes the luma plane alone, with the rows the given stride apart
+type RunLuma = fn(&Source, &mut [u8], usize, &Coefficients, Option<&Histogram>);
+
+/// Writes the chroma planes alone, with the rows the given stride apart
+type RunChroma = fn(&Source, ChromaBand, usize, &Coefficients, ChromaFilter);
+
+/// The passes of the kernel for one pixel layout
+#[derive(Debug, Clone, Copy)]
+struct Kernel {
+    bands: RunBands,
+    luma: RunLuma,
+    chroma: RunChroma,
+}
+
+impl Kernel {
+    fn of<P: ReadRgb>() -> Kernel {
+        Kernel { bands: run_bands::<P>, luma: luma_plane::<P>, chroma: chroma_planes::<P> }
+    }
+}
+
+/// Distances between the starts of two rows of the luma and the chroma planes in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Strides {
+    pub y: usize,
+    pub chroma: usize,
+}
+
+/// The planes of a frame to convert into
+pub(crate) struct Planes<'a> {
+    pub y: &'a mut [u8],
+    pub chroma: ChromaPlanes<'a>,
+    pub strides: Strides,
+    /// A full resolution plane for the alpha channel, with the rows `strides.y` apart
+    pub alpha: Option<&'a mut [u8]>,
+    /// Counts the luma codes written
+    pub stats: Option<&'a Histogram>,
+}
+
+impl<'a> Planes<'a> {
+    /// The planes of a tightly packed frame in the first `frame_size` bytes of `yuv`
+    pub fn packed(yuv: &'a mut [u8], format: YuvFormat, width: usize, height: usize) -> Planes<'a> {
+        let (chroma_width, chroma_height) = chroma_dimensions(width, height);
+        let luma_size = width * height;
+        let chroma_size = chroma_width * chroma_height;
+        let (y, chroma) = yuv[..luma_size + 2 * chroma_size].split_at_mut(luma_size);
+        let (chroma, chroma_stride) = match format {
+            YuvFormat::I420 => {
+                let (u, v) = chroma.split_at_mut(chroma_size);
+                (ChromaPlanes::Planar(u, v), chroma_width)
+            }
+            YuvFormat::Nv12 => (ChromaPlanes::SemiPlanar(chroma), 2 * chroma_width),
+        };
+        Planes { y, chroma, strides: Strides { y: width, chroma: chroma_stride }, alpha: None, stats: None }
+    }
+
+    /// Splits off the first `rows` (an even number of) luma rows and their chroma rows
+    #[cfg(feature = "alloc")]
+    pub fn split_rows(self, rows: usize) -> (Planes<'a>, Planes<'a>) {
+        debug_assert!(rows.is_multiple_of(2));
+        let strides = self.strides;
+        let split = |plane: &'a mut [u8], at: usize| plane.split_at_mut(at.min(plane.len()));
+        let (y, rest_y) = split(self.y, rows * strides.y);
+        let (chroma, rest_chroma) = match self.chroma {
+            ChromaPlanes::Planar(u, v) => {
+                let (u, rest_u) = split(u, rows / 2 * strides.chroma);
+                let (v, rest_v) = split(v, rows / 2 * strides.chroma);
+                (ChromaPlanes::Planar(u, v), ChromaPlanes::Planar(rest_u, rest_v))
+            }
+            ChromaPlanes::SemiPlanar(uv) => {
+                let (uv, rest_uv) = split(uv, rows / 2 * strides.chroma);
+                (ChromaPlanes::SemiPlanar(uv), ChromaPlanes::SemiPlanar(rest_uv))
+            }
+        };
+        let (alpha, rest_alpha) = match self.alpha {
+            Some(alpha) => {
+                let (alpha, rest) = split(alpha, rows * strides.y);
+                (Some(alpha), Some(rest))
+            }
+            None => (None, None),
+        };
+        let stats = self.stats;
+        (Planes { y, chroma, strides, alpha, stats },
+         Planes { y: rest_y, chroma: rest_chroma, strides, alpha: rest_alpha, stats })
+    }
+
+    /// The planes from the pixel at (`x`, `y`) on, both even, keeping the strides, for
+    /// converting a region of the frame
+    pub fn offset(self, x: usize, y: usize) -> Planes<'a> {
+        debug_assert!(x.is_multiple_of(2) && y.is_multiple_of(2));
+        let strides = self.strides;
+        let skip = |plane: &'a mut [u8], start: usize| {
+            let start = start.min(plane.len());
+            &mut plane[start..]
+        };
+        let chroma_row = y / 2 * strides.chroma;
+        let chroma = match self.chroma {
+            ChromaPlanes::Planar(u, v) =>
+                ChromaPlanes::Planar(skip(u, chroma_row + x / 2), skip(v, chroma_row + x / 2)),
+            ChromaPlanes::SemiPlanar(uv) => ChromaPlanes::SemiPlanar(skip(uv, chroma_row + x)),
+        };
+        Planes {
+            y: skip(self.y, y * strides.y + x),
+            chroma,
+            strides,
+            alpha: self.alpha.map(|alpha| skip(alpha, y * strides.y + x)),
+            stats: self.stats,
+        }
+    }
+
+    /// The rows of the top or the bottom field of an interlaced frame: every other luma row
+    /// and every other chroma row, starting with the first or the second one
+    #[cfg(feature = "alloc")]
+    pub fn field(self, bottom: bool) -> Planes<'a> {
+        let strides = self.strides;
+        let skip = |plane: &'a mut [u8], stride: usize| {
+            let start = if bottom { stride.min(plane.len()) } else { 0 };
+            &mut plane[start..]
+        };
+        let chroma = match self.chroma {
+            ChromaPlanes::Planar(u, v) => ChromaPlanes::Planar(skip(u, strides.chroma), skip(v, strides.chroma)),
+            ChromaPlanes::SemiPlanar(uv) => ChromaPlanes::SemiPlanar(skip(uv, strides.chroma)),
+        };
+        Planes {
+            y: skip(self.y, strides.y),
+            chroma,
+            strides: Strides { y: 2 * strides.y, chroma: 2 * strides.chroma },
+            alpha: self.alpha.map(|alpha| skip(alpha, strides.y)),
+            stats: self.stats,
+        }
+    }
+}
+
+pub(crate) enum ChromaPlanes<'a> {
+    Planar(&'a mut [u8], &'a mut [u8]),
+    SemiPlanar(&'a mut [u8]),
+}
+
+// the kernel for pixels of `layout`, with its name; without `layout-kernels` every 8 bit layout
+// shares the generic one
+fn select(layout: PixelLayout, wide: bool) -> (Kernel, &'static str) {
+    match (wide, layout.bytes_per_pixel, layout.r, layout.g, layout.b) {
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 0, 1, 2) => (Kernel::of::<Fixed<3, 0, 1, 2>>(), "rgb"),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 0, 1, 2) => (Kernel::of::<Fixed<4, 0, 1, 2>>(), "rgbx"),
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 2, 1, 0) => (Kernel::of::<Fixed<3, 2, 1, 0>>(), "bgr"),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 2, 1, 0) => (Kernel::of::<Fixed<4, 2, 1, 0>>(), "bgrx"),
+        (false, ..) => (Kernel::of::<PixelLayout>(), "generic"),
+        (true, ..) => (Kernel::of::<Wide>(), "generic_16_bit"),
+    }
+}
+
+/// Name of the kernel converting `src`, as reported to `tracing` and in `ThroughputStats`
+#[cfg(feature = "std")]
+pub(crate) fn kernel_name(src: &Source) -> &'static str {
+    select(src.layout, src.wide).1
+}
+
+/// Everything `convert_into` works out from the configuration and the frame size, so that
+/// frames of the same size and layout can be converted without repeating it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Setup {
+    format: YuvFormat,
+    coef: Coefficients,
+    filter: ChromaFilter,
+    width: usize,
+    height: usize,
+    bands: usize,
+    band_rows: usize,
+    run: Kernel,
+    passes: PlanePasses,
+    keyer: Option<Keyer>,
+    // reported to `tracing`
+    #[cfg_attr(not(feature = "tracing"), allow(dead_code))]
+    kernel: &'static str,
+}
+
+impl Setup {
+    pub fn new(width: usize, height: usize, layout: PixelLayout, wide: bool, format: YuvFormat,
+               cfg: &ConversionConfig) -> Setup {
+        let chroma_height = chroma_dimensions(width, height).1;
+        #[cfg(feature = "std")]
+        let bands = threads::limit(cfg.threads).clamp(1, chroma_height.max(1));
+        #[cfg(not(feature = "std"))]
+        let bands = 1;
+        let (run, kernel) = select(layout, wide);
+        Setup {
+            format,
+            coef: cfg.coefficients_for(format),
+            filter: cfg.sampled_chroma_filter(),
+            width,
+            height,
+            bands,
+            band_rows: chroma_height.div_ceil(bands),
+            run,
+            passes: cfg.plane_passes,
+            keyer: cfg.chroma_key.map(|key| key.keyer()),
+            kernel,
+        }
+    }
+
+    /// Converts `src`, which must have the size and layout the setup was made for, into the
+    /// first `frame_size` bytes of `yuv`
+    pub fn run(&self, src: &Source, yuv: &mut [u8]) {
+        self.run_planes(src, Planes::packed(yuv, self.format, self.width, self.height));
+    }
+
+    /// Converts `src` into `dst`, whose planes must hold the rows of the frame at their strides
+    /// (the padding at the end of the rows is left alone)
+    pub fn run_planes(&self, src: &Source, dst: Planes) {
+        if self.width * self.height == 0 {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("rgb2yuv420::convert", width = self.width, height = self.height,
+                                         format = ?self.format, kernel = self.kernel, bands = self.bands)
+            .entered();
+        self.run_part(src, dst);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(bytes_read = self.height * src.row_bytes(),
+                        bytes_written = frame_size(self.width, self.height), "converted frame");
+    }
+
+    /// `run_planes` for one part of a frame converted in pieces, which get no span of their own
+    pub fn run_part(&self, src: &Source, dst: Planes) {
+        debug_assert_eq!((src.width, src.height), (self.width, self.height));
+        let (width, height, band_rows, strides) = (self.width, self.height, self.band_rows, dst.strides);
+        let (chroma_width, chroma_height) = chroma_dimensions(width, height);
+        if width * height == 0 {
+            return;
+        }
+        // without the padding after the last rows, so that the bands end with the planes
+        let extent = |stride: usize, rows: usize, row_bytes: usize| (rows - 1) * stride + row_bytes;
+        let y = &mut dst.y[..extent(strides.y, height, width)];
+        let chroma = match dst.chroma {
+            ChromaPlanes::Planar(u, v) => {
+                let len = extent(strides.chroma, chroma_height, chroma_width);
+                ChromaBand::Planar(&mut u[..len], &mut v[..len])
+            }
+            ChromaPlanes::SemiPlanar(uv) => {
+                let len = extent(strides.chroma, chroma_height, 2 * chroma_width);
+                ChromaBand::SemiPlanar(&mut uv[..len])
+            }
+        };
+        match self.passes {
+            PlanePasses::Fused => {
+                let y_bands = y.chunks_mut(band_rows * 2 * strides.y);
+                let first_rows = (0..height).step_by(band_rows * 2);
+                let chroma_bands = match chroma {
+                    ChromaBand::Planar(u, v) => ChromaBands::Planar(u.chunks_mut(band_rows * strides.chroma),
+                                                                    v.chunks_mut(band_rows * strides.chroma)),
+                    ChromaBand::SemiPlanar(uv) => ChromaBands::SemiPlanar(uv.chunks_mut(band_rows * strides.chroma)),
+                };
+                (self.run.bands)(first_rows.zip(y_bands).zip(chroma_bands), self.bands, src, strides, &self.coef,
+                                 self.filter, dst.stats);
+            }
+            PlanePasses::Separate => {
+                let (run, coef, filter, stats) = (self.run, &self.coef, self.filter, dst.stats);
+                join(self.bands > 1, move || (run.luma)(src, y, strides.y, coef, stats),
+                     move || (run.chroma)(src, chroma, strides.chroma, coef, filter));
+            }
+        }
+        if let Some(alpha) = dst.alpha {
+            copy_alpha(src, alpha, strides.y, self.keyer);
+        }
+    }
+}
+
+/// Runs `first` and `second`, at once on two threads if `parallel`
+fn join<A: FnOnce() + Send, B: FnOnce()>(parallel: bool, first: A, second: B) {
+    #[cfg(feature = "std")]
+    {
+        if parallel {
+            thread::scope(|s| {
+                s.spawn(first);
+                second();
+            });
+            return;
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = parallel;
+    first();
+    second();
+}
+
+/// Copies the alpha channel of `src` into a plane with rows `stride` apart, which is opaque
+/// if the layout has no alpha channel, or keys it out of the colors with `keyer`
+fn copy_alpha(src: &Source, alpha: &mut [u8], stride: usize, keyer: Option<Keyer>) {
+    let (bpp, layout) = (src.pixel_bytes(), src.layout);
+    for (row, out) in alpha.chunks_mut(stride).take(src.height).enumerate() {
+        let out = &mut out[..src.width];
+        if let Some(keyer) = keyer {
+            for (a, px) in out.iter_mut().zip(src.row(row).chunks_exact(bpp)) {
+                let (r, g, b) = if src.wide { Wide(layout).read(px) } else { layout.read(px) };
+                *a = keyer.alpha(r, g, b);
+            }
+            continue;
+        }
+        match layout.alpha {
+            Some(channel) if src.wide => {
+                for (a, px) in out.iter_mut().zip(src.row(row).chunks_exact(bpp)) {
+                    *a = read_wide(px, channel) as u8;
+                }
+            }
+            Some(channel) => {
+                for (a, px) in out.iter_mut().zip(src.row(row).chunks_exact(bpp)) {
+                    *a = px[channel];
+                }
+            }
+            None => out.fill(255),
+        }
+    }
+}
+
+// kept out of line so that every layout is optimized on its own
+#[inline(never)]
+fn run_bands<P: ReadRgb>(jobs: Jobs, bands: usize, src: &Source, strides: Strides, coef: &Coefficients,
+                         filter: ChromaFilter, stats: Option<&Histogram>) {
+    let pixel = P::from_layout(src.layout);
+    // the band spans of worker threads belong to the frame being converted
+    #[cfg(feature = "tracing")]
+    let frame = tracing::Span::current();
+    let band = |first_row, y, chroma| {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(parent: &frame, "band", first_row).entered();
+        convert_band(src, first_row, pixel, strides, coef, filter, y, chroma, stats);
+    };
+    // without `std` there are no threads and every frame is a single band
+    #[cfg(feature = "std")]
+    {
+        if bands > 1 {
+            let band = &band;
+            thread::scope(|s| {
+                for (worker, ((first_row, y), chroma)) in jobs.enumerate() {
+                    s.spawn(move || {
+                        threads::pin_worker(worker);
+                        band(first_row, y, chroma)
+                    });
+                }
+            });
+            return;
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = bands;
+    for ((first_row, y), chroma) in jobs {
+        band(first_row, y, chroma);
+    }
+}
+
+/// Converts the luma of `src` into the rows of `y`, `stride` apart, without the chroma
+#[cfg(feature = "alloc")]
+pub(crate) fn convert_luma(src: &Source, cfg: &ConversionConfig, y: &mut [u8], stride: usize) {
+    (select(src.layout, src.wide).0.luma)(src, y, stride, &cfg.coefficients(), None);
+}
+
+// the luma pass of `PlanePasses::Separate`
+#[inline(never)]
+fn luma_plane<P: ReadRgb>(src: &Source, y: &mut [u8], stride: usize, coef: &Coefficients,
+                          stats: Option<&Histogram>) {
+    let pixel = P::from_layout(src.layout);
+    let mut counts = [0; 256];
+    for (j, row) in y.chunks_mut(stride).take(src.height).enumerate() {
+        let row = &mut row[..src.width];
+        luma_row(src.row(j), pixel, coef, row);
+        if stats.is_some() {
+            stats::count_row(&mut counts, row);
+        }
+    }
+    if let Some(stats) = stats {
+        stats.add(&counts);
+    }
+}
+
+// the chroma pass of `PlanePasses::Separate`
+#[inline(never)]
+fn chroma_planes<P: ReadRgb>(src: &Source, chroma: ChromaBand, stride: usize, coef: &Coefficients,
+                             filter: ChromaFilter) {
+    let pixel = P::from_layout(src.layout);
+    let rows = |cy: usize| {
+        let bottom = 2 * cy + 1;
+        (src.row(2 * cy), if bottom < src.height { Some(src.row(bottom)) } else { None })
+    };
+    match chroma {
+        ChromaBand::Planar(u, v) => {
+            for (cy, (u, v)) in u.chunks_mut(stride).zip(v.chunks_mut(stride)).enumerate() {
+                let (top, bottom) = rows(cy);
+                chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+                    u[i] = cb;
+                    v[i] = cr;
+                });
+            }
+        }
+        ChromaBand::SemiPlanar(uv) => {
+            for (cy, uv) in uv.chunks_mut(stride).enumerate() {
+                let (top, bottom) = rows(cy);
+                chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+                    uv[2 * i] = cb;
+                    uv[2 * i + 1] = cr;
+                });
+            }
+        }
+    }
+}
+
+/// Converts an image plane by plane into `out`, holding a single output row in memory
+///
+/// The source is read once per plane and `cfg.threads` is ignored.
+#[cfg(feature = "std")]
+pub(crate) fn write<W: Write>(src: &Source, format: YuvFormat, cfg: &ConversionConfig, out: &mut W)
+                              -> io::Result<()> {
+    let coef = cfg.coefficients_for(format);
+    let filter = cfg.sampled_chroma_filter();
+    let layout = src.layout;
+    match (src.wide, layout.bytes_per_pixel, layout.r, layout.g, layout.b) {
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 0, 1, 2) => write_planes(src, format, Fixed::<3, 0, 1, 2>, &coef, filter, out),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 0, 1, 2) => write_planes(src, format, Fixed::<4, 0, 1, 2>, &coef, filter, out),
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 2, 1, 0) => write_planes(src, format, Fixed::<3, 2, 1, 0>, &coef, filter, out),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 2, 1, 0) => write_planes(src, format, Fixed::<4, 2, 1, 0>, &coef, filter, out),
+        (false, ..) => write_planes(src, format, layout, &coef, filter, out),
+        (true, ..) => write_planes(src, format, Wide(layout), &coef, filter, out),
+    }
+}
+
+#[cfg(feature = "std")]
+#[inline(never)]
+fn write_planes<P: ReadRgb, W: Write>(src: &Source, format: YuvFormat, pixel: P, coef: &Coefficients,
+                                      filter: ChromaFilter, out: &mut W) -> io::Result<()> {
+    let (chroma_width, chroma_height) = chroma_dimensions(src.width, src.height);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("rgb2yuv420::convert_to_writer", width = src.width, height = src.height,
+                                     format = ?format).entered();
+    let mut row = vec![0; src.width.max(2 * chroma_width)];
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("plane", plane = "y").entered();
+        for j in 0..src.height {
+            luma_row(src.row(j), pixel, coef, &mut row[..src.width]);
+            out.write_all(&row[..src.width])?;
+        }
+    }
+    let chroma_rows = (0..chroma_height).map(|cy| {
+        let bottom = 2 * cy + 1;
+        (src.row(2 * cy), if bottom < src.height { Some(src.row(bottom)) } else { None })
+    });
+    match format {
+        YuvFormat::I420 => {
+            // U and V are computed together, so each plane's pass discards the other half
+            for plane in 0..2 {
+                #[cfg(feature = "tracing")]
+                let _span = tracing::trace_span!("plane", plane = if plane == 0 { "u" } else { "v" }).entered();
+                for (top, bottom) in chroma_rows.clone() {
+                    chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| row[i] = if plane == 0 { cb } else { cr });
+                    out.write_all(&row[..chroma_width])?;
+                }
+            }
+        }
+        YuvFormat::Nv12 => {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("plane", plane = "uv").entered();
+            for (top, bottom) in chroma_rows {
+                chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+                    row[2 * i] = cb;
+                    row[2 * i + 1] = cr;
+                });
+                out.write_all(&row[..2 * chroma_width])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Converts an image into `sink` row by row on the calling thread
+#[cfg(feature = "alloc")]
+pub(crate) fn convert_into_sink<S: PlaneSink>(src: &Source, cfg: &ConversionConfig, sink: &mut S)
+                                              -> Result<(), S::Error> {
+    let coef = cfg.coefficients();
+    let filter = cfg.sampled_chroma_filter();
+    let layout = src.layout;
+    match (src.wide, layout.bytes_per_pixel, layout.r, layout.g, layout.b) {
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 0, 1, 2) => sink_rows(src, Fixed::<3, 0, 1, 2>, &coef, filter, sink),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 0, 1, 2) => sink_rows(src, Fixed::<4, 0, 1, 2>, &coef, filter, sink),
+        #[cfg(feature = "layout-kernels")]
+        (false, 3, 2, 1, 0) => sink_rows(src, Fixed::<3, 2, 1, 0>, &coef, filter, sink),
+        #[cfg(feature = "layout-kernels")]
+        (false, 4, 2, 1, 0) => sink_rows(src, Fixed::<4, 2, 1, 0>, &coef, filter, sink),
+        (false, ..) => sink_rows(src, layout, &coef, filter, sink),
+        (true, ..) => sink_rows(src, Wide(layout), &coef, filter, sink),
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[inline(never)]
+fn sink_rows<P: ReadRgb, S: PlaneSink>(src: &Source, pixel: P, coef: &Coefficients, filter: ChromaFilter,
+                                       sink: &mut S) -> Result<(), S::Error> {
+    let (chroma_width, chroma_height) = chroma_dimensions(src.width, src.height);
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!("rgb2yuv420::convert_rows", width = src.width, height = src.height).entered();
+    let mut y = vec![0; src.width];
+    let (mut u, mut v) = (vec![0; chroma_width], vec![0; chroma_width]);
+    for cy in 0..chroma_height {
+        let top = src.row(2 * cy);
+        luma_row(top, pixel, coef, &mut y);
+        sink.write_y_row(2 * cy, &y)?;
+        let bottom = if 2 * cy + 1 < src.height { Some(src.row(2 * cy + 1)) } else { None };
+        if let Some(bottom) = bottom {
+            luma_row(bottom, pixel, coef, &mut y);
+            sink.write_y_row(2 * cy + 1, &y)?;
+        }
+        chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+            u[i] = cb;
+            v[i] = cr;
+        });
+        sink.write_chroma_row(cy, &u, &v)?;
+    }
+    sink.finish()
+}
+
+/// Converts the luma sample of the pixel at `x`, `row` on its own
+pub(crate) fn luma_at(src: &Source, coef: &Coefficients, x: usize, row: usize) -> u8 {
+    let bpp = src.pixel_bytes();
+    let px = &src.row(row)[x * bpp..][..bpp];
+    let mut y = [0];
+    if src.wide {
+        luma_row(px, Wide(src.layout), coef, &mut y);
+    } else {
+        luma_row(px, src.layout, coef, &mut y);
+    }
+    y[0]
+}
+
+/// Converts the chroma sample at `cx`, `cy` of the chroma planes on its own
+pub(crate) fn chroma_at(src: &Source, coef: &Coefficients, filter: ChromaFilter, cx: usize, cy: usize)
+                        -> (u8, u8) {
+    let bpp = src.pixel_bytes();
+    let block = |row: usize| &src.row(row)[2 * cx * bpp..(2 * cx + 2).min(src.width) * bpp];
+    let top = block(2 * cy);
+    let bottom = if 2 * cy + 1 < src.height { Some(block(2 * cy + 1)) } else { None };
+    let mut uv = (0, 0);
+    if src.wide {
+        chroma_row(top, bottom, Wide(src.layout), coef, filter, |_, u, v| uv = (u, v));
+    } else {
+        chroma_row(top, bottom, src.layout, coef, filter, |_, u, v| uv = (u, v));
+    }
+    uv
+}
+
+/// Reads the color channels of one pixel
+///
+/// Implemented for runtime `PixelLayout`s and for the common layouts fixed at compile time,
+/// which lets the compiler drop the per-channel bounds checks and vectorize the row loops.
+trait ReadRgb: Copy + Send + Sync {
+    fn from_layout(layout: PixelLayout) -> Self;
+
+    fn bytes_per_pixel(&self) -> usize;
+
+    /// `px` starts at the pixel and is at least `bytes_per_pixel` long
+    fn read(&self, px: &[u8]) -> (i32, i32, i32);
+}
+
+impl ReadRgb for PixelLayout {
+    fn from_layout(layout: PixelLayout) -> PixelLayout {
+        layout
+    }
+
+    #[inline(always)]
+    fn bytes_per_pixel(&self) -> usize {
+        self.bytes_per_pixel
+    }
+
+    #[inline(always)]
+    fn read(&self, px: &[u8]) -> (i32, i32, i32) {
+        read_rgb(px, self.r, self.g, self.b)
+    }
+}
+
+#[cfg(feature = "layout-kernels")]
+#[derive(Clone, Copy)]
+struct Fixed<const BPP: usize, const R: usize, const G: usize, const B: usize>;
+
+#[cfg(feature = "layout-kernels")]
+impl<const BPP: usize, const R: usize, const G: usize, const B: usize> ReadRgb for Fixed<BPP, R, G, B> {
+    fn from_layout(_: PixelLayout) -> Self {
+        Fixed
+    }
+
+    #[inline(always)]
+    fn bytes_per_pixel(&self) -> usize {
+        BPP
+    }
+
+    #[inline(always)]
+    fn read(&self, px: &[u8]) -> (i32, i32, i32) {
+        read_rgb(px, R, G, B)
+    }
+}
+
+/// A runtime layout of 16 bit channels
+#[derive(Clone, Copy)]
+struct Wide(PixelLayout);
+
+impl ReadRgb for Wide {
+    fn from_layout(layout: PixelLayout) -> Wide {
+        Wide(layout)
+    }
+
+    #[inline(always)]
+    fn bytes_per_pixel(&self) -> usize {
+        2 * self.0.bytes_per_pixel
+    }
+
+    #[inline(always)]
+    fn read(&self, px: &[u8]) -> (i32, i32, i32) {
+        (read_wide(px, self.0.r), read_wide(px, self.0.g), read_wide(px, self.0.b))
+    }
+}
+
+/// The chroma rows belonging to one band of luma rows
+enum ChromaBand<'a> {
+    Planar(&'a mut [u8], &'a mut [u8]),
+    SemiPlanar(&'a mut [u8]),
+}
+
+/// Splits the chroma planes into `ChromaBand`s
+enum ChromaBands<'a> {
+    Planar(ChunksMut<'a, u8>, ChunksMut<'a, u8>),
+    SemiPlanar(ChunksMut<'a, u8>),
+}
+
+impl<'a> Iterator for ChromaBands<'a> {
+    type Item = ChromaBand<'a>;
+
+    fn next(&mut self) -> Option<ChromaBand<'a>> {
+        match *self {
+            ChromaBands::Planar(ref mut u, ref mut v) => Some(ChromaBand::Planar(u.next()?, v.next()?)),
+            ChromaBands::SemiPlanar(ref mut uv) => uv.next().map(ChromaBand::SemiPlanar),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn convert_band<P: ReadRgb>(src: &Source, first_row: usize, pixel: P, strides: Strides, coef: &Coefficients,
+                            filter: ChromaFilter, y_plane: &mut [u8], chroma: ChromaBand, stats: Option<&Histogram>) {
+    // row pair by row pair, so that the chroma pass reads the pixels while they are still cached
+    let pairs = y_plane.chunks_mut(2 * strides.y).enumerate().map(|(i, y)| (first_row + 2 * i, y));
+    let mut counts = [0; 256];
+    let mut count = |y: &[u8], rows: usize| {
+        if stats.is_some() {
+            for row in y.chunks(strides.y).take(rows) {
+                stats::count_row(&mut counts, &row[..src.width]);
+            }
+        }
+    };
+    match chroma {
+        ChromaBand::Planar(u, v) => {
+            for ((row, y), (u, v)) in pairs.zip(u.chunks_mut(strides.chroma).zip(v.chunks_mut(strides.chroma))) {
+                let (top, bottom) = luma_pair(src, row, pixel, strides.y, coef, y);
+                count(y, 1 + bottom.is_some() as usize);
+                chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+                    u[i] = cb;
+                    v[i] = cr;
+                });
+            }
+        }
+        ChromaBand::SemiPlanar(uv) => {
+            for ((row, y), uv) in pairs.zip(uv.chunks_mut(strides.chroma)) {
+                let (top, bottom) = luma_pair(src, row, pixel, strides.y, coef, y);
+                count(y, 1 + bottom.is_some() as usize);
+                chroma_row(top, bottom, pixel, coef, filter, |i, cb, cr| {
+                    uv[2 * i] = cb;
+                    uv[2 * i + 1] = cr;
+                });
+            }
+        }
+    }
+    if let Some(stats) = stats {
+        stats.add(&counts);
+    }
+}
+
+/// Converts the luma of the pair of rows starting at `row` (or of the last, unpaired row)
+/// into the rows of `y`, `y_stride` apart, and returns the source rows
+fn luma_pair<'a, P: ReadRgb>(src: &Source<'a>, row: usize, pixel: P, y_stride: usize, coef: &Coefficients,
+                             y: &mut [u8]) -> (&'a [u8], Option<&'a [u8]>) {
+    let top = src.row(row);
+    if row + 1 == src.height {
+        luma_row(top, pixel, coef, y);
+        (top, None)
+    } else {
+        let (y_top, y_bottom) = y.split_at_mut(y_stride);
+        luma_row(top, pixel, coef, y_top);
+        let bottom = src.row(row + 1);
+        luma_row(bottom, pixel, coef, y_bottom);
+        (top, Some(bottom))
+    }
+}
+
+fn luma_row<P: ReadRgb>(src: &[u8], pixel: P, coef: &Coefficients, dst: &mut [u8]) {
+    if coef.fast {
+        luma_row_unrolled(src, pixel, coef, dst);
+    } else {
+        for (px, y) in src.chunks_exact(pixel.bytes_per_pixel()).zip(dst.iter_mut()) {
+            let (r, g, b) = pixel.read(px);
+            *y = coef.luma(r, g, b);
+        }
+    }
+    // a second pass over the cached row, so that the unadjusted loop stays the same
+    let pixels = (src.len() / pixel.bytes_per_pixel()).min(dst.len());
+    coef.adjust_luma(&mut dst[..pixels]);
+}
+
+// four pixels at a time with a single store of their luma, for `Quality::Fast`
+fn luma_row_unrolled<P: ReadRgb>(src: &[u8], pixel: P, coef: &Coefficients, dst: &mut [u8]) {
+    let bpp = pixel.bytes_per_pixel();
+    let blocks = src.chunks_exact(4 * bpp);
+    let rest = blocks.remainder();
+    let unrolled = src.len() / (4 * bpp) * 4;
+    for (px, y) in blocks.zip(dst.chunks_exact_mut(4)) {
+        let luma = |i: usize| {
+            let (r, g, b) = pixel.read(&px[i * bpp..]);
+            coef.luma(r, g, b)
+        };
+        y.copy_from_slice(&[luma(0), luma(1), luma(2), luma(3)]);
+    }
+    for (px, y) in rest.chunks_exact(bpp).zip(dst.iter_mut().skip(unrolled)) {
+        let (r, g, b) = pixel.read(px);
+        *y = coef.luma(r, g, b);
+    }
+}
+
+fn chroma_row<P, F>(top: &[u8], bottom: Option<&[u8]>, pixel: P, coef: &Coefficients, filter: ChromaFilter,
+                    mut store: F)
+    where P: ReadRgb, F: FnMut(usize, u8, u8) {
+    let bpp = pixel.bytes_per_pixel();
+    match filter {
+        ChromaFilter::Point => {
+            for (i, pair) in top.chunks(2 * bpp).enumerate() {
+                let (r, g, b) = pixel.read(&pair[pair.len() - bpp..]);
+                let (u, v) = coef.chroma(r, g, b);
+                store(i, u, v);
+            }
+        }
+        ChromaFilter::Average => average_row(top, bottom, pixel, coef, |c| c, |c| c, store),
+        ChromaFilter::LinearAverage => {
+            average_row(top, bottom, pixel, coef, transfer::to_linear, transfer::from_linear, store)
+        }
+        ChromaFilter::Neutral => {
+            for i in 0..top.len().div_ceil(2 * bpp) {
+                store(i, 128, 128);
+            }
+        }
+    }
+}
+
+// averages the channels of every 2x2 block once `decode`d, and `encode`s the averages back
+#[inline(always)]
+fn average_row<P, D, E, F>(top: &[u8], bottom: Option<&[u8]>, pixel: P, coef: &Coefficients, decode: D, encode: E,
+                          mut store: F)
+    where P: ReadRgb, D: Fn(i32) -> i32, E: Fn(i32) -> i32, F: FnMut(usize, u8, u8) {
+    let bpp = pixel.bytes_per_pixel();
+    for (i, pair) in top.chunks(2 * bpp).enumerate() {
+        let below = bottom.map(|row| &row[i * 2 * bpp..i * 2 * bpp + pair.len()]);
+        let mut sum = (0, 0, 0);
+        let mut count = 0;
+        for px in pair.chunks_exact(bpp).chain(below.into_iter().flat_map(|p| p.chunks_exact(bpp))) {
+            let (r, g, b) = pixel.read(px);
+            sum = (sum.0 + decode(r), sum.1 + decode(g), sum.2 + decode(b));
+            count += 1;
+        }
+        let average = |sum: i32| encode((sum + count / 2) / count);
+        let (u, v) = coef.chroma(average(sum.0), average(sum.1), average(sum.2));
+        store(i, u, v);
+    }
+}
+
+#[inline(always)]
+fn read_rgb(px: &[u8], r: usize, g: usize, b: usize) -> (i32, i32, i32) {
+    (i32::from(byte(px, r)), i32::from(byte(px, g)), i32::from(byte(px, b)))
+}
+
+/// Reads the 16 bit channel at `offset` rounded to 8 bits
+#[inline(always)]
+fn read_wide(px: &[u8], offset: usize) -> i32 {
+    let val = i32::from(u16::from_ne_bytes([byte(px, 2 * offset), byte(px, 2 * offset + 1)]));
+    (val + 128) / 257
+}
+
+#[cfg(not(feature = "unsafe-fast"))]
+#[inline(always)]
+fn byte(px: &[u8], i: usize) -> u8 {
+    px[i]
+}
+
+#[cfg(feature = "unsafe-fast")]
+#[inline(always)]
+fn byte(px: &[u8], i: usize) -> u8 {
+    unsafe { *px.get_unchecked(i) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, convert_into_sink, convert_luma, Source};
+    use config::{ChromaFilter, Colorimetry, ConversionConfig, PlanePasses};
+    use format::{PixelLayout, YuvFormat};
+    use sink::PlaneSink;
+
+    #[test]
+    fn threads_do_not_change_output() {
+        let rgb: Vec<u8> = (0..16 * 10 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = Source::packed(&rgb, 16, 10, PixelLayout::packed(3)).unwrap();
+        let single = convert(&src, YuvFormat::I420, &ConversionConfig::default());
+        let cfg = ConversionConfig { threads: 3, ..Default::default() };
+        assert_eq!(convert(&src, YuvFormat::I420, &cfg), single);
+    }
+
+    #[test]
+    fn separate_passes_match_the_fused_one() {
+        let rgb: Vec<u8> = (0..7 * 5 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let src = Source::packed(&rgb, 7, 5, PixelLayout::packed(4)).unwrap();
+        for (format, threads) in [(YuvFormat::I420, 1), (YuvFormat::Nv12, 2)] {
+            let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average, threads, ..Default::default() };
+            let fused = convert(&src, format, &cfg);
+            let cfg = ConversionConfig { plane_passes: PlanePasses::Separate, ..cfg };
+            assert_eq!(convert(&src, format, &cfg), fused);
+            let mut y = [0; 7 * 5];
+            convert_luma(&src, &cfg, &mut y, 7);
+            assert_eq!(y[..], fused[..7 * 5]);
+        }
+    }
+
+    /// Assembles NV12 the way `convert` lays it out
+    struct Nv12(Vec<u8>, Vec<u8>);
+
+    impl PlaneSink for Nv12 {
+        type Error = ();
+
+        fn write_y_row(&mut self, row: usize, y: &[u8]) -> Result<(), ()> {
+            assert_eq!(self.0.len(), row * y.len());
+            self.0.extend_from_slice(y);
+            Ok(())
+        }
+
+        fn write_chroma_row(&mut self, _row: usize, u: &[u8], v: &[u8]) -> Result<(), ()> {
+            self.1.extend(u.iter().zip(v).flat_map(|(&u, &v)| vec![u, v]));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_receives_converted_rows() {
+        let rgb: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let src = Source::packed(&rgb, 5, 3, PixelLayout::packed(4)).unwrap();
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average, ..Default::default() };
+        let mut sink = Nv12(Vec::new(), Vec::new());
+        convert_into_sink(&src, &cfg, &mut sink).unwrap();
+        sink.0.extend(sink.1);
+        assert_eq!(sink.0, convert(&src, YuvFormat::Nv12, &cfg));
+    }
+
+    #[test]
+    fn average_filter_handles_odd_dimensions() {
+        let rgb = vec![255u8; 3 * 3 * 3];
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average,
+                                     ..ConversionConfig::from(Colorimetry::Bt601FullJfif) };
+        let yuv = convert(&Source::packed(&rgb, 3, 3, PixelLayout::packed(3)).unwrap(), YuvFormat::Nv12, &cfg);
+        assert_eq!(yuv.len(), 9 + 2 * 2 * 2);
+        assert!(yuv[..9].iter().all(|&y| y == 255));
+        assert!(yuv[9..].iter().all(|&c| c == 128));
+    }
+
+    #[test]
+    fn linear_average_blends_in_linear_light() {
+        // a checker of red and green, which blends to a dark olive when averaged as it is coded
+        let rgb: Vec<u8> = [[255, 0, 0], [0, 255, 0], [0, 255, 0], [255, 0, 0]].concat();
+        let src = Source::packed(&rgb, 2, 2, PixelLayout::packed(3)).unwrap();
+        let chroma = |filter: ChromaFilter, rgb: [u8; 3]| {
+            let cfg = ConversionConfig { chroma_filter: filter, ..Default::default() };
+            let uniform = [rgb; 4].concat();
+            let flat = convert(&Source::packed(&uniform, 2, 2, PixelLayout::packed(3)).unwrap(), YuvFormat::Nv12, &cfg);
+            (convert(&src, YuvFormat::Nv12, &cfg)[4..].to_vec(), flat[4..].to_vec())
+        };
+        let (average, olive) = chroma(ChromaFilter::Average, [128, 128, 0]);
+        assert_eq!(average, olive);
+        let (linear, yellow) = chroma(ChromaFilter::LinearAverage, [188, 188, 0]);
+        assert_eq!(linear, yellow);
+        assert_ne!(linear, average);
+    }
+
+    #[test]
+    fn neutral_filter_keeps_the_luma() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = Source::packed(&rgb, 5, 3, PixelLayout::packed(3)).unwrap();
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Neutral, ..Default::default() };
+        let gray = convert(&src, YuvFormat::I420, &cfg);
+        assert_eq!(gray[..15], convert(&src, YuvFormat::I420, &ConversionConfig::default())[..15]);
+        assert!(gray[15..].iter().all(|&c| c == 128));
+    }
+}
@@ -0,0 +1,88 @@
+//! Layout of the tightly packed frames written by the converters
+
+use format::YuvFormat;
+use kernel;
+
+/// Size of a tightly packed `width` x `height` frame in bytes
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{required_buffer_size, YuvFormat};
+///
+/// assert_eq!(required_buffer_size(YuvFormat::I420, 640, 480), 640 * 480 * 3 / 2);
+/// assert_eq!(required_buffer_size(YuvFormat::Nv12, 5, 3), 15 + 2 * 3 * 2);
+/// ```
+pub const fn required_buffer_size(format: YuvFormat, width: u32, height: u32) -> usize {
+    // both formats hold the same samples, only their order differs
+    let _ = format;
+    kernel::frame_size(width as usize, height as usize)
+}
+
+/// Byte offsets of the planes of a tightly packed `width` x `height` frame
+///
+/// # Return
+///
+/// The offsets of the Y and U planes and of the V plane for I420, or of the Y and interleaved
+/// UV planes (and `None`) for NV12
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{plane_offsets, YuvFormat};
+///
+/// assert_eq!(plane_offsets(YuvFormat::I420, 4, 2), (0, 8, Some(10)));
+/// assert_eq!(plane_offsets(YuvFormat::Nv12, 4, 2), (0, 8, None));
+/// ```
+pub const fn plane_offsets(format: YuvFormat, width: u32, height: u32) -> (usize, usize, Option<usize>) {
+    let luma_size = width as usize * height as usize;
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+    match format {
+        YuvFormat::I420 => (0, luma_size, Some(luma_size + chroma_width * chroma_height)),
+        YuvFormat::Nv12 => (0, luma_size, None),
+    }
+}
+
+/// Distances between the starts of two rows of a tightly packed frame `width` pixels wide
+///
+/// # Return
+///
+/// The luma stride and the chroma stride (of the U and V planes for I420 and of the
+/// interleaved plane for NV12) in bytes
+pub const fn plane_strides(format: YuvFormat, width: u32) -> (usize, usize) {
+    let (chroma_width, _) = kernel::chroma_dimensions(width as usize, 0);
+    match format {
+        YuvFormat::I420 => (width as usize, chroma_width),
+        YuvFormat::Nv12 => (width as usize, 2 * chroma_width),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plane_offsets, plane_strides, required_buffer_size};
+    use converter::Converter;
+    use format::YuvFormat;
+
+    #[test]
+    fn layout_matches_converted_frames() {
+        let rgb = vec![200u8; 7 * 5 * 3];
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let frame = Converter::builder().output(format).build().convert(&rgb, 7, 5).unwrap();
+            let data = frame.data();
+            assert_eq!(required_buffer_size(format, 7, 5), data.len());
+            assert_eq!(plane_strides(format, 7), (frame.y_stride(), frame.chroma_stride()));
+            let (y, chroma, v) = plane_offsets(format, 7, 5);
+            assert_eq!(data[y..].as_ptr(), frame.y().as_ptr());
+            match format {
+                YuvFormat::I420 => {
+                    assert_eq!(data[chroma..].as_ptr(), frame.u().unwrap().as_ptr());
+                    assert_eq!(data[v.unwrap()..].as_ptr(), frame.v().unwrap().as_ptr());
+                }
+                YuvFormat::Nv12 => {
+                    assert_eq!(data[chroma..].as_ptr(), frame.uv().unwrap().as_ptr());
+                    assert_eq!(v, None);
+                }
+            }
+        }
+    }
+}
@@ -1,16 +1,575 @@
 //! Simple RGB to YUV420 converter
-//! (full swing)
-//! (only supports 8 bit RGB color depth)
+//! (full swing BT.601 by default unless a `default-*` feature says otherwise, see `Converter`
+//! for other matrices and ranges)
+//! (8 bit RGB input, or 16 bit through `RgbView`)
+//!
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`;
+//! frames are then converted on the calling thread and the `io::Write` functions and
+//! GPU backends are unavailable. Without `alloc` either, nothing is allocated and
+//! conversions go through `convert_into` with caller provided buffers. The default
+//! `layout-kernels` feature can be left out too, trading the speed of the RGB(X) and
+//! BGR(X) kernels for a smaller binary.
+//!
+//! The `default-bt709`, `default-bt2020` and `default-limited` features change the matrix and
+//! range of `ConversionConfig::default`. Unlike the other features they aren't additive: one
+//! crate in the dependency graph enabling them changes the output of the legacy functions and
+//! of every default configuration for all the others, so libraries should set a `Colorimetry`
+//! rather than rely on the default.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+#[cfg_attr(not(any(feature = "std", test)), macro_use)]
+extern crate alloc;
+#[cfg(any(feature = "std", test))]
+extern crate core;
+
+#[cfg(feature = "arrayvec")]
+extern crate arrayvec;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "crossbeam")]
+extern crate crossbeam_channel;
+#[cfg(feature = "cuda")]
+extern crate cudarc;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+#[cfg(feature = "image")]
+extern crate image;
+#[cfg(feature = "affinity")]
+extern crate libc;
+#[cfg(feature = "memmap")]
+extern crate memmap2;
+#[cfg(feature = "opencl")]
+extern crate opencl3;
+#[cfg(feature = "png")]
+extern crate png;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+#[cfg(feature = "wgpu")]
+extern crate wgpu;
+
+mod adjust;
+mod allocator;
+#[cfg(feature = "image")]
+mod animation;
+#[cfg(feature = "alloc")]
+mod backend;
+mod blend;
+#[cfg(feature = "bmp")]
+mod bmp_reader;
+mod buffer;
+#[cfg(feature = "burn-in")]
+mod burn_in;
+#[cfg(feature = "bytes")]
+mod bytes_frame;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod compare;
+mod config;
+#[cfg(feature = "alloc")]
+mod converter;
+#[cfg(feature = "alloc")]
+pub mod dcp;
+#[cfg(feature = "alloc")]
+mod depth;
+#[cfg(feature = "std")]
+mod double_buffer;
+mod error;
+mod ffi;
+#[cfg(feature = "std")]
+mod ffmpeg;
+pub mod fixed;
+mod flexible;
+#[cfg(feature = "cuda")]
+pub mod cuda;
+mod format;
+#[cfg(feature = "alloc")]
+mod frame;
+#[cfg(feature = "std")]
+mod frame_queue;
+#[cfg(feature = "std")]
+mod framemd5;
+#[cfg(feature = "alloc")]
+mod geometry;
+#[cfg(feature = "alloc")]
+mod in_place;
+#[cfg(feature = "alloc")]
+mod inverse;
+mod iter;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+mod kernel;
+mod layout;
+#[cfg(feature = "alloc")]
+mod luma;
+mod mirror;
+#[cfg(feature = "memmap")]
+mod mmap;
+#[cfg(feature = "alloc")]
+mod negotiate;
+#[cfg(feature = "opencl")]
+pub mod opencl;
+mod partial;
+#[cfg(feature = "alloc")]
+pub mod patterns;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "alloc")]
+mod plan;
+mod planes;
+#[cfg(feature = "png")]
+mod png_reader;
+#[cfg(feature = "preview")]
+mod preview;
+#[cfg(feature = "std")]
+mod raw;
+mod repack;
+#[cfg(feature = "ros")]
+mod ros;
+#[cfg(feature = "alloc")]
+mod roundtrip;
+#[cfg(feature = "alloc")]
+mod scale;
+mod shm;
+mod sink;
+#[cfg(feature = "alloc")]
+mod soa;
+mod split;
+mod stats;
+#[cfg(feature = "tokio")]
+mod stream;
+#[cfg(feature = "std")]
+mod threads;
+#[cfg(feature = "std")]
+mod throughput;
+#[cfg(feature = "std")]
+mod tiled;
+#[cfg(feature = "alloc")]
+mod to_yuv;
+mod transfer;
+#[cfg(feature = "alloc")]
+mod upsample;
+#[cfg(feature = "alloc")]
+mod v4l2;
+mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "std")]
+pub mod y4m;
+#[cfg(feature = "alloc")]
+mod yuv422;
+
+pub use adjust::{adjust_yuv, convert_range};
+#[cfg(feature = "alloc")]
+pub use allocator::Global;
+pub use allocator::{AllocatedFrame, FrameAllocator};
+#[cfg(feature = "image")]
+pub use animation::{AnimationFrame, AnimationFrames};
+#[cfg(feature = "alloc")]
+pub use backend::{Convert, CpuBackend};
+pub use blend::{blend_yuv, Opacity};
+#[cfg(feature = "bmp")]
+pub use bmp_reader::from_bmp_reader;
+pub use buffer::OutputBuffer;
+#[cfg(feature = "bytes")]
+pub use bytes_frame::BytesFrame;
+pub use compare::{compare_yuv, FrameDiff, PlaneDiff};
+pub use config::{Adjustment, ChromaFilter, ChromaKey, ChromaUpsampling, Colorimetry, ConversionConfig, Matrix,
+                 PlanePasses, Quality, Range};
+#[cfg(feature = "alloc")]
+pub use converter::{Converter, ConverterBuilder};
+#[cfg(feature = "alloc")]
+pub use depth::convert_10bit_to_8bit;
+#[cfg(feature = "std")]
+pub use double_buffer::DoubleBuffer;
+pub use error::ConversionError;
+pub use ffi::{VpxPlanes, X264Planes, VPX_IMG_FMT_I420, VPX_IMG_FMT_NV12, X264_CSP_I420, X264_CSP_NV12};
+#[cfg(feature = "std")]
+pub use ffmpeg::{ffmpeg_input_args, FfmpegPipe};
+pub use flexible::FlexibleChroma;
+pub use format::{ChannelOrder, FrameDescriptor, PackedYuvFormat, Yuv10Format, YuvFormat};
+#[cfg(feature = "alloc")]
+pub use frame::{FrameTiming, SharedYuvFrame, YuvFrame};
+#[cfg(feature = "std")]
+pub use frame_queue::{Backpressure, FrameQueue};
+#[cfg(feature = "std")]
+pub use framemd5::{md5_planes, FrameMd5Writer};
+#[cfg(feature = "alloc")]
+pub use geometry::{Backdrop, Overlay, Rect, Rotation, ScaleFilter};
+#[cfg(feature = "alloc")]
+pub use in_place::convert_in_place;
+#[cfg(feature = "alloc")]
+pub use inverse::{convert_nv12_to_rgb, convert_nv21_to_rgba, convert_packed_yuv_to_rgb, convert_yuv420p_to_rgb,
+                  picture_to_rgb, yuv_to_rgb, DecodedPicture};
+pub use iter::YuvBytes;
+pub use layout::{plane_offsets, plane_strides, required_buffer_size};
+#[cfg(feature = "alloc")]
+pub use luma::{convert_luma, extract_luma, extract_packed_luma};
+pub use mirror::mirror_yuv;
+#[cfg(feature = "memmap")]
+pub use mmap::MmapWriter;
+#[cfg(feature = "alloc")]
+pub use negotiate::{EncoderFormat, Negotiated};
+pub use partial::{convert_cancellable, PartialConversion, CANCEL_BAND_ROWS};
+#[cfg(feature = "crossbeam")]
+pub use pipeline::Pipeline;
+#[cfg(feature = "alloc")]
+pub use plan::Plan;
+pub use planes::{convert_into_frame, convert_into_strided, FrameMut, PlaneInfo, PlaneMut};
+#[cfg(feature = "png")]
+pub use png_reader::from_png_reader;
+#[cfg(feature = "std")]
+pub use raw::{RgbReader, Sidecar, YuvReader, YuvWriter};
+pub use repack::{repack_i420_to_nv12, repack_nv12_to_i420, swap_uv, swap_uv_in_place};
+#[cfg(feature = "ros")]
+pub use ros::{from_ros_image, to_ros_image};
+#[cfg(feature = "alloc")]
+pub use roundtrip::{roundtrip, ErrorStats};
+#[cfg(feature = "alloc")]
+pub use scale::scale_yuv;
+pub use shm::{ShmHeader, SHM_HEADER_SIZE, SHM_MAGIC, SHM_VERSION};
+pub use sink::{Plane, PlaneSink};
+#[cfg(feature = "alloc")]
+pub use sink::ScatterSink;
+#[cfg(feature = "alloc")]
+pub use soa::RgbPlanes;
+pub use split::{PlaneRef, YuvPlanes};
+pub use stats::FrameStats;
+#[cfg(feature = "tokio")]
+pub use stream::ConvertStream;
+#[cfg(feature = "std")]
+pub use threads::{max_threads, set_max_threads, set_worker_cores, worker_cores};
+#[cfg(feature = "std")]
+pub use throughput::{Throughput, ThroughputStats};
+#[cfg(feature = "std")]
+pub use tiled::{TileGrid, TiledWriter};
+#[cfg(feature = "image")]
+pub use to_yuv::convert_image;
+#[cfg(feature = "alloc")]
+pub use to_yuv::ToYuv420;
+#[cfg(feature = "alloc")]
+pub use upsample::upsample_to_yuv444;
+#[cfg(feature = "alloc")]
+pub use v4l2::convert_v4l2;
+#[cfg(feature = "alloc")]
+pub use view::RgbFrame;
+pub use view::RgbView;
+#[cfg(feature = "alloc")]
+pub use yuv422::{convert_packed_yuv_to_yuv420, convert_yuv422p_to_yuv420};
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+#[cfg(feature = "alloc")]
+use format::PixelLayout;
+#[cfg(feature = "alloc")]
+use kernel::{Setup, Source};
+#[cfg(feature = "alloc")]
+use sink::RowCallback;
+
+/// Converts an RGB image to the YUV420 layout chosen at runtime
+///
+/// # Arguments
+///
+/// * `src` - the source image, in any channel order, stride and bit depth
+///
+/// * `dst_format` - the output layout (eg.: the one an encoder negotiated)
+///
+/// * `cfg` - the colorimetry and threading settings
+///
+/// # Return
+///
+/// The frame in `dst_format`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// let bgra = vec![0u8; 16];
+/// let src = RgbView::new(&bgra, 2, 2, ChannelOrder::Bgra).unwrap();
+/// let frame = rgb2yuv420::convert(&src, YuvFormat::Nv12, &ConversionConfig::default());
+/// assert_eq!(frame.data(), &[0, 0, 0, 0, 128, 128][..]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn convert(src: &RgbView, dst_format: YuvFormat, cfg: &ConversionConfig) -> YuvFrame {
+    let data = kernel::convert(src.source(), dst_format, cfg);
+    YuvFrame::from_packed(data, dst_format, src.width(), src.height(), cfg)
+}
+
+/// Converts the BGRA pixels `glReadPixels` returns (`GL_BGRA`, `GL_UNSIGNED_BYTE`) into an
+/// NV12 frame, flipping the bottom-up rows while converting
+///
+/// # Arguments
+///
+/// * `stride` - distance between the starts of two rows in bytes, `width * 4` rounded up to
+///   `GL_PACK_ALIGNMENT` or the row length of a pixel buffer object
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is shorter than a row and `BufferTooSmall` if `pixels` is too
+/// short
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::ConversionConfig;
+///
+/// // a white bottom row and a black top row, 8 byte aligned
+/// let pixels = [255, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let frame = rgb2yuv420::convert_gl_readback(&pixels, 1, 2, 8, &ConversionConfig::default()).unwrap();
+/// assert_eq!(frame.data(), &[0, 255, 128, 128][..]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn convert_gl_readback(pixels: &[u8], width: u32, height: u32, stride: usize, cfg: &ConversionConfig)
+                           -> Result<YuvFrame, ConversionError> {
+    let src = Source::new(pixels, width, height, stride, ChannelOrder::Bgra.layout(), false)?;
+    let data = kernel::convert(&Source { bottom_up: true, ..src }, YuvFormat::Nv12, cfg);
+    Ok(YuvFrame::from_packed(data, YuvFormat::Nv12, width, height, cfg))
+}
+
+/// Reconverts the `regions` of `src` that changed since `dst` was converted from it (eg.: the
+/// damage rectangles of a screen capture) and leaves the rest of `dst` alone
+///
+/// The regions are widened to the 2x2 chroma blocks they touch, so that `dst` ends up the
+/// same as converting all of `src` with `cfg`.
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` if `src` and `dst` differ in size, `UnsupportedFormat` if `dst` was
+/// converted with another matrix or range and `InvalidRegion` if a region reaches outside of
+/// `src`; `dst` is left alone on errors
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, Rect, RgbView, YuvFormat};
+///
+/// let cfg = ConversionConfig::default();
+/// let mut desktop = vec![0u8; 64 * 32 * 4];
+/// let mut frame = rgb2yuv420::convert(&RgbView::new(&desktop, 64, 32, ChannelOrder::Bgra).unwrap(),
+///                                     YuvFormat::Nv12, &cfg);
+/// // a white pixel at (3, 5)
+/// desktop[(5 * 64 + 3) * 4..][..4].copy_from_slice(&[255; 4]);
+/// let src = RgbView::new(&desktop, 64, 32, ChannelOrder::Bgra).unwrap();
+/// let damage = [Rect { x: 3, y: 5, width: 1, height: 1 }];
+/// rgb2yuv420::convert_regions(&src, &damage, &cfg, &mut frame).unwrap();
+/// assert_eq!(frame, rgb2yuv420::convert(&src, YuvFormat::Nv12, &cfg));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn convert_regions(src: &RgbView, regions: &[Rect], cfg: &ConversionConfig, dst: &mut YuvFrame)
+                       -> Result<(), ConversionError> {
+    if (src.width(), src.height()) != (dst.width(), dst.height()) {
+        return Err(ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() });
+    }
+    if (dst.matrix(), dst.range()) != (cfg.matrix, cfg.range) {
+        return Err(ConversionError::UnsupportedFormat("the frame was converted with another matrix or range"));
+    }
+    let (width, height) = (u64::from(src.width()), u64::from(src.height()));
+    let blocks = regions.iter().filter(|rect| rect.width > 0 && rect.height > 0).map(|&rect| {
+        let Rect { x, y, width: w, height: h } = rect;
+        if u64::from(x) + u64::from(w) > width || u64::from(y) + u64::from(h) > height {
+            return Err(ConversionError::InvalidRegion { x, y, width: w, height: h });
+        }
+        let (right, bottom) = ((u64::from(x + w) + 1) & !1, (u64::from(y + h) + 1) & !1);
+        let (x, y) = (x & !1, y & !1);
+        let (w, h) = (right.min(width) - u64::from(x), bottom.min(height) - u64::from(y));
+        Ok(Rect { x, y, width: w as u32, height: h as u32 })
+    });
+    let blocks = blocks.collect::<Result<Vec<Rect>, ConversionError>>()?;
+    let format = dst.format();
+    for Rect { x, y, width, height } in blocks {
+        let region = src.crop(x, y, width, height)?;
+        let region = region.source();
+        Setup::new(region.width, region.height, region.layout, region.wide, format, cfg)
+            .run_planes(region, dst.kernel_planes().offset(x as usize, y as usize));
+    }
+    Ok(())
+}
+
+/// Converts each of the `regions` of interest of `src` into a frame of its own, walking the
+/// rows of `src` once for all of them (eg.: the participants of a composited call or the tiles
+/// of a shared screen, re-encoded independently)
+///
+/// Every frame is the same as converting the region cropped out of `src`; regions may overlap
+/// and start at odd coordinates. The frames are converted on the calling thread whatever
+/// `cfg.threads`.
+///
+/// # Errors
+///
+/// `InvalidRegion` if a region reaches outside of `src`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, Rect, RgbView, YuvFormat};
+///
+/// let rgb: Vec<u8> = (0..8 * 4 * 3).map(|i| i as u8).collect();
+/// let src = RgbView::new(&rgb, 8, 4, ChannelOrder::Rgb).unwrap();
+/// let cfg = ConversionConfig::default();
+/// let tiles = [Rect { x: 0, y: 0, width: 4, height: 4 }, Rect { x: 3, y: 1, width: 5, height: 3 }];
+/// let frames = rgb2yuv420::convert_rois(&src, &tiles, YuvFormat::I420, &cfg).unwrap();
+/// assert_eq!(frames[1], rgb2yuv420::convert(&src.crop(3, 1, 5, 3).unwrap(), YuvFormat::I420, &cfg));
+/// ```
+#[cfg(feature = "alloc")]
+pub fn convert_rois(src: &RgbView, regions: &[Rect], format: YuvFormat, cfg: &ConversionConfig)
+                    -> Result<Vec<YuvFrame>, ConversionError> {
+    let cfg = ConversionConfig { threads: 1, ..cfg.clone() };
+    let (layout, wide) = (src.source().layout, src.source().wide);
+    let mut rois = Vec::with_capacity(regions.len());
+    for &rect in regions {
+        let Rect { x, y, width, height } = rect;
+        if u64::from(x) + u64::from(width) > u64::from(src.width())
+            || u64::from(y) + u64::from(height) > u64::from(src.height()) {
+            return Err(ConversionError::InvalidRegion { x, y, width, height });
+        }
+        let (width, height) = (width as usize, height as usize);
+        // a setup for the row pairs, and for the last row of an odd height
+        let setups = [2, 1].map(|rows| Setup::new(width, rows, layout, wide, format, &cfg));
+        rois.push((rect, setups, vec![0; kernel::frame_size(width, height)]));
+    }
+    for row in 0..src.height() {
+        for &mut (rect, ref setups, ref mut data) in rois.iter_mut() {
+            if rect.width == 0 || row < rect.y || row >= rect.y + rect.height || !(row - rect.y).is_multiple_of(2) {
+                continue;
+            }
+            let rows = (rect.y + rect.height - row).min(2);
+            let pair = src.crop(rect.x, row, rect.width, rows)?;
+            let planes = kernel::Planes::packed(data, format, rect.width as usize, rect.height as usize)
+                .offset(0, (row - rect.y) as usize);
+            setups[2 - rows as usize].run_part(pair.source(), planes);
+        }
+    }
+    Ok(rois.into_iter().map(|(rect, _, data)| YuvFrame::from_packed(data, format, rect.width, rect.height, &cfg))
+        .collect())
+}
+
+/// Converts an RGB image into a caller provided container
+///
+/// # Arguments
+///
+/// * `dst` - receives the tightly packed frame in `dst_format`; growable containers
+///   (`Vec`, `SmallVec`) are resized to fit, fixed ones (slices, arrays, `Box<[u8]>`,
+///   `ArrayVec`) must hold at least `width * height + 2 * ((width + 1) / 2) * ((height + 1) / 2)`
+///   bytes
+///
+/// # Return
+///
+/// The number of bytes written, or `BufferTooSmall` if `dst` is too short
+///
+/// Apart from growing `dst`, only splitting the frame across threads (`cfg.threads`
+/// above 1) allocates.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// static RGB: [u8; 12] = [255; 12];
+/// let mut yuv = [0u8; 6];
+/// let src = RgbView::new(&RGB, 2, 2, ChannelOrder::Rgb).unwrap();
+/// let written = rgb2yuv420::convert_into(&src, YuvFormat::I420, &ConversionConfig::default(), &mut yuv).unwrap();
+/// assert_eq!((written, yuv), (6, [255, 255, 255, 255, 128, 128]));
+/// ```
+pub fn convert_into<B>(src: &RgbView, dst_format: YuvFormat, cfg: &ConversionConfig, dst: &mut B)
+                       -> Result<usize, ConversionError>
+    where B: OutputBuffer + ?Sized {
+    let len = required_buffer_size(dst_format, src.width(), src.height());
+    kernel::convert_into(src.source(), dst_format, cfg, dst.prepare(len)?);
+    Ok(len)
+}
+
+/// Converts an RGB image to the YUV420 layout chosen at runtime and streams it into `out`
+///
+/// The planes are written in the order `convert` would lay them out, one row at a time, so
+/// memory use doesn't grow with the frame size; `cfg.threads` is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+///
+/// let rgb = vec![0u8; 12];
+/// let src = RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap();
+/// let mut out = Vec::new();
+/// rgb2yuv420::convert_to_writer(&src, YuvFormat::I420, &ConversionConfig::default(), &mut out).unwrap();
+/// assert_eq!(out, [0, 0, 0, 0, 128, 128]);
+/// ```
+#[cfg(feature = "std")]
+pub fn convert_to_writer<W: Write>(src: &RgbView, dst_format: YuvFormat, cfg: &ConversionConfig, out: &mut W)
+                                   -> io::Result<()> {
+    kernel::write(src.source(), dst_format, cfg, out)
+}
+
+/// Converts an RGB image lazily, one byte of the frame in `dst_format` per iteration
+///
+/// Nothing is allocated and `cfg.threads` is ignored; see `YuvBytes`.
+pub fn convert_iter<'a>(src: &RgbView<'a>, dst_format: YuvFormat, cfg: &ConversionConfig) -> YuvBytes<'a> {
+    YuvBytes::new(src, dst_format, cfg)
+}
+
+/// Converts an RGB image row by row into a custom destination
+///
+/// Converts on the calling thread; `cfg.threads` is ignored.
+///
+/// # Return
+///
+/// The first error returned by `sink`
+#[cfg(feature = "alloc")]
+pub fn convert_into_sink<S: PlaneSink>(src: &RgbView, cfg: &ConversionConfig, sink: &mut S) -> Result<(), S::Error> {
+    kernel::convert_into_sink(src.source(), cfg, sink)
+}
+
+/// Converts an RGB image and calls `callback` with every output row as soon as it's done
+///
+/// The callback receives the plane, the row index within the plane and the samples. Rows
+/// arrive in source order (two luma rows, then the chroma covering them), which suits slice
+/// based encoders and progressive transmission. Converts on the calling thread; `cfg.threads`
+/// is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, Plane, RgbView, YuvFormat};
+///
+/// let rgb = vec![0u8; 4 * 2 * 3];
+/// let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+/// let mut rows = Vec::new();
+/// rgb2yuv420::convert_rows(&src, YuvFormat::Nv12, &ConversionConfig::default(), |plane, row, data| {
+///     rows.push((plane, row, data.len()));
+/// });
+/// assert_eq!(rows, [(Plane::Y, 0, 4), (Plane::Y, 1, 4), (Plane::Uv, 0, 4)]);
+/// ```
+#[cfg(feature = "alloc")]
+pub fn convert_rows<F>(src: &RgbView, dst_format: YuvFormat, cfg: &ConversionConfig, callback: F)
+    where F: FnMut(Plane, usize, &[u8]) {
+    let mut sink = RowCallback::new(dst_format, callback);
+    match kernel::convert_into_sink(src.source(), cfg, &mut sink) {
+        Ok(()) => {}
+        Err(never) => match never {},
+    }
+}
 
 /// Converts an RGB image to YUV420p (planar/3 planes)
 ///
 /// # Arguments
 ///
 /// * `img` - should contain the pixel data in the following format:
-/// `[r, g, b, ... , r, g, b, ... , r, g, b, ...]`
+///   `[r, g, b, ... , r, g, b, ... , r, g, b, ...]`
 ///
 /// * `bytes_per_pixel` - should contain the number of bytes used by one pixel
-/// (eg.: RGB is 3 bytes and RGBA is 4 bytes)
+///   (eg.: RGB is 3 bytes and RGBA is 4 bytes)
 ///
 /// # Return
 ///
@@ -23,12 +582,10 @@
 /// let yuv = rgb2yuv420::convert_rgb_to_yuv420p(&rgb, 2, 2, 3);
 /// assert_eq!(yuv.len(), rgb.len() / 2);
 /// ```
+#[cfg(feature = "alloc")]
 pub fn convert_rgb_to_yuv420p(img: &[u8], width: u32, height: u32, bytes_per_pixel: usize) -> Vec<u8> {
-    convert_rgb_to_yuv420(img, width, height, bytes_per_pixel, |yuv, uv_index, chroma_size, u, v| {
-        yuv[*uv_index] = u;
-        yuv[*uv_index + (f32::ceil(chroma_size as f32 / 2.0) as usize)] = v;
-        *uv_index += 1;
-    })
+    kernel::convert(&packed_source(img, width, height, bytes_per_pixel), YuvFormat::I420,
+                    &ConversionConfig::default())
 }
 
 /// Converts an RGB image to YUV420sp NV12 (semi-planar/2 planes)
@@ -36,10 +593,10 @@ pub fn convert_rgb_to_yuv420p(img: &[u8], width: u32, height: u32, bytes_per_pix
 /// # Arguments
 ///
 /// * `img` - should contain the pixel data in the following format:
-/// [r, g, b, ... , r, g, b, ... , r, g, b, ...]
+///   [r, g, b, ... , r, g, b, ... , r, g, b, ...]
 ///
 /// * `bytes_per_pixel` - should contain the number of bytes used by one pixel
-/// (eg.: RGB is 3 bytes and RGBA is 4 bytes)
+///   (eg.: RGB is 3 bytes and RGBA is 4 bytes)
 ///
 /// # Return
 ///
@@ -52,63 +609,166 @@ pub fn convert_rgb_to_yuv420p(img: &[u8], width: u32, height: u32, bytes_per_pix
 /// let yuv = rgb2yuv420::convert_rgb_to_yuv420sp_nv12(&rgb, 2, 2, 3);
 /// assert_eq!(yuv.len(), rgb.len() / 2);
 /// ```
+#[cfg(feature = "alloc")]
 pub fn convert_rgb_to_yuv420sp_nv12(img: &[u8], width: u32, height: u32, bytes_per_pixel: usize) -> Vec<u8> {
-    convert_rgb_to_yuv420(img, width, height, bytes_per_pixel, |yuv, uv_index, _cs, u, v| {
-        yuv[*uv_index] = u;
-        *uv_index += 1;
-        yuv[*uv_index] = v;
-        *uv_index += 1;
-    })
+    kernel::convert(&packed_source(img, width, height, bytes_per_pixel), YuvFormat::Nv12,
+                    &ConversionConfig::default())
 }
 
-fn convert_rgb_to_yuv420<T>(img: &[u8], width: u32, height: u32, bytes_per_pixel: usize, store_uv: T) -> Vec<u8>
-    where T: Fn(&mut Vec<u8>, &mut usize, usize, u8, u8) -> () {
-    let frame_size: usize = (width * height) as usize;
-    let chroma_size: usize = frame_size / 4;
-    let mut y_index: usize = 0;
-    let mut uv_index = frame_size;
-    let mut yuv = vec![0; (width * height * 3 / 2) as usize];
-    let mut index: usize = 0;
-    for j in 0..height {
-        for _ in 0..width {
-            let r = i32::from(img[index * bytes_per_pixel]);
-            let g = i32::from(img[index * bytes_per_pixel + 1]);
-            let b = i32::from(img[index * bytes_per_pixel + 2]);
-            index += 1;
-            yuv[y_index] = clamp((77 * r + 150 * g + 29 * b + 128) >> 8);
-            y_index += 1;
-            if j % 2 == 0 && index % 2 == 0 {
-                store_uv(&mut yuv,
-                         &mut uv_index,
-                         chroma_size,
-                         clamp(((-43 * r - 84 * g + 127 * b + 128) >> 8) + 128),
-                         clamp(((127 * r - 106 * g - 21 * b + 128) >> 8) + 128));
-            }
-        }
-    }
-    yuv
+/// Converts an RGB image to YUV420p (planar/3 planes) and streams it into `out`
+///
+/// See `convert_rgb_to_yuv420p` for the arguments and `convert_to_writer` for memory use.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::BufWriter;
+///
+/// let rgb = vec![0u8; 12];
+/// let mut out = BufWriter::new(Vec::new());
+/// rgb2yuv420::convert_rgb_to_yuv420p_to_writer(&rgb, 2, 2, 3, &mut out).unwrap();
+/// assert_eq!(out.into_inner().unwrap().len(), rgb.len() / 2);
+/// ```
+#[cfg(feature = "std")]
+pub fn convert_rgb_to_yuv420p_to_writer<W: Write>(img: &[u8], width: u32, height: u32, bytes_per_pixel: usize,
+                                                  out: &mut W) -> io::Result<()> {
+    kernel::write(&packed_source(img, width, height, bytes_per_pixel), YuvFormat::I420,
+                  &ConversionConfig::default(), out)
 }
 
-fn clamp(val: i32) -> u8 {
-    match val {
-        ref v if *v < 0 => 0,
-        ref v if *v > 255 => 255,
-        v => v as u8,
-    }
+/// Converts an RGB image to YUV420sp NV12 (semi-planar/2 planes) and streams it into `out`
+///
+/// See `convert_rgb_to_yuv420sp_nv12` for the arguments and `convert_to_writer` for memory use.
+#[cfg(feature = "std")]
+pub fn convert_rgb_to_yuv420sp_nv12_to_writer<W: Write>(img: &[u8], width: u32, height: u32,
+                                                        bytes_per_pixel: usize, out: &mut W) -> io::Result<()> {
+    kernel::write(&packed_source(img, width, height, bytes_per_pixel), YuvFormat::Nv12,
+                  &ConversionConfig::default(), out)
+}
+
+// the legacy functions panic on invalid input
+#[cfg(feature = "alloc")]
+fn packed_source<'a>(img: &'a [u8], width: u32, height: u32, bytes_per_pixel: usize) -> Source<'a> {
+    Source::packed(img, width, height, PixelLayout::packed(bytes_per_pixel)).unwrap_or_else(|e| panic!("{}", e))
 }
 
 #[cfg(test)]
 mod tests {
     #[test]
     fn rgb_to_yuv() {
-        use super::convert_rgb_to_yuv420p;
+        use super::{convert_rgb_to_yuv420p, ChannelOrder, Colorimetry, ConversionConfig, RgbView, YuvFormat};
         let rgb = vec![0u8; 12];
-        let expected = vec![0u8, 0u8, 0u8, 0u8, 128u8, 128u8];
-        let yuv = convert_rgb_to_yuv420p(&rgb, 2, 2, 3);
+        let expected = [0u8, 0u8, 0u8, 0u8, 128u8, 128u8];
+        let src = RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap();
+        let yuv = ::convert(&src, YuvFormat::I420, &ConversionConfig::from(Colorimetry::Bt601FullJfif)).into_vec();
         assert_eq!(yuv.len(), rgb.len() / 2);
         for (val, exp) in yuv.iter().zip(expected.iter()) {
             assert_eq!(val, exp);
         }
+        // the legacy functions convert with the default configuration
+        let legacy = convert_rgb_to_yuv420p(&rgb, 2, 2, 3);
+        assert_eq!(legacy, ::convert(&src, YuvFormat::I420, &ConversionConfig::default()).into_vec());
+    }
+
+    #[test]
+    fn yuv420p_chroma_matches_nv12() {
+        use super::{convert_rgb_to_yuv420p, convert_rgb_to_yuv420sp_nv12};
+        let rgb: Vec<u8> = (0..4 * 4 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let i420 = convert_rgb_to_yuv420p(&rgb, 4, 4, 3);
+        let nv12 = convert_rgb_to_yuv420sp_nv12(&rgb, 4, 4, 3);
+        assert_eq!(i420[..16], nv12[..16]);
+        for i in 0..4 {
+            assert_eq!(i420[16 + i], nv12[16 + i * 2]);
+            assert_eq!(i420[20 + i], nv12[16 + i * 2 + 1]);
+        }
+    }
+
+    #[test]
+    fn gl_readbacks_are_flipped() {
+        use config::ConversionConfig;
+        use format::{ChannelOrder, YuvFormat};
+        use view::RgbView;
+
+        let pixels: Vec<u8> = (0..5 * 24).map(|i| (i * 47 % 256) as u8).collect();
+        let flipped: Vec<u8> = pixels.chunks(24).rev().flat_map(|row| row[..5 * 4].to_vec()).collect();
+        let cfg = ConversionConfig { threads: 2, ..ConversionConfig::default() };
+        let frame = super::convert_gl_readback(&pixels, 5, 5, 24, &cfg).unwrap();
+        let src = RgbView::new(&flipped, 5, 5, ChannelOrder::Bgra).unwrap();
+        assert_eq!(frame, super::convert(&src, YuvFormat::Nv12, &cfg));
+    }
+
+    #[test]
+    fn regions_are_reconverted_in_place() {
+        use config::ConversionConfig;
+        use error::ConversionError;
+        use format::{ChannelOrder, YuvFormat};
+        use geometry::Rect;
+        use view::RgbView;
+
+        let cfg = ConversionConfig { threads: 2, ..ConversionConfig::default() };
+        for &format in [YuvFormat::I420, YuvFormat::Nv12].iter() {
+            let mut rgb = vec![40u8; 9 * 7 * 3];
+            let mut frame = super::convert(&RgbView::new(&rgb, 9, 7, ChannelOrder::Rgb).unwrap(), format, &cfg);
+            for (i, px) in rgb.iter_mut().enumerate().filter(|&(i, _)| i / 27 >= 3 || i % 27 < 6) {
+                *px = (i * 31 % 256) as u8;
+            }
+            let src = RgbView::new(&rgb, 9, 7, ChannelOrder::Rgb).unwrap();
+            let regions = [Rect { x: 0, y: 0, width: 2, height: 3 }, Rect { x: 1, y: 3, width: 8, height: 4 }];
+            let err = super::convert_regions(&src, &[Rect { x: 8, y: 0, width: 2, height: 1 }], &cfg, &mut frame);
+            assert_eq!(err, Err(ConversionError::InvalidRegion { x: 8, y: 0, width: 2, height: 1 }));
+            super::convert_regions(&src, &regions, &cfg, &mut frame).unwrap();
+            assert_eq!(frame, super::convert(&src, format, &cfg));
+        }
+    }
+
+    #[test]
+    fn rois_convert_like_their_crops() {
+        use config::{ChromaFilter, ConversionConfig};
+        use error::ConversionError;
+        use format::{ChannelOrder, YuvFormat};
+        use geometry::Rect;
+        use view::RgbView;
+
+        let rgba: Vec<u8> = (0..9 * 7 * 4).map(|i| (i * 31 % 256) as u8).collect();
+        let src = RgbView::new(&rgba, 9, 7, ChannelOrder::Rgba).unwrap();
+        let cfg = ConversionConfig { chroma_filter: ChromaFilter::Average, threads: 2, ..ConversionConfig::default() };
+        let rois = [Rect { x: 0, y: 0, width: 9, height: 7 }, Rect { x: 1, y: 3, width: 5, height: 3 },
+                    Rect { x: 4, y: 2, width: 0, height: 2 }, Rect { x: 2, y: 1, width: 6, height: 6 }];
+        for &format in [YuvFormat::I420, YuvFormat::Nv12].iter() {
+            let frames = super::convert_rois(&src, &rois, format, &cfg).unwrap();
+            for (frame, rect) in frames.iter().zip(&rois) {
+                if rect.width == 0 {
+                    assert!(frame.data().is_empty());
+                    continue;
+                }
+                let crop = src.crop(rect.x, rect.y, rect.width, rect.height).unwrap();
+                assert_eq!(*frame, super::convert(&crop, format, &cfg));
+            }
+        }
+        assert_eq!(super::convert_rois(&src, &[Rect { x: 8, y: 0, width: 2, height: 1 }], YuvFormat::I420, &cfg),
+                   Err(ConversionError::InvalidRegion { x: 8, y: 0, width: 2, height: 1 }));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn writer_matches_buffer() {
+        use super::{convert_rgb_to_yuv420p, convert_rgb_to_yuv420p_to_writer, convert_rgb_to_yuv420sp_nv12,
+                    convert_rgb_to_yuv420sp_nv12_to_writer};
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let mut out = Vec::new();
+        convert_rgb_to_yuv420p_to_writer(&rgb, 5, 3, 3, &mut out).unwrap();
+        assert_eq!(out, convert_rgb_to_yuv420p(&rgb, 5, 3, 3));
+        out.clear();
+        convert_rgb_to_yuv420sp_nv12_to_writer(&rgb, 5, 3, 3, &mut out).unwrap();
+        assert_eq!(out, convert_rgb_to_yuv420sp_nv12(&rgb, 5, 3, 3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn short_input_panics() {
+        use super::convert_rgb_to_yuv420sp_nv12;
+        let rgb = vec![0u8; 11];
+        convert_rgb_to_yuv420sp_nv12(&rgb, 2, 2, 3);
     }
 
     #[test]
@@ -0,0 +1,149 @@
+//! Grayscale copies of the luma of YUV frames, eg.: for computer vision code that only
+//! looks at brightness
+
+use alloc::vec::Vec;
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::PackedYuvFormat;
+use frame::YuvFrame;
+use kernel;
+use planes::check_plane;
+use view::RgbView;
+
+/// Converts only the luma of `src` into the rows of `y`, `stride` bytes apart, as a grayscale
+/// image in the range of `cfg`
+///
+/// This is the luma pass of `PlanePasses::Separate` on its own, so no chroma is computed.
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than the width and `BufferTooSmall` if `y` can't
+/// hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView};
+///
+/// let rgb = [255, 255, 255, 0, 0, 0];
+/// let src = RgbView::new(&rgb, 2, 1, ChannelOrder::Rgb).unwrap();
+/// let mut gray = [0; 2];
+/// rgb2yuv420::convert_luma(&src, &ConversionConfig::default(), &mut gray, 2).unwrap();
+/// assert_eq!(gray, [255, 0]);
+/// ```
+pub fn convert_luma(src: &RgbView, cfg: &ConversionConfig, y: &mut [u8], stride: usize)
+                    -> Result<(), ConversionError> {
+    check_plane(y.len(), stride, src.width() as usize, src.height() as usize)?;
+    if src.width() > 0 {
+        kernel::convert_luma(src.source(), cfg, y, stride);
+    }
+    Ok(())
+}
+
+/// Copies the first `height` rows of `width` samples of a luma plane, `stride` bytes apart,
+/// into a tightly packed grayscale image
+///
+/// The Y plane comes first in I420 and NV12 buffers, so the whole buffer can be passed in.
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than `width` and `BufferTooSmall` if `y` can't hold
+/// all of the rows
+///
+/// # Examples
+///
+/// ```
+/// // a 2x2 NV12 frame with rows padded to 4 bytes
+/// let nv12 = [1, 2, 0, 0, 3, 4, 0, 0, 128, 128, 0, 0];
+/// assert_eq!(rgb2yuv420::extract_luma(&nv12, 4, 2, 2).unwrap(), [1, 2, 3, 4]);
+/// ```
+pub fn extract_luma(y: &[u8], stride: usize, width: u32, height: u32) -> Result<Vec<u8>, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    check_plane(y.len(), stride, width, height)?;
+    let mut gray = Vec::with_capacity(width * height);
+    if width > 0 {
+        for row in y.chunks(stride).take(height) {
+            gray.extend_from_slice(&row[..width]);
+        }
+    }
+    Ok(gray)
+}
+
+/// Copies the luma samples of a packed 4:2:2 frame (YUYV or UYVY) into a tightly packed
+/// grayscale image
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than a row and `BufferTooSmall` if `yuv` can't
+/// hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::PackedYuvFormat;
+///
+/// let uyvy = [128, 1, 128, 2];
+/// assert_eq!(rgb2yuv420::extract_packed_luma(&uyvy, 4, 2, 1, PackedYuvFormat::Uyvy).unwrap(), [1, 2]);
+/// ```
+pub fn extract_packed_luma(yuv: &[u8], stride: usize, width: u32, height: u32, format: PackedYuvFormat)
+                           -> Result<Vec<u8>, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    check_plane(yuv.len(), stride, 4 * width.div_ceil(2), height)?;
+    let [y0, _, y1, _] = format.offsets();
+    let mut gray = Vec::with_capacity(width * height);
+    if width > 0 {
+        for row in yuv.chunks(stride).take(height) {
+            gray.extend(row.chunks_exact(4).flat_map(|pair| [pair[y0], pair[y1]]).take(width));
+        }
+    }
+    Ok(gray)
+}
+
+impl YuvFrame {
+    /// The luma plane without the padding of its rows, as a grayscale image
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ConversionConfig, YuvFormat, YuvFrame};
+    ///
+    /// let frame = YuvFrame::filled([255, 255, 255], YuvFormat::I420, 3, 2, &ConversionConfig::default());
+    /// assert_eq!(frame.luma(), [255; 6]);
+    /// ```
+    pub fn luma(&self) -> Vec<u8> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let mut gray = Vec::with_capacity(width * height);
+        if width > 0 {
+            for row in self.y().chunks(self.y_stride()).take(height) {
+                gray.extend_from_slice(&row[..width]);
+            }
+        }
+        gray
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_luma, extract_packed_luma};
+    use converter::Converter;
+    use error::ConversionError;
+    use format::{ChannelOrder, PackedYuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn luma_is_copied_without_padding() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 31 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let packed = Converter::builder().build().convert_view(&src);
+        let padded = Converter::builder().alignment(16).build().convert_view(&src);
+        assert_eq!(padded.luma(), &packed.y()[..15]);
+        assert_eq!(extract_luma(padded.data(), padded.y_stride(), 5, 3).unwrap(), padded.luma());
+        assert_eq!(extract_luma(padded.y(), 4, 5, 3), Err(ConversionError::StrideMismatch { stride: 4, row_bytes: 5 }));
+        // 3x2 YUYV with 8 byte rows, the last luma sample of each row is padding
+        let yuyv = [1, 128, 2, 128, 3, 128, 0, 128, 4, 128, 5, 128, 6, 128, 0, 128];
+        assert_eq!(extract_packed_luma(&yuyv, 8, 3, 2, PackedYuvFormat::Yuyv).unwrap(), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(extract_packed_luma(&yuyv[..15], 8, 3, 2, PackedYuvFormat::Yuyv),
+                   Err(ConversionError::BufferTooSmall { expected: 16, got: 15 }));
+    }
+}
@@ -0,0 +1,120 @@
+//! Horizontal mirroring of YUV420 frames, eg.: the self view of a camera
+
+use kernel::{self, ChromaPlanes};
+use planes::FrameMut;
+#[cfg(feature = "alloc")]
+use frame::YuvFrame;
+
+// reverses the first `width` samples of `sample` bytes of every row
+fn reverse_rows(plane: &mut [u8], stride: usize, sample: usize, width: usize, height: usize) {
+    for row in 0..height {
+        let row = &mut plane[row * stride..][..width * sample];
+        row.reverse();
+        // reversing the bytes reversed the samples within pairs too
+        if sample == 2 {
+            for pair in row.chunks_exact_mut(2) {
+                pair.swap(0, 1);
+            }
+        }
+    }
+}
+
+// after `reverse_rows`, moves the chroma of a mirrored odd width back onto the 2x2 grid:
+// every sample but the last one covers the first pixel of a reversed sample and the second
+// pixel of the next one
+fn realign_chroma(plane: &mut [u8], stride: usize, sample: usize, width: usize, height: usize) {
+    for row in 0..height {
+        let row = &mut plane[row * stride..][..width * sample];
+        for i in 0..(width - 1) * sample {
+            row[i] = (u16::from(row[i]) + u16::from(row[i + sample])).div_ceil(2) as u8;
+        }
+    }
+}
+
+/// Mirrors the frame left to right in place, eg.: for showing users their own camera
+///
+/// With an even width the chroma samples are reversed as they are. With an odd width the
+/// 2x2 blocks don't line up with the mirrored ones, so every chroma sample but the last one
+/// is the mean of the two it now straddles.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::FrameMut;
+///
+/// // a 4x2 NV12 frame
+/// let (mut y, mut uv) = ([1, 2, 3, 4, 5, 6, 7, 8], [10, 20, 30, 40]);
+/// rgb2yuv420::mirror_yuv(&mut FrameMut::nv12(4, 2, &mut y, 4, &mut uv, 4).unwrap());
+/// assert_eq!(y, [4, 3, 2, 1, 8, 7, 6, 5]);
+/// assert_eq!(uv, [30, 40, 10, 20]);
+/// ```
+pub fn mirror_yuv(frame: &mut FrameMut) {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let planes = frame.planes();
+    reverse_rows(planes.y, planes.strides.y, 1, width, height);
+    let stride = planes.strides.chroma;
+    let chroma: &mut [&mut [u8]] = match planes.chroma {
+        ChromaPlanes::Planar(u, v) => &mut [u, v],
+        ChromaPlanes::SemiPlanar(uv) => &mut [uv],
+    };
+    let sample = 3 - chroma.len();
+    for plane in chroma {
+        reverse_rows(plane, stride, sample, chroma_width, chroma_height);
+        if !width.is_multiple_of(2) {
+            realign_chroma(plane, stride, sample, chroma_width, chroma_height);
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl YuvFrame {
+    /// Mirrors the frame left to right in place, along with its alpha plane
+    ///
+    /// See `mirror_yuv`.
+    pub fn mirror(&mut self) {
+        let ((width, height), stride) = (self.alpha_dimensions(), self.alpha_stride());
+        // a subsampled plane is realigned like the chroma planes
+        let realign = self.width() % 2 == 1 && width < self.width() as usize;
+        if let Some(alpha) = self.alpha_mut() {
+            reverse_rows(alpha, stride, 1, width, height);
+            if realign {
+                realign_chroma(alpha, stride, 1, width, height);
+            }
+        }
+        mirror_yuv(&mut self.as_frame_mut());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::ChromaFilter;
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+
+    // the pixels of `width` pixel rows of `bpp` bytes, mirrored
+    fn mirrored(pixels: &[u8], width: usize, bpp: usize) -> Vec<u8> {
+        pixels.chunks(width * bpp).flat_map(|row| row.chunks(bpp).rev().flatten().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    #[test]
+    fn mirrored_frames_match_mirrored_images() {
+        let rgba: Vec<u8> = (0..6 * 3 * 4).map(|i| (i * 47 % 256) as u8).collect();
+        // averaged chroma doesn't depend on which pixel of a block ends up where
+        let builder = Converter::builder().input(ChannelOrder::Rgba).chroma_filter(ChromaFilter::Average);
+        let converter = builder.clone().output(YuvFormat::Nv12).alpha_plane(true).build();
+        let mut frame = converter.convert(&rgba, 6, 3).unwrap();
+        frame.mirror();
+        assert_eq!(frame, converter.convert(&mirrored(&rgba, 6, 4), 6, 3).unwrap());
+        // with an odd width the chroma is resampled between the blocks it straddles
+        let converter = builder.build();
+        let mut frame = converter.convert(&rgba[..5 * 2 * 4], 5, 2).unwrap();
+        let original = frame.clone();
+        frame.mirror();
+        assert_eq!(frame.y(), converter.convert(&mirrored(&rgba[..40], 5, 4), 5, 2).unwrap().y());
+        let u = original.u().unwrap();
+        let mean = |a: u8, b: u8| (u16::from(a) + u16::from(b)).div_ceil(2) as u8;
+        assert_eq!(frame.u().unwrap(), &[mean(u[2], u[1]), mean(u[1], u[0]), u[0]]);
+    }
+}
@@ -0,0 +1,161 @@
+//! `memmap2` integration (`memmap` feature)
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+use memmap2::MmapMut;
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::YuvFormat;
+use layout;
+use view::RgbView;
+
+/// Converts frames of one size straight into a memory mapped raw YUV file
+///
+/// Frame `i` starts at `i * frame_size()` bytes; the file grows as frames are written, so a
+/// long recording never needs a second copy of the frames in memory.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, MmapWriter, RgbView, YuvFormat};
+///
+/// let path = std::env::temp_dir().join("rgb2yuv420-mmap-doc.yuv");
+/// let mut writer = MmapWriter::create(&path, YuvFormat::I420, 4, 2).unwrap();
+/// let rgb = vec![0u8; 4 * 2 * 3];
+/// for _ in 0..3 {
+///     writer.write_frame(&RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap(), &ConversionConfig::default())
+///         .unwrap();
+/// }
+/// assert_eq!(writer.frame_offset(2), 24);
+/// writer.finish().unwrap();
+/// assert_eq!(std::fs::metadata(&path).unwrap().len(), 36);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct MmapWriter {
+    file: File,
+    map: Option<MmapMut>,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    // frames the file currently has room for
+    capacity: usize,
+    frames: usize,
+}
+
+impl MmapWriter {
+    /// Creates (or truncates) the file at `path` for `width` x `height` frames in `format`
+    pub fn create<P: AsRef<Path>>(path: P, format: YuvFormat, width: u32, height: u32) -> io::Result<MmapWriter> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        Ok(MmapWriter { file, map: None, format, width, height, capacity: 0, frames: 0 })
+    }
+
+    /// Size of one frame in the file in bytes
+    pub fn frame_size(&self) -> usize {
+        layout::required_buffer_size(self.format, self.width, self.height)
+    }
+
+    /// Position of frame `index` in the file
+    pub fn frame_offset(&self, index: usize) -> usize {
+        index * self.frame_size()
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// The converted frame `index`, if it was written
+    pub fn frame(&self, index: usize) -> Option<&[u8]> {
+        match self.map {
+            Some(ref map) if index < self.frames => Some(&map[self.frame_offset(index)..][..self.frame_size()]),
+            _ => None,
+        }
+    }
+
+    /// Converts `src` into the next frame of the file
+    ///
+    /// # Return
+    ///
+    /// The index of the frame, the error of growing the file, or an `InvalidInput` error
+    /// holding `UnsupportedDimensions` if `src` isn't the size of the file's frames
+    pub fn write_frame(&mut self, src: &RgbView, cfg: &ConversionConfig) -> io::Result<usize> {
+        if (src.width(), src.height()) != (self.width, self.height) {
+            let err = ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() };
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+        }
+        if self.frames == self.capacity {
+            self.grow()?;
+        }
+        let (index, offset, size) = (self.frames, self.frame_offset(self.frames), self.frame_size());
+        if let Some(ref mut map) = self.map {
+            ::convert_into(src, self.format, cfg, &mut map[offset..offset + size])
+                .expect("the frame fits the mapping");
+        }
+        self.frames += 1;
+        Ok(index)
+    }
+
+    /// Writes the mapped frames back to the file
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.as_ref().map_or(Ok(()), |map| map.flush())
+    }
+
+    /// Flushes the frames and shrinks the file to them
+    pub fn finish(mut self) -> io::Result<File> {
+        self.flush()?;
+        self.map = None;
+        self.file.set_len((self.frames * self.frame_size()) as u64)?;
+        Ok(self.file)
+    }
+
+    // doubles the room in the file (starting at 8 frames) and maps it again
+    fn grow(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.map = None;
+        let capacity = (2 * self.capacity).max(8);
+        let len = capacity * self.frame_size();
+        self.file.set_len(len as u64)?;
+        if len > 0 {
+            // SAFETY: the file was created by this writer and is only accessed through the map
+            // while it exists; other processes modifying it is the caller's responsibility.
+            self.map = Some(unsafe { MmapMut::map_mut(&self.file)? });
+        }
+        self.capacity = capacity;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::MmapWriter;
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn frames_are_laid_out_back_to_back() {
+        let path = ::std::env::temp_dir().join(format!("rgb2yuv420-mmap-{}.yuv", ::std::process::id()));
+        let mut writer = MmapWriter::create(&path, YuvFormat::Nv12, 6, 4).unwrap();
+        let images: Vec<Vec<u8>> = (0..20u8).map(|i| vec![i * 12; 6 * 4 * 3]).collect();
+        let cfg = ConversionConfig::default();
+        for (i, rgb) in images.iter().enumerate() {
+            assert_eq!(writer.write_frame(&RgbView::new(rgb, 6, 4, ChannelOrder::Rgb).unwrap(), &cfg).unwrap(), i);
+        }
+        let other = vec![0u8; 4 * 4 * 3];
+        let err = writer.write_frame(&RgbView::new(&other, 4, 4, ChannelOrder::Rgb).unwrap(), &cfg).unwrap_err();
+        assert_eq!(err.get_ref().and_then(|err| err.downcast_ref()),
+                   Some(&ConversionError::UnsupportedDimensions { width: 4, height: 4 }));
+        assert_eq!(writer.frame(3).unwrap(), &::convert_rgb_to_yuv420sp_nv12(&images[3], 6, 4, 3)[..]);
+        writer.finish().unwrap();
+        let expected: Vec<u8> = images.iter().flat_map(|rgb| ::convert_rgb_to_yuv420sp_nv12(rgb, 6, 4, 3)).collect();
+        assert_eq!(fs::read(&path).unwrap(), expected);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+//! Picking the output format out of those an encoder accepts
+
+use converter::{Converter, ConverterBuilder};
+use error::ConversionError;
+use format::YuvFormat;
+
+/// An input format of an encoder, as its capability queries name it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EncoderFormat {
+    /// A V4L2 (or FFmpeg) fourcc, eg.: `*b"NV12"` from `VIDIOC_ENUM_FMT` on the output queue
+    /// of a mem2mem encoder
+    Fourcc([u8; 4]),
+    /// A color format constant of `MediaCodecInfo.CodecCapabilities`, eg.: 21 for
+    /// `COLOR_FormatYUV420SemiPlanar`
+    MediaCodec(i32),
+}
+
+const COLOR_FORMAT_YUV420_PLANAR: i32 = 19;
+const COLOR_FORMAT_YUV420_PACKED_PLANAR: i32 = 20;
+const COLOR_FORMAT_YUV420_SEMI_PLANAR: i32 = 21;
+const COLOR_FORMAT_YUV420_PACKED_SEMI_PLANAR: i32 = 39;
+const COLOR_FORMAT_YUV420_FLEXIBLE: i32 = 0x7f42_0888;
+
+impl EncoderFormat {
+    // the output and `swap_uv` converting into the format, and its rank: NV12 first, as most
+    // hardware encoders take it as it is, then I420, NV21 and the flexible layouts
+    fn output(self) -> Option<(usize, YuvFormat, bool)> {
+        Some(match self {
+            EncoderFormat::Fourcc(ref fourcc) => match fourcc {
+                b"NV12" | b"NM12" => (0, YuvFormat::Nv12, false),
+                b"YU12" | b"YM12" | b"I420" | b"IYUV" => (1, YuvFormat::I420, false),
+                b"NV21" | b"NM21" => (2, YuvFormat::Nv12, true),
+                _ => return None,
+            },
+            EncoderFormat::MediaCodec(color_format) => match color_format {
+                COLOR_FORMAT_YUV420_SEMI_PLANAR | COLOR_FORMAT_YUV420_PACKED_SEMI_PLANAR => {
+                    (0, YuvFormat::Nv12, false)
+                }
+                COLOR_FORMAT_YUV420_PLANAR | COLOR_FORMAT_YUV420_PACKED_PLANAR => (1, YuvFormat::I420, false),
+                // copied into the planes of the input `Image` with `YuvPlanes::copy_into_flexible`
+                COLOR_FORMAT_YUV420_FLEXIBLE => (3, YuvFormat::I420, false),
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// The format picked by `ConverterBuilder::negotiate` and a converter into it
+#[derive(Debug, Clone)]
+pub struct Negotiated {
+    /// The format to configure the encoder with, out of those it accepts
+    pub format: EncoderFormat,
+    /// Converts into `format`
+    pub converter: Converter,
+}
+
+impl ConverterBuilder {
+    /// Picks the best of the formats an encoder accepts and builds a converter into it, with
+    /// the output (and `swap_uv`) set for it and everything else as configured
+    ///
+    /// NV12 is picked over I420, NV21 and `COLOR_FormatYUV420Flexible`; formats the
+    /// converters don't write, eg.: 10 bit ones such as P010 or packed 4:2:2 ones, are passed
+    /// over.
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` if none of `accepted` can be converted into
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, EncoderFormat, YuvFormat};
+    ///
+    /// // an encoder taking P010, NV21 and I420 buffers
+    /// let accepted = [EncoderFormat::Fourcc(*b"P010"), EncoderFormat::Fourcc(*b"NV21"),
+    ///                 EncoderFormat::Fourcc(*b"YU12")];
+    /// let negotiated = Converter::builder().negotiate(&accepted).unwrap();
+    /// assert_eq!(negotiated.format, EncoderFormat::Fourcc(*b"YU12"));
+    /// assert_eq!(negotiated.converter.output(), YuvFormat::I420);
+    /// assert!(Converter::builder().negotiate(&[EncoderFormat::MediaCodec(0x7f00_a000)]).is_err());
+    /// ```
+    pub fn negotiate(self, accepted: &[EncoderFormat]) -> Result<Negotiated, ConversionError> {
+        let best = accepted.iter().filter_map(|&format| format.output().map(|output| (format, output)))
+            .min_by_key(|&(_, (rank, _, _))| rank);
+        match best {
+            Some((format, (_, output, swap_uv))) => {
+                Ok(Negotiated { format, converter: self.output(output).swap_uv(swap_uv).build() })
+            }
+            None => Err(ConversionError::UnsupportedFormat("none of the encoder's formats can be converted into")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EncoderFormat;
+    use converter::Converter;
+    use format::YuvFormat;
+
+    #[test]
+    fn encoders_get_their_best_format() {
+        let negotiate = |accepted: &[EncoderFormat]| {
+            let negotiated = Converter::builder().threads(2).negotiate(accepted).unwrap();
+            let converter = negotiated.converter;
+            assert_eq!(converter.config().threads, 2);
+            (negotiated.format, converter.output(), converter.config().swap_uv)
+        };
+        let nv21 = EncoderFormat::Fourcc(*b"NV21");
+        assert_eq!(negotiate(&[nv21]), (nv21, YuvFormat::Nv12, true));
+        let semi_planar = EncoderFormat::MediaCodec(21);
+        let accepted = [EncoderFormat::MediaCodec(0x7f42_0888), EncoderFormat::MediaCodec(19), semi_planar];
+        assert_eq!(negotiate(&accepted), (semi_planar, YuvFormat::Nv12, false));
+        let flexible = EncoderFormat::MediaCodec(0x7f42_0888);
+        assert_eq!(negotiate(&[flexible, EncoderFormat::Fourcc(*b"YUYV")]), (flexible, YuvFormat::I420, false));
+        assert!(Converter::builder().negotiate(&[]).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! OpenCL backend (`opencl` feature)
+//!
+//! Converts on any OpenCL device using a context and command queue owned by the caller.
+//! The OpenCL library is loaded at runtime, so enabling the feature doesn't add a link-time
+//! dependency.
+
+use std::mem;
+use std::ptr;
+
+use opencl3::command_queue::CommandQueue;
+use opencl3::context::Context;
+use opencl3::error_codes::ClError;
+use opencl3::event::Event;
+use opencl3::kernel::{ExecuteKernel, Kernel};
+use opencl3::memory::{Buffer, CL_MEM_READ_ONLY, CL_MEM_WRITE_ONLY};
+use opencl3::program::Program;
+use opencl3::types::{cl_int, cl_uchar, cl_uint, CL_BLOCKING, CL_NON_BLOCKING};
+
+use backend::Convert;
+use config::{ChromaFilter, Coefficients, ConversionConfig, Quality};
+use error::ConversionError;
+use format::YuvFormat;
+use frame::YuvFrame;
+use view::RgbView;
+
+// One work item per 2x2 block, using the same integer math and chroma siting as the CPU kernel.
+// The coefficients are the 8.8 fixed point `[r, g, b]` weights of each plane.
+const PROGRAM_SOURCE: &str = r#"
+int3 rgb_at(global const uchar* img, uint x, uint y, uint stride, uint bytes_per_pixel, uint3 offsets) {
+    global const uchar* p = img + y * stride + x * bytes_per_pixel;
+    return (int3)(p[offsets.x], p[offsets.y], p[offsets.z]);
+}
+
+uchar weigh(int3 c, int3 coef, int offset) {
+    return (uchar)clamp(((coef.x * c.x + coef.y * c.y + coef.z * c.z + 128) >> 8) + offset, 0, 255);
+}
+
+kernel void rgb_to_yuv420(global const uchar* img, global uchar* yuv,
+                          uint width, uint height, uint stride, uint bytes_per_pixel, uint3 offsets,
+                          uint semi_planar,
+                          int3 y_coef, int3 u_coef, int3 v_coef, int y_offset, uint average) {
+    const uint cx = get_global_id(0);
+    const uint cy = get_global_id(1);
+    if (cx * 2 >= width || cy * 2 >= height) {
+        return;
+    }
+    int3 sum = (int3)(0, 0, 0);
+    for (uint dy = 0; dy < 2; dy++) {
+        for (uint dx = 0; dx < 2; dx++) {
+            const uint x = cx * 2 + dx;
+            const uint y = cy * 2 + dy;
+            const int3 c = rgb_at(img, x, y, stride, bytes_per_pixel, offsets);
+            yuv[y * width + x] = weigh(c, y_coef, y_offset);
+            sum += c;
+        }
+    }
+    const int3 c = average ? (sum + 2) / 4 : rgb_at(img, cx * 2 + 1, cy * 2, stride, bytes_per_pixel, offsets);
+    const uchar u = weigh(c, u_coef, 128);
+    const uchar v = weigh(c, v_coef, 128);
+    const uint frame_size = width * height;
+    const uint chroma_index = cy * (width / 2) + cx;
+    if (semi_planar) {
+        yuv[frame_size + chroma_index * 2] = u;
+        yuv[frame_size + chroma_index * 2 + 1] = v;
+    } else {
+        yuv[frame_size + chroma_index] = u;
+        yuv[frame_size + frame_size / 4 + chroma_index] = v;
+    }
+}
+"#;
+
+/// A compiled RGB to YUV420 OpenCL program
+///
+/// Building the program compiles the kernel for the context's devices, so create it once per
+/// context and reuse it.
+pub struct ClConverter<'a> {
+    context: &'a Context,
+    kernel: Kernel,
+    coefficients: Coefficients,
+    average: cl_uint,
+    swap_uv: bool,
+}
+
+impl<'a> ClConverter<'a> {
+    /// Builds the conversion kernel for `context`
+    ///
+    /// The matrix, range and chroma filter of `config` are honored; `threads` is ignored.
+    ///
+    /// # Errors
+    ///
+    /// `Device` with the build log on failure, or `UnsupportedFormat` if `config` adjusts
+    /// the colors, averages the chroma in linear light or asks for `Quality::Fast`
+    pub fn new(context: &'a Context, config: &ConversionConfig) -> Result<ClConverter<'a>, ConversionError> {
+        if !config.adjustment.is_neutral() {
+            return Err(ConversionError::UnsupportedFormat("the OpenCL backend doesn't adjust colors"));
+        }
+        if config.quality == Quality::Fast {
+            return Err(ConversionError::UnsupportedFormat("the OpenCL backend only converts accurately"));
+        }
+        let program = Program::create_and_build_from_source(context, PROGRAM_SOURCE, "")
+            .map_err(ConversionError::Device)?;
+        let kernel = Kernel::create(&program, "rgb_to_yuv420")?;
+        let mut coefficients = config.coefficients();
+        let average = match config.sampled_chroma_filter() {
+            ChromaFilter::Point => 0,
+            ChromaFilter::Average => 1,
+            ChromaFilter::LinearAverage => {
+                return Err(ConversionError::UnsupportedFormat("the OpenCL backend doesn't average in linear light"))
+            }
+            ChromaFilter::Neutral => {
+                // all chroma weights 0 leave every sample at 128
+                coefficients.u = [0; 3];
+                coefficients.v = [0; 3];
+                0
+            }
+        };
+        Ok(ClConverter { context, kernel, coefficients, average, swap_uv: config.swap_uv })
+    }
+
+    /// Starts converting an RGB image on the device without waiting for the result
+    ///
+    /// # Arguments
+    ///
+    /// * `queue` - must belong to the context the converter was built for
+    ///
+    /// * `src` - an 8 bit image with even width and height; it is uploaded before this returns
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if the image or its frame is too big to index with the kernel's
+    /// 32 bit offsets, besides the odd sizes and other depths
+    pub fn convert_async(&self, queue: &CommandQueue, src: &RgbView, format: YuvFormat)
+                         -> Result<PendingFrame, ConversionError> {
+        let (width, height) = (src.width(), src.height());
+        if !width.is_multiple_of(2) || !height.is_multiple_of(2) || width == 0 || height == 0 {
+            return Err(ConversionError::UnsupportedDimensions { width, height });
+        }
+        if src.bit_depth() != 8 {
+            return Err(ConversionError::UnsupportedFormat("the OpenCL backend only supports 8 bit channels"));
+        }
+        let layout = src.channel_order().layout();
+        // the view checked the image fits its data, so only the kernel's `uint` math can overflow
+        let img = &src.data()[..src.stride() * (height as usize - 1) + width as usize * layout.bytes_per_pixel];
+        let yuv_size = (width as usize).checked_mul(height as usize).and_then(|size| size.checked_mul(3))
+            .map(|size| size / 2)
+            .filter(|&size| size <= u32::MAX as usize && img.len() <= u32::MAX as usize)
+            .ok_or(ConversionError::UnsupportedDimensions { width, height })?;
+        let offsets: [cl_uint; 4] = [layout.r as cl_uint, layout.g as cl_uint, layout.b as cl_uint, 0];
+        let mut input = unsafe { Buffer::<cl_uchar>::create(self.context, CL_MEM_READ_ONLY, img.len(), ptr::null_mut())? };
+        let dst = unsafe { Buffer::<cl_uchar>::create(self.context, CL_MEM_WRITE_ONLY, yuv_size, ptr::null_mut())? };
+        unsafe {
+            queue.enqueue_write_buffer(&mut input, CL_BLOCKING, 0, img, &[])?;
+        }
+        let semi_planar: cl_uint = match format {
+            YuvFormat::I420 => 0,
+            YuvFormat::Nv12 => 1,
+        };
+        let (u, v) = match semi_planar {
+            1 if self.swap_uv => (self.coefficients.v, self.coefficients.u),
+            _ => (self.coefficients.u, self.coefficients.v),
+        };
+        let kernel_event = unsafe {
+            ExecuteKernel::new(&self.kernel)
+                .set_arg(&input)
+                .set_arg(&dst)
+                .set_arg(&(width as cl_uint))
+                .set_arg(&(height as cl_uint))
+                .set_arg(&(src.stride() as cl_uint))
+                .set_arg(&(layout.bytes_per_pixel as cl_uint))
+                .set_arg(&offsets)
+                .set_arg(&semi_planar)
+                .set_arg(&int3(self.coefficients.y))
+                .set_arg(&int3(u))
+                .set_arg(&int3(v))
+                .set_arg(&self.coefficients.y_offset)
+                .set_arg(&self.average)
+                .set_global_work_sizes(&[(width / 2) as usize, (height / 2) as usize])
+                .enqueue_nd_range(queue)?
+        };
+        let mut data = vec![0; yuv_size];
+        // the read targets the Vec's heap allocation, which stays put while `PendingFrame` moves
+        let event = unsafe { queue.enqueue_read_buffer(&dst, CL_NON_BLOCKING, 0, &mut data, &[kernel_event.get()])? };
+        Ok(PendingFrame { event, data, _buffers: (input, dst) })
+    }
+
+    /// Converts an RGB image on the device and waits for the result
+    ///
+    /// See `convert_async` for the arguments.
+    pub fn convert(&self, queue: &CommandQueue, src: &RgbView, format: YuvFormat) -> Result<Vec<u8>, ConversionError> {
+        self.convert_async(queue, src, format)?.wait()
+    }
+}
+
+/// A `ClConverter` with the queue it converts on, as a `Convert` backend
+///
+/// It converts 8 bit images with even widths and heights, waiting for each frame.
+pub struct ClBackend<'a> {
+    converter: ClConverter<'a>,
+    queue: &'a CommandQueue,
+    // the colorimetry of the frames
+    config: ConversionConfig,
+}
+
+impl<'a> ClBackend<'a> {
+    /// Builds the conversion kernel for `context`, see `ClConverter::new`
+    ///
+    /// `queue` must belong to `context`.
+    pub fn new(context: &'a Context, queue: &'a CommandQueue, config: &ConversionConfig)
+               -> Result<ClBackend<'a>, ConversionError> {
+        Ok(ClBackend { converter: ClConverter::new(context, config)?, queue, config: config.clone() })
+    }
+}
+
+impl<'a> Convert for ClBackend<'a> {
+    fn name(&self) -> &'static str {
+        "opencl"
+    }
+
+    fn convert_frame(&self, src: &RgbView, format: YuvFormat) -> Result<YuvFrame, ConversionError> {
+        let data = self.converter.convert(self.queue, src, format)?;
+        Ok(YuvFrame::from_packed(data, format, src.width(), src.height(), &self.config))
+    }
+}
+
+impl From<ClError> for ConversionError {
+    fn from(err: ClError) -> ConversionError {
+        ConversionError::Device(err.to_string())
+    }
+}
+
+// OpenCL's int3 and uint3 have the size and alignment of int4
+fn int3(coef: [i32; 3]) -> [cl_int; 4] {
+    [coef[0], coef[1], coef[2], 0]
+}
+
+/// A conversion whose readback is still in flight
+///
+/// Dropping it blocks until the device is done writing into the host buffer.
+pub struct PendingFrame {
+    event: Event,
+    data: Vec<u8>,
+    _buffers: (Buffer<cl_uchar>, Buffer<cl_uchar>),
+}
+
+impl PendingFrame {
+    /// Blocks until the frame is read back and returns it
+    pub fn wait(mut self) -> Result<Vec<u8>, ConversionError> {
+        self.event.wait()?;
+        Ok(mem::take(&mut self.data))
+    }
+}
+
+impl Drop for PendingFrame {
+    fn drop(&mut self) {
+        let _ = self.event.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencl3::command_queue::CommandQueue;
+    use opencl3::context::Context;
+    use opencl3::device::{get_all_devices, Device, CL_DEVICE_TYPE_ALL};
+
+    use super::ClConverter;
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn matches_cpu_kernel() {
+        let device_id = match get_all_devices(CL_DEVICE_TYPE_ALL).ok().and_then(|d| d.first().cloned()) {
+            Some(id) => id,
+            None => return,
+        };
+        let context = Context::from_device(&Device::new(device_id)).unwrap();
+        #[allow(deprecated)]
+        let queue = CommandQueue::create_default(&context, 0).unwrap();
+        let converter = ClConverter::new(&context, &ConversionConfig::default()).unwrap();
+        let rgb: Vec<u8> = (0..8 * 4 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let view = RgbView::new(&rgb, 8, 4, ChannelOrder::Rgb).unwrap();
+        let i420 = converter.convert(&queue, &view, YuvFormat::I420).unwrap();
+        assert_eq!(i420, ::convert_rgb_to_yuv420p(&rgb, 8, 4, 3));
+        let nv12 = converter.convert(&queue, &view, YuvFormat::Nv12).unwrap();
+        assert_eq!(nv12, ::convert_rgb_to_yuv420sp_nv12(&rgb, 8, 4, 3));
+    }
+}
@@ -0,0 +1,187 @@
+//! Conversions spread over several calls, a bounded number of rows at a time, for schedulers
+//! that hand out short slices of time instead of threads, or that cancel conversions whose
+//! frames went stale
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use config::ConversionConfig;
+use error::ConversionError;
+use kernel::Setup;
+use planes::FrameMut;
+use view::RgbView;
+
+/// How far a conversion of a `width` x `height` image into a `FrameMut` has come
+///
+/// It holds no borrows, so the source and the destination only need to be at hand while
+/// `resume` runs, and can be swapped for other views of the same buffers in between.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, FrameMut, PartialConversion, RgbView};
+///
+/// let rgb = vec![255u8; 4 * 6 * 3];
+/// let src = RgbView::new(&rgb, 4, 6, ChannelOrder::Rgb).unwrap();
+/// let (mut y, mut uv) = ([0; 4 * 6], [0; 4 * 3]);
+/// let mut frame = FrameMut::nv12(4, 6, &mut y, 4, &mut uv, 4).unwrap();
+/// let mut conversion = PartialConversion::new(4, 6);
+/// // a pair of rows per slice
+/// let mut slices = 0;
+/// while !conversion.resume(&src, &ConversionConfig::default(), &mut frame, 2).unwrap() {
+///     slices += 1;
+/// }
+/// assert_eq!((slices, conversion.rows_done()), (2, 6));
+/// assert_eq!(uv, [128; 12]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PartialConversion {
+    width: u32,
+    height: u32,
+    row: u32,
+}
+
+impl PartialConversion {
+    /// A conversion that hasn't converted any rows yet
+    pub fn new(width: u32, height: u32) -> PartialConversion {
+        PartialConversion { width, height, row: 0 }
+    }
+
+    /// Number of rows converted so far, the first of them that `resume` converts next
+    pub fn rows_done(&self) -> u32 {
+        self.row
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.row >= self.height
+    }
+
+    /// Converts the next `max_rows` rows of `src` with `cfg` into `dst` and returns whether
+    /// the frame is done
+    ///
+    /// An odd `max_rows` is rounded down (and 0 up) to a number of row pairs, as both rows of
+    /// a pair go into the same chroma row.
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if `src` or `dst` isn't the size the conversion was made for
+    pub fn resume(&mut self, src: &RgbView, cfg: &ConversionConfig, dst: &mut FrameMut, max_rows: u32)
+                  -> Result<bool, ConversionError> {
+        for size in &[(src.width(), src.height()), (dst.width(), dst.height())] {
+            if *size != (self.width, self.height) {
+                return Err(ConversionError::UnsupportedDimensions { width: size.0, height: size.1 });
+            }
+        }
+        if self.is_done() {
+            return Ok(true);
+        }
+        let rows = (max_rows & !1).max(2).min(self.height - self.row);
+        let band = src.crop(0, self.row, self.width, rows)?;
+        let band = band.source();
+        let setup = Setup::new(band.width, band.height, band.layout, band.wide, dst.format(), cfg);
+        setup.run_part(band, dst.planes().offset(0, self.row as usize));
+        self.row += rows;
+        Ok(self.is_done())
+    }
+}
+
+/// Rows `convert_cancellable` converts between two checks of its flag
+pub const CANCEL_BAND_ROWS: u32 = 32;
+
+/// Converts `src` with `cfg` into `dst` like `convert_into_frame`, `CANCEL_BAND_ROWS` rows at
+/// a time, and stops early once `cancel` is set
+///
+/// The flag is checked before every band, so a conversion stops within a band of being
+/// cancelled. The returned conversion tells how far it came: it `is_done` unless it was
+/// cancelled, and a cancelled one can be resumed later.
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` if `src` and `dst` differ in size
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::AtomicBool;
+///
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, FrameMut, RgbView};
+///
+/// let rgb = vec![0u8; 64 * 64 * 3];
+/// let src = RgbView::new(&rgb, 64, 64, ChannelOrder::Rgb).unwrap();
+/// let (mut y, mut uv) = (vec![0; 64 * 64], vec![0; 64 * 32]);
+/// let mut frame = FrameMut::nv12(64, 64, &mut y, 64, &mut uv, 64).unwrap();
+/// // eg.: set by the UI thread when a newer frame comes in
+/// let cancel = AtomicBool::new(true);
+/// let cfg = ConversionConfig::default();
+/// let conversion = rgb2yuv420::convert_cancellable(&src, &cfg, &mut frame, &cancel).unwrap();
+/// assert!(!conversion.is_done());
+/// assert_eq!(conversion.rows_done(), 0);
+/// ```
+pub fn convert_cancellable(src: &RgbView, cfg: &ConversionConfig, dst: &mut FrameMut, cancel: &AtomicBool)
+                           -> Result<PartialConversion, ConversionError> {
+    let mut conversion = PartialConversion::new(src.width(), src.height());
+    while !cancel.load(Ordering::Relaxed) && !conversion.resume(src, cfg, dst, CANCEL_BAND_ROWS)? {}
+    Ok(conversion)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use super::{convert_cancellable, PartialConversion, CANCEL_BAND_ROWS};
+    use config::{ChromaFilter, ConversionConfig, Matrix};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use planes::FrameMut;
+    use view::RgbView;
+
+    #[test]
+    fn slices_add_up_to_the_whole_frame() {
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, chroma_filter: ChromaFilter::Average,
+                                     ..ConversionConfig::default() };
+        let rgb: Vec<u8> = (0..5 * 7 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 7, ChannelOrder::Rgb).unwrap();
+        let expected = ::convert(&src, YuvFormat::I420, &cfg);
+        let (mut y, mut u, mut v) = (vec![0; 5 * 7], vec![0; 3 * 4], vec![0; 3 * 4]);
+        let mut conversion = PartialConversion::new(5, 7);
+        {
+            let mut frame = FrameMut::i420(5, 7, &mut y, 5, &mut u, &mut v, 3).unwrap();
+            let mut slices = 0;
+            // rounded down to 2 rows, the last slice converts the unpaired row
+            while !conversion.resume(&src, &cfg, &mut frame, 3).unwrap() {
+                slices += 1;
+                assert_eq!(conversion.rows_done(), 2 * slices);
+            }
+            assert_eq!(slices, 3);
+            assert_eq!(conversion.resume(&src, &cfg, &mut frame, 3), Ok(true));
+        }
+        assert_eq!([y, u, v].concat(), expected.data());
+        let small = RgbView::new(&rgb, 5, 6, ChannelOrder::Rgb).unwrap();
+        let (mut y, mut uv) = ([0; 5 * 7], [0; 6 * 4]);
+        let mut frame = FrameMut::nv12(5, 7, &mut y, 5, &mut uv, 6).unwrap();
+        assert_eq!(PartialConversion::new(5, 7).resume(&small, &cfg, &mut frame, 2),
+                   Err(ConversionError::UnsupportedDimensions { width: 5, height: 6 }));
+    }
+
+    #[test]
+    fn cancelled_conversions_can_be_resumed() {
+        let cfg = ConversionConfig::default();
+        let (width, height) = (8, 3 * CANCEL_BAND_ROWS - 1);
+        let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i * 29 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, width, height, ChannelOrder::Rgb).unwrap();
+        let expected = ::convert(&src, YuvFormat::Nv12, &cfg);
+        let cancel = AtomicBool::new(false);
+        for &cancelled in &[false, true] {
+            cancel.store(cancelled, Ordering::Relaxed);
+            let mut yuv = vec![0; expected.data().len()];
+            {
+                let (y, uv) = yuv.split_at_mut((width * height) as usize);
+                let mut frame = FrameMut::nv12(width, height, y, 8, uv, 8).unwrap();
+                let mut conversion = convert_cancellable(&src, &cfg, &mut frame, &cancel).unwrap();
+                let rows = if cancelled { 0 } else { height };
+                assert_eq!((conversion.is_done(), conversion.rows_done()), (!cancelled, rows));
+                while !conversion.resume(&src, &cfg, &mut frame, CANCEL_BAND_ROWS).unwrap() {}
+            }
+            assert_eq!(yuv, expected.data());
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Test patterns generated directly as YUV420 frames
+//!
+//! The patterns are computed in YUV with the matrix and range of the configuration, so
+//! they hold values no RGB image converts to (the PLUGE bars below black) and come out
+//! exactly the same at every resolution, for checking what an encoder or a display does
+//! to a known signal.
+//!
+//! # Examples
+//!
+//! ```
+//! use rgb2yuv420::{patterns, ConversionConfig, Range, YuvFormat};
+//!
+//! let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+//! let bars = patterns::color_bars(YuvFormat::Nv12, 1280, 720, &cfg);
+//! // 75% white in the top left and 100% white in the bottom row
+//! assert_eq!(bars.y()[0], 180);
+//! assert_eq!(bars.y()[719 * 1280 + 300], 235);
+//! ```
+
+use config::{Coefficients, ConversionConfig};
+use format::YuvFormat;
+use frame::{Pitch, YuvFrame};
+use kernel;
+
+/// The seven 75% bars, from white to blue
+const BARS: [[i32; 3]; 7] = [[191, 191, 191], [191, 191, 0], [0, 191, 191], [0, 191, 0], [191, 0, 191],
+                             [191, 0, 0], [0, 0, 191]];
+
+/// SMPTE color bars (EG 1): seven 75% bars, a strip of reversed blue bars and a bottom row
+/// of -I, 100% white, +Q, black and the PLUGE bars at -4%, 0% and +4% of black
+///
+/// The -I and +Q chips are black with 20% chroma on the I and Q axes. The PLUGE bar below
+/// black clips at 0 in full range.
+pub fn color_bars(format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig) -> YuvFrame {
+    let coef = cfg.coefficients_for(format);
+    let (w, h) = (width as usize, height as usize);
+    let chip = move |x: usize, y: usize| -> [u8; 3] {
+        let black = coef.luma(0, 0, 0);
+        if y < h * 2 / 3 {
+            return rgb(&coef, BARS[x * 7 / w]);
+        }
+        if y < h * 3 / 4 {
+            let bar = x * 7 / w;
+            return if bar % 2 == 1 { [black, 128, 128] } else { rgb(&coef, BARS[6 - bar]) };
+        }
+        match (x * 28 / w, x * 21 / w) {
+            (0..=4, _) => axis(&coef, 109, -168),
+            (5..=9, _) => rgb(&coef, [255; 3]),
+            (10..=14, _) => axis(&coef, 168, 109),
+            (_, 15) => [luma_step(&coef, -4), 128, 128],
+            (_, 17) => [luma_step(&coef, 4), 128, 128],
+            _ => [black, 128, 128],
+        }
+    };
+    generate(format, width, height, cfg, chip)
+}
+
+/// A circular zone plate: luma rings whose frequency rises from zero in the center to the
+/// Nyquist limit at the nearest edge, for spotting aliasing and filtering in the luma path
+///
+/// The chroma is neutral.
+pub fn zone_plate(format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig) -> YuvFrame {
+    let coef = cfg.coefficients_for(format);
+    let (black, white) = (f64::from(coef.luma(0, 0, 0)), f64::from(coef.luma(255, 255, 255)));
+    // the phase r² / (4 * radius) cycles reaches half a cycle per pixel at r = radius
+    let radius = f64::from(width.min(height).max(2)) / 2.0;
+    let center = (f64::from(width) / 2.0, f64::from(height) / 2.0);
+    let chip = move |x: usize, y: usize| -> [u8; 3] {
+        let (dx, dy) = (x as f64 + 0.5 - center.0, y as f64 + 0.5 - center.1);
+        let cycles = (dx * dx + dy * dy) / (4.0 * radius);
+        let level = (1.0 + cos(cycles - cycles as u64 as f64)) / 2.0;
+        [(black + (white - black) * level + 0.5) as u8, 128, 128]
+    };
+    generate(format, width, height, cfg, chip)
+}
+
+/// Gradient ramps from black on the left to full intensity on the right, in four bands from
+/// top to bottom: gray, red, green and blue
+///
+/// The gray band steps through every luma code of the range when the frame is wide enough.
+pub fn ramps(format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig) -> YuvFrame {
+    let coef = cfg.coefficients_for(format);
+    let (w, h) = (width as usize, height as usize);
+    let (black, white) = (coef.luma(0, 0, 0) as usize, coef.luma(255, 255, 255) as usize);
+    let step = move |x: usize, top: usize| if w > 1 { (x * top + (w - 1) / 2) / (w - 1) } else { top };
+    let chip = move |x: usize, y: usize| -> [u8; 3] {
+        match y * 4 / h {
+            // straight in luma, so that no code is skipped
+            0 => [(black + step(x, white - black)) as u8, 128, 128],
+            band => {
+                let mut color = [0; 3];
+                color[band - 1] = step(x, 255) as i32;
+                rgb(&coef, color)
+            }
+        }
+    };
+    generate(format, width, height, cfg, chip)
+}
+
+// fills a frame from the `[y, u, v]` of every pixel, taking the chroma of the top left pixel of
+// each 2x2 block
+fn generate<F: Fn(usize, usize) -> [u8; 3]>(format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig,
+                                            chip: F) -> YuvFrame {
+    let mut frame = YuvFrame::pitched(format, width, height, cfg, Pitch::default());
+    let (w, h) = (width as usize, height as usize);
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(w, h);
+    let (y_stride, chroma_stride) = (frame.y_stride(), frame.chroma_stride());
+    let (y_plane, chroma, v_plane) = frame.planes_mut();
+    for (row, line) in y_plane.chunks_mut(y_stride).take(h).enumerate() {
+        for (x, px) in line[..w].iter_mut().enumerate() {
+            *px = chip(x, row)[0];
+        }
+    }
+    let mut v_rows = v_plane.map(|v| v.chunks_mut(chroma_stride));
+    for (row, line) in chroma.chunks_mut(chroma_stride).take(chroma_height).enumerate() {
+        let v_line = v_rows.as_mut().and_then(Iterator::next);
+        let samples = (0..chroma_width).map(|x| chip(2 * x, 2 * row));
+        match v_line {
+            Some(v_line) => {
+                for ((u, v), [_, cb, cr]) in line.iter_mut().zip(v_line.iter_mut()).zip(samples) {
+                    *u = cb;
+                    *v = cr;
+                }
+            }
+            None => {
+                for (uv, [_, cb, cr]) in line.chunks_mut(2).zip(samples) {
+                    uv.copy_from_slice(&[cb, cr]);
+                }
+            }
+        }
+    }
+    frame
+}
+
+fn rgb(coef: &Coefficients, [r, g, b]: [i32; 3]) -> [u8; 3] {
+    let (u, v) = coef.chroma(r, g, b);
+    [coef.luma(r, g, b), u, v]
+}
+
+// black with a chroma vector of `u` and `v` thousandths of the full chroma swing
+fn axis(coef: &Coefficients, u: i32, v: i32) -> [u8; 3] {
+    let swing = coef.v[0];
+    let chroma = |c: i32| (128 + (c * swing + 500).div_euclid(1000)) as u8;
+    [coef.luma(0, 0, 0), chroma(u), chroma(v)]
+}
+
+// black moved by `percent` of the luma range
+fn luma_step(coef: &Coefficients, percent: i32) -> u8 {
+    let (black, white) = (i32::from(coef.luma(0, 0, 0)), i32::from(coef.luma(255, 255, 255)));
+    (black + ((white - black) * percent * 2 + 100).div_euclid(200)).clamp(0, 255) as u8
+}
+
+// `cos(2π * turns)` for `turns` in [0, 1), to within 0.002 (Bhaskara's approximation);
+// `f64::cos` needs `std`
+fn cos(turns: f64) -> f64 {
+    // cos(2πt) = sin(π(2t + 1/2)), folded into sin over [0, π] with a sign
+    let half_turns = 2.0 * turns + 0.5;
+    let (x, sign) = if half_turns < 1.0 { (half_turns, 1.0) }
+                    else if half_turns < 2.0 { (half_turns - 1.0, -1.0) }
+                    else { (half_turns - 2.0, 1.0) };
+    // with x in half turns, sin(πx) ≈ 16x(1 - x) / (5 - 4x(1 - x))
+    let p = x * (1.0 - x);
+    sign * 16.0 * p / (5.0 - 4.0 * p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{color_bars, ramps, zone_plate};
+    use config::{ConversionConfig, Range};
+    use format::YuvFormat;
+
+    #[test]
+    fn patterns_match_in_both_formats() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        let generators: [fn(YuvFormat, u32, u32, &ConversionConfig) -> _; 3] = [color_bars, zone_plate, ramps];
+        for generate in generators.iter() {
+            let (i420, nv12) = (generate(YuvFormat::I420, 71, 40, &cfg), generate(YuvFormat::Nv12, 71, 40, &cfg));
+            assert_eq!(i420.y(), nv12.y());
+            let interleaved: Vec<u8> = i420.u().unwrap().iter().zip(i420.v().unwrap())
+                .flat_map(|(&u, &v)| vec![u, v]).collect();
+            assert_eq!(nv12.uv().unwrap(), &interleaved[..]);
+        }
+        let bars = color_bars(YuvFormat::I420, 70, 40, &cfg);
+        // the PLUGE bars at -4%, 0% and +4%, then black
+        assert_eq!(&bars.y()[39 * 70 + 50..][..12], &[7, 7, 7, 7, 16, 16, 16, 25, 25, 25, 16, 16]);
+        let plate = zone_plate(YuvFormat::I420, 64, 64, &cfg);
+        assert_eq!(plate.y()[32 * 64 + 32], 235);
+        assert!(plate.u().unwrap().iter().all(|&u| u == 128));
+        let ramp = ramps(YuvFormat::I420, 220, 8, &cfg);
+        assert!(ramp.y()[..220].windows(2).all(|pair| pair[1] == pair[0] + 1));
+    }
+}
@@ -0,0 +1,444 @@
+//! Pipelines of the stages a frame goes through: `Stages`, decoding, transforming,
+//! converting and writing frames one after the other with its buffers reused between them,
+//! and `Pipeline`, converting on worker threads between `crossbeam` channels (`crossbeam`
+//! feature)
+
+#[cfg(feature = "crossbeam")]
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::mem;
+#[cfg(feature = "crossbeam")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "crossbeam")]
+use std::thread;
+
+#[cfg(feature = "crossbeam")]
+use crossbeam_channel::{self, Receiver, RecvError, SendError, Sender};
+
+use config::Colorimetry;
+use converter::{Converter, ConverterBuilder};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use frame::{FrameTiming, YuvFrame};
+use geometry::{Rect, Rotation};
+use raw::YuvWriter;
+#[cfg(feature = "crossbeam")]
+use threads;
+#[cfg(feature = "crossbeam")]
+use view::RgbFrame;
+use view::RgbView;
+use y4m::Y4mWriter;
+
+/// The image a decode adapter of `Stages::push_decoded` left in the RGB buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decoded {
+    pub width: u32,
+    pub height: u32,
+    pub channel_order: ChannelOrder,
+    /// The timing of the frame, numbered by the stages if it has no index
+    pub timing: FrameTiming,
+}
+
+impl Decoded {
+    pub fn new(width: u32, height: u32, channel_order: ChannelOrder) -> Decoded {
+        Decoded { width, height, channel_order, timing: FrameTiming::default() }
+    }
+}
+
+/// The last stage of `Stages`, taking the converted frames, eg.: an encoder
+///
+/// Implemented for closures and for the Y4M and raw YUV writers; the frames are reused, so
+/// sinks keeping them have to clone them.
+pub trait FrameSink {
+    fn write_frame(&mut self, frame: &YuvFrame) -> io::Result<()>;
+
+    /// Called by `Stages::finish` after the last frame, eg.: to flush
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&YuvFrame) -> io::Result<()>> FrameSink for F {
+    fn write_frame(&mut self, frame: &YuvFrame) -> io::Result<()> {
+        self(frame)
+    }
+}
+
+impl<W: Write> FrameSink for Y4mWriter<W> {
+    fn write_frame(&mut self, frame: &YuvFrame) -> io::Result<()> {
+        self.write_timed_frame(frame)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl<W: Write> FrameSink for YuvWriter<W> {
+    fn write_frame(&mut self, frame: &YuvFrame) -> io::Result<()> {
+        YuvWriter::write_frame(self, &frame.as_planes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+/// Configures `Stages`
+///
+/// The image is cropped, then rotated and then scaled, like `ConverterBuilder` does it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StagesBuilder {
+    converter: ConverterBuilder,
+    crop: Option<Rect>,
+}
+
+impl Default for StagesBuilder {
+    fn default() -> StagesBuilder {
+        StagesBuilder::new()
+    }
+}
+
+impl StagesBuilder {
+    pub fn new() -> StagesBuilder {
+        StagesBuilder { converter: Converter::builder(), crop: None }
+    }
+
+    /// Converts with `converter`, eg.: for its chroma filter or threads; as it replaces the
+    /// scale, rotation, colorimetry and output set so far, it goes first
+    pub fn converter(mut self, converter: ConverterBuilder) -> StagesBuilder {
+        self.converter = converter;
+        self
+    }
+
+    /// Keeps the `rect` of the decoded images, without copying them
+    ///
+    /// Pushing images `rect` doesn't fit into fails with `InvalidRegion`.
+    pub fn crop(mut self, rect: Rect) -> StagesBuilder {
+        self.crop = Some(rect);
+        self
+    }
+
+    pub fn rotate(mut self, rotation: Rotation) -> StagesBuilder {
+        self.converter = self.converter.rotate(rotation);
+        self
+    }
+
+    pub fn scale(mut self, width: u32, height: u32) -> StagesBuilder {
+        self.converter = self.converter.scale(width, height);
+        self
+    }
+
+    pub fn colorimetry(mut self, colorimetry: Colorimetry) -> StagesBuilder {
+        self.converter = self.converter.colorimetry(colorimetry);
+        self
+    }
+
+    pub fn output(mut self, output: YuvFormat) -> StagesBuilder {
+        self.converter = self.converter.output(output);
+        self
+    }
+
+    /// The stages writing the converted frames into `sink`
+    pub fn build<S: FrameSink>(self, sink: S) -> Stages<S> {
+        Stages { converter: self.converter.build(), crop: self.crop, rgb: Vec::new(), frame: None, frames: 0, sink }
+    }
+}
+
+/// Decodes, crops, rotates, scales and converts frames and writes them into a `FrameSink`,
+/// on the calling thread
+///
+/// The decoded image and the converted frame are kept between frames, so a stream of frames
+/// of the same size is converted without allocating.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, Colorimetry, Rect, Rotation, YuvFormat};
+/// use rgb2yuv420::pipeline::{Decoded, StagesBuilder};
+///
+/// let mut lumas = Vec::new();
+/// let mut stages = StagesBuilder::new()
+///     .crop(Rect { x: 0, y: 0, width: 8, height: 4 })
+///     .rotate(Rotation::Cw90)
+///     .scale(2, 4)
+///     .colorimetry(Colorimetry::Bt709Full)
+///     .output(YuvFormat::Nv12)
+///     .build(|frame: &rgb2yuv420::YuvFrame| {
+///         lumas.push((frame.width(), frame.height(), frame.y()[0], frame.timing().index));
+///         Ok(())
+///     });
+/// for gray in [0u8, 255] {
+///     // eg.: a decoder filling the buffer it is given
+///     stages.push_decoded(|rgb: &mut Vec<u8>| {
+///         rgb.resize(16 * 8 * 3, gray);
+///         Ok(Decoded::new(16, 8, ChannelOrder::Rgb))
+///     }).unwrap();
+/// }
+/// assert!(stages.finish().is_ok());
+/// assert_eq!(lumas, [(2, 4, 0, Some(0)), (2, 4, 255, Some(1))]);
+/// ```
+#[derive(Debug)]
+pub struct Stages<S> {
+    converter: Converter,
+    crop: Option<Rect>,
+    rgb: Vec<u8>,
+    frame: Option<YuvFrame>,
+    frames: u64,
+    sink: S,
+}
+
+fn invalid_input(err: ConversionError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+impl<S: FrameSink> Stages<S> {
+    /// Runs the decode adapter `decode` on the (emptied) RGB buffer of the stages, eg.: a PNG
+    /// or camera decoder, and pushes the image it decoded through the other stages
+    ///
+    /// # Errors
+    ///
+    /// The errors of `decode` and `push`, and `InvalidInput` if `decode` left less than a
+    /// `Decoded` image in the buffer
+    pub fn push_decoded<F>(&mut self, decode: F) -> io::Result<()>
+        where F: FnOnce(&mut Vec<u8>) -> io::Result<Decoded> {
+        let mut rgb = mem::take(&mut self.rgb);
+        rgb.clear();
+        let pushed = decode(&mut rgb).and_then(|decoded| {
+            let src = RgbView::new(&rgb, decoded.width, decoded.height, decoded.channel_order).map_err(invalid_input)?;
+            self.push(&src, decoded.timing)
+        });
+        self.rgb = rgb;
+        pushed
+    }
+
+    /// Pushes an image decoded elsewhere through the stages after decoding, numbering it in
+    /// `timing` if it has no index
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` wrapping `InvalidRegion` if the crop doesn't fit into `src`, and the
+    /// errors of the sink
+    pub fn push(&mut self, src: &RgbView, timing: FrameTiming) -> io::Result<()> {
+        let src = match self.crop {
+            Some(Rect { x, y, width, height }) => src.crop(x, y, width, height).map_err(invalid_input)?,
+            None => *src,
+        };
+        let frame = match self.frame {
+            Some(ref mut frame) => {
+                self.converter.convert_view_into(&src, frame);
+                frame
+            }
+            None => self.frame.insert(self.converter.convert_view(&src)),
+        };
+        frame.set_timing(FrameTiming { index: timing.index.or(Some(self.frames)), ..timing });
+        self.frames += 1;
+        self.sink.write_frame(frame)
+    }
+
+    /// Number of frames pushed into the sink so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn converter(&self) -> &Converter {
+        &self.converter
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Finishes the sink and returns it
+    pub fn finish(mut self) -> io::Result<S> {
+        self.sink.finish()?;
+        Ok(self.sink)
+    }
+}
+
+/// A conversion stage between two channels, running on its own worker threads
+///
+/// Frames sent into the pipeline are converted by `workers` threads and come out of it in
+/// the order they went in, numbered from 0 in their `FrameTiming`. The threads exit once
+/// every sender of the pipeline is dropped and the converted frames are drained.
+///
+/// # Examples
+///
+/// ```
+/// use std::thread;
+///
+/// use rgb2yuv420::{ChannelOrder, Converter, Pipeline, RgbFrame};
+///
+/// let pipeline = Pipeline::new(Converter::builder().build(), 4, 8);
+/// let frames = pipeline.sender();
+/// thread::spawn(move || {
+///     for i in 0..16u8 {
+///         frames.send(RgbFrame::new(vec![i; 4 * 2 * 3], 4, 2, ChannelOrder::Rgb).unwrap()).unwrap();
+///     }
+/// });
+/// let converted = pipeline.finish();
+/// let lumas: Vec<u8> = converted.iter().map(|frame| frame.y()[0]).collect();
+/// assert_eq!(lumas, (0..16).collect::<Vec<u8>>());
+/// ```
+#[cfg(feature = "crossbeam")]
+#[derive(Debug)]
+pub struct Pipeline {
+    frames: Sender<RgbFrame>,
+    converted: Receiver<YuvFrame>,
+}
+
+#[cfg(feature = "crossbeam")]
+impl Pipeline {
+    /// Starts `workers` (at least 1, and at most `max_threads`) threads converting with
+    /// `converter`
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - number of frames that can wait for a worker, and for the consumer,
+    ///   before sending blocks (0 hands every frame over directly)
+    pub fn new(converter: Converter, workers: usize, capacity: usize) -> Pipeline {
+        let (frames, input) = crossbeam_channel::bounded(capacity);
+        let (results, unordered) = crossbeam_channel::bounded::<(u64, YuvFrame)>(capacity);
+        let (ordered, converted) = crossbeam_channel::bounded(capacity);
+        let converter = Arc::new(converter);
+        // numbered while receiving, so the numbers follow the order the frames were sent in
+        let input = Arc::new(Mutex::new((0u64, input)));
+        for worker in 0..threads::limit(workers).max(1) {
+            let (converter, input, results) = (converter.clone(), input.clone(), results.clone());
+            thread::spawn(move || {
+                threads::pin_worker(worker);
+                loop {
+                    let (index, frame) = {
+                        let mut input = input.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                        let frame: RgbFrame = match input.1.recv() {
+                            Ok(frame) => frame,
+                            Err(RecvError) => return,
+                        };
+                        input.0 += 1;
+                        (input.0 - 1, frame)
+                    };
+                    let timing = FrameTiming { index: Some(index), ..FrameTiming::default() };
+                    if results.send((index, converter.convert_view(&frame.view()).with_timing(timing))).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(results);
+        thread::spawn(move || {
+            let mut next = 0;
+            let mut early = BTreeMap::new();
+            for (index, frame) in unordered {
+                early.insert(index, frame);
+                while let Some(frame) = early.remove(&next) {
+                    if ordered.send(frame).is_err() {
+                        return;
+                    }
+                    next += 1;
+                }
+            }
+        });
+        Pipeline { frames, converted }
+    }
+
+    /// Another handle for sending frames into the pipeline, eg.: for a capture thread
+    pub fn sender(&self) -> Sender<RgbFrame> {
+        self.frames.clone()
+    }
+
+    /// Another handle for receiving the converted frames, eg.: for an encoder thread
+    pub fn receiver(&self) -> Receiver<YuvFrame> {
+        self.converted.clone()
+    }
+
+    pub fn send(&self, frame: RgbFrame) -> Result<(), SendError<RgbFrame>> {
+        self.frames.send(frame)
+    }
+
+    pub fn recv(&self) -> Result<YuvFrame, RecvError> {
+        self.converted.recv()
+    }
+
+    /// Stops sending from this handle and returns the converted frames, which end once the
+    /// other senders are dropped too
+    pub fn finish(self) -> Receiver<YuvFrame> {
+        self.converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    #[cfg(feature = "crossbeam")]
+    use std::thread;
+
+    use super::{Decoded, StagesBuilder};
+    #[cfg(feature = "crossbeam")]
+    use super::Pipeline;
+    use config::Colorimetry;
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+    use frame::FrameTiming;
+    use geometry::{Rect, Rotation};
+    #[cfg(feature = "crossbeam")]
+    use view::RgbFrame;
+    use view::RgbView;
+
+    #[test]
+    fn stages_reuse_their_buffers() {
+        let converter = Converter::builder().colorimetry(Colorimetry::Bt709Limited).output(YuvFormat::Nv12)
+            .rotate(Rotation::Cw270).scale(4, 6);
+        let images: Vec<Vec<u8>> = (0..3u32).map(|i| (0..12 * 10 * 3).map(|j| (i * 11 + j * 7) as u8).collect())
+            .collect();
+        let mut written = Vec::new();
+        let mut stages = StagesBuilder::new().converter(converter.clone().rotate(Rotation::None))
+            .crop(Rect { x: 2, y: 1, width: 8, height: 6 }).rotate(Rotation::Cw270).build(|frame: &::YuvFrame| {
+                written.push((frame.data().as_ptr() as usize, frame.timing().index, frame.clone()));
+                Ok(())
+            });
+        for rgb in &images {
+            stages.push_decoded(|buffer: &mut Vec<u8>| {
+                assert!(buffer.is_empty());
+                buffer.extend_from_slice(rgb);
+                Ok(Decoded::new(12, 10, ChannelOrder::Bgr))
+            }).unwrap();
+        }
+        let timing = FrameTiming { index: Some(7), ..FrameTiming::default() };
+        let src = RgbView::new(&images[0], 12, 10, ChannelOrder::Bgr).unwrap();
+        stages.push(&src, timing).unwrap();
+        let err = stages.push(&src.crop(0, 0, 9, 6).unwrap(), timing).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let failed = stages.push_decoded(|_: &mut Vec<u8>| Ok(Decoded::new(2, 2, ChannelOrder::Rgb))).unwrap_err();
+        assert_eq!(failed.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(stages.frames(), 4);
+        assert!(stages.finish().is_ok());
+        let converter = converter.build();
+        assert_eq!(written.iter().map(|frame| frame.1).collect::<Vec<_>>(), [Some(0), Some(1), Some(2), Some(7)]);
+        for ((buffer, _, frame), rgb) in written.iter().zip(images.iter().chain(&images[..1])) {
+            assert_eq!(*buffer, written[0].0);
+            let src = RgbView::new(rgb, 12, 10, ChannelOrder::Bgr).unwrap().crop(2, 1, 8, 6).unwrap();
+            assert_eq!(*frame, converter.convert_view(&src));
+        }
+    }
+
+    #[cfg(feature = "crossbeam")]
+    #[test]
+    fn frames_come_out_in_order() {
+        let pipeline = Pipeline::new(Converter::builder().output(YuvFormat::Nv12).build(), 3, 2);
+        let images: Vec<Vec<u8>> = (0..32u32).map(|i| (0..10 * 6 * 3).map(|j| (i * 7 + j) as u8).collect()).collect();
+        let frames = pipeline.sender();
+        let sent = images.clone();
+        let producer = thread::spawn(move || {
+            for rgb in sent {
+                frames.send(RgbFrame::new(rgb, 10, 6, ChannelOrder::Rgb).unwrap()).unwrap();
+            }
+        });
+        let converted: Vec<_> = pipeline.finish().iter().collect();
+        producer.join().unwrap();
+        assert_eq!(converted.len(), images.len());
+        for (i, (rgb, yuv)) in images.iter().zip(converted).enumerate() {
+            assert_eq!(yuv.timing().index, Some(i as u64));
+            assert_eq!(yuv.into_vec(), ::convert_rgb_to_yuv420sp_nv12(rgb, 10, 6, 3));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use buffer::OutputBuffer;
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use frame::{Pitch, YuvFrame};
+use geometry::Geometry;
+use kernel::{Planes, Setup, Source};
+use layout;
+
+/// A converter fixed to one frame size
+///
+/// The coefficients, the plane layout, the split of the frame across threads and the kernel
+/// for the input layout are all chosen when the plan is made, so converting a frame only
+/// checks the length of the input. Like `Converter` it is `Send + Sync` and keeps no state
+/// between frames.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, Converter, YuvFormat};
+///
+/// let plan = Converter::builder().input(ChannelOrder::Bgra).output(YuvFormat::Nv12).plan(4, 2);
+/// let mut yuv = vec![0u8; plan.frame_size()];
+/// for frame in vec![vec![0u8; 4 * 2 * 4]; 3] {
+///     plan.convert_into(&frame, &mut yuv).unwrap();
+/// }
+/// assert_eq!(&yuv[8..], &[128; 4][..]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Plan {
+    input: ChannelOrder,
+    output: YuvFormat,
+    config: ConversionConfig,
+    pitch: Pitch,
+    geometry: Geometry,
+    width: u32,
+    height: u32,
+    setup: Setup,
+}
+
+impl Plan {
+    pub(crate) fn new(input: ChannelOrder, output: YuvFormat, config: ConversionConfig, pitch: Pitch,
+                      geometry: Geometry, width: u32, height: u32) -> Plan {
+        let setup = Setup::new(width as usize, height as usize, input.layout(), false, output, &config);
+        Plan { input, output, config, pitch, geometry, width, height, setup }
+    }
+
+    /// Converts a tightly packed image of the planned size
+    ///
+    /// # Return
+    ///
+    /// The frame in the configured output format and layout, or `BufferTooSmall` if `img`
+    /// is too short
+    pub fn convert(&self, img: &[u8]) -> Result<YuvFrame, ConversionError> {
+        let src = Source::packed(img, self.width, self.height, self.input.layout())?;
+        let (width, height) = self.output_size();
+        let (padded_width, padded_height) = self.pitch.padded(width, height);
+        let mut frame = YuvFrame::pitched(self.output, padded_width, padded_height, &self.config, self.pitch);
+        self.run(&src, frame.kernel_planes());
+        frame.finish(width, height, self.pitch.alpha_filter);
+        Ok(frame)
+    }
+
+    /// Converts a tightly packed image of the planned size into `dst`, tightly packed too
+    ///
+    /// # Return
+    ///
+    /// The number of bytes written, or `BufferTooSmall` if `img` or `dst` is too short
+    pub fn convert_into<B: OutputBuffer + ?Sized>(&self, img: &[u8], dst: &mut B) -> Result<usize, ConversionError> {
+        let src = Source::packed(img, self.width, self.height, self.input.layout())?;
+        let len = self.frame_size();
+        let (width, height) = self.output_size();
+        self.run(&src, Planes::packed(dst.prepare(len)?, self.output, width as usize, height as usize));
+        Ok(len)
+    }
+
+    /// Size of the converted frames in bytes
+    pub fn frame_size(&self) -> usize {
+        let (width, height) = self.output_size();
+        layout::required_buffer_size(self.output, width, height)
+    }
+
+    // size of the converted pictures, without the padding of `pad_to`
+    fn output_size(&self) -> (u32, u32) {
+        let (width, height) = self.geometry.output_size(self.width as usize, self.height as usize);
+        (width as u32, height as u32)
+    }
+
+    fn run(&self, src: &Source, dst: Planes) {
+        if self.geometry.is_identity(src) {
+            self.setup.run_planes(src, dst);
+        } else {
+            self.geometry.run(src, self.output, &self.config, dst);
+        }
+    }
+
+    pub fn input(&self) -> ChannelOrder {
+        self.input
+    }
+
+    pub fn output(&self) -> YuvFormat {
+        self.output
+    }
+
+    pub fn config(&self) -> &ConversionConfig {
+        &self.config
+    }
+
+    /// Width of the images the plan converts
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use config::ChromaFilter;
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+
+    #[test]
+    fn matches_the_converter() {
+        let rgba: Vec<u8> = (0..7 * 5 * 4).map(|i| (i * 53 % 256) as u8).collect();
+        let builder = Converter::builder().input(ChannelOrder::Rgba).output(YuvFormat::I420)
+            .chroma_filter(ChromaFilter::Average).threads(3);
+        let plan = builder.clone().plan(7, 5);
+        assert_eq!(plan.convert(&rgba).unwrap(), builder.build().convert(&rgba, 7, 5).unwrap());
+        assert!(plan.convert(&rgba[4..]).is_err());
+    }
+
+    #[test]
+    fn one_plan_serves_many_threads() {
+        let plan = &Converter::builder().plan(4, 4);
+        let frames: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i * 60; 4 * 4 * 3]).collect();
+        let converted: Vec<Vec<u8>> = thread::scope(|s| {
+            let jobs: Vec<_> = frames.iter().map(|rgb| s.spawn(move || plan.convert(rgb).unwrap().into_vec())).collect();
+            jobs.into_iter().map(|job| job.join().unwrap()).collect()
+        });
+        for (rgb, yuv) in frames.iter().zip(converted) {
+            assert_eq!(yuv, ::convert_rgb_to_yuv420p(rgb, 4, 4, 3));
+        }
+    }
+}
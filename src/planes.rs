@@ -0,0 +1,328 @@
+//! Destinations with caller provided planes, such as mapped DMA-BUFs
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::YuvFormat;
+use kernel::{self, ChromaPlanes, Planes, Setup, Strides};
+use view::RgbView;
+
+/// Where a plane starts in a mapped buffer and how far apart its rows are, in bytes
+///
+/// These are the `offsets` and `pitches` of a DRM framebuffer or the `data_offset` and
+/// `bytesperline` of a V4L2 plane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlaneInfo {
+    pub offset: usize,
+    pub stride: usize,
+}
+
+/// A plane in caller provided memory, eg.: a mapped Vulkan or D3D staging buffer, with the
+/// starts of its rows `stride` bytes apart
+#[derive(Debug)]
+pub struct PlaneMut<'a> {
+    pub data: &'a mut [u8],
+    pub stride: usize,
+}
+
+/// A YUV420 frame in caller provided memory, with a row stride per plane
+///
+/// The padding at the end of the rows is never written to.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, FrameMut, PlaneInfo, RgbView, YuvFormat};
+///
+/// // an NV12 dmabuf with 8 byte pitches and the chroma plane at offset 32
+/// let mut mapping = [0u8; 48];
+/// let planes = [PlaneInfo { offset: 0, stride: 8 }, PlaneInfo { offset: 32, stride: 8 }];
+/// let mut frame = FrameMut::from_mapping(&mut mapping, YuvFormat::Nv12, 6, 4, &planes).unwrap();
+/// let rgb = [255u8; 6 * 4 * 3];
+/// let src = RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap();
+/// rgb2yuv420::convert_into_frame(&src, &ConversionConfig::default(), &mut frame).unwrap();
+/// assert_eq!(&mapping[..8], &[255, 255, 255, 255, 255, 255, 0, 0]);
+/// assert_eq!(&mapping[40..48], &[128, 128, 128, 128, 128, 128, 0, 0]);
+/// ```
+#[derive(Debug)]
+pub struct FrameMut<'a> {
+    width: u32,
+    height: u32,
+    y: &'a mut [u8],
+    chroma: FrameChroma<'a>,
+    strides: Strides,
+}
+
+#[derive(Debug)]
+enum FrameChroma<'a> {
+    Planar(&'a mut [u8], &'a mut [u8]),
+    SemiPlanar(&'a mut [u8]),
+}
+
+impl<'a> FrameMut<'a> {
+    /// An I420 frame in three planes; U and V share `chroma_stride`
+    ///
+    /// # Errors
+    ///
+    /// `StrideMismatch` if a stride is smaller than a row of its plane and `BufferTooSmall`
+    /// if a plane can't hold all of its rows
+    pub fn i420(width: u32, height: u32, y: &'a mut [u8], y_stride: usize, u: &'a mut [u8], v: &'a mut [u8],
+                chroma_stride: usize) -> Result<FrameMut<'a>, ConversionError> {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        check_plane(y.len(), y_stride, width as usize, height as usize)?;
+        check_plane(u.len(), chroma_stride, chroma_width, chroma_height)?;
+        check_plane(v.len(), chroma_stride, chroma_width, chroma_height)?;
+        Ok(FrameMut {
+            width,
+            height,
+            y,
+            chroma: FrameChroma::Planar(u, v),
+            strides: Strides { y: y_stride, chroma: chroma_stride },
+        })
+    }
+
+    /// An NV12 frame in two planes
+    ///
+    /// # Errors
+    ///
+    /// See `FrameMut::i420`
+    pub fn nv12(width: u32, height: u32, y: &'a mut [u8], y_stride: usize, uv: &'a mut [u8],
+                uv_stride: usize) -> Result<FrameMut<'a>, ConversionError> {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        check_plane(y.len(), y_stride, width as usize, height as usize)?;
+        check_plane(uv.len(), uv_stride, 2 * chroma_width, chroma_height)?;
+        Ok(FrameMut {
+            width,
+            height,
+            y,
+            chroma: FrameChroma::SemiPlanar(uv),
+            strides: Strides { y: y_stride, chroma: uv_stride },
+        })
+    }
+
+    /// A frame whose planes all live in one mapped buffer (eg.: a single DMA-BUF fd)
+    ///
+    /// # Arguments
+    ///
+    /// * `planes` - Y, U and V for I420 or Y and UV for NV12; the U and V strides must match
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` if the number of planes doesn't match `format`, the planes overlap
+    /// or the chroma strides differ, and the errors of `FrameMut::i420`
+    pub fn from_mapping(mapping: &'a mut [u8], format: YuvFormat, width: u32, height: u32, planes: &[PlaneInfo])
+                        -> Result<FrameMut<'a>, ConversionError> {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        let expected = match format {
+            YuvFormat::I420 => 3,
+            YuvFormat::Nv12 => 2,
+        };
+        if planes.len() != expected {
+            return Err(ConversionError::UnsupportedFormat("wrong number of planes for the format"));
+        }
+        let rows = |plane: usize| match (plane, format) {
+            (0, _) => (width as usize, height as usize),
+            (_, YuvFormat::I420) => (chroma_width, chroma_height),
+            (_, YuvFormat::Nv12) => (2 * chroma_width, chroma_height),
+        };
+        // carve the planes out of the mapping in the order they appear in it
+        let mut order = [0, 1, 2];
+        let order = &mut order[..expected];
+        order.sort_unstable_by_key(|&plane| planes[plane].offset);
+        let mut slices: [Option<&'a mut [u8]>; 3] = [None, None, None];
+        let len = mapping.len();
+        let (mut rest, mut start) = (mapping, 0);
+        for &plane in order.iter() {
+            let PlaneInfo { offset, stride } = planes[plane];
+            let (row_bytes, rows) = rows(plane);
+            if offset < start {
+                return Err(ConversionError::UnsupportedFormat("the planes of the mapping overlap"));
+            }
+            let skip = (offset - start).min(rest.len());
+            let extent = check_plane(rest.len() - skip, stride, row_bytes, rows).map_err(|err| match err {
+                ConversionError::BufferTooSmall { expected, .. } =>
+                    ConversionError::BufferTooSmall { expected: offset + expected, got: len },
+                err => err,
+            })?;
+            let (plane_bytes, tail) = rest[skip..].split_at_mut(extent);
+            slices[plane] = Some(plane_bytes);
+            rest = tail;
+            start = offset + extent;
+        }
+        let [y, chroma, v] = slices;
+        let (y, chroma) = (y.unwrap_or_default(), chroma.unwrap_or_default());
+        match format {
+            YuvFormat::I420 => {
+                if planes[1].stride != planes[2].stride {
+                    return Err(ConversionError::UnsupportedFormat("the U and V planes need the same stride"));
+                }
+                FrameMut::i420(width, height, y, planes[0].stride, chroma, v.unwrap_or_default(), planes[1].stride)
+            }
+            YuvFormat::Nv12 => FrameMut::nv12(width, height, y, planes[0].stride, chroma, planes[1].stride),
+        }
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        match self.chroma {
+            FrameChroma::Planar(..) => YuvFormat::I420,
+            FrameChroma::SemiPlanar(_) => YuvFormat::Nv12,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub(crate) fn planes(&mut self) -> Planes<'_> {
+        let chroma = match self.chroma {
+            FrameChroma::Planar(ref mut u, ref mut v) => ChromaPlanes::Planar(u, v),
+            FrameChroma::SemiPlanar(ref mut uv) => ChromaPlanes::SemiPlanar(uv),
+        };
+        Planes { y: self.y, chroma, strides: self.strides, alpha: None, stats: None }
+    }
+}
+
+/// Converts an image into the planes of `dst` without allocating (unless the frame is split
+/// across threads)
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` if `src` and `dst` differ in size
+pub fn convert_into_frame(src: &RgbView, cfg: &ConversionConfig, dst: &mut FrameMut) -> Result<(), ConversionError> {
+    if (src.width(), src.height()) != (dst.width, dst.height) {
+        return Err(ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() });
+    }
+    let src = src.source();
+    Setup::new(src.width, src.height, src.layout, src.wide, dst.format(), cfg).run_planes(src, dst.planes());
+    Ok(())
+}
+
+/// Converts an image into `dst_planes` like `convert_into_frame`, each plane with its own
+/// stride: Y, U and V for I420 or Y and UV for NV12
+///
+/// The row pitch of staging memory is often rounded up to 256 bytes or more, and the chroma
+/// planes are rounded on their own, so their pitch isn't half the luma pitch.
+///
+/// # Errors
+///
+/// `UnsupportedFormat` if there are neither 2 nor 3 planes or the U and V strides differ,
+/// and the errors of `FrameMut::i420` and `convert_into_frame`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, PlaneMut, RgbView};
+///
+/// // NV12 in staging memory with rows pitched to 256 bytes
+/// let (mut y, mut uv) = (vec![0u8; 256 * 4], vec![0u8; 256 * 2]);
+/// let rgb = [255u8; 6 * 4 * 3];
+/// let src = RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap();
+/// let mut planes = [PlaneMut { data: &mut y, stride: 256 }, PlaneMut { data: &mut uv, stride: 256 }];
+/// rgb2yuv420::convert_into_strided(&src, &ConversionConfig::default(), &mut planes).unwrap();
+/// assert_eq!(&y[256..256 + 7], &[255, 255, 255, 255, 255, 255, 0]);
+/// assert_eq!(&uv[256..256 + 7], &[128, 128, 128, 128, 128, 128, 0]);
+/// ```
+pub fn convert_into_strided(src: &RgbView, cfg: &ConversionConfig, dst_planes: &mut [PlaneMut])
+                            -> Result<(), ConversionError> {
+    let (width, height) = (src.width(), src.height());
+    let mut frame = match *dst_planes {
+        [ref mut y, ref mut u, ref mut v] => {
+            if u.stride != v.stride {
+                return Err(ConversionError::UnsupportedFormat("the U and V planes need the same stride"));
+            }
+            FrameMut::i420(width, height, y.data, y.stride, u.data, v.data, u.stride)?
+        }
+        [ref mut y, ref mut uv] => FrameMut::nv12(width, height, y.data, y.stride, uv.data, uv.stride)?,
+        _ => return Err(ConversionError::UnsupportedFormat("wrong number of planes for the format")),
+    };
+    convert_into_frame(src, cfg, &mut frame)
+}
+
+// returns the bytes the rows span
+pub(crate) fn check_plane(len: usize, stride: usize, row_bytes: usize, rows: usize) -> Result<usize, ConversionError> {
+    if stride < row_bytes {
+        return Err(ConversionError::StrideMismatch { stride, row_bytes });
+    }
+    // a span overflowing `usize` fits in no buffer
+    let expected = match rows {
+        0 => 0,
+        _ => (rows - 1).checked_mul(stride).and_then(|start| start.checked_add(row_bytes)).unwrap_or(usize::MAX),
+    };
+    if len < expected {
+        return Err(ConversionError::BufferTooSmall { expected, got: len });
+    }
+    Ok(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_into_frame, convert_into_strided, FrameMut, PlaneInfo, PlaneMut};
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn pitched_planes_match_packed_rows() {
+        let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 41 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 7, 5, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig { threads: 2, ..ConversionConfig::default() };
+        let packed = ::convert_rgb_to_yuv420p(&rgb, 7, 5, 3);
+        // U before Y, V last, each plane pitched to 16 bytes
+        let planes = [PlaneInfo { offset: 64, stride: 16 }, PlaneInfo { offset: 0, stride: 16 },
+                      PlaneInfo { offset: 160, stride: 16 }];
+        let mut mapping = vec![0xaa; 160 + 2 * 16 + 4];
+        {
+            let mut frame = FrameMut::from_mapping(&mut mapping, YuvFormat::I420, 7, 5, &planes).unwrap();
+            convert_into_frame(&src, &cfg, &mut frame).unwrap();
+        }
+        let rows = |offset: usize, width: usize, rows: usize| -> Vec<u8> {
+            (0..rows).flat_map(|row| mapping[offset + row * 16..][..width].to_vec()).collect()
+        };
+        assert_eq!(rows(64, 7, 5), &packed[..35]);
+        assert_eq!(rows(0, 4, 3), &packed[35..47]);
+        assert_eq!(rows(160, 4, 3), &packed[47..]);
+        assert!(mapping[64 + 7..64 + 16].iter().all(|&b| b == 0xaa));
+    }
+
+    #[test]
+    fn invalid_mappings_are_rejected() {
+        let mut mapping = [0u8; 64];
+        let nv12 = |planes: &[PlaneInfo], mapping: &mut [u8]| {
+            FrameMut::from_mapping(mapping, YuvFormat::Nv12, 4, 4, planes).map(|_| ())
+        };
+        let y = PlaneInfo { offset: 0, stride: 8 };
+        assert_eq!(nv12(&[y, PlaneInfo { offset: 16, stride: 8 }], &mut mapping),
+                   Err(ConversionError::UnsupportedFormat("the planes of the mapping overlap")));
+        assert_eq!(nv12(&[y, PlaneInfo { offset: 32, stride: 2 }], &mut mapping),
+                   Err(ConversionError::StrideMismatch { stride: 2, row_bytes: 4 }));
+        assert_eq!(nv12(&[y, PlaneInfo { offset: 60, stride: 8 }], &mut mapping),
+                   Err(ConversionError::BufferTooSmall { expected: 72, got: 64 }));
+        assert_eq!(nv12(&[y], &mut mapping),
+                   Err(ConversionError::UnsupportedFormat("wrong number of planes for the format")));
+    }
+
+    #[test]
+    fn strided_planes_match_packed_rows() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 43 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let packed = ::convert_rgb_to_yuv420p(&rgb, 5, 3, 3);
+        let (mut y, mut u, mut v) = (vec![0; 2 * 8 + 5], vec![0; 4 + 3], vec![0; 4 + 3]);
+        {
+            let mut planes = [PlaneMut { data: &mut y, stride: 8 }, PlaneMut { data: &mut u, stride: 4 },
+                              PlaneMut { data: &mut v, stride: 4 }];
+            convert_into_strided(&src, &cfg, &mut planes).unwrap();
+            planes[2].stride = 3;
+            assert_eq!(convert_into_strided(&src, &cfg, &mut planes),
+                       Err(ConversionError::UnsupportedFormat("the U and V planes need the same stride")));
+        }
+        let rows = |plane: &[u8], stride: usize, width: usize| -> Vec<u8> {
+            plane.chunks(stride).flat_map(|row| row[..width].to_vec()).collect()
+        };
+        assert_eq!([rows(&y, 8, 5), rows(&u, 4, 3), rows(&v, 4, 3)].concat(), packed);
+    }
+}
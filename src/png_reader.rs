@@ -0,0 +1,150 @@
+//! PNG decoding straight into YUV420 frames (`png` feature)
+
+use std::io::Read;
+
+use png::{ColorType, Decoder, DecodingError, HasParameters, Transformations};
+
+use config::ConversionConfig;
+use format::{ChannelOrder, YuvFormat};
+use frame::YuvFrame;
+use kernel::ChromaPlanes;
+use layout;
+use planes::{self, FrameMut};
+use view::RgbView;
+
+// rows decoded before they are converted; even, so every strip starts on a chroma row
+const STRIP_ROWS: usize = 16;
+
+// the channels of the PNG's pixels, which of them each channel of the pixels converted
+// takes (gray is spread over R, G and B) and the pixels' channel order
+fn channels(color_type: ColorType) -> (usize, &'static [usize], ChannelOrder) {
+    match color_type {
+        ColorType::Grayscale => (1, &[0, 0, 0], ChannelOrder::Rgb),
+        ColorType::GrayscaleAlpha => (2, &[0, 0, 0, 1], ChannelOrder::Rgba),
+        ColorType::RGB => (3, &[0, 1, 2], ChannelOrder::Rgb),
+        ColorType::RGBA => (4, &[0, 1, 2, 3], ChannelOrder::Rgba),
+        ColorType::Indexed => unreachable!("palettes are expanded while decoding"),
+    }
+}
+
+// copies a decoded row into `out`, spreading its channels by `map` and turning big endian
+// 16 bit samples into native endian ones
+fn expand_row(row: &[u8], out: &mut [u8], samples: usize, map: &[usize], wide: bool) {
+    let bytes = if wide { 2 } else { 1 };
+    for (out, px) in out.chunks_mut(map.len() * bytes).zip(row.chunks(samples * bytes)) {
+        for (out, &channel) in out.chunks_mut(bytes).zip(map) {
+            let sample = &px[channel * bytes..][..bytes];
+            if wide {
+                out.copy_from_slice(&u16::from_be_bytes([sample[0], sample[1]]).to_ne_bytes());
+            } else {
+                out[0] = sample[0];
+            }
+        }
+    }
+}
+
+/// Decodes the PNG in `input` and converts it with `cfg` into a `format` frame
+///
+/// The scanlines are converted as they are decoded, a few rows at a time, so the image is
+/// never held in RGB as a whole. Gray images are converted as RGB with equal channels,
+/// palettes and transparent colours are expanded, and 16 bit images keep their precision
+/// until they are converted like a 16 bit `RgbView`. Alpha is ignored, as it is for RGBA
+/// views.
+///
+/// # Errors
+///
+/// The error of reading or decoding `input`, or `DecodingError::Other` if the image is
+/// interlaced, as Adam7 passes can't be converted before the last one
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+///
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// let frame = rgb2yuv420::from_png_reader(File::open("pic/ferris.png").unwrap(), YuvFormat::Nv12,
+///                                         &ConversionConfig::default()).unwrap();
+/// assert_eq!((frame.width(), frame.height()), (1200, 800));
+/// ```
+pub fn from_png_reader<R: Read>(input: R, format: YuvFormat, cfg: &ConversionConfig)
+                                -> Result<YuvFrame, DecodingError> {
+    let mut decoder = Decoder::new(input);
+    // palettes, low bit depths and transparent colours become 8 bit channels, 16 bit ones stay
+    decoder.set(Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info()?;
+    if reader.info().interlaced {
+        return Err(DecodingError::Other("interlaced PNGs can't be converted row by row".into()));
+    }
+    let wide = reader.info().bit_depth as u8 == 16;
+    let (samples, map, order) = channels(info.color_type);
+    let (width, height) = (info.width, info.height as usize);
+    let stride = map.len() * if wide { 2 } else { 1 } * width as usize;
+    let mut strip = vec![0; STRIP_ROWS * stride];
+    let data = vec![0; layout::required_buffer_size(format, width, info.height)];
+    let mut frame = YuvFrame::from_packed(data, format, width, info.height, cfg);
+    {
+        let mut dst = frame.as_frame_mut();
+        let mut planes = dst.planes();
+        let (y_stride, chroma_stride) = (planes.strides.y, planes.strides.chroma);
+        for top in (0..height).step_by(STRIP_ROWS) {
+            let rows = STRIP_ROWS.min(height - top);
+            for out in strip.chunks_mut(stride).take(rows) {
+                let row = reader.next_row()?.ok_or_else(|| DecodingError::Format("the image data ends early".into()))?;
+                expand_row(row, out, samples, map, wide);
+            }
+            let src = RgbView::with_layout(&strip, width, rows as u32, stride, order, if wide { 16 } else { 8 })
+                .expect("the strip holds its rows");
+            let (y, chroma) = (&mut planes.y[top * y_stride..], top / 2 * chroma_stride);
+            let mut rows = match planes.chroma {
+                ChromaPlanes::Planar(ref mut u, ref mut v) => {
+                    FrameMut::i420(width, rows as u32, y, y_stride, &mut u[chroma..], &mut v[chroma..], chroma_stride)
+                }
+                ChromaPlanes::SemiPlanar(ref mut uv) => {
+                    FrameMut::nv12(width, rows as u32, y, y_stride, &mut uv[chroma..], chroma_stride)
+                }
+            }.expect("the planes of a frame hold all of its rows");
+            planes::convert_into_frame(&src, cfg, &mut rows).expect("the strip and its rows are the same size");
+        }
+    }
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use png::{BitDepth, ColorType, Decoder, Encoder, HasParameters};
+
+    use super::from_png_reader;
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn decoded_rows_convert_like_the_whole_image() {
+        let cfg = ConversionConfig::default();
+        let (info, mut reader) = Decoder::new(File::open("pic/ferris.png").unwrap()).read_info().unwrap();
+        let mut rgba = vec![0; info.buffer_size()];
+        reader.next_frame(&mut rgba).unwrap();
+        let src = RgbView::new(&rgba, info.width, info.height, ChannelOrder::Rgba).unwrap();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let frame = from_png_reader(File::open("pic/ferris.png").unwrap(), format, &cfg).unwrap();
+            assert_eq!(frame, ::convert(&src, format, &cfg));
+        }
+        // 16 bit gray with alpha, 5x37 so the last strip is short and odd
+        let gray: Vec<u16> = (0..5 * 37 * 2).map(|i| (i * 4099 % 65536) as u16).collect();
+        let mut png = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut png, 5, 37);
+            encoder.set(ColorType::GrayscaleAlpha).set(BitDepth::Sixteen);
+            let be: Vec<u8> = gray.iter().flat_map(|sample| sample.to_be_bytes()).collect();
+            encoder.write_header().unwrap().write_image_data(&be).unwrap();
+        }
+        let rgba: Vec<u8> = gray.chunks(2).flat_map(|px| [px[0], px[0], px[0], px[1]])
+            .flat_map(|sample| sample.to_ne_bytes()).collect();
+        let src = RgbView::with_layout(&rgba, 5, 37, 5 * 8, ChannelOrder::Rgba, 16).unwrap();
+        let frame = from_png_reader(&png[..], YuvFormat::I420, &cfg).unwrap();
+        assert_eq!(frame, ::convert(&src, YuvFormat::I420, &cfg));
+    }
+}
@@ -0,0 +1,83 @@
+//! Frames as `0RGB` pixels, as softbuffer and other software framebuffers take them
+//! (`preview` feature)
+
+use alloc::vec::Vec;
+
+use config::ChromaUpsampling;
+use error::ConversionError;
+use format::ChannelOrder;
+use frame::YuvFrame;
+
+impl YuvFrame {
+    /// Converts the frame back into tightly packed `0x00RRGGBB` pixels, in the matrix and
+    /// range of the frame and spreading the chroma with `upsampling`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChromaUpsampling, Converter};
+    ///
+    /// let frame = Converter::builder().build().convert(&[255u8; 2 * 2 * 3], 2, 2).unwrap();
+    /// assert_eq!(frame.to_0rgb(ChromaUpsampling::Nearest), [0x00ff_ffff; 4]);
+    /// ```
+    pub fn to_0rgb(&self, upsampling: ChromaUpsampling) -> Vec<u32> {
+        self.to_rgb(ChannelOrder::Rgb, upsampling).chunks(3)
+            .map(|px| u32::from(px[0]) << 16 | u32::from(px[1]) << 8 | u32::from(px[2])).collect()
+    }
+
+    /// Converts the frame back into the top left corner of a framebuffer of `0RGB` pixels in
+    /// rows of `dst_width`, eg.: the buffer of a softbuffer surface of a window at least as
+    /// large as the frame; the rest of `dst` is left alone
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if `dst_width` is less than the width of the frame and
+    /// `BufferTooSmall` if `dst` has fewer rows than the frame
+    pub fn fill_0rgb(&self, upsampling: ChromaUpsampling, dst: &mut [u32], dst_width: u32)
+                     -> Result<(), ConversionError> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        if (dst_width as usize) < width {
+            return Err(ConversionError::UnsupportedDimensions { width: dst_width, height: self.height() });
+        }
+        if height == 0 {
+            return Ok(());
+        }
+        let expected = (height - 1) * dst_width as usize + width;
+        if dst.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: dst.len() });
+        }
+        for (dst, row) in dst.chunks_mut(dst_width as usize).zip(self.to_0rgb(upsampling).chunks(width)) {
+            dst[..width].copy_from_slice(row);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{ChromaUpsampling, ConversionConfig};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn framebuffers_get_the_rgb_of_the_frame() {
+        let rgb: Vec<u8> = (0..3 * 2 * 3).map(|i| (i * 53 % 256) as u8).collect();
+        let frame = ::convert(&RgbView::new(&rgb, 3, 2, ChannelOrder::Rgb).unwrap(), YuvFormat::I420,
+                              &ConversionConfig::default());
+        let pixels = frame.to_0rgb(ChromaUpsampling::Bilinear);
+        let expected: Vec<u32> = frame.to_rgb(ChannelOrder::Bgra, ChromaUpsampling::Bilinear).chunks(4)
+            .map(|px| u32::from_le_bytes([px[0], px[1], px[2], 0])).collect();
+        assert_eq!(pixels, expected);
+        // a 4 pixel wide window, 3 rows high
+        let mut window = [7; 4 * 3];
+        frame.fill_0rgb(ChromaUpsampling::Bilinear, &mut window, 4).unwrap();
+        assert_eq!(window[..3], pixels[..3]);
+        assert_eq!(window[4..7], pixels[3..]);
+        assert_eq!((window[3], &window[7..]), (7, &[7; 5][..]));
+        assert_eq!(frame.fill_0rgb(ChromaUpsampling::Bilinear, &mut window[..6], 4),
+                   Err(ConversionError::BufferTooSmall { expected: 7, got: 6 }));
+        assert_eq!(frame.fill_0rgb(ChromaUpsampling::Bilinear, &mut window, 2),
+                   Err(ConversionError::UnsupportedDimensions { width: 2, height: 2 }));
+    }
+}
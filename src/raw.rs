@@ -0,0 +1,469 @@
+//! Raw .yuv files: frames of one size and format back to back, without any header, and
+//! raw RGB streams of the same kind, eg.: on a pipe
+
+use std::io::{self, Read, Write};
+
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{ChannelOrder, FrameDescriptor, YuvFormat};
+use frame::YuvFrame;
+use kernel;
+use layout;
+use split::YuvPlanes;
+use view::RgbView;
+
+// the `InvalidInput` error of a frame that isn't `width` x `height`
+pub(crate) fn check_size(frame: (u32, u32), width: u32, height: u32) -> io::Result<()> {
+    if frame != (width, height) {
+        let err = ConversionError::UnsupportedDimensions { width: frame.0, height: frame.1 };
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+    }
+    Ok(())
+}
+
+// reads from `input` until `data` is full or the input ends, returning the bytes read
+fn fill<R: Read>(input: &mut R, data: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < data.len() {
+        match input.read(&mut data[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(filled)
+}
+
+// whether frame `frame` was read in full, `false` if the input ended before it and an
+// `UnexpectedEof` error if within it
+fn frame_read(filled: usize, frame: u64, size: usize) -> io::Result<bool> {
+    if filled == 0 && size != 0 {
+        return Ok(false);
+    }
+    if filled < size {
+        let message = format!("the file ends {} bytes into frame {} of {} bytes", filled, frame, size);
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, message));
+    }
+    Ok(true)
+}
+
+/// Writes the planes of `frame` in `format` into `out`, tightly packed and repacking the
+/// chroma if the frame is in the other format; `row` holds a repacked chroma row
+pub(crate) fn write_planes<W: Write>(out: &mut W, frame: &YuvPlanes, format: YuvFormat, row: &mut Vec<u8>)
+                                     -> io::Result<()> {
+    for y in frame.y().rows() {
+        out.write_all(y)?;
+    }
+    match (frame.u(), frame.v(), frame.uv(), format) {
+        (Some(u), Some(v), _, YuvFormat::I420) => {
+            for chroma in u.rows().chain(v.rows()) {
+                out.write_all(chroma)?;
+            }
+        }
+        (Some(u), Some(v), _, YuvFormat::Nv12) => {
+            for (u, v) in u.rows().zip(v.rows()) {
+                row.clear();
+                row.extend(u.iter().zip(v).flat_map(|(&u, &v)| [u, v]));
+                out.write_all(row)?;
+            }
+        }
+        (_, _, Some(uv), YuvFormat::I420) => {
+            for sample in 0..2 {
+                for uv in uv.rows() {
+                    row.clear();
+                    row.extend(uv.iter().skip(sample).step_by(2));
+                    out.write_all(row)?;
+                }
+            }
+        }
+        (_, _, Some(uv), YuvFormat::Nv12) => {
+            for uv in uv.rows() {
+                out.write_all(uv)?;
+            }
+        }
+        _ => unreachable!("frames have either U and V or UV planes"),
+    }
+    Ok(())
+}
+
+/// Appends frames of one size to a raw .yuv file in one format
+///
+/// Every frame is written tightly packed in the writer's format: Y, then U and V (I420) or
+/// UV (NV12), without the padding of the rows, whatever the format and strides of the frame
+/// it was given. Frame `i` starts at `i * frame_size()` bytes.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat, YuvPlanes, YuvWriter};
+///
+/// let mut writer = YuvWriter::new(Vec::new(), YuvFormat::I420, 2, 2);
+/// let rgb = [0u8; 2 * 2 * 3];
+/// writer.write_rgb(&RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap(), &ConversionConfig::default()).unwrap();
+/// // an NV12 frame, written as I420
+/// let nv12 = [1, 2, 3, 4, 10, 20];
+/// writer.write_frame(&YuvPlanes::split(&nv12, YuvFormat::Nv12, 2, 2).unwrap()).unwrap();
+/// assert_eq!(writer.frames(), 2);
+/// assert_eq!(writer.into_inner(), [0, 0, 0, 0, 128, 128, 1, 2, 3, 4, 10, 20]);
+/// ```
+#[derive(Debug)]
+pub struct YuvWriter<W: Write> {
+    out: W,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    frames: u64,
+    // a chroma row of a frame in the other format, repacked
+    row: Vec<u8>,
+}
+
+impl<W: Write> YuvWriter<W> {
+    /// A writer of `width` x `height` frames in `format` into `out`
+    pub fn new(out: W, format: YuvFormat, width: u32, height: u32) -> YuvWriter<W> {
+        YuvWriter { out, format, width, height, frames: 0, row: Vec::new() }
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        self.format
+    }
+
+    /// Size of one frame in bytes
+    pub fn frame_size(&self) -> usize {
+        layout::required_buffer_size(self.format, self.width, self.height)
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Appends `frame`
+    ///
+    /// # Errors
+    ///
+    /// The error of writing, or an `InvalidInput` error holding `UnsupportedDimensions` if
+    /// `frame` isn't the size of the file's frames
+    pub fn write_frame(&mut self, frame: &YuvPlanes) -> io::Result<()> {
+        check_size((frame.width(), frame.height()), self.width, self.height)?;
+        write_planes(&mut self.out, frame, self.format, &mut self.row)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Converts `src` with `cfg` and appends it, streaming the rows into the file
+    ///
+    /// # Errors
+    ///
+    /// See `write_frame`
+    pub fn write_rgb(&mut self, src: &RgbView, cfg: &ConversionConfig) -> io::Result<()> {
+        check_size((src.width(), src.height()), self.width, self.height)?;
+        kernel::write(src.source(), self.format, cfg, &mut self.out)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Describes the file written so far, whose frames are in `matrix` and `range` (eg.: those
+    /// of a `Colorimetry`), as the file itself doesn't say
+    pub fn sidecar(&self, matrix: Matrix, range: Range) -> Sidecar {
+        let (y_stride, chroma_stride) = layout::plane_strides(self.format, self.width);
+        let frame = FrameDescriptor { format: self.format, width: self.width, height: self.height, y_stride,
+                                      chroma_stride, matrix, range };
+        Sidecar { frame, frames: self.frames }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// The destination of the frames, eg.: to finish the file they are written to
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// What a raw .yuv file holds, to be kept next to it (eg.: as `video.yuv.json`) so that it
+/// can be read back without knowing its size, format and colorimetry
+///
+/// With the `serde` feature it can be written in any format serde supports, eg.: JSON or TOML.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{Matrix, Range, YuvFormat, YuvPlanes, YuvWriter};
+///
+/// let mut writer = YuvWriter::new(Vec::new(), YuvFormat::Nv12, 2, 2);
+/// writer.write_frame(&YuvPlanes::split(&[16, 16, 16, 16, 128, 128], YuvFormat::Nv12, 2, 2).unwrap()).unwrap();
+/// let sidecar = writer.sidecar(Matrix::Bt709, Range::Limited);
+/// assert_eq!((sidecar.frame.y_stride, sidecar.frames), (2, 1));
+/// let file = writer.into_inner();
+/// let frame = sidecar.reader(&file[..]).unwrap().next().unwrap().unwrap();
+/// assert_eq!((frame.matrix(), frame.range()), (Matrix::Bt709, Range::Limited));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sidecar {
+    /// The layout and colorimetry of every frame
+    pub frame: FrameDescriptor,
+    /// Number of frames in the file
+    pub frames: u64,
+}
+
+impl Sidecar {
+    /// A reader of the file described, in `input`
+    ///
+    /// # Errors
+    ///
+    /// An `InvalidInput` error holding `StrideMismatch` if the rows of the frames are said to
+    /// be padded, which they never are in raw files
+    pub fn reader<R: Read>(&self, input: R) -> io::Result<YuvReader<R>> {
+        let FrameDescriptor { format, width, height, y_stride, chroma_stride, matrix, range } = self.frame;
+        let (y_row, chroma_row) = layout::plane_strides(format, width);
+        for &(stride, row_bytes) in &[(y_stride, y_row), (chroma_stride, chroma_row)] {
+            if stride != row_bytes {
+                let err = ConversionError::StrideMismatch { stride, row_bytes };
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+            }
+        }
+        let cfg = ConversionConfig { matrix, range, ..ConversionConfig::default() };
+        Ok(YuvReader::new(input, format, width, height, &cfg))
+    }
+}
+
+/// Reads the frames of a raw .yuv file of `width` x `height` frames in one format
+///
+/// Raw files don't say what they hold, so the size, format and colorimetry have to be
+/// known. A file that ends within a frame is an error rather than a shorter last frame,
+/// as it usually means one of them is wrong.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, YuvFormat, YuvReader};
+///
+/// let file = [16, 16, 16, 16, 128, 128, 235, 235, 235, 235, 128, 128];
+/// let mut reader = YuvReader::new(&file[..], YuvFormat::Nv12, 2, 2, &ConversionConfig::default());
+/// assert_eq!(reader.next().unwrap().unwrap().y(), &[16; 4]);
+/// assert_eq!(reader.next().unwrap().unwrap().y(), &[235; 4]);
+/// assert!(reader.next().is_none());
+/// // half a frame more
+/// let mut reader = YuvReader::new(&[0; 9][..], YuvFormat::I420, 2, 2, &ConversionConfig::default());
+/// assert!(reader.next().unwrap().is_ok());
+/// assert!(reader.next().unwrap().is_err());
+/// ```
+#[derive(Debug)]
+pub struct YuvReader<R: Read> {
+    input: R,
+    format: YuvFormat,
+    width: u32,
+    height: u32,
+    // the matrix and range of the frames
+    cfg: ConversionConfig,
+    frames: u64,
+}
+
+impl<R: Read> YuvReader<R> {
+    /// A reader of the frames in `input`, which get the matrix and range of `cfg`
+    pub fn new(input: R, format: YuvFormat, width: u32, height: u32, cfg: &ConversionConfig) -> YuvReader<R> {
+        let cfg = ConversionConfig { matrix: cfg.matrix, range: cfg.range, ..ConversionConfig::default() };
+        YuvReader { input, format, width, height, cfg, frames: 0 }
+    }
+
+    /// Size of one frame in bytes
+    pub fn frame_size(&self) -> usize {
+        layout::required_buffer_size(self.format, self.width, self.height)
+    }
+
+    /// Number of frames read so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // reads the next frame, `None` at the end of the file
+    fn read_frame(&mut self) -> io::Result<Option<YuvFrame>> {
+        let mut data = vec![0; self.frame_size()];
+        let filled = fill(&mut self.input, &mut data)?;
+        if !frame_read(filled, self.frames, data.len())? {
+            return Ok(None);
+        }
+        self.frames += 1;
+        Ok(Some(YuvFrame::from_packed(data, self.format, self.width, self.height, &self.cfg)))
+    }
+}
+
+/// Yields the frames up to the end of the file, or an `UnexpectedEof` error if it ends
+/// within a frame
+impl<R: Read> Iterator for YuvReader<R> {
+    type Item = io::Result<YuvFrame>;
+
+    fn next(&mut self) -> Option<io::Result<YuvFrame>> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Reads tightly packed 8 bit RGB frames of one size from a stream, eg.: the standard
+/// input of a program in a pipeline
+///
+/// The frames are read one at a time into a buffer of the reader, so however long the
+/// stream, only one frame is held. Like `YuvReader`, a stream that ends within a frame is
+/// an error.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io;
+///
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbReader, YuvFormat, YuvWriter};
+///
+/// // rgb frames in, I420 frames out
+/// let mut reader = RgbReader::new(io::stdin().lock(), 1280, 720, ChannelOrder::Rgb);
+/// let mut writer = YuvWriter::new(io::stdout().lock(), YuvFormat::I420, 1280, 720);
+/// while let Some(frame) = reader.next_frame()? {
+///     writer.write_rgb(&frame, &ConversionConfig::default())?;
+/// }
+/// writer.flush()?;
+/// # Ok::<(), io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct RgbReader<R: Read> {
+    input: R,
+    width: u32,
+    height: u32,
+    channel_order: ChannelOrder,
+    frames: u64,
+    // the last frame read
+    data: Vec<u8>,
+}
+
+impl<R: Read> RgbReader<R> {
+    /// A reader of `width` x `height` frames in `channel_order` from `input`
+    pub fn new(input: R, width: u32, height: u32, channel_order: ChannelOrder) -> RgbReader<R> {
+        let data = vec![0; width as usize * height as usize * channel_order.bytes_per_pixel()];
+        RgbReader { input, width, height, channel_order, frames: 0, data }
+    }
+
+    /// Size of one frame in bytes
+    pub fn frame_size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of frames read so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Reads the next frame, `None` at the end of the stream
+    ///
+    /// # Errors
+    ///
+    /// The error of reading, or `UnexpectedEof` if the stream ends within the frame
+    pub fn next_frame(&mut self) -> io::Result<Option<RgbView<'_>>> {
+        let filled = fill(&mut self.input, &mut self.data)?;
+        if !frame_read(filled, self.frames, self.data.len())? {
+            return Ok(None);
+        }
+        self.frames += 1;
+        let frame = RgbView::new(&self.data, self.width, self.height, self.channel_order);
+        Ok(Some(frame.expect("the frame is read in full")))
+    }
+
+    /// The stream of the frames, eg.: to read what follows them
+    pub fn into_inner(self) -> R {
+        self.input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::{RgbReader, YuvReader, YuvWriter};
+    use config::{ConversionConfig, Matrix, Range};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn frames_are_written_in_the_writers_format() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 13 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        // a pitched frame loses its padding
+        let pitched = ::Converter::builder().output(YuvFormat::I420).alignment(16).build().convert_view(&src);
+        for &(format, expected) in &[(YuvFormat::I420, &i420), (YuvFormat::Nv12, &nv12)] {
+            let mut writer = YuvWriter::new(Vec::new(), format, 5, 3);
+            for frame in &[&i420, &nv12, &pitched] {
+                writer.write_frame(&frame.as_planes()).unwrap();
+            }
+            writer.write_rgb(&src, &cfg).unwrap();
+            let err = writer.write_rgb(&src.crop(0, 0, 4, 3).unwrap(), &cfg).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert_eq!((writer.frames(), writer.frame_size()), (4, 27));
+            assert_eq!(writer.into_inner(), expected.data().repeat(4));
+        }
+    }
+    #[test]
+    fn written_files_read_back() {
+        let rgb: Vec<u8> = (0..6 * 2 * 3).map(|i| (i * 11 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 6, 2, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig { range: ::Range::Limited, ..ConversionConfig::default() };
+        let mut writer = YuvWriter::new(Vec::new(), YuvFormat::Nv12, 6, 2);
+        for _ in 0..3 {
+            writer.write_rgb(&src, &cfg).unwrap();
+        }
+        let file = writer.into_inner();
+        let frames: Vec<_> = YuvReader::new(&file[..], YuvFormat::Nv12, 6, 2, &cfg).collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames, vec![::convert(&src, YuvFormat::Nv12, &cfg); 3]);
+        let mut reader = YuvReader::new(&file[..file.len() - 5], YuvFormat::Nv12, 6, 2, &cfg);
+        assert_eq!(reader.by_ref().take(2).count(), 2);
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(err.to_string(), "the file ends 13 bytes into frame 2 of 18 bytes");
+        assert_eq!(reader.frames(), 2);
+    }
+
+    #[test]
+    fn rgb_streams_are_read_frame_by_frame() {
+        let rgb: Vec<u8> = (0..4 * 2 * 4 * 3).map(|i| (i * 17 % 256) as u8).collect();
+        let mut reader = RgbReader::new(&rgb[..], 4, 2, ChannelOrder::Bgra);
+        let cfg = ConversionConfig::default();
+        let mut writer = YuvWriter::new(Vec::new(), YuvFormat::Nv12, 4, 2);
+        while let Some(frame) = reader.next_frame().unwrap() {
+            writer.write_rgb(&frame, &cfg).unwrap();
+        }
+        assert_eq!((reader.frames(), reader.frame_size()), (3, 32));
+        let expected: Vec<u8> = rgb.chunks(32).flat_map(|frame| {
+            ::convert(&RgbView::new(frame, 4, 2, ChannelOrder::Bgra).unwrap(), YuvFormat::Nv12, &cfg).data().to_vec()
+        }).collect();
+        assert_eq!(writer.into_inner(), expected);
+        let mut reader = RgbReader::new(&rgb[..40], 4, 2, ChannelOrder::Bgra);
+        assert!(reader.next_frame().unwrap().is_some());
+        let err = reader.next_frame().unwrap_err();
+        assert_eq!(err.to_string(), "the file ends 8 bytes into frame 1 of 32 bytes");
+    }
+
+    #[test]
+    fn sidecars_describe_the_files_written() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 7 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let mut writer = YuvWriter::new(Vec::new(), YuvFormat::I420, 5, 3);
+        writer.write_rgb(&src, &cfg).unwrap();
+        writer.write_rgb(&src, &cfg).unwrap();
+        let sidecar = writer.sidecar(cfg.matrix, cfg.range);
+        assert_eq!((sidecar.frame.y_stride, sidecar.frame.chroma_stride, sidecar.frames), (5, 3, 2));
+        let file = writer.into_inner();
+        let frames: Vec<_> = sidecar.reader(&file[..]).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames, vec![::convert(&src, YuvFormat::I420, &cfg); 2]);
+        #[cfg(feature = "serde")]
+        {
+            extern crate serde_json;
+
+            let json = serde_json::to_string(&sidecar).unwrap();
+            assert_eq!(serde_json::from_str::<super::Sidecar>(&json).unwrap(), sidecar);
+        }
+        let mut padded = sidecar;
+        padded.frame.chroma_stride = 4;
+        assert_eq!(padded.reader(&file[..]).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,192 @@
+//! Shuffles between the chroma layouts of YUV420 frames, eg.: for feeding the output of a
+//! software decoder or an Android camera to a hardware encoder
+//!
+//! The luma plane is the same in every layout, so only the chroma is repacked; the Y plane
+//! can be shared or copied as is.
+
+use error::ConversionError;
+use kernel;
+use planes::check_plane;
+
+/// Interleaves the U and V planes of an I420 frame into the UV plane of an NV12 frame
+///
+/// The rows are zipped a sample at a time, which the compiler turns into vector shuffles.
+/// The padding at the end of the rows of `uv` is never written to.
+///
+/// # Arguments
+///
+/// * `width`, `height` - size of the frame, not of the chroma planes
+/// * `chroma_stride` - distance between two rows of `u` and of `v`
+///
+/// # Errors
+///
+/// `StrideMismatch` if a stride is smaller than a row of its plane and `BufferTooSmall` if a
+/// plane can't hold all of its rows
+///
+/// # Examples
+///
+/// ```
+/// // a 4x2 frame, the UV rows pitched to 6 bytes
+/// let (u, v) = ([1, 2], [3, 4]);
+/// let mut uv = [0; 6];
+/// rgb2yuv420::repack_i420_to_nv12(4, 2, &u, &v, 2, &mut uv, 6).unwrap();
+/// assert_eq!(uv, [1, 3, 2, 4, 0, 0]);
+/// ```
+pub fn repack_i420_to_nv12(width: u32, height: u32, u: &[u8], v: &[u8], chroma_stride: usize, uv: &mut [u8],
+                           uv_stride: usize) -> Result<(), ConversionError> {
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+    check_plane(u.len(), chroma_stride, chroma_width, chroma_height)?;
+    check_plane(v.len(), chroma_stride, chroma_width, chroma_height)?;
+    check_plane(uv.len(), uv_stride, 2 * chroma_width, chroma_height)?;
+    if chroma_width == 0 {
+        return Ok(());
+    }
+    let rows = u.chunks(chroma_stride).zip(v.chunks(chroma_stride)).zip(uv.chunks_mut(uv_stride));
+    for ((u, v), uv) in rows.take(chroma_height) {
+        for ((&u, &v), uv) in u[..chroma_width].iter().zip(&v[..chroma_width]).zip(uv.chunks_exact_mut(2)) {
+            uv[0] = u;
+            uv[1] = v;
+        }
+    }
+    Ok(())
+}
+
+/// Splits the UV plane of an NV12 frame into the U and V planes of an I420 frame
+///
+/// The inverse of `repack_i420_to_nv12`, with the same arguments and errors.
+///
+/// # Examples
+///
+/// ```
+/// let uv = [1, 3, 2, 4, 0, 0];
+/// let (mut u, mut v) = ([0; 2], [0; 2]);
+/// rgb2yuv420::repack_nv12_to_i420(4, 2, &uv, 6, &mut u, &mut v, 2).unwrap();
+/// assert_eq!((u, v), ([1, 2], [3, 4]));
+/// ```
+pub fn repack_nv12_to_i420(width: u32, height: u32, uv: &[u8], uv_stride: usize, u: &mut [u8], v: &mut [u8],
+                           chroma_stride: usize) -> Result<(), ConversionError> {
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+    check_plane(uv.len(), uv_stride, 2 * chroma_width, chroma_height)?;
+    check_plane(u.len(), chroma_stride, chroma_width, chroma_height)?;
+    check_plane(v.len(), chroma_stride, chroma_width, chroma_height)?;
+    if chroma_width == 0 {
+        return Ok(());
+    }
+    let rows = uv.chunks(uv_stride).zip(u.chunks_mut(chroma_stride).zip(v.chunks_mut(chroma_stride)));
+    for (uv, (u, v)) in rows.take(chroma_height) {
+        for (uv, (u, v)) in uv.chunks_exact(2).zip(u[..chroma_width].iter_mut().zip(&mut v[..chroma_width])) {
+            *u = uv[0];
+            *v = uv[1];
+        }
+    }
+    Ok(())
+}
+
+/// Swaps the samples of an interleaved chroma plane in place, turning NV12 into NV21 (the
+/// preview format of Android cameras) and back
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than a row of the plane and `BufferTooSmall` if
+/// `uv` can't hold all of its rows
+///
+/// # Examples
+///
+/// ```
+/// let mut vu = [3, 1, 4, 2];
+/// rgb2yuv420::swap_uv_in_place(4, 2, &mut vu, 4).unwrap();
+/// assert_eq!(vu, [1, 3, 2, 4]);
+/// ```
+pub fn swap_uv_in_place(width: u32, height: u32, uv: &mut [u8], stride: usize) -> Result<(), ConversionError> {
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+    check_plane(uv.len(), stride, 2 * chroma_width, chroma_height)?;
+    if chroma_width == 0 {
+        return Ok(());
+    }
+    for row in uv.chunks_mut(stride).take(chroma_height) {
+        for pair in row[..2 * chroma_width].chunks_exact_mut(2) {
+            pair.swap(0, 1);
+        }
+    }
+    Ok(())
+}
+
+/// Copies an interleaved chroma plane into `dst` with its samples swapped, the NV12 to NV21
+/// (or back) counterpart of `repack_i420_to_nv12`
+///
+/// # Errors
+///
+/// See `swap_uv_in_place`, for either plane
+///
+/// # Examples
+///
+/// ```
+/// let mut uv = [0; 6];
+/// rgb2yuv420::swap_uv(4, 2, &[3, 1, 4, 2], 4, &mut uv, 6).unwrap();
+/// assert_eq!(uv, [1, 3, 2, 4, 0, 0]);
+/// ```
+pub fn swap_uv(width: u32, height: u32, src: &[u8], src_stride: usize, dst: &mut [u8], dst_stride: usize)
+               -> Result<(), ConversionError> {
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+    check_plane(src.len(), src_stride, 2 * chroma_width, chroma_height)?;
+    check_plane(dst.len(), dst_stride, 2 * chroma_width, chroma_height)?;
+    if chroma_width == 0 {
+        return Ok(());
+    }
+    for (src, dst) in src.chunks(src_stride).zip(dst.chunks_mut(dst_stride)).take(chroma_height) {
+        for (src, dst) in src[..2 * chroma_width].chunks_exact(2).zip(dst.chunks_exact_mut(2)) {
+            dst[0] = src[1];
+            dst[1] = src[0];
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repack_i420_to_nv12, repack_nv12_to_i420, swap_uv, swap_uv_in_place};
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn repacked_chroma_matches_converted_frames() {
+        let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 29 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 7, 5, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        // UV rows pitched to 10 bytes, U and V rows to 6
+        let mut uv = vec![0xaa; 2 * 10 + 8];
+        repack_i420_to_nv12(7, 5, i420.u().unwrap(), i420.v().unwrap(), 4, &mut uv, 10).unwrap();
+        let rows: Vec<u8> = uv.chunks(10).flat_map(|row| row[..8].to_vec()).collect();
+        assert_eq!(rows, nv12.uv().unwrap());
+        assert!(uv[8..10].iter().all(|&b| b == 0xaa));
+        let (mut u, mut v) = (vec![0; 2 * 6 + 4], vec![0; 2 * 6 + 4]);
+        repack_nv12_to_i420(7, 5, &uv, 10, &mut u, &mut v, 6).unwrap();
+        let rows = |plane: &[u8]| -> Vec<u8> { plane.chunks(6).flat_map(|row| row[..4].to_vec()).collect() };
+        assert_eq!((rows(&u), rows(&v)), (i420.u().unwrap().to_vec(), i420.v().unwrap().to_vec()));
+        assert_eq!(repack_nv12_to_i420(7, 5, &uv, 10, &mut u, &mut v, 3),
+                   Err(ConversionError::StrideMismatch { stride: 3, row_bytes: 4 }));
+        assert_eq!(repack_i420_to_nv12(7, 5, &u, &v, 6, &mut uv[..27], 10),
+                   Err(ConversionError::BufferTooSmall { expected: 28, got: 27 }));
+    }
+
+    #[test]
+    fn swapping_twice_restores_the_plane() {
+        // a 5x5 frame: 3 chroma rows of 6 bytes, pitched to 8
+        let nv12: Vec<u8> = (0..2 * 8 + 6).map(|i| i as u8).collect();
+        let mut nv21 = vec![0xaa; nv12.len()];
+        swap_uv(5, 5, &nv12, 8, &mut nv21, 8).unwrap();
+        assert_eq!(&nv21[..8], &[1, 0, 3, 2, 5, 4, 0xaa, 0xaa]);
+        let mut restored = nv21.clone();
+        swap_uv_in_place(5, 5, &mut restored, 8).unwrap();
+        for (row, (restored, nv12)) in restored.chunks(8).zip(nv12.chunks(8)).enumerate() {
+            assert_eq!(restored[..6], nv12[..6], "row {}", row);
+        }
+        assert_eq!(swap_uv_in_place(5, 5, &mut restored[..21], 8),
+                   Err(ConversionError::BufferTooSmall { expected: 22, got: 21 }));
+        assert_eq!(swap_uv(5, 5, &nv12, 8, &mut nv21, 5),
+                   Err(ConversionError::StrideMismatch { stride: 5, row_bytes: 6 }));
+    }
+}
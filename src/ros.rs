@@ -0,0 +1,128 @@
+//! Conversions of the pixels of ROS `sensor_msgs/Image` messages, picked by their
+//! `encoding` (`ros` feature)
+
+use alloc::vec::Vec;
+
+use config::{ChromaUpsampling, ConversionConfig, Range};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use frame::YuvFrame;
+use planes::check_plane;
+use view::RgbView;
+
+const UNSUPPORTED: ConversionError = ConversionError::UnsupportedFormat("unsupported ROS image encoding");
+
+fn channel_order(encoding: &str) -> Option<ChannelOrder> {
+    match encoding {
+        "rgb8" => Some(ChannelOrder::Rgb),
+        "bgr8" => Some(ChannelOrder::Bgr),
+        "rgba8" => Some(ChannelOrder::Rgba),
+        "bgra8" => Some(ChannelOrder::Bgra),
+        _ => None,
+    }
+}
+
+/// Converts the `data` of an image message into a tightly packed `format` frame with `cfg`
+///
+/// `rgb8`, `bgr8`, `rgba8`, `bgra8` and `mono8` images are converted, whose rows are `step`
+/// bytes apart; `mono8` is converted as RGB with equal channels, and alpha is ignored.
+///
+/// # Errors
+///
+/// `UnsupportedFormat` for other encodings, `StrideMismatch` if `step` is smaller than a row
+/// and `BufferTooSmall` if `data` can't hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// // a white and a black pixel in rows of 4 bytes
+/// let frame = rgb2yuv420::from_ros_image(&[255, 255, 0, 0, 0, 0], "mono8", 2, 2, 4, YuvFormat::Nv12,
+///                                        &ConversionConfig::default()).unwrap();
+/// assert_eq!(frame.y(), [255, 255, 0, 0]);
+/// ```
+pub fn from_ros_image(data: &[u8], encoding: &str, width: u32, height: u32, step: usize, format: YuvFormat,
+                      cfg: &ConversionConfig) -> Result<YuvFrame, ConversionError> {
+    if encoding == "mono8" {
+        check_plane(data.len(), step, width as usize, height as usize)?;
+        // by index, as a step of 0 is fine for rows of 0 pixels
+        let rgb: Vec<u8> = (0..height as usize)
+            .flat_map(|row| data[row * step..][..width as usize].iter().flat_map(|&gray| [gray; 3]))
+            .collect();
+        return Ok(::convert(&RgbView::new(&rgb, width, height, ChannelOrder::Rgb)?, format, cfg));
+    }
+    let order = channel_order(encoding).ok_or(UNSUPPORTED)?;
+    Ok(::convert(&RgbView::with_layout(data, width, height, step, order, 8)?, format, cfg))
+}
+
+/// Converts `frame` back into the `data` of an image message in `encoding`, spreading the
+/// chroma with `upsampling`, and returns it with its `step`
+///
+/// The encodings of `from_ros_image` are supported; `mono8` is the luma, stretched to full
+/// range for limited range frames.
+///
+/// # Errors
+///
+/// `UnsupportedFormat` for other encodings
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChromaUpsampling, Converter};
+///
+/// let frame = Converter::builder().build().convert(&[255u8; 2 * 2 * 3], 2, 2).unwrap();
+/// let (data, step) = rgb2yuv420::to_ros_image(&frame, "bgra8", ChromaUpsampling::Nearest).unwrap();
+/// assert_eq!((data, step), (vec![255; 2 * 2 * 4], 8));
+/// ```
+pub fn to_ros_image(frame: &YuvFrame, encoding: &str, upsampling: ChromaUpsampling)
+                    -> Result<(Vec<u8>, u32), ConversionError> {
+    if encoding == "mono8" {
+        let mut gray = frame.luma();
+        if frame.range() == Range::Limited {
+            for sample in &mut gray {
+                *sample = ((u32::from((*sample).clamp(16, 235) - 16) * 255 + 109) / 219) as u8;
+            }
+        }
+        return Ok((gray, frame.width()));
+    }
+    let order = channel_order(encoding).ok_or(UNSUPPORTED)?;
+    Ok((frame.to_rgb(order, upsampling), frame.width() * order.bytes_per_pixel() as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_ros_image, to_ros_image};
+    use config::{ChromaUpsampling, ConversionConfig, Range};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn image_messages_convert_by_their_encoding() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        let rgb: Vec<u8> = (0..3 * 2 * 3).map(|i| (i * 43 % 256) as u8).collect();
+        let expected = ::convert(&RgbView::new(&rgb, 3, 2, ChannelOrder::Rgb).unwrap(), YuvFormat::I420, &cfg);
+        // BGR in rows of 12 bytes
+        let bgr: Vec<u8> = rgb.chunks(9).flat_map(|row| {
+            row.chunks(3).flat_map(|px| [px[2], px[1], px[0]]).chain([0; 3]).collect::<Vec<_>>()
+        }).collect();
+        let frame = from_ros_image(&bgr, "bgr8", 3, 2, 12, YuvFormat::I420, &cfg).unwrap();
+        assert_eq!(frame, expected);
+        let (data, step) = to_ros_image(&frame, "rgb8", ChromaUpsampling::Bilinear).unwrap();
+        assert_eq!((data, step), (frame.to_rgb(ChannelOrder::Rgb, ChromaUpsampling::Bilinear), 9));
+        let gray = [0, 60, 128, 255, 16, 200];
+        let frame = from_ros_image(&gray, "mono8", 3, 2, 3, YuvFormat::Nv12, &cfg).unwrap();
+        assert_eq!(frame.luma(), [16, 68, 126, 235, 30, 188]);
+        // limited range has fewer levels, so not every gray comes back
+        let (mono, step) = to_ros_image(&frame, "mono8", ChromaUpsampling::Nearest).unwrap();
+        assert_eq!((mono, step), (vec![0, 61, 128, 255, 16, 200], 3));
+        assert_eq!(from_ros_image(&gray, "yuv422", 3, 2, 3, YuvFormat::Nv12, &cfg), Err(super::UNSUPPORTED));
+        // empty rows may have no step
+        let empty = from_ros_image(&[], "mono8", 0, 2, 0, YuvFormat::Nv12, &cfg);
+        assert!(empty.is_ok());
+        assert_eq!(empty, from_ros_image(&[], "rgb8", 0, 2, 0, YuvFormat::Nv12, &cfg));
+        assert_eq!(to_ros_image(&frame, "16UC1", ChromaUpsampling::Nearest),
+                   Err(ConversionError::UnsupportedFormat("unsupported ROS image encoding")));
+    }
+}
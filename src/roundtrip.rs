@@ -0,0 +1,86 @@
+//! Measuring what a configuration loses by converting to YUV420 and back
+
+use alloc::vec::Vec;
+
+use config::ConversionConfig;
+use format::{ChannelOrder, YuvFormat};
+use inverse;
+use view::RgbView;
+
+/// How far the channels of an image moved in a round trip through YUV420, in 8 bit codes
+///
+/// The channels are in red, green, blue order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorStats {
+    pub max: [u8; 3],
+    pub mean: [f64; 3],
+}
+
+/// Converts `src` to I420 with `cfg` and back to RGB24 (`[r, g, b, r, g, b, ...]`) with the
+/// same matrix, range and chroma upsampling, and compares the result with `src`
+///
+/// 16 bit sources are compared after rounding them to 8 bits.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ChromaFilter, ChromaUpsampling, ConversionConfig, RgbView};
+///
+/// // a smooth gradient
+/// let rgb: Vec<u8> = (0..16 * 16).flat_map(|i| [(i % 16 * 8) as u8, (i / 16 * 8) as u8, 128]).collect();
+/// let src = RgbView::new(&rgb, 16, 16, ChannelOrder::Rgb).unwrap();
+/// let (back, point) = rgb2yuv420::roundtrip(&src, &ConversionConfig::default());
+/// assert_eq!(back.len(), rgb.len());
+/// // filtering the chroma both ways loses less of it
+/// let smooth = ConversionConfig { chroma_filter: ChromaFilter::Average, chroma_upsampling: ChromaUpsampling::Bilinear,
+///                                 ..ConversionConfig::default() };
+/// let smooth = rgb2yuv420::roundtrip(&src, &smooth).1;
+/// assert!(smooth.mean.iter().zip(&point.mean).all(|(smooth, point)| smooth < point));
+/// assert!(smooth.max[0] < point.max[0]);
+/// ```
+pub fn roundtrip(src: &RgbView, cfg: &ConversionConfig) -> (Vec<u8>, ErrorStats) {
+    let frame = ::convert(src, YuvFormat::I420, cfg);
+    let back = inverse::yuv_to_rgb(frame.data(), YuvFormat::I420, src.width(), src.height(), ChannelOrder::Rgb, cfg)
+        .expect("converted frames are tightly packed");
+    let source = src.source();
+    let (mut max, mut sum) = ([0; 3], [0u64; 3]);
+    let pixels = (0..source.height).flat_map(|row| source.rgb_row(row));
+    for (px, back) in pixels.zip(back.chunks_exact(3)) {
+        for c in 0..3 {
+            let error = px[c].abs_diff(back[c]);
+            max[c] = max[c].max(error);
+            sum[c] += u64::from(error);
+        }
+    }
+    let count = (source.width * source.height).max(1) as f64;
+    let mean = sum.map(|sum| sum as f64 / count);
+    (back, ErrorStats { max, mean })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::roundtrip;
+    use config::ConversionConfig;
+    use format::ChannelOrder;
+    use view::RgbView;
+
+    #[test]
+    fn errors_are_measured_per_channel() {
+        // gray survives exactly, a red and green checkerboard loses all of its chroma detail
+        let gray = [77u8; 4 * 2 * 3];
+        let (back, stats) = roundtrip(&RgbView::new(&gray, 4, 2, ChannelOrder::Rgb).unwrap(),
+                                      &ConversionConfig::default());
+        assert_eq!((back, stats.max, stats.mean), (gray.to_vec(), [0; 3], [0.0; 3]));
+        let checkers: Vec<u8> = (0..4 * 2).flat_map(|i| if (i + i / 4) % 2 == 0 { [0, 255, 0, 255] }
+                                                                              else { [0, 0, 255, 255] }).collect();
+        let src = RgbView::new(&checkers, 4, 2, ChannelOrder::Bgra).unwrap();
+        let (back, stats) = roundtrip(&src, &ConversionConfig::default());
+        let rgb: Vec<u8> = src.source().rgb_row(0).chain(src.source().rgb_row(1)).flatten().collect();
+        let expected_max: Vec<u8> = (0..3).map(|c| {
+            rgb.iter().skip(c).step_by(3).zip(back.iter().skip(c).step_by(3)).map(|(&a, &b)| a.abs_diff(b)).max()
+                .unwrap()
+        }).collect();
+        assert_eq!(&stats.max[..], &expected_max[..]);
+        assert!(stats.max[0] > 100 && stats.max[1] > 100);
+    }
+}
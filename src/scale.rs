@@ -0,0 +1,190 @@
+//! Scaling of YUV420 frames, eg.: for thumbnails or the lower simulcast layers of a frame
+//! that has already been converted
+
+use alloc::vec::Vec;
+
+use config::ConversionConfig;
+use error::ConversionError;
+use frame::YuvFrame;
+use geometry::{self, ScaleFilter};
+use kernel::{self, ChromaPlanes, Planes};
+use layout;
+use planes::FrameMut;
+use split::YuvPlanes;
+
+// a plane of `size` samples, `step` bytes apart from `offset` on, in rows `stride` bytes apart
+#[derive(Clone, Copy)]
+struct Samples {
+    offset: usize,
+    stride: usize,
+    step: usize,
+    size: (usize, usize),
+}
+
+impl Samples {
+    fn at(&self, x: usize, y: usize) -> usize {
+        self.offset + y * self.stride + x * self.step
+    }
+}
+
+// the samples of `from` each sample of `to` is made of, and their weights out of 256
+fn taps(filter: ScaleFilter, from: usize, to: usize) -> Vec<Vec<(usize, u32)>> {
+    (0..to).map(|i| match filter {
+        _ if from == to => vec![(i, 256)],
+        ScaleFilter::Nearest => vec![(geometry::nearest(i, from, to), 256)],
+        ScaleFilter::Bilinear => {
+            let (first, second, weight) = geometry::taps(i, from, to);
+            vec![(first, 256 - weight), (second, weight)]
+        }
+        ScaleFilter::Box => {
+            let (first, count) = geometry::span(i, from, to);
+            (first..first + count).map(|sample| (sample, 256)).collect()
+        }
+    }).collect()
+}
+
+fn scale_plane(src: &[u8], from: Samples, dst: &mut [u8], to: Samples, filter: ScaleFilter) {
+    let (columns, rows) = (taps(filter, from.size.0, to.size.0), taps(filter, from.size.1, to.size.1));
+    for (y, rows) in rows.iter().enumerate() {
+        for (x, columns) in columns.iter().enumerate() {
+            let (mut sum, mut total) = (0, 0);
+            for &(row, wy) in rows {
+                for &(column, wx) in columns {
+                    sum += u64::from(wy * wx) * u64::from(src[from.at(column, row)]);
+                    total += u64::from(wy * wx);
+                }
+            }
+            dst[to.at(x, y)] = ((2 * sum + total) / (2 * total)) as u8;
+        }
+    }
+}
+
+// scales the planes of `src` into the planes of `dst`, which are of `size`, repacking the
+// chroma if they are in the other format
+fn scale_planes(src: &YuvPlanes, dst: Planes, size: (usize, usize), filter: ScaleFilter) {
+    let from = (src.width() as usize, src.height() as usize);
+    if from.0 == 0 || from.1 == 0 || size.0 == 0 || size.1 == 0 {
+        return;
+    }
+    let y = src.y();
+    scale_plane(y.data(), Samples { offset: 0, stride: y.stride(), step: 1, size: from }, dst.y,
+                Samples { offset: 0, stride: dst.strides.y, step: 1, size }, filter);
+    let (from, size) = (kernel::chroma_dimensions(from.0, from.1), kernel::chroma_dimensions(size.0, size.1));
+    // U and V with where their samples are
+    let (u, v, planes) = match (src.u(), src.v(), src.uv()) {
+        (Some(u), Some(v), _) => (u.data(), v.data(), [(0, u.stride(), 1), (0, v.stride(), 1)]),
+        (_, _, Some(uv)) => (uv.data(), uv.data(), [(0, uv.stride(), 2), (1, uv.stride(), 2)]),
+        _ => unreachable!("frames have either U and V or UV planes"),
+    };
+    let from = planes.map(|(offset, stride, step)| Samples { offset, stride, step, size: from });
+    let stride = dst.strides.chroma;
+    match dst.chroma {
+        ChromaPlanes::Planar(dst_u, dst_v) => {
+            scale_plane(u, from[0], dst_u, Samples { offset: 0, stride, step: 1, size }, filter);
+            scale_plane(v, from[1], dst_v, Samples { offset: 0, stride, step: 1, size }, filter);
+        }
+        ChromaPlanes::SemiPlanar(uv) => {
+            scale_plane(u, from[0], uv, Samples { offset: 0, stride, step: 2, size }, filter);
+            scale_plane(v, from[1], uv, Samples { offset: 1, stride, step: 2, size }, filter);
+        }
+    }
+}
+
+/// Scales the planes of a YUV420 frame into `dst`, which gives the size as well as the
+/// strides and format, eg.: the lower layers of a simulcast from the full size frame
+///
+/// Each plane is scaled on its own, so the chroma is resampled at its own resolution; the
+/// padding of the rows of `dst` is never written to. `Box` averages the samples each one
+/// covers, which is what thumbnails want.
+///
+/// # Errors
+///
+/// `UnsupportedDimensions` if `src` is empty but `dst` isn't
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{FrameMut, ScaleFilter, YuvFormat, YuvPlanes};
+///
+/// // a 4x2 I420 frame into a 2x1 NV12 one
+/// let i420 = [10, 20, 30, 40, 50, 60, 70, 80, 100, 200, 0, 50];
+/// let planes = YuvPlanes::split(&i420, YuvFormat::I420, 4, 2).unwrap();
+/// let (mut y, mut uv) = ([0; 2], [0; 2]);
+/// let mut dst = FrameMut::nv12(2, 1, &mut y, 2, &mut uv, 2).unwrap();
+/// rgb2yuv420::scale_yuv(&planes, ScaleFilter::Box, &mut dst).unwrap();
+/// assert_eq!((y, uv), ([35, 55], [150, 25]));
+/// ```
+pub fn scale_yuv(src: &YuvPlanes, filter: ScaleFilter, dst: &mut FrameMut) -> Result<(), ConversionError> {
+    let (width, height) = (dst.width() as usize, dst.height() as usize);
+    if (src.width() == 0 || src.height() == 0) && width != 0 && height != 0 {
+        return Err(ConversionError::UnsupportedDimensions { width: src.width(), height: src.height() });
+    }
+    scale_planes(src, dst.planes(), (width, height), filter);
+    Ok(())
+}
+
+impl YuvFrame {
+    /// A tightly packed copy scaled to `width` x `height`, with the matrix and range of the
+    /// frame; the alpha plane is dropped
+    ///
+    /// See `scale_yuv`, which scales into buffers of its own.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{Converter, ScaleFilter};
+    ///
+    /// let frame = Converter::builder().build().convert(&vec![200u8; 64 * 48 * 3], 64, 48).unwrap();
+    /// let thumbnail = frame.scaled(16, 12, ScaleFilter::Box);
+    /// assert_eq!((thumbnail.width(), thumbnail.height()), (16, 12));
+    /// assert!(thumbnail.y().iter().all(|&y| y == frame.y()[0]));
+    /// ```
+    pub fn scaled(&self, width: u32, height: u32, filter: ScaleFilter) -> YuvFrame {
+        let data = vec![0; layout::required_buffer_size(self.format(), width, height)];
+        let cfg = ConversionConfig { matrix: self.matrix(), range: self.range(), ..ConversionConfig::default() };
+        let mut scaled = YuvFrame::from_packed(data, self.format(), width, height, &cfg).with_timing(self.timing());
+        scale_planes(&self.as_planes(), scaled.kernel_planes(), (width as usize, height as usize), filter);
+        scaled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scale_yuv;
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use geometry::ScaleFilter;
+    use planes::FrameMut;
+    use split::YuvPlanes;
+    use view::RgbView;
+
+    #[test]
+    fn scaled_planes_match_across_formats_and_strides() {
+        let rgb: Vec<u8> = (0..8 * 6 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 8, 6, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        // halving with a box averages 2x2 blocks, like the mip levels do
+        assert_eq!(i420.scaled(4, 3, ScaleFilter::Box), i420.halved());
+        for &filter in &[ScaleFilter::Nearest, ScaleFilter::Bilinear, ScaleFilter::Box] {
+            let (scaled, other) = (i420.scaled(5, 3, filter), nv12.scaled(5, 3, filter));
+            assert_eq!(scaled.y(), other.y());
+            assert_eq!(scaled.u().unwrap(), other.data()[15..].iter().step_by(2).cloned().collect::<Vec<_>>());
+            // NV12 into I420 with padded rows
+            let (mut y, mut u, mut v) = ([0xaa; 3 * 8], [0xaa; 2 * 4], [0xaa; 2 * 4]);
+            let planes = YuvPlanes::split(nv12.data(), YuvFormat::Nv12, 8, 6).unwrap();
+            let mut dst = FrameMut::i420(5, 3, &mut y, 8, &mut u, &mut v, 4).unwrap();
+            scale_yuv(&planes, filter, &mut dst).unwrap();
+            assert_eq!(&y[8..13], &scaled.y()[5..10]);
+            assert_eq!(&y[13..16], &[0xaa; 3]);
+            assert_eq!((&u[4..7], &v[..3]), (&scaled.u().unwrap()[3..], &scaled.v().unwrap()[..3]));
+        }
+        // unscaled planes are copied
+        assert_eq!(nv12.scaled(8, 6, ScaleFilter::Bilinear), nv12);
+        let empty = YuvPlanes::split(&[], YuvFormat::I420, 0, 0).unwrap();
+        let (mut y, mut uv) = ([0; 4], [0; 2]);
+        assert_eq!(scale_yuv(&empty, ScaleFilter::Box, &mut FrameMut::nv12(2, 2, &mut y, 2, &mut uv, 2).unwrap()),
+                   Err(ConversionError::UnsupportedDimensions { width: 0, height: 0 }));
+    }
+}
@@ -0,0 +1,47 @@
+// RGB(A) texture to NV12, using the same integer math as the CPU kernel.
+// Every invocation produces a 4x2 block: two words of luma and one word of interleaved chroma.
+
+struct Params {
+    // width, height, unused, unused
+    size: vec4<u32>,
+}
+
+@group(0) @binding(0) var src: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> dst: array<u32>;
+@group(0) @binding(2) var<uniform> params: Params;
+
+fn rgb_at(x: u32, y: u32) -> vec3<i32> {
+    let c = textureLoad(src, vec2<i32>(i32(x), i32(y)), 0);
+    return vec3<i32>(round(c.rgb * 255.0));
+}
+
+fn luma(c: vec3<i32>) -> u32 {
+    return u32(clamp((77 * c.r + 150 * c.g + 29 * c.b + 128) >> 8u, 0, 255));
+}
+
+fn chroma(c: vec3<i32>) -> u32 {
+    let u = clamp(((-43 * c.r - 84 * c.g + 127 * c.b + 128) >> 8u) + 128, 0, 255);
+    let v = clamp(((127 * c.r - 106 * c.g - 21 * c.b + 128) >> 8u) + 128, 0, 255);
+    return u32(u) | (u32(v) << 8u);
+}
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let width = params.size.x;
+    let height = params.size.y;
+    let x = id.x * 4u;
+    let y = id.y * 2u;
+    if (x >= width || y >= height) {
+        return;
+    }
+    for (var row = 0u; row < 2u; row++) {
+        var word = 0u;
+        for (var i = 0u; i < 4u; i++) {
+            word |= luma(rgb_at(x + i, y + row)) << (8u * i);
+        }
+        dst[((y + row) * width + x) / 4u] = word;
+    }
+    // chroma is taken from the second pixel of every pair on even rows, like on the CPU
+    let uv = chroma(rgb_at(x + 1u, y)) | (chroma(rgb_at(x + 3u, y)) << 16u);
+    dst[(width * height + (y / 2u) * width + x) / 4u] = uv;
+}
@@ -0,0 +1,255 @@
+//! A frame layout for shared memory, so a capture process can convert frames straight into
+//! memory an encoder process reads them from
+//!
+//! A frame is a `SHM_HEADER_SIZE` byte header followed by its planes. The header holds, as
+//! little endian integers:
+//!
+//! | offset | size | field |
+//! |-------:|-----:|-------|
+//! | 0 | 4 | `SHM_MAGIC` |
+//! | 4 | 4 | `SHM_VERSION` |
+//! | 8 | 4 | format: 0 for I420, 1 for NV12 |
+//! | 12 | 4 | matrix: 0 for BT.601, 1 for BT.709, 2 for BT.2020 |
+//! | 16 | 4 | range: 0 for full, 1 for limited |
+//! | 20 | 4 | width |
+//! | 24 | 4 | height |
+//! | 32 | 8 | luma stride |
+//! | 40 | 8 | chroma stride |
+//! | 48 | 8 | sequence number |
+//! | 56 | 8 | offset of the Y plane from the start of the header |
+//! | 64 | 8 | offset of the U (I420) or UV (NV12) plane |
+//! | 72 | 8 | offset of the V plane, 0 for NV12 |
+//!
+//! and zeros up to its end. Writing and reading the same memory at once has to be prevented
+//! by the processes, eg.: with a semaphore or by double buffering.
+
+use core::convert::TryFrom;
+
+use config::{Matrix, Range};
+use error::ConversionError;
+use format::{FrameDescriptor, YuvFormat};
+use kernel;
+use planes::FrameMut;
+use split::YuvPlanes;
+
+/// The first bytes of a frame in shared memory
+pub const SHM_MAGIC: [u8; 4] = *b"Y420";
+/// The version of the layout written
+pub const SHM_VERSION: u32 = 1;
+/// Bytes of the header in front of the planes; the Y plane starts right after it
+pub const SHM_HEADER_SIZE: usize = 128;
+
+fn read_u32(shm: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([shm[at], shm[at + 1], shm[at + 2], shm[at + 3]])
+}
+
+fn read_usize(shm: &[u8], at: usize) -> Result<usize, ConversionError> {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&shm[at..at + 8]);
+    usize::try_from(u64::from_le_bytes(bytes)).map_err(|_| ConversionError::UnsupportedFormat("the frame is too large"))
+}
+
+fn invalid() -> ConversionError {
+    ConversionError::UnsupportedFormat("invalid shared memory frame header")
+}
+
+/// The header of a frame in shared memory: its layout, and a sequence number telling frames
+/// apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShmHeader {
+    pub frame: FrameDescriptor,
+    pub sequence: u64,
+}
+
+impl ShmHeader {
+    // the offsets of the Y, U or UV, and V planes and the end of the frame, `None` if the
+    // strides of a header from elsewhere put them past `usize::MAX`
+    fn offsets(&self) -> Option<[usize; 4]> {
+        let (_, chroma_height) = kernel::chroma_dimensions(self.frame.width as usize, self.frame.height as usize);
+        let chroma_plane = self.frame.chroma_stride.checked_mul(chroma_height)?;
+        let chroma = self.frame.y_stride.checked_mul(self.frame.height as usize)?.checked_add(SHM_HEADER_SIZE)?;
+        let (v, end) = match self.frame.format {
+            YuvFormat::I420 => {
+                let v = chroma.checked_add(chroma_plane)?;
+                (v, v.checked_add(chroma_plane)?)
+            }
+            YuvFormat::Nv12 => (0, chroma.checked_add(chroma_plane)?),
+        };
+        Some([SHM_HEADER_SIZE, chroma, v, end])
+    }
+
+    /// Bytes the header and the planes take, `usize::MAX` (which no buffer holds) if that
+    /// overflows
+    pub fn size(&self) -> usize {
+        self.offsets().map_or(usize::MAX, |offsets| offsets[3])
+    }
+
+    /// Writes the header to the start of `shm` and returns its planes in `shm`, to convert
+    /// or copy the frame into
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `shm` is shorter than `size` and `StrideMismatch` if a stride of
+    /// `frame` is smaller than a row of its plane; nothing is written on errors
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, ConversionConfig, FrameDescriptor, RgbView, ShmHeader, YuvFormat};
+    ///
+    /// let cfg = ConversionConfig::default();
+    /// let frame = FrameDescriptor { format: YuvFormat::Nv12, width: 2, height: 2, y_stride: 2, chroma_stride: 2,
+    ///                               matrix: cfg.matrix, range: cfg.range };
+    /// let header = ShmHeader { frame, sequence: 7 };
+    /// // eg.: a memory mapped file or a POSIX shared memory object
+    /// let mut shm = vec![0; header.size()];
+    /// let src = RgbView::new(&[255; 2 * 2 * 3], 2, 2, ChannelOrder::Rgb).unwrap();
+    /// rgb2yuv420::convert_into_frame(&src, &cfg, &mut header.write(&mut shm).unwrap()).unwrap();
+    ///
+    /// let (read, planes) = ShmHeader::read(&shm).unwrap();
+    /// assert_eq!((read, planes.y().data()), (header, &[255; 4][..]));
+    /// ```
+    pub fn write<'a>(&self, shm: &'a mut [u8]) -> Result<FrameMut<'a>, ConversionError> {
+        let size = self.size();
+        if shm.len() < size {
+            return Err(ConversionError::BufferTooSmall { expected: size, got: shm.len() });
+        }
+        let frame = self.frame;
+        let too_small = ConversionError::BufferTooSmall { expected: size, got: shm.len() };
+        let [y_at, chroma_at, v_at, _] = self.offsets().ok_or(too_small)?;
+        let (header, planes) = shm[..size].split_at_mut(y_at);
+        let (y, chroma) = planes.split_at_mut(chroma_at - y_at);
+        let planes = match frame.format {
+            YuvFormat::I420 => {
+                let (u, v) = chroma.split_at_mut(v_at - chroma_at);
+                FrameMut::i420(frame.width, frame.height, y, frame.y_stride, u, v, frame.chroma_stride)?
+            }
+            YuvFormat::Nv12 => {
+                FrameMut::nv12(frame.width, frame.height, y, frame.y_stride, chroma, frame.chroma_stride)?
+            }
+        };
+        let format = match frame.format {
+            YuvFormat::I420 => 0,
+            YuvFormat::Nv12 => 1,
+        };
+        let matrix = match frame.matrix {
+            Matrix::Bt601 => 0,
+            Matrix::Bt709 => 1,
+            Matrix::Bt2020 => 2,
+        };
+        let range = match frame.range {
+            Range::Full => 0,
+            Range::Limited => 1,
+        };
+        for byte in header.iter_mut() {
+            *byte = 0;
+        }
+        header[..4].copy_from_slice(&SHM_MAGIC);
+        for (at, field) in [(4, SHM_VERSION), (8, format), (12, matrix), (16, range), (20, frame.width),
+                            (24, frame.height)] {
+            header[at..at + 4].copy_from_slice(&field.to_le_bytes());
+        }
+        for (at, field) in [(32, frame.y_stride as u64), (40, frame.chroma_stride as u64), (48, self.sequence),
+                            (56, y_at as u64), (64, chroma_at as u64), (72, v_at as u64)] {
+            header[at..at + 8].copy_from_slice(&field.to_le_bytes());
+        }
+        Ok(planes)
+    }
+
+    /// Reads the header at the start of `shm` and the planes it describes
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` if `shm` doesn't start with a header of this version, and
+    /// `BufferTooSmall` or `StrideMismatch` if the planes don't fit in `shm` or their strides
+    pub fn read(shm: &[u8]) -> Result<(ShmHeader, YuvPlanes<'_>), ConversionError> {
+        if shm.len() < SHM_HEADER_SIZE {
+            return Err(ConversionError::BufferTooSmall { expected: SHM_HEADER_SIZE, got: shm.len() });
+        }
+        if shm[..4] != SHM_MAGIC {
+            return Err(ConversionError::UnsupportedFormat("not a shared memory frame"));
+        }
+        if read_u32(shm, 4) != SHM_VERSION {
+            return Err(ConversionError::UnsupportedFormat("unsupported shared memory frame version"));
+        }
+        let format = match read_u32(shm, 8) {
+            0 => YuvFormat::I420,
+            1 => YuvFormat::Nv12,
+            _ => return Err(invalid()),
+        };
+        let matrix = match read_u32(shm, 12) {
+            0 => Matrix::Bt601,
+            1 => Matrix::Bt709,
+            2 => Matrix::Bt2020,
+            _ => return Err(invalid()),
+        };
+        let range = match read_u32(shm, 16) {
+            0 => Range::Full,
+            1 => Range::Limited,
+            _ => return Err(invalid()),
+        };
+        let (width, height) = (read_u32(shm, 20), read_u32(shm, 24));
+        let (y_stride, chroma_stride) = (read_usize(shm, 32)?, read_usize(shm, 40)?);
+        let mut sequence = [0; 8];
+        sequence.copy_from_slice(&shm[48..56]);
+        let header = ShmHeader {
+            frame: FrameDescriptor { format, width, height, y_stride, chroma_stride, matrix, range },
+            sequence: u64::from_le_bytes(sequence),
+        };
+        let plane = |at: usize| -> Result<&[u8], ConversionError> {
+            let offset = read_usize(shm, at)?;
+            shm.get(offset..).ok_or(ConversionError::BufferTooSmall { expected: offset, got: shm.len() })
+        };
+        let planes = match format {
+            YuvFormat::I420 => YuvPlanes::i420(width, height, plane(56)?, y_stride, plane(64)?, plane(72)?,
+                                               chroma_stride)?,
+            YuvFormat::Nv12 => YuvPlanes::nv12(width, height, plane(56)?, y_stride, plane(64)?, chroma_stride)?,
+        };
+        Ok((header, planes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShmHeader, SHM_HEADER_SIZE};
+    use config::{ConversionConfig, Matrix, Range};
+    use error::ConversionError;
+    use format::{ChannelOrder, FrameDescriptor, YuvFormat};
+    use planes;
+    use view::RgbView;
+
+    #[test]
+    fn frames_written_to_shared_memory_read_back() {
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 47 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let expected = ::convert(&src, YuvFormat::I420, &cfg);
+        let frame = FrameDescriptor { format: YuvFormat::I420, width: 5, height: 3, y_stride: 8, chroma_stride: 4,
+                                      matrix: cfg.matrix, range: cfg.range };
+        let header = ShmHeader { frame, sequence: 1 << 40 };
+        assert_eq!(header.size(), SHM_HEADER_SIZE + 8 * 3 + 2 * 4 * 2);
+        let mut shm = vec![0xff; header.size() + 3];
+        planes::convert_into_frame(&src, &cfg, &mut header.write(&mut shm).unwrap()).unwrap();
+        let (read, planes) = ShmHeader::read(&shm).unwrap();
+        assert_eq!(read, header);
+        assert_eq!(planes.to_vec(YuvFormat::I420), expected.data());
+        assert_eq!(header.write(&mut shm[..header.size() - 1]).err(),
+                   Some(ConversionError::BufferTooSmall { expected: header.size(), got: header.size() - 1 }));
+        // the last chroma row doesn't need its padding
+        assert!(ShmHeader::read(&shm[..header.size() - 1]).is_ok());
+        assert_eq!(ShmHeader::read(&shm[..header.size() - 2]).err(),
+                   Some(ConversionError::BufferTooSmall { expected: 7, got: 6 }));
+        // strides and sizes from a hostile writer
+        let huge = ShmHeader { frame: FrameDescriptor { y_stride: 1 << 62, height: 8, ..frame }, ..header };
+        assert_eq!(huge.size(), usize::MAX);
+        assert!(matches!(huge.write(&mut shm), Err(ConversionError::BufferTooSmall { .. })));
+        for (at, field) in [(32, 1u64 << 62), (32, u64::MAX), (40, u64::MAX), (20, u64::from(u32::MAX))] {
+            let mut hostile = shm.clone();
+            hostile[at..at + 8].copy_from_slice(&field.to_le_bytes());
+            hostile[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(ShmHeader::read(&hostile).is_err(), "{}", at);
+        }
+        shm[0] = b'I';
+        assert_eq!(ShmHeader::read(&shm).err(), Some(ConversionError::UnsupportedFormat("not a shared memory frame")));
+    }
+}
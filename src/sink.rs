@@ -0,0 +1,327 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::convert::Infallible;
+
+#[cfg(feature = "alloc")]
+use error::ConversionError;
+#[cfg(feature = "alloc")]
+use format::YuvFormat;
+#[cfg(feature = "alloc")]
+use kernel;
+
+/// A destination receiving a frame row by row as it is converted
+///
+/// The rows arrive in source order, two luma rows followed by the chroma row covering them
+/// (the last luma row of an odd height frame is unpaired), which lets a sink fill ring
+/// buffers, staging memory or packets without waiting for the whole frame.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, PlaneSink, RgbView};
+///
+/// /// Counts the bytes of every plane
+/// #[derive(Default)]
+/// struct Count(usize, usize);
+///
+/// impl PlaneSink for Count {
+///     type Error = ();
+///
+///     fn write_y_row(&mut self, _row: usize, y: &[u8]) -> Result<(), ()> {
+///         self.0 += y.len();
+///         Ok(())
+///     }
+///
+///     fn write_chroma_row(&mut self, _row: usize, u: &[u8], v: &[u8]) -> Result<(), ()> {
+///         self.1 += u.len() + v.len();
+///         Ok(())
+///     }
+/// }
+///
+/// let rgb = vec![0u8; 4 * 4 * 3];
+/// let src = RgbView::new(&rgb, 4, 4, ChannelOrder::Rgb).unwrap();
+/// let mut count = Count::default();
+/// rgb2yuv420::convert_into_sink(&src, &ConversionConfig::default(), &mut count).unwrap();
+/// assert_eq!((count.0, count.1), (16, 8));
+/// ```
+pub trait PlaneSink {
+    type Error;
+
+    /// Receives luma row `row`, `width` samples long
+    fn write_y_row(&mut self, row: usize, y: &[u8]) -> Result<(), Self::Error>;
+
+    /// Receives chroma row `row`, with the `(width + 1) / 2` U and V samples of the row
+    fn write_chroma_row(&mut self, row: usize, u: &[u8], v: &[u8]) -> Result<(), Self::Error>;
+
+    /// Called once after the last row
+    fn finish(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A plane of a YUV420 frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Plane {
+    Y,
+    /// The U plane of an I420 frame
+    U,
+    /// The V plane of an I420 frame
+    V,
+    /// The interleaved chroma plane of an NV12 frame
+    Uv,
+}
+
+/// Hands every output row of `format` to a callback
+#[cfg(feature = "alloc")]
+pub(crate) struct RowCallback<F> {
+    format: YuvFormat,
+    callback: F,
+    uv: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl<F: FnMut(Plane, usize, &[u8])> RowCallback<F> {
+    pub fn new(format: YuvFormat, callback: F) -> RowCallback<F> {
+        RowCallback { format, callback, uv: Vec::new() }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: FnMut(Plane, usize, &[u8])> PlaneSink for RowCallback<F> {
+    type Error = Infallible;
+
+    fn write_y_row(&mut self, row: usize, y: &[u8]) -> Result<(), Infallible> {
+        (self.callback)(Plane::Y, row, y);
+        Ok(())
+    }
+
+    fn write_chroma_row(&mut self, row: usize, u: &[u8], v: &[u8]) -> Result<(), Infallible> {
+        match self.format {
+            YuvFormat::I420 => {
+                (self.callback)(Plane::U, row, u);
+                (self.callback)(Plane::V, row, v);
+            }
+            YuvFormat::Nv12 => {
+                self.uv.clear();
+                self.uv.extend(u.iter().zip(v).flat_map(|(&u, &v)| [u, v]));
+                (self.callback)(Plane::Uv, row, &self.uv);
+            }
+        }
+        Ok(())
+    }
+}
+
+// where a `ScatterSink` writes
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+enum Scatter<'a, 'b> {
+    // a buffer per row: the luma rows, then the U and V rows or the UV rows
+    Rows(&'a mut [&'b mut [u8]]),
+    // the tightly packed frame, across buffers starting at the offsets of `starts` within it
+    Segments { segments: &'a mut [&'b mut [u8]], starts: Vec<usize> },
+}
+
+/// A `PlaneSink` writing the rows of a frame straight into buffers that aren't contiguous,
+/// eg.: the slots of a ring buffer or the payloads of network packets, without a staging frame
+///
+/// `ScatterSink::rows` takes a buffer per row of every plane and `ScatterSink::segments` the
+/// tightly packed frame (as `required_buffer_size` lays it out) split across buffers of any
+/// size, like an iovec.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, ScatterSink, YuvFormat};
+///
+/// let rgb = [255u8; 4 * 2 * 3];
+/// let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+/// // 12 bytes of NV12 over packets of 5 bytes
+/// let (mut first, mut second, mut third) = ([0; 5], [0; 5], [0; 5]);
+/// let mut packets = [&mut first[..], &mut second[..], &mut third[..]];
+/// let mut sink = ScatterSink::segments(YuvFormat::Nv12, 4, 2, &mut packets).unwrap();
+/// rgb2yuv420::convert_into_sink(&src, &ConversionConfig::default(), &mut sink).unwrap();
+/// assert_eq!([first, second, third].concat()[..12], [255, 255, 255, 255, 255, 255, 255, 255, 128, 128, 128, 128]);
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug)]
+pub struct ScatterSink<'a, 'b> {
+    format: YuvFormat,
+    width: usize,
+    height: usize,
+    scatter: Scatter<'a, 'b>,
+    // an interleaved NV12 chroma row
+    uv: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, 'b> ScatterSink<'a, 'b> {
+    /// Writes every row of a `width` x `height` frame into a buffer of its own out of `rows`:
+    /// the luma rows from the top, then the U and then the V rows for I420, or the UV rows for
+    /// NV12; longer buffers keep their bytes past the row
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if there are fewer buffers than rows (in rows) or a buffer is shorter
+    /// than its row (in bytes)
+    pub fn rows(format: YuvFormat, width: u32, height: u32, rows: &'a mut [&'b mut [u8]])
+                -> Result<ScatterSink<'a, 'b>, ConversionError> {
+        let mut sink = ScatterSink::new(format, width, height, Scatter::Rows(&mut []));
+        let (chroma_rows, chroma_bytes) = sink.chroma_plane();
+        let planes = match format {
+            YuvFormat::I420 => 2,
+            YuvFormat::Nv12 => 1,
+        };
+        let expected = sink.height + planes * chroma_rows;
+        if rows.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: rows.len() });
+        }
+        for (i, row) in rows[..expected].iter().enumerate() {
+            let expected = if i < sink.height { sink.width } else { chroma_bytes };
+            if row.len() < expected {
+                return Err(ConversionError::BufferTooSmall { expected, got: row.len() });
+            }
+        }
+        sink.scatter = Scatter::Rows(rows);
+        Ok(sink)
+    }
+
+    /// Writes a tightly packed `width` x `height` frame across `segments`, filling each one
+    /// before the next
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if the segments hold fewer bytes than the frame
+    pub fn segments(format: YuvFormat, width: u32, height: u32, segments: &'a mut [&'b mut [u8]])
+                    -> Result<ScatterSink<'a, 'b>, ConversionError> {
+        let expected = kernel::frame_size(width as usize, height as usize);
+        let mut starts = Vec::with_capacity(segments.len());
+        let mut got = 0;
+        for segment in segments.iter() {
+            starts.push(got);
+            got += segment.len();
+        }
+        if got < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got });
+        }
+        Ok(ScatterSink::new(format, width, height, Scatter::Segments { segments, starts }))
+    }
+
+    fn new(format: YuvFormat, width: u32, height: u32, scatter: Scatter<'a, 'b>) -> ScatterSink<'a, 'b> {
+        ScatterSink { format, width: width as usize, height: height as usize, scatter, uv: Vec::new() }
+    }
+
+    // the number of rows of a chroma plane and the bytes of each
+    fn chroma_plane(&self) -> (usize, usize) {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(self.width, self.height);
+        match self.format {
+            YuvFormat::I420 => (chroma_height, chroma_width),
+            YuvFormat::Nv12 => (chroma_height, 2 * chroma_width),
+        }
+    }
+
+    // writes `data` as row `row` of the frame counting the rows of every plane from the top,
+    // at `offset` in the packed frame
+    fn put(&mut self, row: usize, offset: usize, data: &[u8]) {
+        match self.scatter {
+            Scatter::Rows(ref mut rows) => rows[row][..data.len()].copy_from_slice(data),
+            Scatter::Segments { ref mut segments, ref starts } => {
+                let (mut offset, mut data) = (offset, data);
+                while !data.is_empty() {
+                    let segment = starts.partition_point(|&start| start <= offset) - 1;
+                    let at = offset - starts[segment];
+                    let len = (segments[segment].len() - at).min(data.len());
+                    segments[segment][at..at + len].copy_from_slice(&data[..len]);
+                    offset += len;
+                    data = &data[len..];
+                }
+            }
+        }
+    }
+
+    // an error unless a row of `len` bytes is row `row` of a plane of `rows` rows of `bytes`
+    fn check(&self, row: usize, len: usize, rows: usize, bytes: usize) -> Result<(), ConversionError> {
+        if row < rows && len == bytes {
+            Ok(())
+        } else {
+            Err(ConversionError::UnsupportedDimensions { width: self.width as u32, height: self.height as u32 })
+        }
+    }
+}
+
+/// Fails with `UnsupportedDimensions` if the image isn't the size of the frame
+#[cfg(feature = "alloc")]
+impl<'a, 'b> PlaneSink for ScatterSink<'a, 'b> {
+    type Error = ConversionError;
+
+    fn write_y_row(&mut self, row: usize, y: &[u8]) -> Result<(), ConversionError> {
+        self.check(row, y.len(), self.height, self.width)?;
+        self.put(row, row * self.width, y);
+        Ok(())
+    }
+
+    fn write_chroma_row(&mut self, row: usize, u: &[u8], v: &[u8]) -> Result<(), ConversionError> {
+        let (chroma_rows, chroma_bytes) = self.chroma_plane();
+        let luma_size = self.width * self.height;
+        match self.format {
+            YuvFormat::I420 => {
+                self.check(row, u.len(), chroma_rows, chroma_bytes)?;
+                self.put(self.height + row, luma_size + row * chroma_bytes, u);
+                let (row, offset) = (chroma_rows + row, chroma_rows * chroma_bytes + row * chroma_bytes);
+                self.put(self.height + row, luma_size + offset, v);
+            }
+            YuvFormat::Nv12 => {
+                self.check(row, 2 * u.len(), chroma_rows, chroma_bytes)?;
+                let mut uv = core::mem::take(&mut self.uv);
+                uv.clear();
+                uv.extend(u.iter().zip(v).flat_map(|(&u, &v)| [u, v]));
+                self.put(self.height + row, luma_size + row * chroma_bytes, &uv);
+                self.uv = uv;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScatterSink;
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn scattered_rows_make_up_the_frame() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 41 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let expected = ::convert(&src, format, &cfg).into_vec();
+            // segments of 7 bytes, the last one longer than the rest of the frame
+            let mut buffer = [0; 7 * 5];
+            let mut segments: Vec<&mut [u8]> = buffer.chunks_mut(7).collect();
+            let mut sink = ScatterSink::segments(format, 5, 3, &mut segments).unwrap();
+            ::convert_into_sink(&src, &cfg, &mut sink).unwrap();
+            assert_eq!(buffer[..expected.len()], expected[..]);
+            // a padded buffer per row
+            let row_bytes: &[usize] = match format {
+                YuvFormat::I420 => &[5, 5, 5, 3, 3, 3, 3],
+                YuvFormat::Nv12 => &[5, 5, 5, 6, 6],
+            };
+            let mut rows = vec![[0xaa; 8]; row_bytes.len()];
+            let mut slices: Vec<&mut [u8]> = rows.iter_mut().map(|row| &mut row[..]).collect();
+            ::convert_into_sink(&src, &cfg, &mut ScatterSink::rows(format, 5, 3, &mut slices).unwrap()).unwrap();
+            let packed: Vec<u8> = rows.iter().zip(row_bytes).flat_map(|(row, &len)| row[..len].to_vec()).collect();
+            assert_eq!(packed, expected);
+            assert!(rows.iter().all(|row| row[7] == 0xaa));
+        }
+        let mut short = [&mut [0; 5][..]];
+        assert_eq!(ScatterSink::segments(YuvFormat::Nv12, 5, 3, &mut short).unwrap_err(),
+                   ConversionError::BufferTooSmall { expected: 27, got: 5 });
+        let mut small = [&mut [0; 6][..]];
+        let mut sink = ScatterSink::segments(YuvFormat::Nv12, 2, 2, &mut small).unwrap();
+        assert_eq!(::convert_into_sink(&src, &cfg, &mut sink),
+                   Err(ConversionError::UnsupportedDimensions { width: 2, height: 2 }));
+    }
+}
@@ -0,0 +1,179 @@
+//! A two stage conversion through separate channel planes, for callers that process the
+//! channels on their own between reading the pixels and converting them
+
+use alloc::vec::Vec;
+
+use config::{ChromaFilter, ConversionConfig};
+use format::YuvFormat;
+use frame::YuvFrame;
+use kernel::{self, ChromaPlanes, Planes};
+use transfer;
+use view::RgbView;
+
+/// The red, green, blue and alpha channels of an image as tightly packed planes of 8 bit
+/// samples, one per pixel
+///
+/// `deinterleave` is the first stage of a conversion and `to_yuv` the second, which runs the
+/// color matrix over the planes and packs the YUV frame. In between, the planes can be
+/// processed a channel at a time with loops that vectorize, eg.: white balance or an alpha
+/// premultiplication.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbPlanes, RgbView, YuvFormat};
+///
+/// let rgb = [200, 100, 50, 200, 100, 50];
+/// let mut planes = RgbPlanes::deinterleave(&RgbView::new(&rgb, 2, 1, ChannelOrder::Rgb).unwrap());
+/// // drop the blue channel
+/// planes.channels_mut()[2].fill(0);
+/// let cfg = ConversionConfig::default();
+/// let yuv = planes.to_yuv(YuvFormat::I420, &cfg);
+/// let yellow = [200, 100, 0, 200, 100, 0];
+/// let src = RgbView::new(&yellow, 2, 1, ChannelOrder::Rgb).unwrap();
+/// assert_eq!(yuv, rgb2yuv420::convert(&src, YuvFormat::I420, &cfg));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RgbPlanes {
+    width: u32,
+    height: u32,
+    channels: [Vec<u8>; 3],
+    alpha: Option<Vec<u8>>,
+}
+
+impl RgbPlanes {
+    /// Splits the pixels of `src` into planes, rounding 16 bit channels to 8 bits; the alpha
+    /// plane is only there if `src` has an alpha channel
+    pub fn deinterleave(src: &RgbView) -> RgbPlanes {
+        let source = src.source();
+        let pixels = source.width * source.height;
+        let mut channels = [Vec::with_capacity(pixels), Vec::with_capacity(pixels), Vec::with_capacity(pixels)];
+        let mut alpha = source.layout.alpha.map(|_| Vec::with_capacity(pixels));
+        for row in 0..source.height {
+            for [r, g, b] in source.rgb_row(row) {
+                channels[0].push(r);
+                channels[1].push(g);
+                channels[2].push(b);
+            }
+            if let (Some(alpha), Some(samples)) = (alpha.as_mut(), source.alpha_row(row)) {
+                alpha.extend(samples);
+            }
+        }
+        RgbPlanes { width: src.width(), height: src.height(), channels, alpha }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The red, green and blue planes
+    pub fn channels(&self) -> [&[u8]; 3] {
+        let [r, g, b] = &self.channels;
+        [r, g, b]
+    }
+
+    pub fn channels_mut(&mut self) -> [&mut [u8]; 3] {
+        let [r, g, b] = &mut self.channels;
+        [r, g, b]
+    }
+
+    pub fn alpha(&self) -> Option<&[u8]> {
+        self.alpha.as_deref()
+    }
+
+    pub fn alpha_mut(&mut self) -> Option<&mut [u8]> {
+        self.alpha.as_deref_mut()
+    }
+
+    /// Converts the planes into a tightly packed frame, as `convert` does the interleaved
+    /// pixels with `cfg`
+    ///
+    /// The alpha plane is for processing the color planes only and isn't carried over.
+    pub fn to_yuv(&self, format: YuvFormat, cfg: &ConversionConfig) -> YuvFrame {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+        let coef = cfg.coefficients_for(format);
+        let filter = cfg.sampled_chroma_filter();
+        let [r, g, b] = self.channels();
+        let rgb = |i: usize| (i32::from(r[i]), i32::from(g[i]), i32::from(b[i]));
+        let mut data = vec![0; kernel::frame_size(width, height)];
+        let Planes { y, mut chroma, strides, .. } = Planes::packed(&mut data, format, width, height);
+        // the matrix stage, a plane at a time
+        for (i, y) in y.iter_mut().enumerate() {
+            let (r, g, b) = rgb(i);
+            *y = coef.luma(r, g, b);
+        }
+        coef.adjust_luma(y);
+        // sampled as `ChromaFilter` describes it
+        let sample = |cx: usize, cy: usize| match filter {
+            ChromaFilter::Point => {
+                let (r, g, b) = rgb(2 * cy * width + (2 * cx + 1).min(width - 1));
+                coef.chroma(r, g, b)
+            }
+            ChromaFilter::Average | ChromaFilter::LinearAverage => {
+                let linear = filter == ChromaFilter::LinearAverage;
+                let decode = |c: i32| if linear { transfer::to_linear(c) } else { c };
+                let (mut sum, mut count) = ((0, 0, 0), 0);
+                for row in 2 * cy..(2 * cy + 2).min(height) {
+                    for x in 2 * cx..(2 * cx + 2).min(width) {
+                        let (r, g, b) = rgb(row * width + x);
+                        sum = (sum.0 + decode(r), sum.1 + decode(g), sum.2 + decode(b));
+                        count += 1;
+                    }
+                }
+                let average = |sum: i32| {
+                    let average = (sum + count / 2) / count;
+                    if linear { transfer::from_linear(average) } else { average }
+                };
+                coef.chroma(average(sum.0), average(sum.1), average(sum.2))
+            }
+            ChromaFilter::Neutral => (128, 128),
+        };
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (cb, cr) = sample(cx, cy);
+                match chroma {
+                    ChromaPlanes::Planar(ref mut u, ref mut v) => {
+                        u[cy * strides.chroma + cx] = cb;
+                        v[cy * strides.chroma + cx] = cr;
+                    }
+                    ChromaPlanes::SemiPlanar(ref mut uv) => {
+                        uv[cy * strides.chroma + 2 * cx] = cb;
+                        uv[cy * strides.chroma + 2 * cx + 1] = cr;
+                    }
+                }
+            }
+        }
+        YuvFrame::from_packed(data, format, self.width, self.height, cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RgbPlanes;
+    use config::{ChromaFilter, ConversionConfig, Range};
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn planes_convert_like_the_interleaved_pixels() {
+        let bgra: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&bgra, 5, 3, ChannelOrder::Bgra).unwrap();
+        let planes = RgbPlanes::deinterleave(&src);
+        assert_eq!(planes.channels()[0][..2], [bgra[2], bgra[6]]);
+        assert_eq!(planes.alpha().map(|alpha| alpha[1]), Some(bgra[7]));
+        for filter in [ChromaFilter::Point, ChromaFilter::Average, ChromaFilter::LinearAverage, ChromaFilter::Neutral] {
+            for format in [YuvFormat::I420, YuvFormat::Nv12] {
+                let cfg = ConversionConfig { chroma_filter: filter, range: Range::Limited, swap_uv: true,
+                                             ..ConversionConfig::default() };
+                assert_eq!(planes.to_yuv(format, &cfg), ::convert(&src, format, &cfg));
+            }
+        }
+        let rgb = [0u8; 2 * 2 * 3];
+        assert!(RgbPlanes::deinterleave(&RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap()).alpha().is_none());
+    }
+}
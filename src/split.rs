@@ -0,0 +1,381 @@
+//! Typed views of the planes of YUV420 buffers, so that callers don't have to work out the
+//! plane offsets and strides themselves
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use error::ConversionError;
+use format::YuvFormat;
+use kernel::{self, ChromaPlanes};
+use layout;
+#[cfg(feature = "alloc")]
+use planes::PlaneInfo;
+use planes::{check_plane, FrameMut};
+use repack;
+
+/// One plane of a YUV420 frame: `rows` rows of `row_bytes` bytes, `stride` bytes apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneRef<'a> {
+    data: &'a [u8],
+    row_bytes: usize,
+    rows: usize,
+    stride: usize,
+}
+
+impl<'a> PlaneRef<'a> {
+    fn new(data: &'a [u8], row_bytes: usize, rows: usize, stride: usize) -> Result<PlaneRef<'a>, ConversionError> {
+        let extent = check_plane(data.len(), stride, row_bytes, rows)?;
+        Ok(PlaneRef { data: &data[..extent], row_bytes, rows, stride })
+    }
+
+    /// The bytes from the start of the first row to the end of the last one
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Bytes of samples in a row, without the padding
+    pub fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows
+    }
+
+    /// Distance between the starts of two rows in bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The samples of `row`, without the padding
+    ///
+    /// # Panics
+    ///
+    /// If `row` is not less than `height`
+    pub fn row(&self, row: usize) -> &'a [u8] {
+        assert!(row < self.rows, "row {} of a plane of {} rows", row, self.rows);
+        &self.data[row * self.stride..][..self.row_bytes]
+    }
+
+    /// The rows of the plane, without the padding
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u8]> + 'a {
+        let PlaneRef { data, row_bytes, rows, stride } = *self;
+        (0..rows).map(move |row| &data[row * stride..][..row_bytes])
+    }
+
+    // the `rows` rows of `row_bytes` bytes from byte `x` of row `y` on, which fit the plane
+    fn crop(&self, x: usize, y: usize, row_bytes: usize, rows: usize) -> PlaneRef<'a> {
+        let data = if row_bytes == 0 || rows == 0 {
+            &self.data[..0]
+        } else {
+            &self.data[y * self.stride + x..][..(rows - 1) * self.stride + row_bytes]
+        };
+        PlaneRef { data, row_bytes, rows, stride: self.stride }
+    }
+
+    // copies the rows into `dst`, `stride` bytes apart
+    fn copy_to(&self, dst: &mut [u8], stride: usize) {
+        for (row, src) in self.rows().enumerate() {
+            dst[row * stride..][..self.row_bytes].copy_from_slice(src);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChromaRef<'a> {
+    Planar(PlaneRef<'a>, PlaneRef<'a>),
+    SemiPlanar(PlaneRef<'a>),
+}
+
+/// The planes of a YUV420 frame, from one contiguous buffer or from separate ones
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{FrameMut, YuvFormat, YuvPlanes};
+///
+/// // a 4x2 I420 frame
+/// let i420 = [1, 2, 3, 4, 5, 6, 7, 8, 10, 20, 30, 40];
+/// let planes = YuvPlanes::split(&i420, YuvFormat::I420, 4, 2).unwrap();
+/// assert_eq!(planes.y().row(1), &[5, 6, 7, 8]);
+/// assert_eq!(planes.v().unwrap().data(), &[30, 40]);
+/// // into NV12 with rows padded to 8 bytes
+/// let (mut y, mut uv) = ([0; 16], [0; 4]);
+/// let mut frame = FrameMut::nv12(4, 2, &mut y, 8, &mut uv, 4).unwrap();
+/// planes.copy_into(&mut frame).unwrap();
+/// assert_eq!(&y[8..12], &[5, 6, 7, 8]);
+/// assert_eq!(uv, [10, 30, 20, 40]);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct YuvPlanes<'a> {
+    width: u32,
+    height: u32,
+    y: PlaneRef<'a>,
+    chroma: ChromaRef<'a>,
+}
+
+impl<'a> YuvPlanes<'a> {
+    /// The planes of a tightly packed frame
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `yuv` is shorter than `required_buffer_size`
+    pub fn split(yuv: &'a [u8], format: YuvFormat, width: u32, height: u32) -> Result<YuvPlanes<'a>, ConversionError> {
+        let expected = layout::required_buffer_size(format, width, height);
+        if yuv.len() < expected {
+            return Err(ConversionError::BufferTooSmall { expected, got: yuv.len() });
+        }
+        let (_, chroma_offset, v_offset) = layout::plane_offsets(format, width, height);
+        let (y_stride, chroma_stride) = layout::plane_strides(format, width);
+        match v_offset {
+            Some(v_offset) => YuvPlanes::i420(width, height, &yuv[..chroma_offset], y_stride,
+                                              &yuv[chroma_offset..v_offset], &yuv[v_offset..], chroma_stride),
+            None => YuvPlanes::nv12(width, height, &yuv[..chroma_offset], y_stride, &yuv[chroma_offset..],
+                                    chroma_stride),
+        }
+    }
+
+    /// An I420 frame in three buffers; U and V share `chroma_stride`
+    ///
+    /// # Errors
+    ///
+    /// `StrideMismatch` if a stride is smaller than a row of its plane and `BufferTooSmall`
+    /// if a plane can't hold all of its rows
+    pub fn i420(width: u32, height: u32, y: &'a [u8], y_stride: usize, u: &'a [u8], v: &'a [u8],
+                chroma_stride: usize) -> Result<YuvPlanes<'a>, ConversionError> {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        Ok(YuvPlanes {
+            width,
+            height,
+            y: PlaneRef::new(y, width as usize, height as usize, y_stride)?,
+            chroma: ChromaRef::Planar(PlaneRef::new(u, chroma_width, chroma_height, chroma_stride)?,
+                                      PlaneRef::new(v, chroma_width, chroma_height, chroma_stride)?),
+        })
+    }
+
+    /// An NV12 frame in two buffers
+    ///
+    /// # Errors
+    ///
+    /// See `YuvPlanes::i420`
+    pub fn nv12(width: u32, height: u32, y: &'a [u8], y_stride: usize, uv: &'a [u8], uv_stride: usize)
+                -> Result<YuvPlanes<'a>, ConversionError> {
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        Ok(YuvPlanes {
+            width,
+            height,
+            y: PlaneRef::new(y, width as usize, height as usize, y_stride)?,
+            chroma: ChromaRef::SemiPlanar(PlaneRef::new(uv, 2 * chroma_width, chroma_height, uv_stride)?),
+        })
+    }
+
+    pub fn format(&self) -> YuvFormat {
+        match self.chroma {
+            ChromaRef::Planar(..) => YuvFormat::I420,
+            ChromaRef::SemiPlanar(_) => YuvFormat::Nv12,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn y(&self) -> PlaneRef<'a> {
+        self.y
+    }
+
+    /// The U plane of I420 frames
+    pub fn u(&self) -> Option<PlaneRef<'a>> {
+        match self.chroma {
+            ChromaRef::Planar(u, _) => Some(u),
+            ChromaRef::SemiPlanar(_) => None,
+        }
+    }
+
+    /// The V plane of I420 frames
+    pub fn v(&self) -> Option<PlaneRef<'a>> {
+        match self.chroma {
+            ChromaRef::Planar(_, v) => Some(v),
+            ChromaRef::SemiPlanar(_) => None,
+        }
+    }
+
+    /// The interleaved chroma plane of NV12 frames
+    pub fn uv(&self) -> Option<PlaneRef<'a>> {
+        match self.chroma {
+            ChromaRef::Planar(..) => None,
+            ChromaRef::SemiPlanar(uv) => Some(uv),
+        }
+    }
+
+    // the U and V of chroma sample (`col`, `row`)
+    pub(crate) fn chroma_sample(&self, col: usize, row: usize) -> (u8, u8) {
+        match self.chroma {
+            ChromaRef::Planar(u, v) => (u.row(row)[col], v.row(row)[col]),
+            ChromaRef::SemiPlanar(uv) => (uv.row(row)[2 * col], uv.row(row)[2 * col + 1]),
+        }
+    }
+
+    /// The planes of the `width` x `height` rectangle whose top left pixel is at (`x`, `y`),
+    /// eg.: to cut a capture down to a size the encoder supports
+    ///
+    /// The planes share the buffers and strides of these, so nothing is copied. `x` and `y`
+    /// have to be even for the chroma samples to stay on the 2x2 grid of the rectangle.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRegion` if the rectangle doesn't fit into the frame or starts at an odd pixel
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{YuvFormat, YuvPlanes};
+    ///
+    /// // the right half of a 4x2 NV12 frame
+    /// let nv12 = [1, 2, 3, 4, 5, 6, 7, 8, 10, 20, 30, 40];
+    /// let planes = YuvPlanes::split(&nv12, YuvFormat::Nv12, 4, 2).unwrap();
+    /// let right = planes.crop(2, 0, 2, 2).unwrap();
+    /// assert_eq!((right.y().row(1), right.y().stride()), (&[7, 8][..], 4));
+    /// assert_eq!(right.to_vec(YuvFormat::Nv12), [3, 4, 7, 8, 30, 40]);
+    /// ```
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<YuvPlanes<'a>, ConversionError> {
+        if u64::from(x) + u64::from(width) > u64::from(self.width)
+            || u64::from(y) + u64::from(height) > u64::from(self.height)
+            || !x.is_multiple_of(2) || !y.is_multiple_of(2) {
+            return Err(ConversionError::InvalidRegion { x, y, width, height });
+        }
+        let (x, y) = (x as usize, y as usize);
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        let chroma = match self.chroma {
+            ChromaRef::Planar(u, v) => ChromaRef::Planar(u.crop(x / 2, y / 2, chroma_width, chroma_height),
+                                                         v.crop(x / 2, y / 2, chroma_width, chroma_height)),
+            ChromaRef::SemiPlanar(uv) => ChromaRef::SemiPlanar(uv.crop(x, y / 2, 2 * chroma_width, chroma_height)),
+        };
+        Ok(YuvPlanes { width, height, y: self.y.crop(x, y, width as usize, height as usize), chroma })
+    }
+
+    /// Copies the planes into `dst` with its strides, repacking the chroma if `dst` is in the
+    /// other format; the padding of the rows of `dst` is never written to
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if `dst` is of a different size
+    pub fn copy_into(&self, dst: &mut FrameMut) -> Result<(), ConversionError> {
+        if (self.width, self.height) != (dst.width(), dst.height()) {
+            return Err(ConversionError::UnsupportedDimensions { width: self.width, height: self.height });
+        }
+        let (width, height) = (self.width, self.height);
+        let planes = dst.planes();
+        self.y.copy_to(planes.y, planes.strides.y);
+        let stride = planes.strides.chroma;
+        match (self.chroma, planes.chroma) {
+            (ChromaRef::Planar(u, v), ChromaPlanes::Planar(dst_u, dst_v)) => {
+                u.copy_to(dst_u, stride);
+                v.copy_to(dst_v, stride);
+            }
+            (ChromaRef::SemiPlanar(uv), ChromaPlanes::SemiPlanar(dst_uv)) => uv.copy_to(dst_uv, stride),
+            (ChromaRef::Planar(u, v), ChromaPlanes::SemiPlanar(uv)) => {
+                repack::repack_i420_to_nv12(width, height, u.data, v.data, u.stride, uv, stride)?
+            }
+            (ChromaRef::SemiPlanar(uv), ChromaPlanes::Planar(u, v)) => {
+                repack::repack_nv12_to_i420(width, height, uv.data, uv.stride, u, v, stride)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Assembles the planes into a tightly packed buffer in `format`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{YuvFormat, YuvPlanes};
+    ///
+    /// // separate Y and UV buffers with rows padded to 4 bytes
+    /// let (y, uv) = ([1, 2, 0, 0, 3, 4], [10, 20, 0, 0]);
+    /// let planes = YuvPlanes::nv12(2, 2, &y, 4, &uv, 4).unwrap();
+    /// assert_eq!(planes.to_vec(YuvFormat::I420), [1, 2, 3, 4, 10, 20]);
+    /// ```
+    #[cfg(feature = "alloc")]
+    pub fn to_vec(&self, format: YuvFormat) -> Vec<u8> {
+        let (width, height) = (self.width, self.height);
+        let mut yuv = vec![0; layout::required_buffer_size(format, width, height)];
+        let (y, chroma, v) = layout::plane_offsets(format, width, height);
+        let (y_stride, chroma_stride) = layout::plane_strides(format, width);
+        let planes = [PlaneInfo { offset: y, stride: y_stride }, PlaneInfo { offset: chroma, stride: chroma_stride },
+                      PlaneInfo { offset: v.unwrap_or(0), stride: chroma_stride }];
+        let count = if v.is_some() { 3 } else { 2 };
+        let mut dst = FrameMut::from_mapping(&mut yuv, format, width, height, &planes[..count])
+            .expect("tightly packed planes fit their buffer");
+        self.copy_into(&mut dst).expect("the frames have the same size");
+        yuv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::YuvPlanes;
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use planes::{FrameMut, PlaneInfo};
+    use view::RgbView;
+
+    #[test]
+    fn planes_are_split_and_merged_across_strides() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 43 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        let planes = YuvPlanes::split(i420.data(), YuvFormat::I420, 5, 3).unwrap();
+        assert_eq!((planes.format(), planes.y().height(), planes.u().unwrap().row_bytes()), (YuvFormat::I420, 3, 3));
+        assert_eq!(planes.y().rows().flatten().cloned().collect::<Vec<_>>(), i420.y());
+        assert_eq!(planes.to_vec(YuvFormat::I420), i420.data());
+        assert_eq!(planes.to_vec(YuvFormat::Nv12), nv12.data());
+        // NV12 in separate, padded buffers back to a single mapping
+        let (y, uv) = (planes.y(), YuvPlanes::split(nv12.data(), YuvFormat::Nv12, 5, 3).unwrap().uv().unwrap());
+        let pad = |plane: &[u8], row: usize| -> Vec<u8> {
+            plane.chunks(row).flat_map(|row| [row, &[0; 3]].concat()).collect()
+        };
+        let (padded_y, padded_uv) = (pad(y.data(), 5), pad(uv.data(), 6));
+        let separate = YuvPlanes::nv12(5, 3, &padded_y, 8, &padded_uv, 9).unwrap();
+        assert_eq!(separate.uv().unwrap().row(1), &nv12.uv().unwrap()[6..]);
+        assert_eq!(separate.to_vec(YuvFormat::I420), i420.data());
+        let mut mapping = [0; 64];
+        let layout = [PlaneInfo { offset: 0, stride: 8 }, PlaneInfo { offset: 32, stride: 4 },
+                      PlaneInfo { offset: 48, stride: 4 }];
+        FrameMut::from_mapping(&mut mapping, YuvFormat::I420, 5, 3, &layout).and_then(|mut frame| {
+            separate.copy_into(&mut frame)
+        }).unwrap();
+        assert_eq!(&mapping[32..35], &i420.u().unwrap()[..3]);
+        assert_eq!(YuvPlanes::nv12(5, 3, &padded_y, 4, &padded_uv, 9),
+                   Err(ConversionError::StrideMismatch { stride: 4, row_bytes: 5 }));
+        let (mut y, mut uv) = ([0; 4], [0; 2]);
+        let mut small = FrameMut::nv12(2, 2, &mut y, 2, &mut uv, 2).unwrap();
+        assert_eq!(separate.copy_into(&mut small), Err(ConversionError::UnsupportedDimensions { width: 5, height: 3 }));
+    }
+
+    #[test]
+    fn crops_match_converting_the_cropped_image() {
+        let rgb: Vec<u8> = (0..9 * 7 * 3).map(|i| (i * 53 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 9, 7, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig::default();
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let frame = ::convert(&src, format, &cfg);
+            let cropped = frame.as_planes().crop(2, 4, 6, 2).unwrap();
+            assert_eq!((cropped.y().stride(), cropped.y().row(0)), (9, &frame.y()[4 * 9 + 2..][..6]));
+            let expected = ::convert(&src.crop(2, 4, 6, 2).unwrap(), format, &cfg);
+            assert_eq!(cropped.to_vec(format), expected.data());
+            assert_eq!(frame.cropped(2, 4, 6, 2).unwrap(), expected);
+            assert_eq!(frame.cropped(8, 6, 1, 1).unwrap().y(), &[frame.y()[62]]);
+            assert!(frame.as_planes().crop(8, 6, 1, 0).unwrap().y().data().is_empty());
+        }
+        let planes = YuvPlanes::split(&rgb, YuvFormat::I420, 9, 7).unwrap();
+        assert_eq!(planes.crop(1, 0, 2, 2), Err(ConversionError::InvalidRegion { x: 1, y: 0, width: 2, height: 2 }));
+        assert_eq!(planes.crop(4, 2, 6, 2), Err(ConversionError::InvalidRegion { x: 4, y: 2, width: 6, height: 2 }));
+    }
+}
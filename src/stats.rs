@@ -0,0 +1,132 @@
+//! Luma statistics gathered while converting
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use config::Range;
+
+/// The luma histogram of a converted frame and what follows from it, eg.: for detecting scene
+/// changes, fades and black frames without a second pass over the frame
+///
+/// Only the pixels of the picture count, not the padding of padded frames.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, Converter, RgbView};
+///
+/// let rgb: Vec<u8> = (0..4 * 2).flat_map(|i| vec![if i == 0 { 255 } else { 0 }; 3]).collect();
+/// let src = RgbView::new(&rgb, 4, 2, ChannelOrder::Rgb).unwrap();
+/// let (_, stats) = Converter::builder().build().convert_with_stats(&src);
+/// assert_eq!((stats.min_luma(), stats.max_luma()), (0, 255));
+/// assert_eq!(stats.histogram()[0], 7);
+/// // one pixel in eight is too many for a black frame
+/// assert!(!stats.is_black());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameStats {
+    histogram: [u32; 256],
+    range: Range,
+}
+
+impl FrameStats {
+    /// Number of pixels with each luma code
+    pub fn histogram(&self) -> &[u32; 256] {
+        &self.histogram
+    }
+
+    pub fn pixels(&self) -> u64 {
+        self.histogram.iter().map(|&count| u64::from(count)).sum()
+    }
+
+    /// The darkest luma code of the frame, 0 for empty frames
+    pub fn min_luma(&self) -> u8 {
+        self.histogram.iter().position(|&count| count > 0).unwrap_or(0) as u8
+    }
+
+    /// The brightest luma code of the frame, 0 for empty frames
+    pub fn max_luma(&self) -> u8 {
+        self.histogram.iter().rposition(|&count| count > 0).unwrap_or(0) as u8
+    }
+
+    /// The mean luma code, 0 for empty frames
+    pub fn average_luma(&self) -> f64 {
+        let sum: u64 = self.histogram.iter().enumerate().map(|(code, &count)| code as u64 * u64::from(count)).sum();
+        match self.pixels() {
+            0 => 0.0,
+            pixels => sum as f64 / pixels as f64,
+        }
+    }
+
+    /// Whether at least 98% of the pixels are within 10% of the luma range above black (the
+    /// defaults of ffmpeg's `blackdetect`)
+    pub fn is_black(&self) -> bool {
+        let (black, white) = match self.range {
+            Range::Full => (0, 255),
+            Range::Limited => (16, 235),
+        };
+        let threshold = black + (white - black) / 10;
+        let dark: u64 = self.histogram[..=threshold].iter().map(|&count| u64::from(count)).sum();
+        dark * 100 >= self.pixels() * 98
+    }
+}
+
+/// A luma histogram the bands of a frame add their counts to
+pub(crate) struct Histogram([AtomicU32; 256]);
+
+impl Histogram {
+    #[cfg(feature = "alloc")]
+    pub fn new() -> Histogram {
+        Histogram([const { AtomicU32::new(0) }; 256])
+    }
+
+    /// Adds the counts of one band
+    pub fn add(&self, counts: &[u32; 256]) {
+        for (total, &count) in self.0.iter().zip(counts.iter()).filter(|&(_, &count)| count > 0) {
+            total.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn into_stats(self, range: Range) -> FrameStats {
+        let mut histogram = [0; 256];
+        for (count, total) in histogram.iter_mut().zip(self.0.iter()) {
+            *count = total.load(Ordering::Relaxed);
+        }
+        FrameStats { histogram, range }
+    }
+}
+
+/// Counts the luma codes of `row`
+#[inline]
+pub(crate) fn count_row(counts: &mut [u32; 256], row: &[u8]) {
+    for &y in row {
+        counts[usize::from(y)] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use converter::Converter;
+    use format::ChannelOrder;
+    use view::RgbView;
+
+    #[test]
+    fn histograms_count_the_picture_of_every_band() {
+        let rgb: Vec<u8> = (0..9 * 7 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 9, 7, ChannelOrder::Rgb).unwrap();
+        let builders = [(Converter::builder().threads(3).pad_to(16).alignment(32), (9, 7)),
+                        (Converter::builder().threads(2).scale(5, 11), (5, 11))];
+        for (builder, (width, height)) in builders.iter().cloned() {
+            let (frame, stats) = builder.build().convert_with_stats(&src);
+            let mut histogram = [0; 256];
+            for row in frame.y().chunks(frame.y_stride()).take(height) {
+                for &y in &row[..width] {
+                    histogram[usize::from(y)] += 1;
+                }
+            }
+            assert_eq!(stats.histogram(), &histogram);
+            assert_eq!(stats.pixels(), (width * height) as u64);
+            assert!(!stats.is_black());
+        }
+    }
+}
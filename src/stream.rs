@@ -0,0 +1,132 @@
+//! `tokio` integration (`tokio` feature)
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::task::{self, JoinHandle};
+
+use converter::Converter;
+use frame::YuvFrame;
+use view::RgbFrame;
+
+/// Converts a `Stream` of frames on tokio's blocking thread pool
+///
+/// Up to `depth` frames are converted at the same time and yielded in the order they
+/// arrived, so capture, conversion and encoding overlap without blocking the async tasks.
+/// Must be polled within a tokio runtime; a panicking conversion panics the stream.
+///
+/// # Examples
+///
+/// ```
+/// extern crate futures;
+/// extern crate rgb2yuv420;
+/// extern crate tokio;
+///
+/// use futures::{stream, StreamExt};
+/// use rgb2yuv420::{ChannelOrder, ConvertStream, Converter, RgbFrame};
+///
+/// # fn main() {
+/// let frames = (0..3).map(|_| RgbFrame::new(vec![0; 4 * 2 * 3], 4, 2, ChannelOrder::Rgb).unwrap());
+/// let converted = ConvertStream::new(stream::iter(frames), Converter::builder().build(), 2);
+/// let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+/// let frames: Vec<_> = runtime.block_on(converted.collect());
+/// assert_eq!(frames.len(), 3);
+/// assert_eq!(frames[0].u(), Some(&[128, 128][..]));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ConvertStream<S> {
+    // `None` once the source stream has ended
+    frames: Option<S>,
+    converter: Arc<Converter>,
+    depth: usize,
+    pending: VecDeque<JoinHandle<YuvFrame>>,
+}
+
+impl<S: Stream<Item = RgbFrame> + Unpin> ConvertStream<S> {
+    /// Converts the frames of `frames` to the output format and config of `converter`,
+    /// at most `depth` (but at least 1) at a time
+    pub fn new(frames: S, converter: Converter, depth: usize) -> ConvertStream<S> {
+        ConvertStream { frames: Some(frames), converter: Arc::new(converter), depth: depth.max(1),
+                        pending: VecDeque::new() }
+    }
+}
+
+impl<S: Stream<Item = RgbFrame> + Unpin> Stream for ConvertStream<S> {
+    type Item = YuvFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<YuvFrame>> {
+        let this = &mut *self;
+        while this.pending.len() < this.depth {
+            let polled = match this.frames {
+                Some(ref mut frames) => Pin::new(frames).poll_next(cx),
+                None => break,
+            };
+            match polled {
+                Poll::Ready(Some(frame)) => {
+                    let converter = this.converter.clone();
+                    this.pending.push_back(task::spawn_blocking(move || converter.convert_view(&frame.view())));
+                }
+                Poll::Ready(None) => this.frames = None,
+                Poll::Pending => break,
+            }
+        }
+        let converted = match this.pending.front_mut() {
+            Some(job) => Pin::new(job).poll(cx),
+            None if this.frames.is_none() => return Poll::Ready(None),
+            None => return Poll::Pending,
+        };
+        match converted {
+            Poll::Ready(result) => {
+                this.pending.pop_front();
+                match result {
+                    Ok(frame) => Poll::Ready(Some(frame)),
+                    Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+                    // the runtime is shutting down
+                    Err(_) => {
+                        this.frames = None;
+                        this.pending.clear();
+                        Poll::Ready(None)
+                    }
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.frames.as_ref().map_or((0, Some(0)), |frames| frames.size_hint());
+        (low.saturating_add(self.pending.len()), high.and_then(|high| high.checked_add(self.pending.len())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate futures;
+
+    use self::futures::{stream, StreamExt};
+    use tokio::runtime::Builder;
+
+    use super::ConvertStream;
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbFrame;
+
+    #[test]
+    fn frames_come_out_in_order() {
+        let images: Vec<Vec<u8>> = (0..8u8).map(|i| vec![i * 30; 6 * 4 * 3]).collect();
+        let frames = images.clone().into_iter().map(|rgb| RgbFrame::new(rgb, 6, 4, ChannelOrder::Rgb).unwrap());
+        let converter = Converter::builder().output(YuvFormat::Nv12).build();
+        let runtime = Builder::new_multi_thread().worker_threads(2).build().unwrap();
+        let converted: Vec<_> = runtime.block_on(ConvertStream::new(stream::iter(frames), converter, 3).collect());
+        assert_eq!(converted.len(), images.len());
+        for (rgb, yuv) in images.iter().zip(converted) {
+            assert_eq!(yuv.into_vec(), ::convert_rgb_to_yuv420sp_nv12(rgb, 6, 4, 3));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Process wide limits on the threads conversions are split across, and the cores they run
+//! on, so that a converter sharing a machine with an encoder leaves the encoder its cores
+//!
+//! Both apply to the threads of every conversion started after they are set: the bands of
+//! `ConversionConfig::threads` and the workers of a `Pipeline`.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+static MAX_THREADS: AtomicUsize = AtomicUsize::new(0);
+static WORKER_CORES: RwLock<Vec<usize>> = RwLock::new(Vec::new());
+
+/// Caps the threads a conversion is split across at `max`, whatever its configuration asks
+/// for; 0 lifts the cap
+///
+/// # Examples
+///
+/// ```
+/// // on an 8 core box running an encoder on 6 of them
+/// rgb2yuv420::set_max_threads(2);
+/// assert_eq!(rgb2yuv420::max_threads(), Some(2));
+/// # rgb2yuv420::set_max_threads(0);
+/// ```
+pub fn set_max_threads(max: usize) {
+    MAX_THREADS.store(max, Ordering::Relaxed);
+}
+
+/// The cap `set_max_threads` set, if any
+pub fn max_threads() -> Option<usize> {
+    match MAX_THREADS.load(Ordering::Relaxed) {
+        0 => None,
+        max => Some(max),
+    }
+}
+
+/// `threads` within the cap
+pub(crate) fn limit(threads: usize) -> usize {
+    max_threads().map_or(threads, |max| threads.min(max))
+}
+
+/// Pins the threads of conversions to `cores` (numbered as the kernel numbers CPUs), the
+/// first thread of a conversion to the first core and so on, wrapping around; an empty slice
+/// leaves them unpinned
+///
+/// Threads are only pinned on Linux with the `affinity` feature; elsewhere the cores are
+/// kept but ignored. The thread a conversion is called on is never pinned.
+pub fn set_worker_cores(cores: &[usize]) {
+    let mut worker_cores = WORKER_CORES.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    worker_cores.clear();
+    worker_cores.extend_from_slice(cores);
+}
+
+/// The cores `set_worker_cores` set
+pub fn worker_cores() -> Vec<usize> {
+    WORKER_CORES.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Pins the calling thread, the `index`th of a conversion, to its worker core
+#[cfg(all(target_os = "linux", feature = "affinity"))]
+pub(crate) fn pin_worker(index: usize) {
+    let cores = WORKER_CORES.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if cores.is_empty() {
+        return;
+    }
+    let core = cores[index % cores.len()];
+    // a core beyond the set or the machine leaves the thread where the scheduler put it
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if core < 8 * std::mem::size_of::<libc::cpu_set_t>() {
+            libc::CPU_SET(core, &mut set);
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "affinity")))]
+pub(crate) fn pin_worker(_index: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit, set_max_threads, set_worker_cores, worker_cores};
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn limits_leave_the_output_alone() {
+        let rgb: Vec<u8> = (0..8 * 12 * 3).map(|i| (i * 31 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 8, 12, ChannelOrder::Rgb).unwrap();
+        let single = ::convert(&src, YuvFormat::Nv12, &ConversionConfig::default());
+        let cfg = ConversionConfig { threads: 4, ..ConversionConfig::default() };
+        // other tests convert meanwhile, which the limits mustn't change either
+        set_max_threads(2);
+        assert_eq!((limit(4), limit(1)), (2, 1));
+        set_worker_cores(&[0]);
+        assert_eq!(worker_cores(), [0]);
+        assert_eq!(::convert(&src, YuvFormat::Nv12, &cfg), single);
+        #[cfg(all(target_os = "linux", feature = "affinity"))]
+        ::std::thread::spawn(|| {
+            super::pin_worker(3);
+            unsafe {
+                let mut set: libc::cpu_set_t = ::std::mem::zeroed();
+                libc::sched_getaffinity(0, ::std::mem::size_of::<libc::cpu_set_t>(), &mut set);
+                assert_eq!(libc::CPU_COUNT(&set), 1);
+                assert!(libc::CPU_ISSET(0, &set));
+            }
+        }).join().unwrap();
+        set_worker_cores(&[]);
+        set_max_threads(0);
+        assert_eq!(limit(4), 4);
+    }
+}
@@ -0,0 +1,139 @@
+//! Counters of the frames a `Converter` converts, for services exporting conversion health
+//! metrics
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Collects the throughput of the converters it is attached to with
+/// `ConverterBuilder::throughput`, from every thread converting with them
+///
+/// Converting a frame updates a few atomic counters, so collecting costs next to nothing.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use rgb2yuv420::{Converter, Throughput};
+///
+/// let throughput = Arc::new(Throughput::new());
+/// let converter = Converter::builder().throughput(Arc::clone(&throughput)).build();
+/// for _ in 0..3 {
+///     converter.convert(&[0u8; 64 * 64 * 3], 64, 64).unwrap();
+/// }
+/// let stats = throughput.stats();
+/// assert_eq!((stats.frames, stats.pixels, stats.kernel), (3, 3 * 64 * 64, Some("rgb")));
+/// // eg.: exported as a gauge every few seconds
+/// let _ = stats.megapixels_per_second();
+/// ```
+#[derive(Debug, Default)]
+pub struct Throughput {
+    frames: AtomicU64,
+    pixels: AtomicU64,
+    nanos: AtomicU64,
+    kernel: Mutex<Option<&'static str>>,
+}
+
+/// What a `Throughput` counted since it was made or reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ThroughputStats {
+    /// Number of frames converted
+    pub frames: u64,
+    /// Number of pixels of the converted frames, after scaling and without padding
+    pub pixels: u64,
+    /// Time spent converting, summed over the threads converting at once
+    ///
+    /// Luma and chroma are usually written in the same pass, see `PlanePasses`, so it isn't split
+    /// by plane.
+    pub busy: Duration,
+    /// The kernel that converted the last frame, eg.: `"rgbx"` for RGBA and RGBX input or
+    /// `"generic"` for channel orders without a kernel of their own
+    pub kernel: Option<&'static str>,
+}
+
+impl ThroughputStats {
+    /// Megapixels converted per second of `busy` time, 0 before the first frame
+    pub fn megapixels_per_second(&self) -> f64 {
+        let seconds = self.busy.as_secs_f64();
+        if seconds == 0.0 { 0.0 } else { self.pixels as f64 / 1e6 / seconds }
+    }
+}
+
+impl Throughput {
+    pub fn new() -> Throughput {
+        Throughput::default()
+    }
+
+    pub(crate) fn record(&self, width: u32, height: u32, elapsed: Duration, kernel: &'static str) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.pixels.fetch_add(u64::from(width) * u64::from(height), Ordering::Relaxed);
+        self.nanos.fetch_add(u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX), Ordering::Relaxed);
+        *self.kernel.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(kernel);
+    }
+
+    /// The counters so far; a snapshot taken while frames are converted may count a frame
+    /// in one counter but not yet in the others
+    pub fn stats(&self) -> ThroughputStats {
+        ThroughputStats {
+            frames: self.frames.load(Ordering::Relaxed),
+            pixels: self.pixels.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            kernel: *self.kernel.lock().unwrap_or_else(|poisoned| poisoned.into_inner()),
+        }
+    }
+
+    /// Returns the counters so far and starts counting from 0, eg.: once per export interval
+    pub fn reset(&self) -> ThroughputStats {
+        let mut kernel = self.kernel.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        ThroughputStats {
+            frames: self.frames.swap(0, Ordering::Relaxed),
+            pixels: self.pixels.swap(0, Ordering::Relaxed),
+            busy: Duration::from_nanos(self.nanos.swap(0, Ordering::Relaxed)),
+            kernel: kernel.take(),
+        }
+    }
+}
+
+/// The `Throughput` of a `Converter`; converters are equal if they report into the same one
+#[derive(Debug, Clone)]
+pub(crate) struct ThroughputHandle(pub Arc<Throughput>);
+
+impl PartialEq for ThroughputHandle {
+    fn eq(&self, other: &ThroughputHandle) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ThroughputHandle {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Throughput, ThroughputStats};
+    use converter::Converter;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn converters_count_their_frames() {
+        let throughput = Arc::new(Throughput::new());
+        let converter = Converter::builder().output(YuvFormat::Nv12).scale(4, 2).throughput(throughput.clone())
+            .build();
+        assert_eq!(converter, converter.clone());
+        assert_ne!(converter, Converter::builder().output(YuvFormat::Nv12).scale(4, 2).build());
+        let rgba = [0u8; 8 * 4 * 4];
+        let src = RgbView::new(&rgba, 8, 4, ChannelOrder::Bgra).unwrap();
+        converter.convert_view(&src);
+        converter.convert_with_stats(&src);
+        let stats = throughput.reset();
+        let kernel = if cfg!(feature = "layout-kernels") { "bgrx" } else { "generic" };
+        assert_eq!((stats.frames, stats.pixels, stats.kernel), (2, 2 * 4 * 2, Some(kernel)));
+        assert_eq!(throughput.stats(), ThroughputStats::default());
+        let stats = ThroughputStats { pixels: 3_000_000, busy: Duration::from_millis(500), ..stats };
+        assert_eq!(stats.megapixels_per_second(), 6.0);
+    }
+}
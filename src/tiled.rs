@@ -0,0 +1,234 @@
+//! Frames too large to hold in memory at once, eg.: gigapixel scans and stitched panoramas,
+//! converted a tile at a time into a file
+//!
+//! A `TileGrid` cuts the frame into tiles of an even size, so every tile covers whole 2x2
+//! chroma blocks and converts on its own into exactly the samples the whole frame would
+//! have. Offsets into the frame are computed in `u64`, as the planes of such frames outgrow
+//! `u32` (and `usize` on 32 bit targets).
+
+use std::io::{self, Seek, SeekFrom, Write};
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::YuvFormat;
+use geometry::Rect;
+use raw::check_size;
+use view::RgbView;
+
+/// The tiles of a `width` x `height` frame, `tile_width` x `tile_height` pixels each except in
+/// the last column and row, which take what is left
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileGrid {
+    width: u32,
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+}
+
+impl TileGrid {
+    /// # Errors
+    ///
+    /// `UnsupportedDimensions` if a side of the tiles is 0 or odd
+    pub fn new(width: u32, height: u32, tile_width: u32, tile_height: u32) -> Result<TileGrid, ConversionError> {
+        if tile_width == 0 || tile_height == 0 || !tile_width.is_multiple_of(2) || !tile_height.is_multiple_of(2) {
+            return Err(ConversionError::UnsupportedDimensions { width: tile_width, height: tile_height });
+        }
+        Ok(TileGrid { width, height, tile_width, tile_height })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Number of tiles in a row of tiles
+    pub fn columns(&self) -> u32 {
+        self.width.div_ceil(self.tile_width)
+    }
+
+    /// Number of rows of tiles
+    pub fn rows(&self) -> u32 {
+        self.height.div_ceil(self.tile_height)
+    }
+
+    /// Number of tiles
+    pub fn tiles(&self) -> u64 {
+        u64::from(self.columns()) * u64::from(self.rows())
+    }
+
+    /// The pixels of tile `column` of tile row `row`
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRegion` if the grid has no such tile
+    pub fn tile(&self, column: u32, row: u32) -> Result<Rect, ConversionError> {
+        if column >= self.columns() || row >= self.rows() {
+            return Err(ConversionError::InvalidRegion { x: column, y: row, width: 1, height: 1 });
+        }
+        let (x, y) = (column * self.tile_width, row * self.tile_height);
+        Ok(Rect { x, y, width: self.tile_width.min(self.width - x), height: self.tile_height.min(self.height - y) })
+    }
+
+    // the width and height of the chroma planes
+    fn chroma_dimensions(&self) -> (u64, u64) {
+        (u64::from(self.width.div_ceil(2)), u64::from(self.height.div_ceil(2)))
+    }
+
+    /// Bytes of the tightly packed `format` frame
+    pub fn frame_size(&self, format: YuvFormat) -> u64 {
+        let (chroma_width, chroma_height) = self.chroma_dimensions();
+        match self.plane_offsets(format) {
+            (_, _, Some(v)) => v + chroma_width * chroma_height,
+            (_, chroma, None) => chroma + 2 * chroma_width * chroma_height,
+        }
+    }
+
+    /// Offsets of the Y, U (or UV) and V planes of the tightly packed `format` frame, like
+    /// `plane_offsets`
+    pub fn plane_offsets(&self, format: YuvFormat) -> (u64, u64, Option<u64>) {
+        let (chroma_width, chroma_height) = self.chroma_dimensions();
+        let luma = u64::from(self.width) * u64::from(self.height);
+        match format {
+            YuvFormat::I420 => (0, luma, Some(luma + chroma_width * chroma_height)),
+            YuvFormat::Nv12 => (0, luma, None),
+        }
+    }
+}
+
+/// Converts the tiles of a `TileGrid` into a tightly packed `format` frame in `out`, writing
+/// the rows of every tile where they belong in the frame
+///
+/// Tiles can be written in any order, eg.: as a decoder or a stitcher produces them, and only
+/// one tile is held in memory at a time.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+///
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, TileGrid, TiledWriter, YuvFormat};
+///
+/// let grid = TileGrid::new(4, 2, 2, 2).unwrap();
+/// let mut writer = TiledWriter::new(Cursor::new(Vec::new()), grid, YuvFormat::I420);
+/// let cfg = ConversionConfig::default();
+/// for (column, &gray) in [255u8, 0].iter().enumerate() {
+///     let rgb = [gray; 2 * 2 * 3];
+///     let tile = RgbView::new(&rgb, 2, 2, ChannelOrder::Rgb).unwrap();
+///     writer.write_tile(column as u32, 0, &tile, &cfg).unwrap();
+/// }
+/// assert_eq!(writer.tiles_written(), grid.tiles());
+/// assert_eq!(writer.into_inner().into_inner(), [255, 255, 0, 0, 255, 255, 0, 0, 128, 128, 128, 128]);
+/// ```
+#[derive(Debug)]
+pub struct TiledWriter<W: Write + Seek> {
+    out: W,
+    grid: TileGrid,
+    format: YuvFormat,
+    tiles: u64,
+}
+
+impl<W: Write + Seek> TiledWriter<W> {
+    /// A writer of the tiles of `grid` into a `format` frame at the start of `out`
+    pub fn new(out: W, grid: TileGrid, format: YuvFormat) -> TiledWriter<W> {
+        TiledWriter { out, grid, format, tiles: 0 }
+    }
+
+    pub fn grid(&self) -> TileGrid {
+        self.grid
+    }
+
+    /// Number of tiles written so far
+    pub fn tiles_written(&self) -> u64 {
+        self.tiles
+    }
+
+    /// Converts `src` with `cfg` into tile `column` of tile row `row` of the frame
+    ///
+    /// # Errors
+    ///
+    /// The error of writing, or an `InvalidInput` error holding `InvalidRegion` if the grid
+    /// has no such tile and `UnsupportedDimensions` if `src` isn't the size of the tile
+    pub fn write_tile(&mut self, column: u32, row: u32, src: &RgbView, cfg: &ConversionConfig) -> io::Result<()> {
+        let tile = self.grid.tile(column, row).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        check_size((src.width(), src.height()), tile.width, tile.height)?;
+        let planes = ::convert(src, self.format, cfg);
+        let planes = planes.as_planes();
+        let width = u64::from(self.grid.width);
+        let (chroma_width, _) = self.grid.chroma_dimensions();
+        let (x, y) = (u64::from(tile.x), u64::from(tile.y));
+        let (_, chroma_at, v_at) = self.grid.plane_offsets(self.format);
+        for (row, samples) in planes.y().rows().enumerate() {
+            self.out.seek(SeekFrom::Start((y + row as u64) * width + x))?;
+            self.out.write_all(samples)?;
+        }
+        // the chroma planes with their offset in the frame and the bytes of one of their rows
+        let chroma = match (planes.u(), planes.v(), planes.uv(), v_at) {
+            (Some(u), Some(v), _, Some(v_at)) => {
+                vec![(u, chroma_at, chroma_width, x / 2), (v, v_at, chroma_width, x / 2)]
+            }
+            (_, _, Some(uv), _) => vec![(uv, chroma_at, 2 * chroma_width, x)],
+            _ => unreachable!("frames have either U and V or UV planes"),
+        };
+        for (plane, at, stride, x) in chroma {
+            for (row, samples) in plane.rows().enumerate() {
+                self.out.seek(SeekFrom::Start(at + (y / 2 + row as u64) * stride + x))?;
+                self.out.write_all(samples)?;
+            }
+        }
+        self.tiles += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// The destination of the frame, eg.: to finish the file it is written to
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{TileGrid, TiledWriter};
+    use config::{ConversionConfig, Matrix};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use geometry::Rect;
+    use view::RgbView;
+
+    #[test]
+    fn tiles_convert_into_the_whole_frame() {
+        let cfg = ConversionConfig { matrix: Matrix::Bt709, ..ConversionConfig::default() };
+        let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 53 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 7, 5, ChannelOrder::Rgb).unwrap();
+        let grid = TileGrid::new(7, 5, 4, 2).unwrap();
+        assert_eq!((grid.columns(), grid.rows(), grid.tiles()), (2, 3, 6));
+        assert_eq!(grid.tile(1, 2), Ok(Rect { x: 4, y: 4, width: 3, height: 1 }));
+        for &format in &[YuvFormat::I420, YuvFormat::Nv12] {
+            let mut writer = TiledWriter::new(Cursor::new(Vec::new()), grid, format);
+            // bottom up, as BMPs and some scanners produce them
+            for row in (0..grid.rows()).rev() {
+                for column in 0..grid.columns() {
+                    let tile = grid.tile(column, row).unwrap();
+                    let tile = src.crop(tile.x, tile.y, tile.width, tile.height).unwrap();
+                    writer.write_tile(column, row, &tile, &cfg).unwrap();
+                }
+            }
+            let frame = writer.into_inner().into_inner();
+            assert_eq!(frame.len() as u64, grid.frame_size(format));
+            assert_eq!(frame, ::convert(&src, format, &cfg).data());
+        }
+        assert_eq!(TileGrid::new(7, 5, 3, 2), Err(ConversionError::UnsupportedDimensions { width: 3, height: 2 }));
+        // 10 gigapixels
+        let grid = TileGrid::new(100_000, 100_000, 4096, 4096).unwrap();
+        assert_eq!(grid.frame_size(YuvFormat::Nv12), 15_000_000_000);
+        assert_eq!(grid.plane_offsets(YuvFormat::I420), (0, 10_000_000_000, Some(12_500_000_000)));
+    }
+}
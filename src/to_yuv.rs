@@ -0,0 +1,181 @@
+//! Conversions of images that know their own layout
+
+#[cfg(feature = "image")]
+use core::ops::Deref;
+#[cfg(feature = "image")]
+use core::slice;
+
+#[cfg(feature = "image")]
+use image::{DynamicImage, ImageBuffer, Rgb, RgbImage, Rgba};
+
+#[cfg(feature = "image")]
+use config::ChromaUpsampling;
+use config::ConversionConfig;
+#[cfg(feature = "image")]
+use format::ChannelOrder;
+use format::YuvFormat;
+use frame::YuvFrame;
+use view::{RgbFrame, RgbView};
+
+/// An image that can describe itself as an `RgbView`, so it converts without passing its
+/// dimensions, stride and channel order around
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbFrame, ToYuv420};
+///
+/// let img = RgbFrame::new(vec![0u8; 4 * 2 * 3], 4, 2, ChannelOrder::Rgb).unwrap();
+/// let frame = img.to_yuv420p(&ConversionConfig::default());
+/// assert_eq!(frame.u(), Some(&[128, 128][..]));
+/// ```
+pub trait ToYuv420 {
+    /// Describes the pixels of the image
+    fn rgb_view(&self) -> RgbView<'_>;
+
+    /// Converts the image to the YUV420 layout chosen at runtime, see `convert`
+    fn to_yuv420(&self, format: YuvFormat, cfg: &ConversionConfig) -> YuvFrame {
+        ::convert(&self.rgb_view(), format, cfg)
+    }
+
+    /// Converts the image to I420
+    fn to_yuv420p(&self, cfg: &ConversionConfig) -> YuvFrame {
+        self.to_yuv420(YuvFormat::I420, cfg)
+    }
+
+    /// Converts the image to NV12
+    fn to_nv12(&self, cfg: &ConversionConfig) -> YuvFrame {
+        self.to_yuv420(YuvFormat::Nv12, cfg)
+    }
+}
+
+impl<'a> ToYuv420 for RgbView<'a> {
+    fn rgb_view(&self) -> RgbView<'_> {
+        *self
+    }
+}
+
+impl ToYuv420 for RgbFrame {
+    fn rgb_view(&self) -> RgbView<'_> {
+        self.view()
+    }
+}
+
+#[cfg(feature = "image")]
+impl<C: Deref<Target = [u8]>> ToYuv420 for ImageBuffer<Rgb<u8>, C> {
+    fn rgb_view(&self) -> RgbView<'_> {
+        RgbView::new(self.as_raw(), self.width(), self.height(), ChannelOrder::Rgb)
+            .expect("image buffers hold all of their pixels")
+    }
+}
+
+#[cfg(feature = "image")]
+impl<C: Deref<Target = [u8]>> ToYuv420 for ImageBuffer<Rgba<u8>, C> {
+    fn rgb_view(&self) -> RgbView<'_> {
+        RgbView::new(self.as_raw(), self.width(), self.height(), ChannelOrder::Rgba)
+            .expect("image buffers hold all of their pixels")
+    }
+}
+
+// the bytes of 16 bit samples, in native endianness as 16 bit views take them
+#[cfg(feature = "image")]
+fn sample_bytes(samples: &[u16]) -> &[u8] {
+    // u8 has no alignment and every byte of a u16 is initialized
+    unsafe { slice::from_raw_parts(samples.as_ptr() as *const u8, 2 * samples.len()) }
+}
+
+#[cfg(feature = "image")]
+impl<C: Deref<Target = [u16]>> ToYuv420 for ImageBuffer<Rgb<u16>, C> {
+    fn rgb_view(&self) -> RgbView<'_> {
+        RgbView::with_layout(sample_bytes(self.as_raw()), self.width(), self.height(), 6 * self.width() as usize,
+                             ChannelOrder::Rgb, 16).expect("image buffers hold all of their pixels")
+    }
+}
+
+#[cfg(feature = "image")]
+impl<C: Deref<Target = [u16]>> ToYuv420 for ImageBuffer<Rgba<u16>, C> {
+    fn rgb_view(&self) -> RgbView<'_> {
+        RgbView::with_layout(sample_bytes(self.as_raw()), self.width(), self.height(), 8 * self.width() as usize,
+                             ChannelOrder::Rgba, 16).expect("image buffers hold all of their pixels")
+    }
+}
+
+/// Converts an image of any kind `image` decodes to the YUV420 layout chosen at runtime
+///
+/// RGB and RGBA images of 8 and 16 bits are converted in place; the others (gray, or float)
+/// are converted to RGB first, in 16 bits if they have more than 8.
+///
+/// # Examples
+///
+/// ```
+/// extern crate image;
+/// # extern crate rgb2yuv420;
+///
+/// use image::{DynamicImage, GrayImage};
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 2, [90].into()));
+/// let frame = rgb2yuv420::convert_image(&gray, YuvFormat::I420, &ConversionConfig::default());
+/// assert_eq!((frame.y()[0], frame.u().unwrap()[0]), (90, 128));
+/// ```
+#[cfg(feature = "image")]
+pub fn convert_image(img: &DynamicImage, format: YuvFormat, cfg: &ConversionConfig) -> YuvFrame {
+    match *img {
+        DynamicImage::ImageRgb8(ref img) => img.to_yuv420(format, cfg),
+        DynamicImage::ImageRgba8(ref img) => img.to_yuv420(format, cfg),
+        DynamicImage::ImageRgb16(ref img) => img.to_yuv420(format, cfg),
+        DynamicImage::ImageRgba16(ref img) => img.to_yuv420(format, cfg),
+        _ if img.color().bytes_per_pixel() > img.color().channel_count() => img.to_rgb16().to_yuv420(format, cfg),
+        _ => img.to_rgb8().to_yuv420(format, cfg),
+    }
+}
+
+#[cfg(feature = "image")]
+impl YuvFrame {
+    /// Converts the frame back into an `RgbImage`, see `to_rgb`
+    pub fn to_rgb_image(&self) -> RgbImage {
+        let rgb = self.to_rgb(ChannelOrder::Rgb, ChromaUpsampling::default());
+        RgbImage::from_raw(self.width(), self.height(), rgb).expect("the image is the size of the frame")
+    }
+}
+
+#[cfg(all(test, feature = "image"))]
+mod tests {
+    use image::{DynamicImage, GrayImage, ImageBuffer, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use super::{convert_image, ToYuv420};
+    use config::ConversionConfig;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn image_buffers_match_the_legacy_functions() {
+        let rgb = RgbImage::from_fn(5, 3, |x, y| Rgb([(x * 50) as u8, (y * 80) as u8, 200]));
+        let rgba = RgbaImage::from_fn(5, 3, |x, y| Rgba([(x * 50) as u8, (y * 80) as u8, 200, 0]));
+        let cfg = ConversionConfig::default();
+        let expected = ::convert_rgb_to_yuv420sp_nv12(rgb.as_raw(), 5, 3, 3);
+        assert_eq!(rgb.to_nv12(&cfg).into_vec(), expected);
+        assert_eq!(rgba.to_nv12(&cfg).into_vec(), expected);
+        assert_eq!(rgba.to_yuv420p(&cfg).into_vec(), ::convert_rgb_to_yuv420p(rgb.as_raw(), 5, 3, 3));
+    }
+
+    #[test]
+    fn dynamic_images_convert_whatever_their_pixels() {
+        let cfg = ConversionConfig::default();
+        let gray = GrayImage::from_fn(5, 3, |x, y| Luma([(x * 40 + y * 20) as u8]));
+        let rgb = RgbImage::from_fn(5, 3, |x, y| Rgb([(x * 40 + y * 20) as u8; 3]));
+        assert_eq!(convert_image(&DynamicImage::ImageLuma8(gray), YuvFormat::Nv12, &cfg), rgb.to_nv12(&cfg));
+        let wide = ImageBuffer::<Rgb<u16>, _>::from_fn(5, 3, |x, y| Rgb([(x * 9000) as u16, (y * 20000) as u16, 4660]));
+        let bytes: Vec<u8> = wide.as_raw().iter().flat_map(|sample| sample.to_ne_bytes()).collect();
+        let view = RgbView::with_layout(&bytes, 5, 3, 30, ChannelOrder::Rgb, 16).unwrap();
+        let frame = convert_image(&DynamicImage::ImageRgb16(wide), YuvFormat::I420, &cfg);
+        assert_eq!(frame, ::convert(&view, YuvFormat::I420, &cfg));
+        // back to RGB, like the tightly packed frame
+        let rgb = RgbImage::from_fn(6, 4, |x, y| Rgb([(x * 20 + 60) as u8, 100, (y * 20 + 80) as u8]));
+        let frame = rgb.to_yuv420p(&cfg);
+        let back = frame.to_rgb_image();
+        assert_eq!(back.dimensions(), (6, 4));
+        let expected = ::yuv_to_rgb(frame.data(), YuvFormat::I420, 6, 4, ChannelOrder::Rgb, &cfg).unwrap();
+        assert_eq!(back.into_raw(), expected);
+    }
+}
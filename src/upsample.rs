@@ -0,0 +1,143 @@
+//! Chroma upsampling from 4:2:0 to full resolution, eg.: for filters that work on 4:4:4
+
+use alloc::vec::Vec;
+
+use config::ChromaUpsampling;
+use error::ConversionError;
+use format::YuvFormat;
+use inverse::YuvSource;
+use kernel;
+
+/// Interpolates the chroma rows of a 4:2:0 frame to one sample per pixel, first down the
+/// columns and then along the rows, taking the samples as centered in their 2x2 blocks
+pub(crate) struct Upsampler {
+    filter: ChromaUpsampling,
+    // the rows of U and V interpolated vertically, scaled by 128
+    u: Vec<i32>,
+    v: Vec<i32>,
+}
+
+impl Upsampler {
+    pub fn new(filter: ChromaUpsampling, src: &YuvSource) -> Upsampler {
+        let chroma_width = kernel::chroma_dimensions(src.width, src.height).0;
+        Upsampler { filter, u: vec![0; chroma_width], v: vec![0; chroma_width] }
+    }
+
+    /// Writes the U and V of the pixels of `row` of `src` into the first `src.width` samples
+    /// of `u` and `v`
+    pub fn row(&mut self, src: &YuvSource, row: usize, u: &mut [u8], v: &mut [u8]) {
+        let chroma_height = kernel::chroma_dimensions(src.width, src.height).1;
+        let (first, weights) = taps(self.filter, row);
+        self.u.iter_mut().for_each(|sum| *sum = 0);
+        self.v.iter_mut().for_each(|sum| *sum = 0);
+        for (i, &weight) in weights.iter().enumerate().filter(|&(_, &weight)| weight != 0) {
+            let (su, sv, step) = src.chroma_row(clamp(first + i as isize, chroma_height));
+            for (x, (u, v)) in self.u.iter_mut().zip(self.v.iter_mut()).enumerate() {
+                *u += weight * i32::from(su[x * step]);
+                *v += weight * i32::from(sv[x * step]);
+            }
+        }
+        let chroma_width = self.u.len();
+        for (x, (u, v)) in u[..src.width].iter_mut().zip(&mut v[..src.width]).enumerate() {
+            let (first, weights) = taps(self.filter, x);
+            let (mut sum_u, mut sum_v) = (0, 0);
+            for (i, &weight) in weights.iter().enumerate().filter(|&(_, &weight)| weight != 0) {
+                let sample = clamp(first + i as isize, chroma_width);
+                sum_u += weight * self.u[sample];
+                sum_v += weight * self.v[sample];
+            }
+            *u = ((sum_u + 8192) >> 14).clamp(0, 255) as u8;
+            *v = ((sum_v + 8192) >> 14).clamp(0, 255) as u8;
+        }
+    }
+}
+
+/// The first of the 4 samples around pixel `pos` and their weights out of 128
+///
+/// Pixel `2k` lies a quarter sample before sample `k`, pixel `2k + 1` a quarter after it.
+fn taps(filter: ChromaUpsampling, pos: usize) -> (isize, [i32; 4]) {
+    let first = (pos as isize + 1) / 2 - 2;
+    let weights = match (filter, pos % 2) {
+        (ChromaUpsampling::Nearest, 0) => [0, 0, 128, 0],
+        (ChromaUpsampling::Nearest, _) => [0, 128, 0, 0],
+        (ChromaUpsampling::Bilinear, 0) => [0, 32, 96, 0],
+        (ChromaUpsampling::Bilinear, _) => [0, 96, 32, 0],
+        (ChromaUpsampling::CatmullRom, 0) => [-3, 29, 111, -9],
+        (ChromaUpsampling::CatmullRom, _) => [-9, 111, 29, -3],
+    };
+    (first, weights)
+}
+
+// repeats the edge samples
+fn clamp(sample: isize, samples: usize) -> usize {
+    sample.clamp(0, samples as isize - 1) as usize
+}
+
+/// Upsamples the chroma of a tightly packed frame in `format` into a planar 4:4:4 frame,
+/// `[y, y, ..., u, u, ..., v, v, ...]` with three planes of `width * height` samples
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `yuv` is shorter than `required_buffer_size`
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChromaUpsampling, YuvFormat};
+///
+/// // a 4x2 frame with U of 0 on the left and 128 on the right
+/// let nv12 = [16, 16, 16, 16, 16, 16, 16, 16, 0, 128, 128, 128];
+/// let yuv444 = rgb2yuv420::upsample_to_yuv444(&nv12, YuvFormat::Nv12, 4, 2, ChromaUpsampling::Bilinear).unwrap();
+/// assert_eq!(&yuv444[8..12], &[0, 32, 96, 128]);
+/// ```
+pub fn upsample_to_yuv444(yuv: &[u8], format: YuvFormat, width: u32, height: u32, filter: ChromaUpsampling)
+                          -> Result<Vec<u8>, ConversionError> {
+    let src = YuvSource::packed(yuv, format, width, height)?;
+    let pixels = src.width * src.height;
+    let mut yuv444 = vec![0; 3 * pixels];
+    if pixels == 0 {
+        return Ok(yuv444);
+    }
+    let (y, chroma) = yuv444.split_at_mut(pixels);
+    let (u, v) = chroma.split_at_mut(pixels);
+    let mut upsampler = Upsampler::new(filter, &src);
+    let rows = y.chunks_mut(src.width).zip(u.chunks_mut(src.width).zip(v.chunks_mut(src.width)));
+    for (row, (y, (u, v))) in rows.enumerate() {
+        y.copy_from_slice(&src.y[row * src.y_stride..][..src.width]);
+        upsampler.row(&src, row, u, v);
+    }
+    Ok(yuv444)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::upsample_to_yuv444;
+    use config::ChromaUpsampling;
+    use error::ConversionError;
+    use format::YuvFormat;
+
+    #[test]
+    fn filters_interpolate_between_block_centers() {
+        // 6x4 I420 with a U ramp across and a V ramp down, and one bright U sample
+        let mut i420 = vec![100; 24];
+        i420.extend_from_slice(&[0, 60, 120, 0, 60, 240]);
+        i420.extend_from_slice(&[30, 30, 30, 90, 90, 90]);
+        let plane = |filter, plane: usize| {
+            let yuv444 = upsample_to_yuv444(&i420, YuvFormat::I420, 6, 4, filter).unwrap();
+            yuv444[24 * plane..][..24].to_vec()
+        };
+        assert_eq!(plane(ChromaUpsampling::Nearest, 0), vec![100; 24]);
+        assert_eq!(&plane(ChromaUpsampling::Nearest, 1)[..6], &[0, 0, 60, 60, 120, 120]);
+        let v = plane(ChromaUpsampling::Bilinear, 2);
+        assert_eq!(v.chunks(6).map(|row| row[0]).collect::<Vec<_>>(), vec![30, 45, 75, 90]);
+        assert!(v.chunks(6).all(|row| row.iter().all(|&v| v == row[0])));
+        let u = plane(ChromaUpsampling::Bilinear, 1);
+        // 3/4 of the first chroma row and 1/4 of the second one: 0, 60 and 150 across
+        assert_eq!(&u[6..][..6], &[0, 15, 45, 83, 128, 150]);
+        // catmull-rom over- and undershoots, clipped at both ends
+        let u = plane(ChromaUpsampling::CatmullRom, 1);
+        assert_eq!(&u[6 * 3..][..6], &[0, 8, 35, 102, 212, 255]);
+        assert_eq!(upsample_to_yuv444(&i420[..35], YuvFormat::I420, 6, 4, ChromaUpsampling::Nearest),
+                   Err(ConversionError::BufferTooSmall { expected: 36, got: 35 }));
+    }
+}
@@ -0,0 +1,109 @@
+//! Conversions of V4L2 capture buffers, picked by the fourcc of their pixel format
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{ChannelOrder, PackedYuvFormat, YuvFormat};
+use frame::YuvFrame;
+use view::RgbView;
+use yuv422;
+
+// how the pixels of a fourcc are converted
+enum Pixels {
+    Rgb(ChannelOrder),
+    Packed(PackedYuvFormat),
+}
+
+fn pixels(fourcc: [u8; 4]) -> Option<Pixels> {
+    Some(match &fourcc {
+        b"RGB3" => Pixels::Rgb(ChannelOrder::Rgb),
+        b"BGR3" => Pixels::Rgb(ChannelOrder::Bgr),
+        // the deprecated RGB32 and BGR32, then the 32 bit formats named after their words
+        b"RGB4" | b"BA24" | b"BX24" => Pixels::Rgb(ChannelOrder::Argb),
+        b"BGR4" | b"AR24" | b"XR24" => Pixels::Rgb(ChannelOrder::Bgra),
+        b"AB24" | b"XB24" => Pixels::Rgb(ChannelOrder::Rgba),
+        b"RA24" | b"RX24" => Pixels::Rgb(ChannelOrder::Abgr),
+        b"YUYV" => Pixels::Packed(PackedYuvFormat::Yuyv),
+        b"UYVY" => Pixels::Packed(PackedYuvFormat::Uyvy),
+        _ => return None,
+    })
+}
+
+/// Converts a V4L2 capture buffer, eg.: a mmap'd one of a webcam, into a tightly packed
+/// `output` frame
+///
+/// `fourcc` is the `pixelformat` of the negotiated `v4l2_pix_format` as bytes
+/// (`pixelformat.to_le_bytes()`). RGB formats (`RGB3`, `BGR3`, `RGB4`, `BGR4` and the
+/// `AR24` family) are converted with `cfg`; alpha and padding bytes are ignored. YUYV and
+/// UYVY are filtered down to 4:2:0 as by `convert_packed_yuv_to_yuv420`, which only records
+/// the matrix and range of `cfg`.
+///
+/// # Arguments
+///
+/// * `bytesperline` - the `bytesperline` of the format, the distance between the starts of
+///   two rows in bytes
+///
+/// # Errors
+///
+/// `UnsupportedFormat` for other pixel formats, `StrideMismatch` if `bytesperline` is
+/// smaller than a row and `BufferTooSmall` if `buffer` can't hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// // 2x2 YUYV in rows of 8 bytes
+/// let yuyv = [16, 100, 17, 200, 0, 0, 0, 0, 18, 60, 19, 120, 0, 0, 0, 0];
+/// let frame = rgb2yuv420::convert_v4l2(&yuyv, *b"YUYV", 2, 2, 8, YuvFormat::I420, &ConversionConfig::default())
+///     .unwrap();
+/// assert_eq!((frame.y(), frame.u().unwrap(), frame.v().unwrap()), (&[16, 17, 18, 19][..], &[80][..], &[160][..]));
+/// ```
+pub fn convert_v4l2(buffer: &[u8], fourcc: [u8; 4], width: u32, height: u32, bytesperline: usize,
+                    output: YuvFormat, cfg: &ConversionConfig) -> Result<YuvFrame, ConversionError> {
+    match pixels(fourcc).ok_or(ConversionError::UnsupportedFormat("unsupported V4L2 pixel format"))? {
+        Pixels::Rgb(order) => {
+            let src = RgbView::with_layout(buffer, width, height, bytesperline, order, 8)?;
+            Ok(::convert(&src, output, cfg))
+        }
+        Pixels::Packed(format) => {
+            yuv422::convert_packed_yuv_to_yuv420(buffer, bytesperline, width, height, format, output, cfg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::convert_v4l2;
+    use config::ConversionConfig;
+    use error::ConversionError;
+    use format::{ChannelOrder, PackedYuvFormat, YuvFormat};
+    use view::RgbView;
+    use yuv422;
+
+    #[test]
+    fn capture_buffers_convert_by_their_fourcc() {
+        let cfg = ConversionConfig::default();
+        let rgb: Vec<u8> = (0..3 * 2 * 3).map(|i| (i * 41 % 256) as u8).collect();
+        let expected = ::convert(&RgbView::new(&rgb, 3, 2, ChannelOrder::Rgb).unwrap(), YuvFormat::Nv12, &cfg);
+        // the pixels in rows of 16 bytes as RGB3 and as BGR4
+        let rows = |pixel: &dyn Fn(&[u8]) -> Vec<u8>| -> Vec<u8> {
+            rgb.chunks(9).flat_map(|row| {
+                let mut row: Vec<u8> = row.chunks(3).flat_map(pixel).collect();
+                row.resize(16, 0xff);
+                row
+            }).collect()
+        };
+        let rgb3 = rows(&|px| px.to_vec());
+        let bgr4 = rows(&|px| vec![px[2], px[1], px[0], 0]);
+        assert_eq!(convert_v4l2(&rgb3, *b"RGB3", 3, 2, 16, YuvFormat::Nv12, &cfg).unwrap(), expected);
+        assert_eq!(convert_v4l2(&bgr4, *b"BGR4", 3, 2, 16, YuvFormat::Nv12, &cfg).unwrap(), expected);
+        assert_eq!(convert_v4l2(&bgr4, *b"BGR4", 3, 2, 8, YuvFormat::Nv12, &cfg),
+                   Err(ConversionError::StrideMismatch { stride: 8, row_bytes: 12 }));
+        let uyvy: Vec<u8> = (0..2 * 12).map(|i| (i * 37 % 256) as u8).collect();
+        assert_eq!(convert_v4l2(&uyvy, *b"UYVY", 3, 2, 12, YuvFormat::I420, &cfg).unwrap(),
+                   yuv422::convert_packed_yuv_to_yuv420(&uyvy, 12, 3, 2, PackedYuvFormat::Uyvy, YuvFormat::I420, &cfg)
+                       .unwrap());
+        assert_eq!(convert_v4l2(&uyvy, *b"MJPG", 3, 2, 12, YuvFormat::I420, &cfg),
+                   Err(ConversionError::UnsupportedFormat("unsupported V4L2 pixel format")));
+    }
+}
@@ -0,0 +1,268 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use config::ConversionConfig;
+use error::ConversionError;
+use format::ChannelOrder;
+#[cfg(feature = "alloc")]
+use format::YuvFormat;
+#[cfg(feature = "alloc")]
+use frame::YuvFrame;
+use kernel::Source;
+
+/// A borrowed, validated description of an interleaved RGB source image
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ChannelOrder, RgbView};
+///
+/// // 2x2 RGBA rows padded to 16 bytes
+/// let rgba = vec![0u8; 16 + 8];
+/// let view = RgbView::with_layout(&rgba, 2, 2, 16, ChannelOrder::Rgba, 8).unwrap();
+/// assert_eq!(view.bytes_per_pixel(), 4);
+/// assert_eq!(view.stride(), 16);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct RgbView<'a> {
+    source: Source<'a>,
+    channel_order: ChannelOrder,
+}
+
+impl<'a> RgbView<'a> {
+    /// Describes tightly packed 8 bit pixels
+    ///
+    /// # Errors
+    ///
+    /// `BufferTooSmall` if `data` is shorter than `width * height` pixels
+    pub fn new(data: &'a [u8], width: u32, height: u32, channel_order: ChannelOrder)
+               -> Result<RgbView<'a>, ConversionError> {
+        // saturated, for `with_layout` to refuse rows past `usize::MAX`
+        let stride = (width as usize).saturating_mul(channel_order.bytes_per_pixel());
+        RgbView::with_layout(data, width, height, stride, channel_order, 8)
+    }
+
+    /// Describes an image whose rows start `stride` bytes apart
+    ///
+    /// # Arguments
+    ///
+    /// * `stride` - distance between the starts of two rows in bytes; the last row doesn't
+    ///   need to be padded
+    ///
+    /// * `bit_depth` - 8, or 16 for native endian 16 bit channels (which are rounded to
+    ///   8 bits before converting)
+    ///
+    /// # Errors
+    ///
+    /// `UnsupportedFormat` if `bit_depth` is not 8 or 16, `StrideMismatch` if `stride` is
+    /// smaller than a row and `BufferTooSmall` if `data` is too short
+    pub fn with_layout(data: &'a [u8], width: u32, height: u32, stride: usize,
+                       channel_order: ChannelOrder, bit_depth: u8) -> Result<RgbView<'a>, ConversionError> {
+        if bit_depth != 8 && bit_depth != 16 {
+            return Err(ConversionError::UnsupportedFormat("bit_depth must be 8 or 16"));
+        }
+        let source = Source::new(data, width, height, stride, channel_order.layout(), bit_depth == 16)?;
+        Ok(RgbView { source, channel_order })
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.source.data
+    }
+
+    pub fn width(&self) -> u32 {
+        self.source.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.source.height as u32
+    }
+
+    /// Distance between the starts of two rows in bytes
+    pub fn stride(&self) -> usize {
+        self.source.stride
+    }
+
+    pub fn channel_order(&self) -> ChannelOrder {
+        self.channel_order
+    }
+
+    /// Bits per channel (8 or 16)
+    pub fn bit_depth(&self) -> u8 {
+        if self.source.wide { 16 } else { 8 }
+    }
+
+    /// Number of bytes used by one pixel
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.source.pixel_bytes()
+    }
+
+    /// Describes the `width` x `height` rectangle whose top left pixel is at (`x`, `y`)
+    ///
+    /// The region shares the buffer and stride of this view, so converting it doesn't copy
+    /// anything first. Chroma is sampled on the 2x2 grid of the region.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRegion` if the rectangle doesn't fit into this view
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::{ChannelOrder, ConversionConfig, RgbView, YuvFormat};
+    ///
+    /// let desktop = vec![0u8; 1920 * 1080 * 4];
+    /// let desktop = RgbView::new(&desktop, 1920, 1080, ChannelOrder::Bgra).unwrap();
+    /// let window = desktop.crop(100, 50, 640, 480).unwrap();
+    /// let frame = rgb2yuv420::convert(&window, YuvFormat::I420, &ConversionConfig::default());
+    /// assert_eq!((frame.width(), frame.height()), (640, 480));
+    /// ```
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<RgbView<'a>, ConversionError> {
+        if u64::from(x) + u64::from(width) > u64::from(self.width())
+            || u64::from(y) + u64::from(height) > u64::from(self.height()) {
+            return Err(ConversionError::InvalidRegion { x, y, width, height });
+        }
+        let source = &self.source;
+        let data = if width == 0 || height == 0 {
+            &source.data[..0]
+        } else {
+            &source.data[y as usize * source.stride + x as usize * source.pixel_bytes()..]
+        };
+        let source = Source::new(data, width, height, source.stride, source.layout, source.wide)?;
+        Ok(RgbView { source, channel_order: self.channel_order })
+    }
+
+    pub(crate) fn source(&self) -> &Source<'a> {
+        &self.source
+    }
+}
+
+/// Converts to I420 with the default `ConversionConfig`
+#[cfg(feature = "alloc")]
+impl<'a, 'b> From<&'b RgbView<'a>> for YuvFrame {
+    fn from(src: &'b RgbView<'a>) -> YuvFrame {
+        ::convert(src, YuvFormat::I420, &ConversionConfig::default())
+    }
+}
+
+/// An owned, validated RGB image
+///
+/// # Examples
+///
+/// ```
+/// use std::convert::TryFrom;
+///
+/// use rgb2yuv420::{ChannelOrder, RgbFrame, YuvFormat, YuvFrame};
+///
+/// let rgb = RgbFrame::new(vec![0u8; 4 * 2 * 3], 4, 2, ChannelOrder::Rgb).unwrap();
+/// let yuv = YuvFrame::from(&rgb);
+/// assert_eq!(yuv.format(), YuvFormat::I420);
+/// assert_eq!(YuvFrame::try_from(&rgb), Ok(yuv));
+/// ```
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RgbFrame {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+    stride: usize,
+    channel_order: ChannelOrder,
+    bit_depth: u8,
+}
+
+#[cfg(feature = "alloc")]
+impl RgbFrame {
+    /// Takes ownership of tightly packed 8 bit pixels
+    ///
+    /// # Errors
+    ///
+    /// See `RgbView::new`
+    pub fn new(data: Vec<u8>, width: u32, height: u32, channel_order: ChannelOrder)
+               -> Result<RgbFrame, ConversionError> {
+        // saturated, for `with_layout` to refuse rows past `usize::MAX`
+        let stride = (width as usize).saturating_mul(channel_order.bytes_per_pixel());
+        RgbFrame::with_layout(data, width, height, stride, channel_order, 8)
+    }
+
+    /// Takes ownership of an image whose rows start `stride` bytes apart
+    ///
+    /// # Errors
+    ///
+    /// See `RgbView::with_layout`
+    pub fn with_layout(data: Vec<u8>, width: u32, height: u32, stride: usize,
+                       channel_order: ChannelOrder, bit_depth: u8) -> Result<RgbFrame, ConversionError> {
+        RgbView::with_layout(&data, width, height, stride, channel_order, bit_depth)?;
+        Ok(RgbFrame { data, width, height, stride, channel_order, bit_depth })
+    }
+
+    /// Borrows the image for converting it
+    pub fn view(&self) -> RgbView<'_> {
+        RgbView::with_layout(&self.data, self.width, self.height, self.stride, self.channel_order, self.bit_depth)
+            .expect("validated on construction")
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Converts to I420 with the default `ConversionConfig`
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a RgbFrame> for YuvFrame {
+    fn from(src: &'a RgbFrame) -> YuvFrame {
+        YuvFrame::from(&src.view())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RgbView;
+    use converter::Converter;
+    use error::ConversionError;
+    use format::ChannelOrder;
+
+    #[test]
+    fn padded_and_16_bit_rows_match_packed() {
+        let rgb: Vec<u8> = (0..6 * 3 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let packed = Converter::builder().build().convert(&rgb, 6, 3).unwrap();
+        let padded: Vec<u8> = rgb.chunks(18).flat_map(|row| row.iter().cloned().chain(vec![0xaa; 6])).collect();
+        let converter = Converter::builder().build();
+        let view = RgbView::with_layout(&padded[..24 * 2 + 18], 6, 3, 24, ChannelOrder::Rgb, 8).unwrap();
+        assert_eq!(converter.convert_view(&view), packed);
+        let wide: Vec<u8> = rgb.iter().flat_map(|&c| (u16::from(c) * 257).to_ne_bytes()).collect();
+        let view = RgbView::with_layout(&wide, 6, 3, 36, ChannelOrder::Rgb, 16).unwrap();
+        assert_eq!(converter.convert_view(&view), packed);
+    }
+
+    #[test]
+    fn oversized_layouts_are_refused() {
+        let rgb = [0u8; 64];
+        let huge = RgbView::with_layout(&rgb, 2, 3, usize::MAX / 2 + 1, ChannelOrder::Rgb, 8);
+        assert_eq!(huge.unwrap_err(), ConversionError::BufferTooSmall { expected: usize::MAX, got: 64 });
+        assert!(RgbView::with_layout(&rgb, 2, 3, usize::MAX / 2 - 1, ChannelOrder::Rgb, 8).is_err());
+        assert!(RgbView::with_layout(&rgb, u32::MAX, 1, usize::MAX, ChannelOrder::Rgb, 16).is_err());
+    }
+
+    #[test]
+    fn crop_matches_copied_region() {
+        let rgb: Vec<u8> = (0..7 * 5 * 3).map(|i| (i * 37 % 256) as u8).collect();
+        let region: Vec<u8> = rgb.chunks(21).skip(1).take(3).flat_map(|row| row[2 * 3..6 * 3].to_vec()).collect();
+        let converter = Converter::builder().build();
+        let view = RgbView::new(&rgb, 7, 5, ChannelOrder::Rgb).unwrap();
+        assert_eq!(converter.convert_view(&view.crop(2, 1, 4, 3).unwrap()),
+                   converter.convert(&region, 4, 3).unwrap());
+        assert_eq!(view.crop(4, 0, 4, 1).unwrap_err(),
+                   ConversionError::InvalidRegion { x: 4, y: 0, width: 4, height: 1 });
+    }
+
+    #[test]
+    fn invalid_views_are_rejected() {
+        let rgb = vec![0u8; 2 * 2 * 3];
+        assert_eq!(RgbView::new(&rgb[..11], 2, 2, ChannelOrder::Rgb).unwrap_err(),
+                   ConversionError::BufferTooSmall { expected: 12, got: 11 });
+        assert_eq!(RgbView::with_layout(&rgb, 2, 2, 5, ChannelOrder::Rgb, 8).unwrap_err(),
+                   ConversionError::StrideMismatch { stride: 5, row_bytes: 6 });
+        assert_eq!(RgbView::with_layout(&rgb, 2, 2, 6, ChannelOrder::Rgb, 10).unwrap_err(),
+                   ConversionError::UnsupportedFormat("bit_depth must be 8 or 16"));
+    }
+}
@@ -0,0 +1,113 @@
+//! JavaScript bindings (`wasm` feature), converting the pixels of canvases and `VideoFrame`s
+//! into the planes of I420 and NV12 `VideoFrame`s for WebCodecs encoders
+//!
+//! Formats go by the names of WebCodecs' `VideoPixelFormat` and `VideoMatrixCoefficients`, so
+//! the output can be passed to `new VideoFrame(data, { format, colorSpace, ... })` with the
+//! same values.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use wasm_bindgen::prelude::*;
+
+use config::{ConversionConfig, Matrix, Range};
+use error::ConversionError;
+use format::{ChannelOrder, YuvFormat};
+use layout;
+use view::RgbView;
+
+fn channel_order(pixel_format: &str) -> Result<ChannelOrder, ConversionError> {
+    match pixel_format {
+        "RGBA" | "RGBX" => Ok(ChannelOrder::Rgba),
+        "BGRA" | "BGRX" => Ok(ChannelOrder::Bgra),
+        _ => Err(ConversionError::UnsupportedFormat("the pixels must be RGBA, RGBX, BGRA or BGRX")),
+    }
+}
+
+fn yuv_format(format: &str) -> Result<YuvFormat, ConversionError> {
+    match format {
+        "I420" => Ok(YuvFormat::I420),
+        "NV12" => Ok(YuvFormat::Nv12),
+        _ => Err(ConversionError::UnsupportedFormat("the output must be I420 or NV12")),
+    }
+}
+
+fn config(matrix: &str, full_range: bool) -> Result<ConversionConfig, ConversionError> {
+    let matrix = match matrix {
+        "smpte170m" | "bt470bg" => Matrix::Bt601,
+        "bt709" => Matrix::Bt709,
+        "bt2020-ncl" => Matrix::Bt2020,
+        _ => {
+            return Err(ConversionError::UnsupportedFormat("the matrix must be smpte170m, bt470bg, bt709 or bt2020-ncl"))
+        }
+    };
+    let range = if full_range { Range::Full } else { Range::Limited };
+    Ok(ConversionConfig { matrix, range, ..ConversionConfig::default() })
+}
+
+// the source, output format and configuration named by the arguments of the bindings
+fn parse<'a>(pixels: &'a [u8], pixel_format: &str, width: u32, height: u32, format: &str, matrix: &str,
+             full_range: bool) -> Result<(RgbView<'a>, YuvFormat, ConversionConfig), ConversionError> {
+    let src = RgbView::new(pixels, width, height, channel_order(pixel_format)?)?;
+    Ok((src, yuv_format(format)?, config(matrix, full_range)?))
+}
+
+fn js_error(err: ConversionError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+/// Bytes of a tightly packed `format` (`"I420"` or `"NV12"`) frame, to allocate the buffer
+/// `convertFrameInto` writes into
+#[wasm_bindgen(js_name = frameSize)]
+pub fn frame_size(format: &str, width: u32, height: u32) -> Result<usize, JsError> {
+    Ok(layout::required_buffer_size(yuv_format(format).map_err(js_error)?, width, height))
+}
+
+/// Converts tightly packed `pixel_format` pixels (`"RGBA"`, `"RGBX"`, `"BGRA"` or `"BGRX"`,
+/// eg.: from `getImageData` or `VideoFrame.copyTo`) into a tightly packed `format` frame
+///
+/// `matrix` is one of `"smpte170m"`, `"bt470bg"` (both BT.601), `"bt709"` and `"bt2020-ncl"`.
+/// Throws if a format is unknown or `pixels` is too short.
+#[wasm_bindgen(js_name = convertFrame)]
+pub fn convert_frame(pixels: &[u8], pixel_format: &str, width: u32, height: u32, format: &str, matrix: &str,
+                     full_range: bool) -> Result<Vec<u8>, JsError> {
+    let (src, format, cfg) = parse(pixels, pixel_format, width, height, format, matrix, full_range).map_err(js_error)?;
+    let mut out = Vec::new();
+    ::convert_into(&src, format, &cfg, &mut out).map_err(js_error)?;
+    Ok(out)
+}
+
+/// Converts like `convertFrame`, into the start of `out` (eg.: a view of a buffer reused for
+/// every frame) instead of a new array
+///
+/// Throws as `convertFrame` does, or if `out` is shorter than `frameSize`.
+#[wasm_bindgen(js_name = convertFrameInto)]
+#[allow(clippy::too_many_arguments)]
+pub fn convert_frame_into(pixels: &[u8], pixel_format: &str, width: u32, height: u32, format: &str, matrix: &str,
+                          full_range: bool, out: &mut [u8]) -> Result<(), JsError> {
+    let (src, format, cfg) = parse(pixels, pixel_format, width, height, format, matrix, full_range).map_err(js_error)?;
+    ::convert_into(&src, format, &cfg, out).map_err(js_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use config::{ConversionConfig, Matrix, Range};
+    use error::ConversionError;
+    use format::{ChannelOrder, YuvFormat};
+    use view::RgbView;
+
+    #[test]
+    fn frames_convert_by_webcodecs_names() {
+        let bgrx: Vec<u8> = (0..4 * 2 * 4).map(|i| (i * 59 % 256) as u8).collect();
+        let bt709 = ConversionConfig { matrix: Matrix::Bt709, range: Range::Limited, ..ConversionConfig::default() };
+        let expected = ::convert(&RgbView::new(&bgrx, 4, 2, ChannelOrder::Bgra).unwrap(), YuvFormat::Nv12, &bt709);
+        let (src, format, cfg) = parse(&bgrx, "BGRX", 4, 2, "NV12", "bt709", false).unwrap();
+        assert_eq!(::convert(&src, format, &cfg), expected);
+        assert_eq!(parse(&bgrx[..31], "BGRX", 4, 2, "NV12", "bt709", false).err(),
+                   Some(ConversionError::BufferTooSmall { expected: 32, got: 31 }));
+        assert_eq!(parse(&bgrx, "BGRX", 4, 2, "I444", "bt709", false).err(),
+                   Some(ConversionError::UnsupportedFormat("the output must be I420 or NV12")));
+    }
+}
@@ -0,0 +1,411 @@
+//! YUV4MPEG2 (.y4m) streams, raw I420 frames behind a header with their size, frame rate
+//! and colorspace, which mpv, ffplay and most encoders read without being told anything
+//!
+//! `Y4mWriter` writes them and `Y4mReader` reads them back into frames, eg.: for comparing
+//! the output of a pipeline with golden frames.
+//!
+//! # Examples
+//!
+//! ```
+//! use rgb2yuv420::y4m::Y4mWriter;
+//! use rgb2yuv420::{Converter, Range};
+//!
+//! let frame = Converter::builder().build().convert(&vec![255u8; 4 * 2 * 3], 4, 2).unwrap();
+//! let mut writer = Y4mWriter::new(Vec::new(), 4, 2, (30000, 1001), Range::Full).unwrap();
+//! writer.write_frame(&frame.as_planes()).unwrap();
+//! let stream = writer.into_inner();
+//! assert!(stream.starts_with(b"YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C420jpeg XYSCSS=420JPEG XCOLORRANGE=FULL\n"));
+//! assert!(stream.ends_with(b"FRAME\n\xff\xff\xff\xff\xff\xff\xff\xff\x80\x80\x80\x80"));
+//! ```
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use config::{Colorimetry, ConversionConfig, Matrix, Range};
+use format::YuvFormat;
+use frame::{FrameTiming, YuvFrame};
+use kernel;
+use raw;
+use split::YuvPlanes;
+
+/// Writes frames of one size into a .y4m stream
+///
+/// The header goes out when the writer is created and every frame after a `FRAME` marker,
+/// as I420 whatever the format of the frames. Y4M has no field for the matrix, so players
+/// guess it from the size (BT.601 for SD, BT.709 for HD); `with_colorimetry` names it in an
+/// `XCOLORMATRIX` tag of the crate's own, for `Y4mReader`.
+///
+/// The frames are shown at the frame rate of the stream; `write_timed_frame` also tags them
+/// with their timestamps (`FRAME XPTS=` in microseconds), which `Y4mReader` reads back.
+#[derive(Debug)]
+pub struct Y4mWriter<W: Write> {
+    out: W,
+    width: u32,
+    height: u32,
+    frames: u64,
+    // a chroma row of NV12 frames split into U and V
+    row: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the header of a stream of `width` x `height` frames in `range`, at `framerate`
+    /// frames per second as a fraction (eg.: `(30000, 1001)` for 29.97)
+    pub fn new(out: W, width: u32, height: u32, framerate: (u32, u32), range: Range) -> io::Result<Y4mWriter<W>> {
+        Y4mWriter::start(out, width, height, framerate, range, None)
+    }
+
+    /// Writes the header of a stream like `new`, tagged with the range and the matrix of
+    /// `colorimetry`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rgb2yuv420::y4m::Y4mWriter;
+    /// use rgb2yuv420::Colorimetry;
+    ///
+    /// let writer = Y4mWriter::with_colorimetry(Vec::new(), 4, 2, (25, 1), Colorimetry::Bt709Limited).unwrap();
+    /// assert!(writer.into_inner().ends_with(b"XCOLORRANGE=LIMITED XCOLORMATRIX=BT709\n"));
+    /// ```
+    pub fn with_colorimetry(out: W, width: u32, height: u32, framerate: (u32, u32), colorimetry: Colorimetry)
+                            -> io::Result<Y4mWriter<W>> {
+        Y4mWriter::start(out, width, height, framerate, colorimetry.range(), Some(colorimetry.matrix()))
+    }
+
+    fn start(mut out: W, width: u32, height: u32, framerate: (u32, u32), range: Range, matrix: Option<Matrix>)
+             -> io::Result<Y4mWriter<W>> {
+        let range = match range {
+            Range::Full => "FULL",
+            Range::Limited => "LIMITED",
+        };
+        write!(out, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XYSCSS=420JPEG XCOLORRANGE={}", width, height,
+               framerate.0, framerate.1, range)?;
+        match matrix {
+            Some(Matrix::Bt601) => writeln!(out, " XCOLORMATRIX=BT601")?,
+            Some(Matrix::Bt709) => writeln!(out, " XCOLORMATRIX=BT709")?,
+            Some(Matrix::Bt2020) => writeln!(out, " XCOLORMATRIX=BT2020")?,
+            None => writeln!(out)?,
+        }
+        Ok(Y4mWriter { out, width, height, frames: 0, row: Vec::new() })
+    }
+
+    /// Appends `frame`, without the padding of its rows
+    ///
+    /// # Errors
+    ///
+    /// The error of writing, or an `InvalidInput` error holding `UnsupportedDimensions` if
+    /// `frame` isn't the size of the stream's frames
+    pub fn write_frame(&mut self, frame: &YuvPlanes) -> io::Result<()> {
+        self.write(frame, None)
+    }
+
+    /// Appends `frame` like `write_frame`, tagged with its presentation timestamp, if it has one
+    ///
+    /// # Errors
+    ///
+    /// See `write_frame`
+    pub fn write_timed_frame(&mut self, frame: &YuvFrame) -> io::Result<()> {
+        self.write(&frame.as_planes(), frame.timing().pts)
+    }
+
+    fn write(&mut self, frame: &YuvPlanes, pts: Option<Duration>) -> io::Result<()> {
+        raw::check_size((frame.width(), frame.height()), self.width, self.height)?;
+        match pts {
+            Some(pts) => writeln!(self.out, "FRAME XPTS={}", pts.as_micros())?,
+            None => self.out.write_all(b"FRAME\n")?,
+        }
+        raw::write_planes(&mut self.out, frame, YuvFormat::I420, &mut self.row)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// The destination of the stream, eg.: to finish the file it is written to
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Reads the frames of a .y4m stream
+///
+/// Only 4:2:0 streams of 8 bit samples are supported (the `C420jpeg`, `C420paldv`,
+/// `C420mpeg2` and `C420` colorspaces, or none). The frames are tightly packed I420 in the
+/// range of the `XCOLORRANGE` tag (limited without one) and the matrix of the `XCOLORMATRIX`
+/// tag of `Y4mWriter::with_colorimetry` (the default one without it). Their
+/// `FrameTiming` numbers them and times them by the frame rate, or by the `XPTS` parameter of
+/// their `FRAME` marker; the other parameters are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::y4m::Y4mReader;
+///
+/// let stream = b"YUV4MPEG2 W2 H2 F25:1 C420jpeg\nFRAME\n\x10\x20\x30\x40\x80\x80";
+/// let mut reader = Y4mReader::new(&stream[..]).unwrap();
+/// assert_eq!((reader.width(), reader.height(), reader.framerate()), (2, 2, (25, 1)));
+/// let frame = reader.next().unwrap().unwrap();
+/// assert_eq!(frame.y(), &[0x10, 0x20, 0x30, 0x40]);
+/// assert!(reader.next().is_none());
+/// ```
+#[derive(Debug)]
+pub struct Y4mReader<R: Read> {
+    input: R,
+    width: u32,
+    height: u32,
+    framerate: (u32, u32),
+    range: Range,
+    matrix: Matrix,
+    frames: u64,
+    // bytes of a frame, checked not to overflow while reading the header
+    frame_size: usize,
+}
+
+// an `InvalidData` error for a malformed stream
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid y4m stream: {}", what))
+}
+
+// reads up to a newline, `None` at the end of the stream before the first byte
+fn read_line<R: Read>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return if line.is_empty() { Ok(None) } else { Err(io::ErrorKind::UnexpectedEof.into()) };
+        }
+        if byte[0] == b'\n' {
+            return Ok(Some(line));
+        }
+        // headers are a few dozen bytes, anything longer isn't one
+        if line.len() == 1024 {
+            return Err(invalid("header line too long"));
+        }
+        line.push(byte[0]);
+    }
+}
+
+impl<R: Read> Y4mReader<R> {
+    /// Reads the header of the stream from `input`, which is read a byte at a time up to
+    /// the frames, so it had better be buffered
+    ///
+    /// # Errors
+    ///
+    /// The error of reading, or an `InvalidData` error if the header is malformed or not of
+    /// a supported colorspace
+    pub fn new(mut input: R) -> io::Result<Y4mReader<R>> {
+        let header = read_line(&mut input)?.ok_or_else(|| invalid("empty"))?;
+        let header = String::from_utf8(header).map_err(|_| invalid("header isn't text"))?;
+        let mut tags = header.split(' ');
+        if tags.next() != Some("YUV4MPEG2") {
+            return Err(invalid("no YUV4MPEG2 signature"));
+        }
+        let (mut width, mut height, mut framerate, mut range) = (None, None, (25, 1), Range::Limited);
+        let mut matrix = ConversionConfig::default().matrix;
+        let number = |value: &str| value.parse::<u32>().map_err(|_| invalid("malformed number"));
+        for tag in tags.filter(|tag| !tag.is_empty()) {
+            let mut chars = tag.chars();
+            let key = chars.next();
+            let value = chars.as_str();
+            match key {
+                Some('W') => width = Some(number(value)?),
+                Some('H') => height = Some(number(value)?),
+                Some('F') => {
+                    let (num, den) = value.split_once(':').ok_or_else(|| invalid("malformed frame rate"))?;
+                    framerate = (number(num)?, number(den)?);
+                }
+                Some('C') if !matches!(value, "420jpeg" | "420paldv" | "420mpeg2" | "420") => {
+                    return Err(invalid("only 8 bit 4:2:0 colorspaces are supported"))
+                }
+                Some('X') => match value {
+                    "COLORRANGE=FULL" => range = Range::Full,
+                    "COLORRANGE=LIMITED" => range = Range::Limited,
+                    "COLORMATRIX=BT601" => matrix = Matrix::Bt601,
+                    "COLORMATRIX=BT709" => matrix = Matrix::Bt709,
+                    "COLORMATRIX=BT2020" => matrix = Matrix::Bt2020,
+                    _ => {}
+                },
+                Some(key) if !key.is_ascii() => return Err(invalid("malformed tag")),
+                _ => {}
+            }
+        }
+        let width = width.ok_or_else(|| invalid("no width"))?;
+        let height = height.ok_or_else(|| invalid("no height"))?;
+        let (chroma_width, chroma_height) = kernel::chroma_dimensions(width as usize, height as usize);
+        let frame_size = (width as usize).checked_mul(height as usize)
+            .zip(chroma_width.checked_mul(chroma_height).and_then(|chroma| chroma.checked_mul(2)))
+            .and_then(|(luma, chroma)| luma.checked_add(chroma))
+            .ok_or_else(|| invalid("frame too large"))?;
+        Ok(Y4mReader { input, width, height, framerate, range, matrix, frames: 0, frame_size })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Frames per second as a fraction
+    pub fn framerate(&self) -> (u32, u32) {
+        self.framerate
+    }
+
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// The matrix and range of the frames
+    pub fn colorimetry(&self) -> Colorimetry {
+        Colorimetry::new(self.matrix, self.range)
+    }
+
+    // reads the next frame, `None` at the end of the stream
+    fn read_frame(&mut self) -> io::Result<Option<YuvFrame>> {
+        let marker = match read_line(&mut self.input)? {
+            Some(marker) => marker,
+            None => return Ok(None),
+        };
+        if marker != b"FRAME" && !marker.starts_with(b"FRAME ") {
+            return Err(invalid("no FRAME marker"));
+        }
+        // grown while reading rather than allocated from the header, for truncated streams
+        let mut data = Vec::new();
+        (&mut self.input).take(self.frame_size as u64).read_to_end(&mut data)?;
+        if data.len() < self.frame_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "invalid y4m stream: truncated frame"));
+        }
+        let cfg = ConversionConfig { matrix: self.matrix, range: self.range, ..ConversionConfig::default() };
+        let timing = self.timing(&marker)?;
+        self.frames += 1;
+        Ok(Some(YuvFrame::from_packed(data, YuvFormat::I420, self.width, self.height, &cfg).with_timing(timing)))
+    }
+
+    // the timing of the next frame, whose `FRAME` marker is `marker`
+    fn timing(&self, marker: &[u8]) -> io::Result<FrameTiming> {
+        let (num, den) = (u128::from(self.framerate.0), u128::from(self.framerate.1));
+        let at = |frames: u64| (u128::from(frames) * den * 1_000_000_000).checked_div(num);
+        let time = |nanos: u128| Duration::from_nanos(nanos as u64);
+        let (start, end) = (at(self.frames), at(self.frames + 1));
+        let mut pts = start.map(time);
+        for tag in marker.split(|&byte| byte == b' ').skip(1) {
+            if let Some(micros) = tag.strip_prefix(b"XPTS=") {
+                let micros = std::str::from_utf8(micros).ok().and_then(|micros| micros.parse().ok());
+                pts = Some(Duration::from_micros(micros.ok_or_else(|| invalid("malformed timestamp"))?));
+            }
+        }
+        let duration = start.zip(end).map(|(start, end)| time(end - start));
+        Ok(FrameTiming { pts, duration, index: Some(self.frames) })
+    }
+}
+
+/// Yields the frames up to the end of the stream, or an `UnexpectedEof` error if it ends
+/// within a frame
+impl<R: Read> Iterator for Y4mReader<R> {
+    type Item = io::Result<YuvFrame>;
+
+    fn next(&mut self) -> Option<io::Result<YuvFrame>> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use std::time::Duration;
+
+    use super::{Y4mReader, Y4mWriter};
+    use config::{Colorimetry, ConversionConfig, Range};
+    use frame::FrameTiming;
+    use format::{ChannelOrder, YuvFormat};
+    use split::YuvPlanes;
+    use view::RgbView;
+
+    #[test]
+    fn frames_are_written_as_i420() {
+        let rgb: Vec<u8> = (0..5 * 3 * 3).map(|i| (i * 19 % 256) as u8).collect();
+        let src = RgbView::new(&rgb, 5, 3, ChannelOrder::Rgb).unwrap();
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        let (i420, nv12) = (::convert(&src, YuvFormat::I420, &cfg), ::convert(&src, YuvFormat::Nv12, &cfg));
+        let mut writer = Y4mWriter::new(Vec::new(), 5, 3, (25, 1), Range::Limited).unwrap();
+        writer.write_frame(&nv12.as_planes()).unwrap();
+        writer.write_frame(&i420.as_planes()).unwrap();
+        let small = [0; 6];
+        let err = writer.write_frame(&YuvPlanes::split(&small, YuvFormat::I420, 2, 2).unwrap()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(writer.frames(), 2);
+        let stream = writer.into_inner();
+        let header = b"YUV4MPEG2 W5 H3 F25:1 Ip A1:1 C420jpeg XYSCSS=420JPEG XCOLORRANGE=LIMITED\n";
+        let frame = [&b"FRAME\n"[..], i420.data()].concat();
+        assert_eq!(stream, [&header[..], &frame, &frame].concat());
+    }
+
+    #[test]
+    fn written_streams_read_back() {
+        let rgb: Vec<u8> = (0..6 * 4 * 3).map(|i| (i * 23 % 256) as u8).collect();
+        let frame = ::convert(&RgbView::new(&rgb, 6, 4, ChannelOrder::Rgb).unwrap(), YuvFormat::Nv12,
+                              &ConversionConfig::default());
+        let mut writer = Y4mWriter::new(Vec::new(), 6, 4, (60, 1), Range::Full).unwrap();
+        for _ in 0..3 {
+            writer.write_frame(&frame.as_planes()).unwrap();
+        }
+        let stream = writer.into_inner();
+        let reader = Y4mReader::new(&stream[..]).unwrap();
+        assert_eq!((reader.width(), reader.height(), reader.framerate(), reader.range()), (6, 4, (60, 1), Range::Full));
+        let frames: Vec<_> = reader.collect::<Result<_, _>>().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|read| read.compare(&frame).unwrap().is_identical() && read.range() == Range::Full));
+        // a stream cut within the last frame
+        let mut reader = Y4mReader::new(&stream[..stream.len() - 1]).unwrap();
+        assert!(reader.nth(1).unwrap().is_ok());
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        for header in [&b"YUV4MPEG2 W6 H4 C444\n"[..], b"YUV4MPEG2 H4\n", b"YUV4MPEG W6 H4\n", b"YUV4MPEG2 Wsix H4\n",
+                       "YUV4MPEG2 W2 H2 \u{e9}x\n".as_bytes(), b"YUV4MPEG2 W4294967295 H4294967295\n"] {
+            assert_eq!(Y4mReader::new(header).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+        // a huge frame is read as far as the stream goes, not allocated up front
+        let mut reader = Y4mReader::new(&b"YUV4MPEG2 W65535 H65535\nFRAME\n\0\0"[..]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(Y4mReader::new(&b"YUV4MPEG2 W2 H2\nFRAMES"[..]).unwrap().range(), Range::Limited);
+    }
+
+    #[test]
+    fn colorimetry_is_tagged_and_read_back() {
+        for &colorimetry in &[Colorimetry::Bt601Limited, Colorimetry::Bt709Full, Colorimetry::Bt2020Limited] {
+            let cfg = ConversionConfig::from(colorimetry);
+            let frame = ::convert(&RgbView::new(&[90; 2 * 2 * 3], 2, 2, ChannelOrder::Rgb).unwrap(), YuvFormat::I420,
+                                  &cfg);
+            let mut writer = Y4mWriter::with_colorimetry(Vec::new(), 2, 2, (25, 1), frame.colorimetry()).unwrap();
+            writer.write_frame(&frame.as_planes()).unwrap();
+            let stream = writer.into_inner();
+            let mut reader = Y4mReader::new(&stream[..]).unwrap();
+            assert_eq!(reader.colorimetry(), colorimetry);
+            assert_eq!(reader.next().unwrap().unwrap().colorimetry(), colorimetry);
+        }
+    }
+
+    #[test]
+    fn timestamps_are_tagged_and_read_back() {
+        let frame = ::convert(&RgbView::new(&[0; 2 * 2 * 3], 2, 2, ChannelOrder::Rgb).unwrap(), YuvFormat::I420,
+                              &ConversionConfig::default());
+        let mut writer = Y4mWriter::new(Vec::new(), 2, 2, (25, 1), Range::Full).unwrap();
+        writer.write_timed_frame(&frame).unwrap();
+        let late = FrameTiming { pts: Some(Duration::from_micros(1_000_500)), ..FrameTiming::default() };
+        writer.write_timed_frame(&frame.clone().with_timing(late)).unwrap();
+        let stream = writer.into_inner();
+        assert!(stream.windows(19).any(|tag| tag == b"FRAME XPTS=1000500\n"));
+        let timings: Vec<FrameTiming> = Y4mReader::new(&stream[..]).unwrap().map(|frame| frame.unwrap().timing())
+            .collect();
+        let duration = Some(Duration::from_millis(40));
+        assert_eq!(timings, [FrameTiming { pts: Some(Duration::ZERO), duration, index: Some(0) },
+                             FrameTiming { pts: late.pts, duration, index: Some(1) }]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Conversions from 4:2:2 down to YUV420, eg.: for feeding SDI or webcam captures to
+//! encoders that only take 4:2:0
+
+use config::ConversionConfig;
+use error::ConversionError;
+use format::{PackedYuvFormat, YuvFormat};
+use frame::YuvFrame;
+use kernel;
+use layout;
+use planes::check_plane;
+
+/// Weights out of 8 of the 4 chroma rows around a 4:2:0 chroma row, which sits between the
+/// two middle ones; filtering instead of dropping every other row keeps vertical chroma
+/// detail from aliasing
+const TAPS: [u32; 4] = [1, 3, 3, 1];
+
+// builds a tightly packed frame from the luma samples and the U and V of a 4:2:2 frame,
+// given as (row, x) and (row, chroma x)
+fn downconvert<Y, C>(width: usize, height: usize, output: YuvFormat, cfg: &ConversionConfig, luma: Y, chroma: C)
+                     -> YuvFrame
+    where Y: Fn(usize, usize) -> u8, C: Fn(usize, usize) -> (u8, u8) {
+    let (chroma_width, chroma_height) = kernel::chroma_dimensions(width, height);
+    let mut data = vec![0; layout::required_buffer_size(output, width as u32, height as u32)];
+    let (y_plane, planes) = data.split_at_mut(width * height);
+    for (row, line) in y_plane.chunks_mut(width.max(1)).enumerate() {
+        for (x, y) in line.iter_mut().enumerate() {
+            *y = luma(row, x);
+        }
+    }
+    for row in 0..chroma_height {
+        for x in 0..chroma_width {
+            let (mut u, mut v) = (4, 4);
+            for (i, &weight) in TAPS.iter().enumerate() {
+                let source_row = (2 * row + i).saturating_sub(1).min(height - 1);
+                let (su, sv) = chroma(source_row, x);
+                u += weight * u32::from(su);
+                v += weight * u32::from(sv);
+            }
+            let (u, v) = ((u / 8) as u8, (v / 8) as u8);
+            match output {
+                YuvFormat::I420 => {
+                    planes[row * chroma_width + x] = u;
+                    planes[(chroma_height + row) * chroma_width + x] = v;
+                }
+                YuvFormat::Nv12 => {
+                    planes[2 * (row * chroma_width + x)] = u;
+                    planes[2 * (row * chroma_width + x) + 1] = v;
+                }
+            }
+        }
+    }
+    YuvFrame::from_packed(data, output, width as u32, height as u32, cfg)
+}
+
+/// Converts a packed 4:2:2 frame (YUYV or UYVY) into a tightly packed YUV420 frame in
+/// `output`, filtering every 4:2:0 chroma sample from the 4 rows around it
+///
+/// The samples are copied as they are; the matrix and range of `cfg` are only recorded in
+/// the frame, so they should be the ones the source was captured with.
+///
+/// # Arguments
+///
+/// * `stride` - distance between the starts of two rows in bytes, at least `4 * ((width + 1) / 2)`
+///
+/// # Errors
+///
+/// `StrideMismatch` if `stride` is smaller than a row and `BufferTooSmall` if `yuv` can't
+/// hold all of the rows
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, PackedYuvFormat, YuvFormat};
+///
+/// // 2x2 with U of 100 and 60 and V of 200 and 120 in the two rows
+/// let yuyv = [16, 100, 17, 200, 18, 60, 19, 120];
+/// let frame = rgb2yuv420::convert_packed_yuv_to_yuv420(&yuyv, 4, 2, 2, PackedYuvFormat::Yuyv, YuvFormat::Nv12,
+///                                                      &ConversionConfig::default()).unwrap();
+/// assert_eq!(frame.y(), &[16, 17, 18, 19]);
+/// assert_eq!(frame.uv().unwrap(), &[80, 160]);
+/// ```
+pub fn convert_packed_yuv_to_yuv420(yuv: &[u8], stride: usize, width: u32, height: u32, format: PackedYuvFormat,
+                                    output: YuvFormat, cfg: &ConversionConfig) -> Result<YuvFrame, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    check_plane(yuv.len(), stride, 4 * width.div_ceil(2), height)?;
+    let [y0, u, y1, v] = format.offsets();
+    let luma = |row: usize, x: usize| yuv[row * stride + x / 2 * 4 + if x.is_multiple_of(2) { y0 } else { y1 }];
+    let chroma = |row: usize, x: usize| (yuv[row * stride + 4 * x + u], yuv[row * stride + 4 * x + v]);
+    Ok(downconvert(width, height, output, cfg, luma, chroma))
+}
+
+/// Converts a tightly packed planar 4:2:2 frame (I422: a full Y plane, then U and V planes
+/// of `(width + 1) / 2` samples by `height` rows) into a tightly packed YUV420 frame in
+/// `output`
+///
+/// See `convert_packed_yuv_to_yuv420` for how the chroma is filtered and how `cfg` applies.
+///
+/// # Errors
+///
+/// `BufferTooSmall` if `yuv` is shorter than the frame
+///
+/// # Examples
+///
+/// ```
+/// use rgb2yuv420::{ConversionConfig, YuvFormat};
+///
+/// // 2x4 with U of 0 in the top two rows and 160 in the bottom two
+/// let i422 = [&[50; 8][..], &[0, 0, 160, 160], &[128; 4]].concat();
+/// let frame = rgb2yuv420::convert_yuv422p_to_yuv420(&i422, 2, 4, YuvFormat::I420, &ConversionConfig::default())
+///     .unwrap();
+/// assert_eq!(frame.u().unwrap(), &[20, 140]);
+/// ```
+pub fn convert_yuv422p_to_yuv420(yuv: &[u8], width: u32, height: u32, output: YuvFormat, cfg: &ConversionConfig)
+                                 -> Result<YuvFrame, ConversionError> {
+    let (width, height) = (width as usize, height as usize);
+    let chroma_width = width.div_ceil(2);
+    let expected = width * height + 2 * chroma_width * height;
+    if yuv.len() < expected {
+        return Err(ConversionError::BufferTooSmall { expected, got: yuv.len() });
+    }
+    let (y, chroma) = yuv.split_at(width * height);
+    let (u, v) = chroma.split_at(chroma_width * height);
+    let luma = |row: usize, x: usize| y[row * width + x];
+    let chroma = |row: usize, x: usize| (u[row * chroma_width + x], v[row * chroma_width + x]);
+    Ok(downconvert(width, height, output, cfg, luma, chroma))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_packed_yuv_to_yuv420, convert_yuv422p_to_yuv420};
+    use config::{ConversionConfig, Range};
+    use error::ConversionError;
+    use format::{PackedYuvFormat, YuvFormat};
+
+    #[test]
+    fn chroma_rows_are_filtered_not_dropped() {
+        let cfg = ConversionConfig { range: Range::Limited, ..ConversionConfig::default() };
+        // 3x5 I422 with luma counting up and chroma rows of U 0, 80, 160, 240, 0 and V 128
+        let mut i422: Vec<u8> = (0..15).collect();
+        i422.extend([0, 80, 160, 240, 0].iter().flat_map(|&u| [u, u]));
+        i422.extend_from_slice(&[128; 10]);
+        let frame = convert_yuv422p_to_yuv420(&i422, 3, 5, YuvFormat::I420, &cfg).unwrap();
+        assert_eq!(frame.y(), &i422[..15]);
+        // rows -1 (repeating 0), 0, 1 and 2; 1, 2, 3 and 4; 3, 4 and 4 twice
+        assert_eq!(frame.u().unwrap(), &[50, 50, 160, 160, 30, 30]);
+        assert_eq!(frame.v().unwrap(), &[128; 6]);
+        assert_eq!(frame.range(), Range::Limited);
+        // the same frame as UYVY with 8 byte rows
+        let uyvy: Vec<u8> = (0..5).flat_map(|row| {
+            let (y, u) = (&i422[3 * row..][..3], i422[15 + 2 * row]);
+            [u, y[0], 128, y[1], u, y[2], 128, 0]
+        }).collect();
+        let nv12 = convert_packed_yuv_to_yuv420(&uyvy, 8, 3, 5, PackedYuvFormat::Uyvy, YuvFormat::Nv12, &cfg).unwrap();
+        assert_eq!(nv12.y(), frame.y());
+        assert_eq!(nv12.uv().unwrap(), &[50, 128, 50, 128, 160, 128, 160, 128, 30, 128, 30, 128]);
+        assert_eq!(convert_packed_yuv_to_yuv420(&uyvy[..39], 8, 3, 5, PackedYuvFormat::Uyvy, YuvFormat::Nv12, &cfg)
+                       .map(|_| ()),
+                   Err(ConversionError::BufferTooSmall { expected: 40, got: 39 }));
+        assert_eq!(convert_yuv422p_to_yuv420(&i422[..24], 3, 5, YuvFormat::I420, &cfg).map(|_| ()),
+                   Err(ConversionError::BufferTooSmall { expected: 35, got: 24 }));
+    }
+}